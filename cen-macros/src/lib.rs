@@ -0,0 +1,117 @@
+//! Procedural macros of cen, used through their re-exports, e.g. `cen::include_shader!`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Ident, LitStr, Token};
+
+/// A `NAME = "value"` macro definition.
+struct ShaderMacro {
+    name: Ident,
+    value: LitStr,
+}
+
+impl Parse for ShaderMacro {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+        Ok(ShaderMacro { name, value })
+    }
+}
+
+struct IncludeShader {
+    path: LitStr,
+    macros: Vec<ShaderMacro>,
+}
+
+impl Parse for IncludeShader {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let mut macros = Vec::new();
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            macros = Punctuated::<ShaderMacro, Token![,]>::parse_terminated(input)?.into_iter().collect();
+        }
+        Ok(IncludeShader { path, macros })
+    }
+}
+
+/// Compile a GLSL shader to SPIR-V at build time, into a `cen::vulkan::ShaderSource`. Release builds embed the
+/// code and skip compiling at runtime, while the shader compiles exactly as it would from a file: relative includes
+/// resolve from the including file, `<cen/...>` includes are the built-in headers and `EP` is defined as `main`.
+///
+/// The path is relative to the crate root, like shader paths of `cargo run`. Macros follow the path, and the crate
+/// rebuilds when the shader or one of its includes changes.
+///
+/// ```ignore
+/// let config = ComputePipelineConfig {
+///     shader_source: cen::include_shader!("shaders/blur.comp", RADIUS = "4"),
+///     ..Default::default()
+/// };
+/// ```
+#[proc_macro]
+pub fn include_shader(input: TokenStream) -> TokenStream {
+    let IncludeShader { path, macros } = parse_macro_input!(input as IncludeShader);
+    let macros = macros.iter()
+        .map(|definition| (definition.name.to_string(), definition.value.value()))
+        .collect::<HashMap<String, String>>();
+
+    let shader_path = PathBuf::from(path.value());
+    let source_file = if cen_shaders::is_builtin_shader(&shader_path) {
+        shader_path
+    } else {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+        manifest_dir.join(shader_path)
+    };
+
+    match compile(&source_file, &macros) {
+        Ok((code, files)) => {
+            let files = files.iter().map(|file| file.to_string_lossy().into_owned());
+            quote! {{
+                // Rebuild when the shader or its includes change
+                #( const _: &[u8] = include_bytes!(#files); )*
+                ::cen::vulkan::ShaderSource::SpirV(::std::vec![#(#code),*])
+            }}.into()
+        }
+        Err(error) => syn::Error::new(path.span(), error).to_compile_error().into(),
+    }
+}
+
+/// Compile with the compiler of the runtime compilation, returning the code and the files that were read.
+fn compile(source_file: &Path, macros: &HashMap<String, String>) -> Result<(Vec<u32>, Vec<PathBuf>), String> {
+    let shader_kind = cen_shaders::shader_kind(source_file)
+        .ok_or_else(|| format!("Unknown shader type of {:?}, expected a .vert, .frag or .comp file", source_file))?;
+
+    let mut files = Vec::new();
+    let source = match cen_shaders::builtin_shader(source_file) {
+        Some(source) => source.to_string(),
+        None => {
+            files.push(source_file.to_path_buf());
+            fs::read_to_string(source_file).map_err(|e| format!("Failed to read {:?}: {}", source_file, e))?
+        }
+    };
+
+    let (code, includes) = cen_shaders::compile_glsl(&source, &source_file.to_string_lossy(), shader_kind, macros)?;
+    files.extend(includes);
+    Ok((code, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_builtin_shader() {
+        let path = Path::new(cen_shaders::FULLSCREEN_VERTEX_SHADER);
+        let (code, files) = compile(path, &HashMap::new()).expect("Failed to compile the built-in shader");
+        assert_eq!(code[0], 0x07230203);
+        assert!(files.is_empty());
+
+        assert!(compile(Path::new("shader.glsl"), &HashMap::new()).unwrap_err().contains("Unknown shader type"));
+    }
+}
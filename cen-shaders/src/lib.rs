@@ -0,0 +1,459 @@
+//! Built-in shaders and headers of cen, and the GLSL compiler that resolves their includes. Shared by the runtime
+//! compilation of cen and the build time compilation of `cen::include_shader!`, so both compile the same sources.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use shaderc::{IncludeType, ResolvedInclude};
+
+pub use shaderc::ShaderKind;
+
+/// Path of the built-in vertex shader that covers the target with a single triangle, usable as
+/// `vertex_shader_source` of a `cen::vulkan::GraphicsPipelineConfig`.
+/// Passes the texture coordinate of the fragment at location 0.
+pub const FULLSCREEN_VERTEX_SHADER: &str = "cen/fullscreen.vert";
+
+/// Shaders that are built into cen, loaded in place of a file with the same path.
+pub fn builtin_shader(path: &Path) -> Option<&'static str> {
+    match path.to_str()? {
+        FULLSCREEN_VERTEX_SHADER => Some(FULLSCREEN_VERT),
+        _ => None,
+    }
+}
+
+pub fn is_builtin_shader(path: &Path) -> bool {
+    builtin_shader(path).is_some()
+}
+
+/// Stage of a GLSL shader file by its extension, `.vert`, `.frag` or `.comp`.
+pub fn shader_kind(path: &Path) -> Option<ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(ShaderKind::Vertex),
+        "frag" => Some(ShaderKind::Fragment),
+        "comp" => Some(ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+/// Compile GLSL source code into SPIR-V, returning the code and the canonical paths of the files it included,
+/// directly or through other includes.
+///
+/// `file_name` is used for error messages and as the base path of relative includes. Standard includes are the
+/// built-in headers, see [`builtin_include`], and `EP` is defined as `main`.
+pub fn compile_glsl(source: &str, file_name: &str, shader_kind: ShaderKind, macros: &HashMap<String, String>) -> Result<(Vec<u32>, Vec<PathBuf>), String> {
+    let includes = RefCell::new(Vec::new());
+    let compiler = shaderc::Compiler::new().expect("Failed to create the shader compiler");
+    let mut options = shaderc::CompileOptions::new().expect("Failed to create the shader compile options");
+    options.set_include_callback(|include_name, include_type, original_source, _| {
+        match include_type {
+            IncludeType::Relative => {
+                // Relative to the including file, which is itself resolved relative to its includer
+                let path = Path::new(original_source).parent().unwrap_or(Path::new("")).join(include_name);
+                let source = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read include {:?}: {}", path, e))?;
+
+                // File watchers report absolute paths
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                let mut includes = includes.borrow_mut();
+                if !includes.contains(&canonical) {
+                    includes.push(canonical);
+                }
+
+                Ok(ResolvedInclude {
+                    resolved_name: path.to_string_lossy().into_owned(),
+                    content: source,
+                })
+            }
+            IncludeType::Standard => {
+                builtin_include(include_name)
+                    .map(|source| ResolvedInclude {
+                        resolved_name: include_name.to_string(),
+                        content: source.to_string(),
+                    })
+                    .ok_or_else(|| format!("Unknown built-in include <{}>, use quotes for relative includes", include_name))
+            }
+        }
+    });
+    options.add_macro_definition("EP", Some("main"));
+    for (name, value) in macros {
+        options.add_macro_definition(name, Some(value));
+    }
+
+    let result = compiler.compile_into_spirv(source, shader_kind, file_name, "main", Some(&options));
+    // The include callback borrows the list
+    drop(options);
+
+    result
+        .map(|artifact| (artifact.as_binary().to_vec(), includes.into_inner()))
+        .map_err(|e| e.to_string())
+}
+
+/// Headers that are built into cen, available to GLSL shaders through standard includes, e.g. `#include <cen/random.glsl>`.
+pub fn builtin_include(name: &str) -> Option<&'static str> {
+    match name {
+        "cen/random.glsl" => Some(RANDOM_GLSL),
+        "cen/materials.glsl" => Some(MATERIALS_GLSL),
+        "cen/globals.glsl" => Some(GLOBALS_GLSL),
+        "cen/noise.glsl" => Some(NOISE_GLSL),
+        "cen/color.glsl" => Some(COLOR_GLSL),
+        "cen/tonemap.glsl" => Some(TONEMAP_GLSL),
+        _ => None,
+    }
+}
+
+// The engine-provided uniforms of FrameGlobals, bound as set 0.
+// Must match the layout of GlobalUniforms.
+const GLOBALS_GLSL: &str = r#"
+#ifndef CEN_GLOBALS_GLSL
+#define CEN_GLOBALS_GLSL
+
+layout( std140, set = 0, binding = 0 ) uniform CenGlobals {
+    float time;
+    float delta_time;
+    uint frame;
+    uint random_seed;
+    vec2 resolution;
+    vec2 mouse;
+    uint mouse_buttons;
+} cen_globals;
+
+#define CEN_MOUSE_LEFT 1u
+#define CEN_MOUSE_RIGHT 2u
+#define CEN_MOUSE_MIDDLE 4u
+
+#endif
+"#;
+
+// Declarations of a MaterialTable. Define CEN_MATERIAL as the material struct to declare the material array,
+// CEN_MATERIAL_SET selects the descriptor set the table is bound to.
+// Include before other declarations, it enables GL_EXT_nonuniform_qualifier.
+const MATERIALS_GLSL: &str = r#"
+#ifndef CEN_MATERIALS_GLSL
+#define CEN_MATERIALS_GLSL
+
+#extension GL_EXT_nonuniform_qualifier : require
+
+#ifndef CEN_MATERIAL_SET
+#define CEN_MATERIAL_SET 0
+#endif
+
+#ifdef CEN_MATERIAL
+layout( std430, set = CEN_MATERIAL_SET, binding = 0 ) readonly buffer CenMaterials {
+    CEN_MATERIAL cen_materials[];
+};
+#endif
+
+layout( set = CEN_MATERIAL_SET, binding = 1 ) uniform sampler2D cen_textures[];
+
+// Sample a texture of the table, the index may differ between invocations
+vec4 cen_texture( uint index, vec2 uv )
+{
+    return texture( cen_textures[ nonuniformEXT( index ) ], uv );
+}
+
+#endif
+"#;
+
+const RANDOM_GLSL: &str = r#"
+#ifndef CEN_RANDOM_GLSL
+#define CEN_RANDOM_GLSL
+
+#ifdef CEN_RANDOM_BINDING
+#ifndef CEN_RANDOM_SET
+#define CEN_RANDOM_SET 0
+#endif
+layout( std430, set = CEN_RANDOM_SET, binding = CEN_RANDOM_BINDING ) readonly buffer CenRandom {
+    uint cen_random[];
+};
+#endif
+
+// https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
+uint pcg_hash( uint v )
+{
+    uint state = v * 747796405u + 2891336453u;
+    uint word = ( ( state >> ( ( state >> 28u ) + 4u ) ) ^ state ) * 277803737u;
+    return ( word >> 22u ) ^ word;
+}
+
+// Advance the state and return a uniform float in [0, 1)
+float random_float( inout uint state )
+{
+    state = pcg_hash( state );
+    return float( state >> 8 ) / 16777216.0f;
+}
+
+// Per pixel random state, combine with the frame seed to avoid repeating patterns across frames
+uint random_seed( uvec2 pixel, uint frame_seed )
+{
+    return pcg_hash( pixel.x + pcg_hash( pixel.y + pcg_hash( frame_seed ) ) );
+}
+
+#endif
+"#;
+
+// Hashes of integer coordinates, value and gradient noise, and fractal sums of noise octaves.
+const NOISE_GLSL: &str = r#"
+#ifndef CEN_NOISE_GLSL
+#define CEN_NOISE_GLSL
+
+#include <cen/random.glsl>
+
+uint hash2( uvec2 v )
+{
+    return pcg_hash( v.x + pcg_hash( v.y ) );
+}
+
+uint hash3( uvec3 v )
+{
+    return pcg_hash( v.x + pcg_hash( v.y + pcg_hash( v.z ) ) );
+}
+
+// Map a hash to a uniform float in [0, 1)
+float hash_to_float( uint h )
+{
+    return float( h >> 8 ) / 16777216.0f;
+}
+
+// Smoothly interpolated random values at the integer coordinates, in [0, 1)
+float value_noise( vec2 p )
+{
+    uvec2 i = uvec2( ivec2( floor( p ) ) );
+    vec2 f = fract( p );
+    vec2 u = f * f * ( 3.0 - 2.0 * f );
+
+    float v00 = hash_to_float( hash2( i ) );
+    float v10 = hash_to_float( hash2( i + uvec2( 1, 0 ) ) );
+    float v01 = hash_to_float( hash2( i + uvec2( 0, 1 ) ) );
+    float v11 = hash_to_float( hash2( i + uvec2( 1, 1 ) ) );
+    return mix( mix( v00, v10, u.x ), mix( v01, v11, u.x ), u.y );
+}
+
+float value_noise( vec3 p )
+{
+    uvec3 i = uvec3( ivec3( floor( p ) ) );
+    vec3 f = fract( p );
+    vec3 u = f * f * ( 3.0 - 2.0 * f );
+
+    float v000 = hash_to_float( hash3( i ) );
+    float v100 = hash_to_float( hash3( i + uvec3( 1, 0, 0 ) ) );
+    float v010 = hash_to_float( hash3( i + uvec3( 0, 1, 0 ) ) );
+    float v110 = hash_to_float( hash3( i + uvec3( 1, 1, 0 ) ) );
+    float v001 = hash_to_float( hash3( i + uvec3( 0, 0, 1 ) ) );
+    float v101 = hash_to_float( hash3( i + uvec3( 1, 0, 1 ) ) );
+    float v011 = hash_to_float( hash3( i + uvec3( 0, 1, 1 ) ) );
+    float v111 = hash_to_float( hash3( i + uvec3( 1, 1, 1 ) ) );
+    return mix(
+        mix( mix( v000, v100, u.x ), mix( v010, v110, u.x ), u.y ),
+        mix( mix( v001, v101, u.x ), mix( v011, v111, u.x ), u.y ),
+        u.z
+    );
+}
+
+// Random unit vector at an integer coordinate
+vec2 noise_gradient( uvec2 i )
+{
+    float angle = hash_to_float( hash2( i ) ) * 6.28318530718;
+    return vec2( cos( angle ), sin( angle ) );
+}
+
+// Perlin style gradient noise, in [-1, 1]
+float gradient_noise( vec2 p )
+{
+    uvec2 i = uvec2( ivec2( floor( p ) ) );
+    vec2 f = fract( p );
+    vec2 u = f * f * f * ( f * ( f * 6.0 - 15.0 ) + 10.0 );
+
+    float v00 = dot( noise_gradient( i ), f );
+    float v10 = dot( noise_gradient( i + uvec2( 1, 0 ) ), f - vec2( 1.0, 0.0 ) );
+    float v01 = dot( noise_gradient( i + uvec2( 0, 1 ) ), f - vec2( 0.0, 1.0 ) );
+    float v11 = dot( noise_gradient( i + uvec2( 1, 1 ) ), f - vec2( 1.0, 1.0 ) );
+    return mix( mix( v00, v10, u.x ), mix( v01, v11, u.x ), u.y ) * 1.41421356;
+}
+
+// Sum of gradient noise octaves, each at twice the frequency and half the amplitude of the previous one
+float fbm( vec2 p, int octaves )
+{
+    float sum = 0.0;
+    float amplitude = 0.5;
+    for ( int octave = 0; octave < octaves; octave++ )
+    {
+        sum += amplitude * gradient_noise( p );
+        p *= 2.0;
+        amplitude *= 0.5;
+    }
+    return sum;
+}
+
+#endif
+"#;
+
+// Transfer functions and color space conversions. Colors are linear unless the name says otherwise.
+const COLOR_GLSL: &str = r#"
+#ifndef CEN_COLOR_GLSL
+#define CEN_COLOR_GLSL
+
+float srgb_to_linear( float c )
+{
+    return c <= 0.04045 ? c / 12.92 : pow( ( c + 0.055 ) / 1.055, 2.4 );
+}
+
+vec3 srgb_to_linear( vec3 c )
+{
+    return vec3( srgb_to_linear( c.r ), srgb_to_linear( c.g ), srgb_to_linear( c.b ) );
+}
+
+float linear_to_srgb( float c )
+{
+    return c <= 0.0031308 ? c * 12.92 : 1.055 * pow( c, 1.0 / 2.4 ) - 0.055;
+}
+
+vec3 linear_to_srgb( vec3 c )
+{
+    return vec3( linear_to_srgb( c.r ), linear_to_srgb( c.g ), linear_to_srgb( c.b ) );
+}
+
+// Relative luminance with the Rec. 709 primaries
+float luminance( vec3 c )
+{
+    return dot( c, vec3( 0.2126, 0.7152, 0.0722 ) );
+}
+
+// Hue, saturation and value, all in [0, 1]
+// https://web.archive.org/web/20200207113336/http://lolengine.net/blog/2013/07/27/rgb-to-hsv-in-glsl
+vec3 rgb_to_hsv( vec3 c )
+{
+    vec4 k = vec4( 0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0 );
+    vec4 p = mix( vec4( c.bg, k.wz ), vec4( c.gb, k.xy ), step( c.b, c.g ) );
+    vec4 q = mix( vec4( p.xyw, c.r ), vec4( c.r, p.yzx ), step( p.x, c.r ) );
+    float d = q.x - min( q.w, q.y );
+    float e = 1.0e-10;
+    return vec3( abs( q.z + ( q.w - q.y ) / ( 6.0 * d + e ) ), d / ( q.x + e ), q.x );
+}
+
+vec3 hsv_to_rgb( vec3 c )
+{
+    vec4 k = vec4( 1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0 );
+    vec3 p = abs( fract( c.xxx + k.xyz ) * 6.0 - k.www );
+    return c.z * mix( k.xxx, clamp( p - k.xxx, 0.0, 1.0 ), c.y );
+}
+
+// Perceptually uniform colormap of matplotlib, for t in [0, 1]
+// https://www.shadertoy.com/view/WlfXRN
+vec3 viridis( float t )
+{
+    const vec3 c0 = vec3( 0.2777273272234177, 0.005407344544966578, 0.3340998053353061 );
+    const vec3 c1 = vec3( 0.1050930431085774, 1.404613529898575, 1.384590162594685 );
+    const vec3 c2 = vec3( -0.3308618287255563, 0.214847559468213, 0.09509516302823659 );
+    const vec3 c3 = vec3( -4.634230498983486, -5.799100973351585, -19.33244095627987 );
+    const vec3 c4 = vec3( 6.228269936347081, 14.17993336680509, 56.69055260068105 );
+    const vec3 c5 = vec3( 4.776384997670288, -13.74514537774601, -65.35303263337234 );
+    const vec3 c6 = vec3( -5.435455855934631, 4.645852612178535, 26.3124352495832 );
+    t = clamp( t, 0.0, 1.0 );
+    return c0 + t * ( c1 + t * ( c2 + t * ( c3 + t * ( c4 + t * ( c5 + t * c6 ) ) ) ) );
+}
+
+// Rainbow colormap with a high contrast, for t in [0, 1]
+// https://gist.github.com/mikhailov-work/0d177465a8151eb6ede1768d51d476c7
+vec3 turbo( float t )
+{
+    const vec4 r4 = vec4( 0.13572138, 4.61539260, -42.66032258, 132.13108234 );
+    const vec4 g4 = vec4( 0.09140261, 2.19418839, 4.84296658, -14.18503333 );
+    const vec4 b4 = vec4( 0.10667330, 12.64194608, -60.58204836, 110.36276771 );
+    const vec2 r2 = vec2( -152.94239396, 59.28637943 );
+    const vec2 g2 = vec2( 4.27729857, 2.82956604 );
+    const vec2 b2 = vec2( -89.90310912, 27.34824973 );
+    t = clamp( t, 0.0, 1.0 );
+    vec4 v4 = vec4( 1.0, t, t * t, t * t * t );
+    vec2 v2 = v4.zw * v4.z;
+    return vec3( dot( v4, r4 ) + dot( v2, r2 ), dot( v4, g4 ) + dot( v2, g2 ), dot( v4, b4 ) + dot( v2, b2 ) );
+}
+
+#endif
+"#;
+
+// Operators that map linear HDR colors to [0, 1], apply them before the conversion to sRGB.
+const TONEMAP_GLSL: &str = r#"
+#ifndef CEN_TONEMAP_GLSL
+#define CEN_TONEMAP_GLSL
+
+#include <cen/color.glsl>
+
+vec3 tonemap_reinhard( vec3 c )
+{
+    return c / ( 1.0 + c );
+}
+
+// Reinhard on the luminance, colors with a luminance of `white` map to 1
+vec3 tonemap_reinhard_extended( vec3 c, float white )
+{
+    float l = luminance( c );
+    float mapped = l * ( 1.0 + l / ( white * white ) ) / ( 1.0 + l );
+    return c * ( mapped / max( l, 1.0e-6 ) );
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 tonemap_aces( vec3 c )
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float d = 2.43;
+    const float e = 0.59;
+    const float f = 0.14;
+    return clamp( ( c * ( a * c + b ) ) / ( c * ( d * c + e ) + f ), 0.0, 1.0 );
+}
+
+vec3 tonemap_hable_curve( vec3 x )
+{
+    const float a = 0.15;
+    const float b = 0.50;
+    const float c = 0.10;
+    const float d = 0.20;
+    const float e = 0.02;
+    const float f = 0.30;
+    return ( ( x * ( a * x + c * b ) + d * e ) / ( x * ( a * x + b ) + d * f ) ) - e / f;
+}
+
+// John Hable's Uncharted 2 filmic curve
+vec3 tonemap_uncharted2( vec3 c )
+{
+    const float exposure_bias = 2.0;
+    const float white = 11.2;
+    return tonemap_hable_curve( c * exposure_bias ) / tonemap_hable_curve( vec3( white ) );
+}
+
+#endif
+"#;
+
+const FULLSCREEN_VERT: &str = r#"
+#version 450
+
+layout( location = 0 ) out vec2 out_uv;
+
+void main()
+{
+    // (0, 0), (2, 0), (0, 2), the triangle covers the whole viewport
+    out_uv = vec2( ( gl_VertexIndex << 1 ) & 2, gl_VertexIndex & 2 );
+    gl_Position = vec4( out_uv * 2.0 - 1.0, 0.0, 1.0 );
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_with_builtin_includes() {
+        assert_eq!(shader_kind(Path::new("shaders/blur.comp")), Some(ShaderKind::Compute));
+        assert_eq!(shader_kind(Path::new("shader.glsl")), None);
+
+        let source = "#version 450\n#include <cen/random.glsl>\nlayout(local_size_x = 1) in;\nvoid main() {}\n";
+        let (code, includes) = compile_glsl(source, "random.comp", ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile with a built-in include");
+        assert_eq!(code[0], 0x07230203);
+        assert!(includes.is_empty());
+
+        let missing = source.replace("cen/random.glsl", "cen/missing.glsl");
+        assert!(compile_glsl(&missing, "missing.comp", ShaderKind::Compute, &HashMap::new()).unwrap_err().contains("Unknown built-in include"));
+    }
+}
@@ -1,13 +1,6 @@
-use ash::vk;
+use cen::prelude::*;
 use ash::vk::{BufferImageCopy, BufferUsageFlags, DeviceSize, Extent3D, ImageLayout, ImageSubresourceLayers};
 use egui::Context;
-use gpu_allocator::MemoryLocation;
-use winit::event::WindowEvent;
-use cen::app::app::{AppComponent, AppConfig, Cen};
-use cen::app::engine::CenContext;
-use cen::app::gui::{GuiComponent, GuiContext};
-use cen::graphics::renderer::RenderComponent;
-use cen::vulkan::{Buffer, ImageTrait};
 
 struct BufferExample {
     buffer: Buffer,
@@ -41,12 +34,11 @@ impl RenderComponent for BufferExample {
         let swapchain_image = ctx.swapchain_image.unwrap();
 
         if self.buffer.size() != (swapchain_image.width() * swapchain_image.height() * 4) as u64 {
-            self.buffer = Buffer::new(
-                &ctx.gfx.device,
-                &mut ctx.gfx.allocator,
-                MemoryLocation::CpuToGpu,
+            // The old buffer stays alive until the frames using it executed
+            self.buffer = self.buffer.resized(
+                ctx,
                 (swapchain_image.width() * swapchain_image.height() * 4) as DeviceSize,
-                BufferUsageFlags::TRANSFER_SRC
+                false
             );
 
             {
@@ -1,14 +1,6 @@
-use cen::graphics::pipeline_store::PipelineKey;
-use ash::vk;
-use ash::vk::{Extent3D, WriteDescriptorSet};
+use cen::prelude::*;
+use ash::vk::WriteDescriptorSet;
 use egui::Context;
-use winit::event::WindowEvent;
-use cen::app::app::{AppComponent, AppConfig, Cen};
-use cen::app::engine::CenContext;
-use cen::app::gui::{GuiComponent, GuiContext};
-use cen::app::{ImageFlags, ImageResource};
-use cen::graphics::renderer::RenderComponent;
-use cen::vulkan::{DescriptorSetLayout, ImageTrait, ImageConfig, ComputePipelineConfig};
 
 struct ComputeExample {
     image: ImageResource,
@@ -19,15 +11,7 @@ struct ComputeExample {
 impl AppComponent for ComputeExample {
     fn new(ctx: &mut CenContext) -> Self {
         let image = ctx.create_image(
-            ImageConfig {
-                extent: Extent3D {
-                    width: 1,
-                    height: 1,
-                    depth: 1
-                },
-                image_usage_flags: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
-                ..Default::default()
-            },
+            ImageConfig::new(1, 1, Format::Rgba8Unorm, ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST),
             ImageFlags::MATCH_SWAPCHAIN_EXTENT
         );
 
@@ -64,73 +48,80 @@ impl AppComponent for ComputeExample {
 impl RenderComponent for ComputeExample {
     fn render(&mut self, ctx: &mut CenContext) {
         let image = ctx.images.get(&self.image);
-
-        ctx.command_buffer.transition(image, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL);
-
         let compute = ctx.pipelines.get(self.pipeline).unwrap();
-        ctx.command_buffer.bind_pipeline(compute);
-
-        let bindings = [image.binding(vk::ImageLayout::GENERAL)];
-
-        let write_descriptor_set = WriteDescriptorSet::default()
-            .dst_binding(0)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-            .image_info(&bindings);
-
-        ctx.command_buffer.bind_push_descriptor(
-            compute,
-            0,
-            &[write_descriptor_set]
-        );
-        ctx.command_buffer.dispatch(500, 500, 1);
-
-        ctx.command_buffer.transition(image, vk::ImageLayout::GENERAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
-
         let swapchain_image = ctx.swapchain_image.unwrap();
 
-        ctx.command_buffer.transition(swapchain_image, vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
-
-        ctx.command_buffer.clear_color_image(
-            swapchain_image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            [0.0, 0.0, 0.0, 1.0]
-        );
-
-        ctx.command_buffer.blit_image(
-            image,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-            swapchain_image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            &[vk::ImageBlit::default()
-                .src_offsets([
-                    vk::Offset3D::default(),
-                    vk::Offset3D::default().x(image.width() as i32).y(image.height() as i32).z(1)
-                ])
-                .dst_offsets([
-                    vk::Offset3D::default(),
-                    vk::Offset3D::default().x(image.width() as i32).y(image.height() as i32).z(1)
-                ])
-                .src_subresource(
-                    vk::ImageSubresourceLayers::default()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .base_array_layer(0)
-                        .layer_count(1)
-                        .mip_level(0)
-                )
-                .dst_subresource(
-                    vk::ImageSubresourceLayers::default()
-                        .aspect_mask(vk::ImageAspectFlags::COLOR)
-                        .base_array_layer(0)
-                        .layer_count(1)
-                        .mip_level(0)
-                )
-            ],
-            vk::Filter::NEAREST,
-        );
-
-        ctx.command_buffer.transition(swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR);
-        ctx.command_buffer.transition(image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::GENERAL);
+        // The graph records the barriers between the passes
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image(image, Layout::Undefined);
+        let target = graph.import_image(swapchain_image, Layout::Present);
+        graph.final_layout(output, Layout::General);
+
+        graph.pass("compute")
+            .image(output, ImageAccess::StorageWrite)
+            .record(|command_buffer| {
+                command_buffer.bind_pipeline(compute);
+
+                let bindings = [image.binding(vk::ImageLayout::GENERAL)];
+
+                let write_descriptor_set = WriteDescriptorSet::default()
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&bindings);
+
+                command_buffer.bind_push_descriptor(
+                    compute,
+                    0,
+                    &[write_descriptor_set]
+                );
+                command_buffer.dispatch(500, 500, 1);
+            });
+
+        graph.pass("blit")
+            .image(output, ImageAccess::TransferSrc)
+            .image(target, ImageAccess::TransferDst)
+            .record(|command_buffer| {
+                command_buffer.clear_color_image(
+                    swapchain_image,
+                    Layout::TransferDst,
+                    [0.0, 0.0, 0.0, 1.0]
+                );
+
+                command_buffer.blit_image(
+                    image,
+                    Layout::TransferSrc,
+                    swapchain_image,
+                    Layout::TransferDst,
+                    &[vk::ImageBlit::default()
+                        .src_offsets([
+                            vk::Offset3D::default(),
+                            vk::Offset3D::default().x(image.width() as i32).y(image.height() as i32).z(1)
+                        ])
+                        .dst_offsets([
+                            vk::Offset3D::default(),
+                            vk::Offset3D::default().x(image.width() as i32).y(image.height() as i32).z(1)
+                        ])
+                        .src_subresource(
+                            vk::ImageSubresourceLayers::default()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .base_array_layer(0)
+                                .layer_count(1)
+                                .mip_level(0)
+                        )
+                        .dst_subresource(
+                            vk::ImageSubresourceLayers::default()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .base_array_layer(0)
+                                .layer_count(1)
+                                .mip_level(0)
+                        )
+                    ],
+                    Filter::Nearest,
+                );
+            });
+
+        graph.execute(ctx.command_buffer);
     }
 }
 
@@ -1,14 +1,6 @@
-use cen::graphics::pipeline_store::{PipelineKey};
-use ash::vk;
+use cen::prelude::*;
 use ash::vk::{Extent3D, WriteDescriptorSet};
 use egui::{Context, Slider};
-use winit::event::WindowEvent;
-use cen::app::app::{AppComponent, AppConfig, Cen};
-use cen::app::gui::{GuiComponent, GuiContext};
-use cen::app::engine::{CenContext};
-use cen::app::{ImageFlags, ImageResource};
-use cen::graphics::renderer::{RenderComponent};
-use cen::vulkan::{DescriptorSetLayout, ImageTrait, ImageConfig, ComputePipelineConfig};
 
 #[allow(dead_code)]
 struct EguiExample {
@@ -153,7 +145,7 @@ impl RenderComponent for EguiExample {
             [0.0, 0.0, 0.0, 1.0]
         );
 
-        // Use a blit, as a copy doesn't synchronize properly to the swapchain on MoltenVK
+        // Use a blit, as the image and the swapchain differ in format and size
         ctx.command_buffer.blit_image(
             image,
             vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
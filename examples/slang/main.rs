@@ -1,14 +1,6 @@
-use cen::graphics::pipeline_store::PipelineKey;
-use ash::vk;
+use cen::prelude::*;
 use ash::vk::{Extent3D, WriteDescriptorSet};
 use egui::Context;
-use winit::event::WindowEvent;
-use cen::app::app::{AppComponent, AppConfig, Cen};
-use cen::app::engine::CenContext;
-use cen::app::gui::{GuiComponent, GuiContext};
-use cen::app::{ImageFlags, ImageResource};
-use cen::graphics::renderer::RenderComponent;
-use cen::vulkan::{DescriptorSetLayout, ImageTrait, ImageConfig, ComputePipelineConfig};
 
 struct SlangExample {
     image: ImageResource,
@@ -1,14 +1,32 @@
 use std::marker::PhantomData;
 use winit::application::ApplicationHandler;
+use std::ffi::CStr;
+use std::time::Duration;
 use std::path::{PathBuf};
+use std::process::ExitCode;
 use env_logger::{Builder, Env};
-use log::{LevelFilter};
+use log::{warn, LevelFilter};
 use winit::event::{DeviceEvent, DeviceId, StartCause, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy};
-use winit::window::WindowId;
+use winit::keyboard::NamedKey;
+use winit::window::{Icon, WindowId};
+use egui::ThemePreference;
 use crate::app::engine::{CenContext, Engine};
 use crate::app::gui::{GuiComponent};
+use crate::app::update::UpdateComponent;
+use crate::app::input::InputComponent;
+use crate::app::gamepad::GamepadEvent;
+use crate::app::splash::Splash;
+use crate::app::logging::LogSubsystem;
+use crate::app::power::ActiveHours;
+use crate::app::window::WindowOptions;
 use crate::graphics::renderer::{RenderComponent};
+use crate::graphics::frame::DEFAULT_FRAMES_IN_FLIGHT;
+use crate::graphics::export::{ExportConfig, FrameSink};
+use crate::graphics::pipeline_store::ShaderPaths;
+use crate::graphics::surface_size::SurfaceSizePolicy;
+use crate::vulkan::{DeviceConfig, DevicePlugin, FeatureProfile, InstanceConfig};
+use gpu_allocator::AllocatorDebugSettings;
 
 /**
  * Entrypoint of a cen application.
@@ -18,41 +36,135 @@ pub struct Cen<C: AppComponent>
 {
     pub proxy: EventLoopProxy<UserEvent>,
     pub app_config: AppConfig,
+    // Handed to the engine once it starts
+    export: Option<ExportConfig>,
     engine: Option<Engine>,
+    // A failure when the soak test found problems, returned by `run`
+    exit_code: ExitCode,
     _marker: PhantomData<C>
 }
 
 pub struct AppConfig {
-    pub(crate) width: u32,
-    pub(crate) height: u32,
+    pub(crate) window_options: WindowOptions,
     pub(crate) vsync: bool,
     pub(crate) log_fps: bool,
-    pub(crate) fullscreen: bool,
-    pub(crate) resizable: bool,
+    pub(crate) log_levels: Vec<(LogSubsystem, LevelFilter)>,
+    pub(crate) logger: bool,
+    pub(crate) fullscreen_hotkey: Option<NamedKey>,
+    pub(crate) exit_on_escape: bool,
+    pub(crate) quantize_animation_time: bool,
+    pub(crate) event_log: Option<PathBuf>,
+    pub(crate) panic_hook: bool,
+    pub(crate) device_plugins: Vec<Box<dyn DevicePlugin>>,
+    pub(crate) device_config: DeviceConfig,
+    pub(crate) instance_config: InstanceConfig,
+    pub(crate) allocator_debug_settings: AllocatorDebugSettings,
+    pub(crate) acquire_timeout: Duration,
+    pub(crate) frames_in_flight: usize,
+    pub(crate) present_from_compute: bool,
+    pub(crate) random_seed: u64,
+    pub(crate) random_buffer_size: usize,
+    pub(crate) fixed_timestep: Option<Duration>,
+    pub(crate) splash: Splash,
+    pub(crate) gpu_profiler: bool,
+    pub(crate) gpu_profiler_overlay: bool,
+    pub(crate) memory_overlay: bool,
+    pub(crate) fps_overlay: bool,
+    pub(crate) fps_overlay_hotkey: Option<NamedKey>,
+    pub(crate) image_viewer: bool,
+    pub(crate) component_inspector: bool,
+    pub(crate) soak_test: Option<u64>,
+    pub(crate) redraw_mode: RedrawMode,
+    pub(crate) active_hours: Option<ActiveHours>,
+    pub(crate) depth_format: Option<ash::vk::Format>,
+    pub(crate) surface_size: SurfaceSizePolicy,
+    pub(crate) clear_color: Option<[f32; 4]>,
+    pub(crate) default_viewport: bool,
+    pub(crate) shader_paths: ShaderPaths,
+    pub(crate) windows: Vec<WindowConfig>,
+    pub(crate) gui_style: Option<GuiStyle>,
+    /// Moved out by [`Cen::run`], the engine starts exporting with it.
+    pub(crate) export: Option<ExportConfig>,
+}
+
+/// Customizes the egui style of a theme, see [`AppConfig::gui_style`].
+pub(crate) type GuiStyle = Box<dyn Fn(&mut egui::Style, egui::Theme)>;
+
+/// When the engine draws frames, see [`AppConfig::redraw_mode`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RedrawMode {
+    /// Draw frames back to back, for apps that animate.
+    #[default]
+    Continuous,
+    /// Only draw after window events and input, when the gui repaints, or when a component calls
+    /// [`WindowController::request_redraw`](crate::app::WindowController::request_redraw). An idle app doesn't
+    /// use the CPU or GPU, e.g. an editor or another tool. Updates run before each frame instead of continuously.
+    OnEvent,
+}
+
+/// Creates the component of an additional window, see [`AppConfig::window`].
+pub(crate) type WindowComponentFactory = Box<dyn Fn(&mut CenContext) -> Box<dyn WindowComponent>>;
+
+pub(crate) struct WindowConfig {
     pub(crate) title: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) create: WindowComponentFactory,
 }
 
 impl AppConfig {
 
     pub fn default() -> Self {
         Self {
-            width: 1000,
-            height: 1000,
+            window_options: WindowOptions::default(),
             vsync: true,
             log_fps: false,
-            fullscreen: false,
-            resizable: false,
-            title: "cen".to_string()
+            log_levels: vec![],
+            logger: true,
+            fullscreen_hotkey: None,
+            exit_on_escape: true,
+            quantize_animation_time: false,
+            event_log: None,
+            panic_hook: false,
+            device_plugins: vec![],
+            device_config: DeviceConfig::default(),
+            instance_config: InstanceConfig::default(),
+            allocator_debug_settings: AllocatorDebugSettings::default(),
+            acquire_timeout: Duration::from_secs(1),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            present_from_compute: false,
+            random_seed: 0,
+            random_buffer_size: 0,
+            fixed_timestep: None,
+            splash: Splash::default(),
+            gpu_profiler: false,
+            gpu_profiler_overlay: false,
+            memory_overlay: false,
+            fps_overlay: false,
+            fps_overlay_hotkey: None,
+            image_viewer: false,
+            component_inspector: false,
+            soak_test: None,
+            redraw_mode: RedrawMode::Continuous,
+            active_hours: None,
+            depth_format: None,
+            surface_size: SurfaceSizePolicy::Surface,
+            clear_color: Some([0.0, 0.0, 0.0, 1.0]),
+            default_viewport: true,
+            shader_paths: ShaderPaths::default(),
+            windows: vec![],
+            gui_style: None,
+            export: None,
         }
     }
 
     pub fn width(mut self, width: u32) -> Self {
-        self.width = width;
+        self.window_options.width = width;
         self
     }
 
     pub fn height(mut self, height: u32) -> Self {
-        self.height = height;
+        self.window_options.height = height;
         self
     }
 
@@ -66,18 +178,377 @@ impl AppConfig {
         self
     }
 
+    /// Log level of a subsystem of cen, e.g. `LevelFilter::Warn` for [`LogSubsystem::Objects`] to silence the
+    /// traces of every created and destroyed object. Levels set in the `LOG_LEVEL` environment variable win.
+    pub fn log_level(mut self, subsystem: LogSubsystem, level: LevelFilter) -> Self {
+        self.log_levels.push((subsystem, level));
+        self
+    }
+
+    /// Install the env_logger of cen on [`Cen::run`]. Enabled by default, disable it when the application sets up its
+    /// own logger, e.g. `tracing` with its `log` bridge. Filter the output of cen with [`LogSubsystem::target`].
+    pub fn logger(mut self, enabled: bool) -> Self {
+        self.logger = enabled;
+        self
+    }
+
     pub fn fullscreen(mut self, fullscreen: bool) -> Self {
-        self.fullscreen = fullscreen;
+        self.window_options.fullscreen = fullscreen;
+        self
+    }
+
+    /// Toggle fullscreen at runtime with the given key, e.g. `NamedKey::F11`.
+    pub fn fullscreen_hotkey(mut self, key: NamedKey) -> Self {
+        self.fullscreen_hotkey = Some(key);
+        self
+    }
+
+    /// Close the app when Escape is pressed. Enabled by default.
+    pub fn exit_on_escape(mut self, exit_on_escape: bool) -> Self {
+        self.exit_on_escape = exit_on_escape;
         self
     }
 
     pub fn resizable(mut self, resizable: bool) -> Self {
-        self.resizable = resizable;
+        self.window_options.resizable = resizable;
         self
     }
 
     pub fn title(mut self, title: &str) -> Self {
-        self.title = title.to_string();
+        self.window_options.title = title.to_string();
+        self
+    }
+
+    /// Show the title bar and borders. Enabled by default.
+    pub fn decorations(mut self, decorations: bool) -> Self {
+        self.window_options.decorations = decorations;
+        self
+    }
+
+    /// Keep the window above other windows.
+    pub fn always_on_top(mut self, always_on_top: bool) -> Self {
+        self.window_options.always_on_top = always_on_top;
+        self
+    }
+
+    /// Smallest size the window can be resized to, in logical pixels.
+    pub fn min_size(mut self, width: u32, height: u32) -> Self {
+        self.window_options.min_size = Some((width, height));
+        self
+    }
+
+    /// Largest size the window can be resized to, in logical pixels.
+    pub fn max_size(mut self, width: u32, height: u32) -> Self {
+        self.window_options.max_size = Some((width, height));
+        self
+    }
+
+    /// Initial position of the top left corner of the window, in logical pixels.
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.window_options.position = Some((x, y));
+        self
+    }
+
+    /// Let the desktop show through where the swapchain image has an alpha below one, see
+    /// [`WindowOptions::transparent`]. Pair it with a transparent [`clear_color`](Self::clear_color) or
+    /// [`FrameFlags::SKIP_CLEAR`](crate::graphics::FrameFlags::SKIP_CLEAR), the pre-clear is opaque black by default.
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.window_options.transparent = transparent;
+        self
+    }
+
+    /// Window icon from `width`x`height` RGBA8 pixels. An invalid icon is skipped with a warning.
+    pub fn window_icon(mut self, rgba: Vec<u8>, width: u32, height: u32) -> Self {
+        match Icon::from_rgba(rgba, width, height) {
+            Ok(icon) => self.window_options.icon = Some(icon),
+            Err(e) => warn!("Invalid window icon: {}", e),
+        }
+        self
+    }
+
+    /// Initial theme of the title bar and the gui, switched at runtime with
+    /// [`WindowController::set_theme`](crate::app::WindowController::set_theme). Follows the OS by default.
+    pub fn theme(mut self, theme: ThemePreference) -> Self {
+        self.window_options.theme = theme;
+        self
+    }
+
+    /// Customize the egui style of the dark and the light theme. The customized style is used whenever the
+    /// theme changes, e.g. when the OS switches to dark mode.
+    pub fn gui_style(mut self, style: impl Fn(&mut egui::Style, egui::Theme) + 'static) -> Self {
+        self.gui_style = Some(Box::new(style));
+        self
+    }
+
+    /// Snap the animation time to the refresh interval of the current monitor.
+    pub fn quantize_animation_time(mut self, quantize: bool) -> Self {
+        self.quantize_animation_time = quantize;
+        self
+    }
+
+    /// Write notable per-frame events as JSON lines to the given file.
+    /// Requires the `event-log` feature.
+    pub fn event_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.event_log = Some(path.into());
+        self
+    }
+
+    /// On panic, log the frame, in-flight submissions and allocator stats, then abort.
+    pub fn panic_hook(mut self, panic_hook: bool) -> Self {
+        self.panic_hook = panic_hook;
+        self
+    }
+
+    /// Request additional device extensions and features, see [`DevicePlugin`].
+    pub fn device_plugin(mut self, plugin: impl DevicePlugin + 'static) -> Self {
+        self.device_plugins.push(Box::new(plugin));
+        self
+    }
+
+    /// Require or request device extensions and features, see [`DeviceConfig`].
+    /// The app panics, listing what is missing, when the device doesn't support the required ones.
+    pub fn device_config(mut self, device_config: DeviceConfig) -> Self {
+        self.device_config = device_config;
+        self
+    }
+
+    /// Require the extensions and features of a workload, see [`FeatureProfile`]. Replaces the
+    /// [`device_config`](Self::device_config), extend [`DeviceConfig::profile`] to combine both.
+    pub fn feature_profile(mut self, profile: FeatureProfile) -> Self {
+        self.device_config = DeviceConfig::profile(profile);
+        self
+    }
+
+    /// Enable an additional instance extension. Skipped with a warning when unavailable.
+    pub fn instance_extension(mut self, name: &CStr) -> Self {
+        self.instance_config.extensions.push(name.to_owned());
+        self
+    }
+
+    /// Enable an additional instance layer, e.g. a capture layer. Skipped with a warning when unavailable.
+    pub fn instance_layer(mut self, name: &CStr) -> Self {
+        self.instance_config.layers.push(name.to_owned());
+        self
+    }
+
+    /// Maximum time to wait for a swapchain image. The frame is skipped when it is exceeded.
+    pub fn acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = timeout;
+        self
+    }
+
+    /// Number of frames the CPU records ahead of the GPU, independent of the number of swapchain images.
+    /// More frames can improve throughput at the cost of latency.
+    pub fn frames_in_flight(mut self, frames: usize) -> Self {
+        assert!(frames > 0, "At least one frame needs to be in flight");
+        self.frames_in_flight = frames;
+        self
+    }
+
+    /// For compute-only apps: run and present whole frames on a compute queue family that supports presenting,
+    /// so they don't contend with other work on the graphics queue. Falls back to the graphics queue when the
+    /// device has no such family, see [`Renderer::presents_from_compute`](crate::graphics::Renderer::presents_from_compute).
+    ///
+    /// Components may only record compute and transfer commands, e.g. copy into the swapchain image instead of
+    /// blitting, the gui is not drawn and the [surface size](Self::surface_size) policy is ignored.
+    pub fn present_from_compute(mut self, present_from_compute: bool) -> Self {
+        self.present_from_compute = present_from_compute;
+        self
+    }
+
+    /// Seed of the per-frame random values, the same seed gives the same sequence.
+    pub fn random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = seed;
+        self
+    }
+
+    /// Number of random `u32` values in the per-frame random buffer. Disabled when zero.
+    pub fn random_buffer_size(mut self, size: usize) -> Self {
+        self.random_buffer_size = size;
+        self
+    }
+
+    /// Run [`UpdateComponent::update`] with a fixed timestep, as often as needed to keep up with real time.
+    /// With `None`, it runs once per frame with the time elapsed since the previous update.
+    pub fn fixed_timestep(mut self, timestep: Option<Duration>) -> Self {
+        self.fixed_timestep = timestep;
+        self
+    }
+
+    /// Shown while the components initialize and until their [`LoadingProgress`](crate::app::LoadingProgress) completes.
+    /// Defaults to black.
+    pub fn splash(mut self, splash: Splash) -> Self {
+        self.splash = splash;
+        self
+    }
+
+    /// Measure the GPU time of the render components and of scopes opened with
+    /// [`CenContext::begin_gpu_scope`], see [`GpuProfiler`](crate::graphics::GpuProfiler).
+    pub fn gpu_profiler(mut self, enabled: bool) -> Self {
+        self.gpu_profiler = enabled;
+        self
+    }
+
+    /// Show the GPU timings in an overlay window, enables the GPU profiler.
+    pub fn gpu_profiler_overlay(mut self, enabled: bool) -> Self {
+        self.gpu_profiler_overlay = enabled;
+        self.gpu_profiler |= enabled;
+        self
+    }
+
+    /// Show the memory usage of the allocator in a debug window, see [`MemoryOverlay`](crate::graphics::MemoryOverlay).
+    pub fn memory_overlay(mut self, enabled: bool) -> Self {
+        self.memory_overlay = enabled;
+        self
+    }
+
+    /// Show the frame rate, frame times, resolution and present mode in a small overlay, see
+    /// [`FpsOverlay`](crate::graphics::FpsOverlay).
+    pub fn fps_overlay(mut self, enabled: bool) -> Self {
+        self.fps_overlay = enabled;
+        self
+    }
+
+    /// Toggle the [`fps_overlay`](Self::fps_overlay) at runtime with the given key, e.g. `NamedKey::F3`, also when it
+    /// starts hidden.
+    pub fn fps_overlay_hotkey(mut self, key: NamedKey) -> Self {
+        self.fps_overlay_hotkey = Some(key);
+        self
+    }
+
+    /// Show the images registered with [`CenContext::register_image`](crate::app::engine::CenContext::register_image)
+    /// in a debug window, see [`ImageViewer`](crate::graphics::ImageViewer).
+    pub fn image_viewer(mut self, enabled: bool) -> Self {
+        self.image_viewer = enabled;
+        self
+    }
+
+    /// Show a debug window to disable the app component, the window components and the components registered with
+    /// [`CenContext::components`](crate::app::engine::CenContext::components) at runtime, see
+    /// [`ComponentInspector`](crate::graphics::ComponentInspector).
+    pub fn component_inspector(mut self, enabled: bool) -> Self {
+        self.component_inspector = enabled;
+        self
+    }
+
+    /// Draw frames continuously, the default, or only when something changed, see [`RedrawMode`].
+    pub fn redraw_mode(mut self, redraw_mode: RedrawMode) -> Self {
+        self.redraw_mode = redraw_mode;
+        self
+    }
+
+    /// Only run at full speed during the daily active hours, e.g. the opening hours of a gallery, and drop to
+    /// their idle [`PowerMode`] otherwise. Components can override it at runtime, see
+    /// [`WindowController::set_power_mode`](crate::app::WindowController::set_power_mode).
+    pub fn active_hours(mut self, active_hours: ActiveHours) -> Self {
+        self.active_hours = Some(active_hours);
+        self
+    }
+
+    /// Have the renderer manage a depth buffer of `format` that matches the swapchain extent, see
+    /// [`CenContext::depth_image`]. Pipelines drawing to it set the same `depth_format`.
+    pub fn depth_buffer(mut self, format: impl Into<ash::vk::Format>) -> Self {
+        self.depth_format = Some(format.into());
+        self
+    }
+
+    /// Extent the components render at when it differs from the surface extent, e.g. the logical window size under
+    /// fractional scaling or a fixed letterboxed extent. Renders at the surface extent by default, components read
+    /// both sizes from [`CenContext::surface_sizes`].
+    ///
+    /// Frames [presented from a compute queue](Self::present_from_compute) always render at the surface extent.
+    pub fn surface_size(mut self, policy: SurfaceSizePolicy) -> Self {
+        self.surface_size = policy;
+        self
+    }
+
+    /// Color the swapchain image is cleared to at the start of every frame, opaque black by default. `None` skips the
+    /// clear for all frames, like [`FrameFlags::SKIP_CLEAR`](crate::graphics::FrameFlags::SKIP_CLEAR) does for
+    /// single frames, when the components overwrite the whole image anyway.
+    pub fn clear_color(mut self, color: Option<[f32; 4]>) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    /// Set the viewport and scissor to the render area whenever the components begin rendering, e.g. the render
+    /// extent for attachments matching the swapchain, so graphics pipelines draw without setting them. Enabled by
+    /// default, components that set a custom viewport after beginning rendering override it either way, see
+    /// [`CommandBuffer::set_default_viewport`](crate::vulkan::CommandBuffer::set_default_viewport).
+    pub fn default_viewport(mut self, enabled: bool) -> Self {
+        self.default_viewport = enabled;
+        self
+    }
+
+    /// Look up relative shader paths in `root` when they don't exist from the working directory, see
+    /// [`ShaderPaths::root`].
+    pub fn shader_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.shader_paths = self.shader_paths.root(root);
+        self
+    }
+
+    /// Load and watch shaders under `from` from the `to` directory instead, e.g. the source tree during development
+    /// of an installed app. See [`ShaderPaths::remap`].
+    pub fn remap_shader_path(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.shader_paths = self.shader_paths.remap(from, to);
+        self
+    }
+
+    /// Open an additional window, e.g. a debug view, drawn by the component that `create` returns.
+    /// Every window has its own swapchain and renderer on the device of the main window, so GPU resources
+    /// can be shared between windows. Closing an additional window leaves the others open.
+    pub fn window<W: WindowComponent + 'static>(mut self, title: &str, width: u32, height: u32, create: impl Fn(&mut CenContext) -> W + 'static) -> Self {
+        self.windows.push(WindowConfig {
+            title: title.to_string(),
+            width,
+            height,
+            create: Box::new(move |ctx| Box::new(create(ctx))),
+        });
+        self
+    }
+
+    /// Export the frames of the main window instead of presenting them, for time-accurate recordings.
+    ///
+    /// Frames are rendered offscreen at exact animation times `1 / frame_rate` seconds apart, regardless of how long
+    /// they take, and handed to `sink`, e.g. a [`MovieBuffer`](crate::graphics::MovieBuffer). Fixed timestep
+    /// updates follow the same timeline. The app exits after `frame_count` frames.
+    pub fn export(mut self, frame_rate: f64, frame_count: Option<u64>, sink: impl FrameSink + 'static) -> Self {
+        self.export = Some(ExportConfig {
+            frame_rate,
+            frame_count,
+            sink: Box::new(sink),
+        });
+        self
+    }
+
+    /// Debug settings of the GPU memory allocator, e.g. to log allocations that leaked when the allocator is
+    /// destroyed, with the stack traces they were made at. Allocations are named after their images and buffers,
+    /// see [`Image::new_named`](crate::vulkan::Image::new_named).
+    pub fn allocator_debug_settings(mut self, settings: AllocatorDebugSettings) -> Self {
+        self.allocator_debug_settings = settings;
+        self
+    }
+
+    /// Enable the Khronos validation layer. Defaults to enabled in debug builds.
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.instance_config.validation = validation;
+        self
+    }
+
+    /// Run `frames` frames with the validation layer, including its synchronization validation, and exit.
+    /// [`Cen::run`] returns a failure if it reported errors or the app stopped early, for integration tests catching
+    /// synchronization regressions. Draws continuously, whatever the redraw mode.
+    ///
+    /// ```ignore
+    /// fn main() -> ExitCode {
+    ///     let mut config = AppConfig::default();
+    ///     if let Some(frames) = std::env::var("SOAK_FRAMES").ok().and_then(|frames| frames.parse().ok()) {
+    ///         config = config.soak_test(frames);
+    ///     }
+    ///     Cen::<App>::run(config)
+    /// }
+    /// ```
+    pub fn soak_test(mut self, frames: u64) -> Self {
+        self.soak_test = Some(frames);
         self
     }
 }
@@ -85,6 +556,45 @@ impl AppConfig {
 pub trait AppComponent : RenderComponent + GuiComponent {
     fn new(ctx: &mut CenContext) -> Self where Self: Sized;
     fn window_event(&mut self, event: WindowEvent);
+
+    /// Called after the swapchain changed extent, e.g. on a resize or fullscreen toggle, with the extent the
+    /// components render at, see [`SurfaceSizes::render`](crate::graphics::SurfaceSizes::render).
+    /// Images created with [`ImageFlags::MATCH_SWAPCHAIN_EXTENT`](crate::app::ImageFlags::MATCH_SWAPCHAIN_EXTENT)
+    /// have already been resized.
+    fn resized(&mut self, _ctx: &mut CenContext, _extent: ash::vk::Extent2D) {}
+
+    /// Called after the swapchain changed format or color space, e.g. when the window moved to a monitor with another
+    /// color space. Pipelines that render to the swapchain image have to be recreated for the new format.
+    fn surface_changed(&mut self, _ctx: &mut CenContext, _format: ash::vk::SurfaceFormatKHR) {}
+
+    /// Simulation logic of the app, opted into by returning `Some(self)` from an app that implements
+    /// [`UpdateComponent`]. Not updated by default.
+    fn update_component(&mut self) -> Option<&mut dyn UpdateComponent> {
+        None
+    }
+
+    /// Input callbacks of the app, opted into by returning `Some(self)` from an app that implements
+    /// [`InputComponent`]. The [`InputState`](crate::app::InputState) of the context is kept either way.
+    fn input_component(&mut self) -> Option<&mut dyn InputComponent> {
+        None
+    }
+}
+
+/// Component of an additional window, see [`AppConfig::window`].
+/// Receives the input and events of its own window only.
+pub trait WindowComponent : RenderComponent + GuiComponent {
+    fn window_event(&mut self, _event: WindowEvent) {}
+
+    /// Called after the swapchain of the window changed extent, see [`AppComponent::resized`].
+    fn resized(&mut self, _ctx: &mut CenContext, _extent: ash::vk::Extent2D) {}
+
+    /// Called after the swapchain of the window changed format, see [`AppComponent::surface_changed`].
+    fn surface_changed(&mut self, _ctx: &mut CenContext, _format: ash::vk::SurfaceFormatKHR) {}
+
+    /// Input callbacks of the window, see [`AppComponent::input_component`].
+    fn input_component(&mut self) -> Option<&mut dyn InputComponent> {
+        None
+    }
 }
 
 #[derive(Debug, Default)]
@@ -92,6 +602,7 @@ pub enum UserEvent {
     #[default]
     None,
     GlslUpdate(PathBuf),
+    Gamepad(GamepadEvent),
 }
 
 impl<C: AppComponent + 'static> ApplicationHandler<UserEvent> for Cen<C>
@@ -109,7 +620,8 @@ impl<C: AppComponent + 'static> ApplicationHandler<UserEvent> for Cen<C>
             self.engine = Some(Engine::new::<C>(
                 self.proxy.clone(),
                 event_loop,
-                &self.app_config
+                &self.app_config,
+                self.export.take()
             ));
         }
 
@@ -121,9 +633,9 @@ impl<C: AppComponent + 'static> ApplicationHandler<UserEvent> for Cen<C>
         }
     }
 
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
         if let Some(engine) = self.engine.as_mut() {
-            engine.window_event(event_loop, event);
+            engine.window_event(event_loop, window_id, event);
         }
     }
 
@@ -138,7 +650,7 @@ impl<C: AppComponent + 'static> ApplicationHandler<UserEvent> for Cen<C>
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
         if let Some(engine) = self.engine.take() {
-            engine.exit();
+            self.exit_code = engine.exit();
         }
     }
 
@@ -148,12 +660,21 @@ impl<C: AppComponent + 'static> ApplicationHandler<UserEvent> for Cen<C>
 
 impl<C: AppComponent + 'static> Cen<C> {
 
-    fn init_logger() {
+    fn init_logger(app_config: &AppConfig) {
+        if !app_config.logger {
+            return;
+        }
+
         let env = Env::default()
             .filter_or("LOG_LEVEL", "trace")
             .write_style_or("LOG_STYLE", "always");
 
-        Builder::from_env(env)
+        // Levels in `LOG_LEVEL` take precedence over the configured ones
+        let mut builder = Builder::new();
+        for (subsystem, level) in &app_config.log_levels {
+            builder.filter(Some(subsystem.target()), *level);
+        }
+        let result = builder.parse_env(env)
             .format_level(true)
             .format_timestamp_millis()
             .filter(Some("winit"), LevelFilter::Error)
@@ -165,31 +686,50 @@ impl<C: AppComponent + 'static> Cen<C> {
             .filter(Some("notify_debouncer_mini"), LevelFilter::Error)
             .filter(Some("egui_ash_renderer"), LevelFilter::Error)
             .filter(Some("egui_winit"), LevelFilter::Error)
-            .init();
+            .try_init();
+        if result.is_err() {
+            warn!("A logger was already installed, keeping it. Disable the logger of cen with `AppConfig::logger`");
+        }
     }
 
-    fn new(app_config: AppConfig, event_loop: &EventLoop<UserEvent>) -> Self {
+    fn new(mut app_config: AppConfig, event_loop: &EventLoop<UserEvent>) -> Self {
 
         let proxy = event_loop.create_proxy();
+        let export = app_config.export.take();
 
         Cen::<C> {
             app_config,
             proxy,
+            export,
             engine: None,
+            exit_code: ExitCode::SUCCESS,
             _marker: Default::default(),
         }
     }
 
-    pub fn run(app_config: AppConfig) {
+    /// Run the app until it exits. Returns a failure when the soak test failed, see
+    /// [`AppConfig::soak_test`], which `main` can return as its exit code.
+    pub fn run(mut app_config: AppConfig) -> ExitCode {
 
-        Self::init_logger();
+        Self::init_logger(&app_config);
+
+        if app_config.soak_test.is_some() {
+            app_config.instance_config.validation = true;
+            app_config.instance_config.synchronization_validation = true;
+            app_config.redraw_mode = RedrawMode::Continuous;
+        }
 
         let event_loop = EventLoopBuilder::default().build().expect("Failed to create event loop.");
-        event_loop.set_control_flow(ControlFlow::Poll);
+        event_loop.set_control_flow(match app_config.redraw_mode {
+            RedrawMode::Continuous => ControlFlow::Poll,
+            RedrawMode::OnEvent => ControlFlow::Wait,
+        });
 
         // App setup
         let mut app: Cen<C> = Cen::<C>::new(app_config, &event_loop);
         event_loop.run_app(&mut app).unwrap();
+
+        app.exit_code
     }
 
 }
@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Switch of a registered component, see [`ComponentRegistry::register`]. Components check it to skip their work,
+/// the engine does so for the app component and the window components.
+#[derive(Clone, Debug)]
+pub struct ComponentToggle {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Default for ComponentToggle {
+    fn default() -> Self {
+        Self { enabled: Arc::new(AtomicBool::new(true)) }
+    }
+}
+
+impl ComponentToggle {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Components that can be disabled at runtime, e.g. to compare passes or to find a slow one without recompiling.
+/// They are listed with checkboxes in the [`ComponentInspector`](crate::graphics::ComponentInspector).
+///
+/// ```ignore
+/// let bloom = ctx.components.register("Bloom");
+/// // In render
+/// if bloom.is_enabled() {
+///     self.bloom.render(ctx);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    toggles: Arc<Mutex<Vec<(String, ComponentToggle)>>>,
+}
+
+impl ComponentRegistry {
+
+    /// Register a component by name, enabled. Registering a name again returns the toggle it already has.
+    pub fn register(&self, name: impl Into<String>) -> ComponentToggle {
+        let name = name.into();
+        let mut toggles = self.toggles.lock().unwrap();
+        if let Some((_, toggle)) = toggles.iter().find(|(registered, _)| *registered == name) {
+            return toggle.clone();
+        }
+        let toggle = ComponentToggle::default();
+        toggles.push((name, toggle.clone()));
+        toggle
+    }
+
+    /// Whether the component is enabled, components that weren't registered are.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.toggles.lock().unwrap().iter()
+            .find(|(registered, _)| registered == name)
+            .is_none_or(|(_, toggle)| toggle.is_enabled())
+    }
+
+    /// The registered components in registration order.
+    pub fn toggles(&self) -> Vec<(String, ComponentToggle)> {
+        self.toggles.lock().unwrap().clone()
+    }
+}
+
+/// Name of a component type without its module path, e.g. `EguiExample`.
+pub(crate) fn component_name<C: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<C>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Component;
+
+    #[test]
+    fn toggle_components() {
+        let registry = ComponentRegistry::default();
+        let bloom = registry.register("Bloom");
+        assert!(bloom.is_enabled());
+
+        registry.register("Bloom").set_enabled(false);
+        assert!(!bloom.is_enabled());
+        assert!(!registry.is_enabled("Bloom"));
+        assert!(registry.is_enabled("Unregistered"));
+        assert_eq!(registry.toggles().len(), 1);
+
+        assert_eq!(component_name::<Component>(), "Component");
+        assert_eq!(component_name::<Vec<Component>>(), "Vec");
+    }
+}
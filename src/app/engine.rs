@@ -1,23 +1,39 @@
-use std::time::SystemTime;
-use log::{debug, error, info};
-use winit::event::{StartCause, WindowEvent};
-use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
-use crate::app::app::{AppComponent, AppConfig, UserEvent};
+use std::collections::HashMap;
+use std::process::ExitCode;
+use ash::vk;
+use std::time::{Duration, Instant, SystemTime};
+use log::{debug, error, info, warn};
+use winit::event::{ElementState, KeyEvent, StartCause, WindowEvent};
+use winit::keyboard::{Key, NamedKey};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoopProxy};
+use winit::window::WindowId;
+use crate::app::app::{AppComponent, AppConfig, RedrawMode, UserEvent, WindowComponent, WindowConfig};
 use crate::app::gui::{GuiComponent, GuiSystem};
-use crate::app::{ImageFlags, ImageResource, Window};
+use crate::app::update::UpdateLoop;
+use crate::app::input::{dispatch_gamepad_event, dispatch_input_event, InputState};
+use crate::app::splash::{LoadingProgress, Splash, SplashScreen};
+use crate::app::components::{component_name, ComponentRegistry, ComponentToggle};
+use crate::app::power::{ActiveHours, PowerMode};
+use crate::app::{ImageFlags, ImageResource, Window, WindowController, WindowOptions};
 use crate::graphics::{Renderer};
-use crate::graphics::{GraphicsContext, ImageContext, PipelineContext};
+use crate::graphics::{AnimationClock, AssetLoader, AsyncCompute, ComponentInspector, FpsOverlay, GpuProfiler, GraphicsContext, ImageContext, ImageViewer, MemoryOverlay, PipelineContext, RandomProvider, SurfaceSizes, Uploader};
 use crate::graphics::renderer::RenderComponent;
+use crate::graphics::crash::{install_panic_hook, PanicHook};
+use crate::graphics::export::ExportConfig;
+use crate::graphics::frame::FrameCallback;
+use crate::graphics::globals::FrameGlobals;
 use crate::graphics::pipeline_store::IntoPipelineHandle;
 use crate::graphics::pipeline_store::PipelineKey;
-use crate::vulkan::{ImageConfig, PipelineErr, WindowState};
-use crate::vulkan::{CommandBuffer, SwapchainImage};
+use crate::vulkan::{Image, ImageConfig, ImageError, PipelineErr, WindowState};
+use crate::vulkan::{CommandBuffer, DescriptorSetLayout, DescriptorWriter, Pipeline, SwapchainImage, TransientDescriptorAllocator};
 
 /**
  * Cen engine
  * Manages and connects all separate components.
  */
 pub struct Engine {
+    // Dropped first, restoring the previous panic hook before the renderer goes away
+    panic_hook: Option<PanicHook>,
     _start_time: SystemTime,
     window: Box<Window>,
     gui_system: GuiSystem,
@@ -25,9 +41,196 @@ pub struct Engine {
     frame_count: usize,
     last_print_time: SystemTime,
     log_fps: bool,
+    update_loop: UpdateLoop,
+    redraw_mode: RedrawMode,
+    active_hours: Option<ActiveHours>,
+    power_mode: PowerMode,
+    // User input arrived while paused, the next redraw draws a frame to react to it
+    paused_redraw: bool,
+    last_frame: Instant,
+    gpu_profiler_overlay: bool,
+    memory_overlay: Option<MemoryOverlay>,
+    // Always there, so the hotkey can show it
+    fps_overlay: FpsOverlay,
+    fps_overlay_hotkey: Option<NamedKey>,
+    image_viewer: Option<ImageViewer>,
+    component_inspector: Option<ComponentInspector>,
+    // Skips the app component while disabled in the component inspector
+    app_toggle: ComponentToggle,
+    // Frames to run before exiting, see `AppConfig::soak_test`
+    soak_test: Option<u64>,
+    splash: Option<SplashScreen>,
+    windows: HashMap<WindowId, SecondaryWindow>,
     app_component: Box<dyn AppComponent>
 }
 
+/// An additional window with its own renderer on the device of the main window, see [`AppConfig::window`].
+struct SecondaryWindow {
+    window: Box<Window>,
+    gui_system: GuiSystem,
+    renderer: Renderer,
+    component: Box<dyn WindowComponent>,
+    toggle: ComponentToggle,
+    redraw_mode: RedrawMode,
+}
+
+impl SecondaryWindow {
+    fn new(proxy: EventLoopProxy<UserEvent>, event_loop: &ActiveEventLoop, app_config: &AppConfig, config: &WindowConfig, main: &Renderer) -> Self {
+        let options = WindowOptions {
+            title: config.title.clone(),
+            width: config.width,
+            height: config.height,
+            resizable: app_config.window_options.resizable,
+            decorations: app_config.window_options.decorations,
+            icon: app_config.window_options.icon.clone(),
+            theme: app_config.window_options.theme,
+            ..Default::default()
+        };
+        let mut window = Box::new(Window::create(event_loop, &options));
+        window.set_fullscreen_hotkey(app_config.fullscreen_hotkey);
+        // Escape and closing only affect the main window
+        window.set_exit_on_escape(false);
+
+        let window_state = WindowState {
+            window_handle: window.window_handle(),
+            display_handle: window.display_handle(),
+            extent2d: window.get_extent(),
+            scale_factor: window.scale_factor(),
+            transparent: window.is_transparent(),
+        };
+        // Only the surface, swapchain and frames are the window's own
+        let gpu = main.gpu.share();
+        let surface = gpu.create_surface(&window_state);
+        let mut renderer = Renderer::with_gpu(gpu, surface, &window_state, proxy, app_config);
+        renderer.clock = AnimationClock::new(window.refresh_rate(), app_config.quantize_animation_time);
+        renderer.components = main.components.clone();
+        let toggle = main.components.register(config.title.clone());
+
+        let mut gui_system = GuiSystem::new(window.as_ref(), &mut renderer);
+        renderer.window.set_theme(app_config.window_options.theme);
+        if let Some(style) = &app_config.gui_style {
+            gui_system.set_style(style.as_ref());
+        }
+        let mut component = renderer.run_single_time(|ctx| (config.create)(ctx));
+        gui_system.initialize(
+            &mut renderer.gpu.gfx,
+            &mut renderer.image_context,
+            &mut renderer.window,
+            &mut [component.as_mut() as &mut dyn GuiComponent]
+        );
+        renderer.window.apply(&window);
+
+        Self {
+            window,
+            gui_system,
+            renderer,
+            component,
+            toggle,
+            redraw_mode: app_config.redraw_mode,
+        }
+    }
+
+    /// Returns whether the component requested to close the window.
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) -> bool {
+        let redraw = matches!(event, WindowEvent::RedrawRequested);
+        self.window.window_event(event.clone(), event_loop);
+
+        let consumed = self.gui_system.on_window_event(self.window.winit_window(), &event);
+        if !consumed || is_release(&event) {
+            self.renderer.input.handle_event(&event);
+            if let Some(input_component) = self.component.input_component() {
+                dispatch_input_event(input_component, &self.renderer.input, &event);
+            }
+        }
+
+        self.component.window_event(event.clone());
+
+        match event {
+            WindowEvent::RedrawRequested => self.draw(),
+            WindowEvent::Resized( .. ) | WindowEvent::ScaleFactorChanged { .. } => self.recreate_swapchain(),
+            WindowEvent::Moved( .. ) if self.renderer.is_surface_outdated() => self.recreate_swapchain(),
+            _ => (),
+        }
+
+        self.renderer.window.apply(&self.window);
+        if self.redraw_mode == RedrawMode::OnEvent {
+            let repaint = self.gui_system.repaint_delay().is_zero();
+            if !redraw || self.renderer.window.take_redraw_request() || repaint {
+                self.window.winit_window().request_redraw();
+            }
+        }
+        self.renderer.window.take_exit_request()
+    }
+
+    fn recreate_swapchain(&mut self) {
+        let extent = self.window.get_extent();
+        if extent.width == 0 || extent.height == 0 {
+            return;
+        }
+
+        let window_state = WindowState {
+            window_handle: self.window.window_handle(),
+            display_handle: self.window.display_handle(),
+            extent2d: extent,
+            scale_factor: self.window.scale_factor(),
+            transparent: self.window.is_transparent(),
+        };
+        let previous_format = self.renderer.surface_format();
+        if self.renderer.on_window_recreation(&mut self.gui_system.gui_data, window_state) {
+            let extent = self.renderer.surface_sizes().render;
+            let component = &mut self.component;
+            self.renderer.run_single_time(|ctx| component.resized(ctx, extent));
+        }
+        let format = self.renderer.surface_format();
+        if format != previous_format {
+            let component = &mut self.component;
+            self.renderer.run_single_time(|ctx| component.surface_changed(ctx, format));
+        }
+        self.window.winit_window().request_redraw();
+    }
+
+    fn draw(&mut self) {
+        let enabled = self.toggle.is_enabled();
+        let mut gui_components: Vec<&mut dyn GuiComponent> = Vec::new();
+        if enabled {
+            gui_components.push(self.component.as_mut());
+        }
+        self.gui_system.update(
+            &mut self.renderer.gpu.gfx,
+            &mut self.renderer.image_context,
+            &mut self.renderer.window,
+            self.window.winit_window(),
+            &mut gui_components
+        );
+        let mut render_components: Vec<&mut dyn RenderComponent> = Vec::new();
+        if enabled {
+            render_components.push(self.component.as_mut());
+        }
+        self.renderer.draw_frame(&mut self.gui_system, &mut render_components);
+        self.renderer.input.end_frame();
+    }
+}
+
+/// Input of the user, as opposed to events of the window itself.
+fn is_user_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(..)
+    )
+}
+
+/// Input used by the gui doesn't reach the components, releases always do so no key gets stuck.
+fn is_release(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { event: KeyEvent { state: ElementState::Released, .. }, .. }
+            | WindowEvent::MouseInput { state: ElementState::Released, .. }
+    )
+}
+
 pub struct CenContext<'a>
 {
     pub gfx: &'a mut GraphicsContext,
@@ -35,6 +238,31 @@ pub struct CenContext<'a>
     pub pipelines: &'a mut PipelineContext,
     pub command_buffer: &'a mut CommandBuffer,
     pub swapchain_image: Option<&'a SwapchainImage>,
+    pub clock: &'a AnimationClock,
+    pub random: &'a RandomProvider,
+    pub input: &'a InputState,
+    pub uploader: &'a mut Uploader,
+    /// Report loading progress to keep the splash up, see [`LoadingProgress`].
+    pub loading: &'a LoadingProgress,
+    /// Register components to disable them at runtime, see [`ComponentRegistry`].
+    pub components: &'a ComponentRegistry,
+    pub assets: &'a mut AssetLoader,
+    pub profiler: &'a mut GpuProfiler,
+    /// Engine-provided uniforms of the frame, see [`FrameGlobals`].
+    pub globals: &'a FrameGlobals,
+    /// Fullscreen, title and cursor of the window the component renders to.
+    pub window: &'a mut WindowController,
+    /// Dedicated compute queue of the current frame, `None` if the device has none or outside of a frame.
+    pub async_compute: Option<&'a mut AsyncCompute>,
+    /// Depth buffer of the renderer, see [`depth_image`](Self::depth_image).
+    pub depth: Option<&'a ImageResource>,
+    /// Surface, logical and render size of the window, see [`surface_sizes`](Self::surface_sizes).
+    pub sizes: SurfaceSizes,
+    pub(crate) swapchain_image_index: Option<usize>,
+    pub(crate) swapchain_image_count: usize,
+    pub(crate) on_finish: &'a mut Vec<FrameCallback>,
+    /// Transient descriptor sets of the frame, `None` outside of a frame.
+    pub(crate) transient_descriptors: Option<&'a mut TransientDescriptorAllocator>,
 }
 
 impl CenContext<'_> {
@@ -42,17 +270,118 @@ impl CenContext<'_> {
         self.images.create_image(self.gfx, config, flags)
     }
 
+    /// Create an image, or list the formats that would work when the device can't use its format with its usage.
+    pub fn try_create_image(&mut self, config: ImageConfig, flags: ImageFlags) -> Result<ImageResource, ImageError> {
+        self.images.try_create_image(self.gfx, config, flags)
+    }
+
+    /// Show an image in debug tools by name, see [`ImageContext::register`].
+    pub fn register_image(&mut self, name: impl Into<String>, resource: &ImageResource, layout: vk::ImageLayout) {
+        self.images.register(name, resource, layout)
+    }
+
     pub fn create_pipeline(&mut self, handle: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
         self.pipelines.create_pipeline(handle)
     }
+
+    /// Depth buffer matching the swapchain extent, `None` unless enabled with
+    /// [`AppConfig::depth_buffer`](crate::app::app::AppConfig::depth_buffer). Its contents are undefined at the
+    /// start of a frame, so the first pass clears it, e.g. with
+    /// [`DepthAttachment::clear`](crate::vulkan::DepthAttachment::clear).
+    pub fn depth_image(&self) -> Option<&Image> {
+        self.depth.map(|depth| self.images.get(depth))
+    }
+
+    /// Sizes of the window the frame renders to. The swapchain image has the `render` extent, which differs from the
+    /// surface extent with a [`SurfaceSizePolicy`](crate::graphics::SurfaceSizePolicy) other than `Surface`.
+    pub fn surface_sizes(&self) -> &SurfaceSizes {
+        &self.sizes
+    }
+
+    /// Index of the swapchain image the frame is presented with, to key resources per swapchain image. `None`
+    /// outside of a frame and while exporting, see [`AppConfig::export`](crate::app::app::AppConfig::export).
+    pub fn swapchain_image_index(&self) -> Option<usize> {
+        self.swapchain_image_index
+    }
+
+    /// Number of swapchain images. It may change whenever the swapchain is recreated, so check it every frame.
+    pub fn swapchain_image_count(&self) -> usize {
+        self.swapchain_image_count
+    }
+
+    /// Time in seconds to drive animations with, see [`AnimationClock::animation_time`].
+    pub fn animation_time(&self) -> f64 {
+        self.clock.animation_time()
+    }
+
+    /// Seconds since the renderer started, sampled once at the start of the frame.
+    pub fn time(&self) -> f64 {
+        self.clock.elapsed().as_secs_f64()
+    }
+
+    /// Seconds since the start of the previous frame, zero in the first frame.
+    pub fn delta_time(&self) -> f64 {
+        self.clock.delta_time().as_secs_f64()
+    }
+
+    /// Number of the frame, counting up from zero. Unlike the frame in flight slots it never wraps around.
+    pub fn frame_index(&self) -> u64 {
+        self.clock.frame_index()
+    }
+
+    /// Random seed of the current frame, see [`RandomProvider`].
+    pub fn random_seed(&self) -> u32 {
+        self.random.seed()
+    }
+
+    /// Run `f` once the commands recorded so far have executed on the GPU, e.g. to read back results.
+    /// Outside of a frame, it runs right after the commands were submitted and waited on.
+    ///
+    /// Dropped images and buffers don't need this, they stay alive until the frames in flight executed,
+    /// see [`Device::defer_drop`](crate::vulkan::Device::defer_drop).
+    pub fn run_on_finish(&mut self, f: impl FnOnce() + 'static) {
+        self.on_finish.push(Box::new(f));
+    }
+
+    /// Write `writer` to a throwaway descriptor set and bind it at `set`, e.g. for a varying number of textures per
+    /// draw. The set is freed once the frame executed, so unlike a [`DescriptorSet`](crate::vulkan::DescriptorSet)
+    /// it needs no managing, and unlike push descriptors it isn't limited in size.
+    pub fn bind_transient_descriptors(&mut self, pipeline: &dyn Pipeline, set: u32, layout: &DescriptorSetLayout, writer: &DescriptorWriter) {
+        self.command_buffer.track_descriptors(writer);
+        match self.transient_descriptors.as_deref_mut() {
+            Some(allocator) => {
+                let descriptor_set = allocator.allocate(layout, writer);
+                self.command_buffer.bind_descriptor_set_handle(pipeline, set, descriptor_set);
+            }
+            None => {
+                // Outside of a frame, the command buffer keeps the set alive until it executed
+                let descriptor_set = self.gfx.descriptor_allocator.allocate(layout);
+                writer.update(&descriptor_set);
+                self.command_buffer.bind_descriptor_set(pipeline, set, &descriptor_set);
+            }
+        }
+    }
+
+    /// Record and submit commands outside of the frame's command buffer and wait for them,
+    /// e.g. for a one-off bake triggered from the gui. See [`GraphicsContext::run_immediate`].
+    pub fn run_immediate<R>(&mut self, f: impl FnOnce(&mut CommandBuffer) -> R) -> R {
+        self.gfx.run_immediate(f)
+    }
+
+    /// Refresh rate of the monitor the window currently resides on, in Hz.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.clock.refresh_rate()
+    }
 }
 
 impl Engine {
 
-    pub fn new<C: AppComponent + 'static>(proxy: EventLoopProxy<UserEvent>, event_loop: &ActiveEventLoop, app_config: &AppConfig) -> Engine {
+    pub fn new<C: AppComponent + 'static>(proxy: EventLoopProxy<UserEvent>, event_loop: &ActiveEventLoop, app_config: &AppConfig, export: Option<ExportConfig>) -> Engine {
 
         // Create the graphics context
-        let window = Box::new(Window::create(event_loop, &app_config.title, app_config.width, app_config.height, app_config.fullscreen, app_config.resizable));
+        let mut window = Box::new(Window::create(event_loop, &app_config.window_options));
+        window.set_fullscreen_hotkey(app_config.fullscreen_hotkey);
+        window.set_exit_on_escape(app_config.exit_on_escape);
 
         // Setup renderer
         let window_state = WindowState {
@@ -60,30 +389,75 @@ impl Engine {
             display_handle: window.display_handle(),
             extent2d: window.get_extent(),
             scale_factor: window.scale_factor(),
+            transparent: window.is_transparent(),
         };
-        let mut renderer = Renderer::new(&window_state, proxy, app_config.vsync);
+        let mut renderer = Renderer::new(&window_state, proxy.clone(), app_config);
+        #[cfg(feature = "gamepad")]
+        crate::app::gamepad::spawn_gamepad_thread(proxy.clone());
+        match export {
+            Some(export) => {
+                renderer.clock = AnimationClock::offline(export.frame_rate);
+                renderer.start_export(export);
+            }
+            None => renderer.clock = AnimationClock::new(window.refresh_rate(), app_config.quantize_animation_time),
+        }
+        if let Some(path) = &app_config.event_log {
+            renderer.gpu.gfx.device.event_log().open(path);
+        }
+        let panic_hook = app_config.panic_hook.then(|| install_panic_hook(&renderer.crash_state));
 
         // Setup gui
-        let gui_system = GuiSystem::new(window.as_ref(), &mut renderer);
+        let mut gui_system = GuiSystem::new(window.as_ref(), &mut renderer);
+        renderer.window.set_theme(app_config.window_options.theme);
+        if let Some(style) = &app_config.gui_style {
+            gui_system.set_style(style.as_ref());
+        }
 
+        // Show the splash while the user components initialize
+        // Images are scaled with a blit, which compute queues can't record
+        let splash_config = match app_config.splash.clone() {
+            Splash::Image { background, .. } if renderer.presents_from_compute() => Splash::Color(background),
+            splash => splash,
+        };
+        let mut splash = SplashScreen::new(splash_config, renderer.loading.clone());
+        renderer.draw_frame(&mut gui_system, &mut [&mut splash as &mut dyn RenderComponent]);
 
         // Initialize the user components
-        let mut command_buffer = CommandBuffer::new(&renderer.graphics_context.device, &renderer.graphics_context.command_pool, false);
-        command_buffer.begin();
-
-        let mut init_context = CenContext {
-            gfx: &mut renderer.graphics_context,
-            images: &mut renderer.image_context,
-            pipelines: &mut renderer.pipeline_context,
-            command_buffer: &mut command_buffer,
-            swapchain_image: None,
-        };
-        let app_component = Box::new(C::new(&mut init_context));
+        let app_toggle = renderer.components.register(component_name::<C>());
+        let mut app_component = Box::new(renderer.run_single_time(|ctx| C::new(ctx)));
+        let mut gui_components: Vec<&mut dyn GuiComponent> = vec![app_component.as_mut()];
+        if app_config.gpu_profiler_overlay {
+            gui_components.push(&mut renderer.profiler);
+        }
+        let mut memory_overlay = app_config.memory_overlay.then(MemoryOverlay::default);
+        if let Some(overlay) = &mut memory_overlay {
+            gui_components.push(overlay);
+        }
+        let mut fps_overlay = FpsOverlay::default();
+        fps_overlay.visible = app_config.fps_overlay;
+        let mut image_viewer = app_config.image_viewer.then(ImageViewer::default);
+        if let Some(viewer) = &mut image_viewer {
+            gui_components.push(viewer);
+        }
+        let mut component_inspector = app_config.component_inspector.then(|| ComponentInspector::new(renderer.components.clone()));
+        if let Some(inspector) = &mut component_inspector {
+            gui_components.push(inspector);
+        }
+        gui_system.initialize(&mut renderer.gpu.gfx, &mut renderer.image_context, &mut renderer.window, &mut gui_components);
+        renderer.window.apply(&window);
+        if renderer.window.take_exit_request() {
+            event_loop.exit();
+        }
 
-        command_buffer.end();
-        renderer.submit_single_time_command_buffer(command_buffer);
+        let windows = app_config.windows.iter()
+            .map(|config| {
+                let window = SecondaryWindow::new(proxy.clone(), event_loop, app_config, config, &renderer);
+                (window.window.winit_window().id(), window)
+            })
+            .collect();
 
         Engine {
+            panic_hook,
             _start_time: SystemTime::now(),
             window,
             renderer,
@@ -92,26 +466,114 @@ impl Engine {
             app_component,
             last_print_time: SystemTime::now(),
             log_fps: app_config.log_fps,
+            update_loop: UpdateLoop::new(app_config.fixed_timestep),
+            redraw_mode: app_config.redraw_mode,
+            active_hours: app_config.active_hours,
+            power_mode: PowerMode::Active,
+            paused_redraw: false,
+            last_frame: Instant::now(),
+            gpu_profiler_overlay: app_config.gpu_profiler_overlay,
+            memory_overlay,
+            fps_overlay,
+            fps_overlay_hotkey: app_config.fps_overlay_hotkey,
+            image_viewer,
+            component_inspector,
+            app_toggle,
+            soak_test: app_config.soak_test,
+            splash: Some(splash),
+            windows,
         }
     }
 
-    pub(crate) fn exit(&self) {
+    /// Release everything, returns a failure if the soak test failed, see [`AppConfig::soak_test`].
+    pub(crate) fn exit(self) -> ExitCode {
         // Wait for all render operations to finish before exiting
         // This ensures we can safely start dropping gpu resources
-        self.renderer.graphics_context.device.wait_idle();
+        // The windows share the device of the main window
+        self.renderer.gpu.gfx.device.wait_idle();
+
+        // Objects still alive once the engine dropped everything it owns are held past exit, see `LeakReport`
+        let registry = self.renderer.gpu.gfx.device.inner.live_objects.clone();
+        // Counted until the instance is destroyed, including messages about the teardown
+        let validation_counts = self.renderer.gpu.instance.validation_counts();
+        let validation_enabled = self.renderer.gpu.instance.is_validation_enabled();
+        let soak_test = self.soak_test.map(|frames| (frames, self.renderer.stats.frames_rendered));
+        drop(self);
+        let report = registry.report();
+        if !report.is_empty() {
+            warn!("GPU objects were not released at exit, the device can't be destroyed: {}", report);
+        }
+
+        let Some((frames, frames_rendered)) = soak_test else {
+            return ExitCode::SUCCESS;
+        };
+        let errors = validation_counts.errors();
+        let warnings = validation_counts.warnings();
+        if !validation_enabled {
+            error!("Soak test failed, the validation layer is not available");
+            ExitCode::FAILURE
+        } else if frames_rendered < frames {
+            error!("Soak test failed, the app exited after {} of {} frames", frames_rendered, frames);
+            ExitCode::FAILURE
+        } else if errors > 0 {
+            error!("Soak test failed with {} validation errors and {} warnings in {} frames", errors, warnings, frames_rendered);
+            ExitCode::FAILURE
+        } else {
+            info!("Soak test passed with {} validation warnings in {} frames", warnings, frames_rendered);
+            ExitCode::SUCCESS
+        }
     }
     
-    pub(crate) fn window_event(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) {
+    pub(crate) fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        if window_id != self.window.winit_window().id() {
+            let close = match self.windows.get_mut(&window_id) {
+                Some(_) if matches!(event, WindowEvent::CloseRequested) => true,
+                Some(_) if self.power_mode == PowerMode::Paused && matches!(event, WindowEvent::RedrawRequested) => false,
+                Some(window) => window.window_event(event_loop, event),
+                None => false,
+            };
+            if close {
+                if let Some(window) = self.windows.remove(&window_id) {
+                    window.renderer.gpu.gfx.device.wait_idle();
+                }
+            }
+            return;
+        }
+
         self.window.window_event( event.clone(), event_loop );
 
-        self.gui_system.on_window_event(self.window.winit_window(), &event);
+        let consumed = self.gui_system.on_window_event(self.window.winit_window(), &event);
+        if !consumed || is_release(&event) {
+            self.renderer.input.handle_event(&event);
+            if let Some(input_component) = self.app_component.input_component() {
+                dispatch_input_event(input_component, &self.renderer.input, &event);
+            }
+        }
 
         self.app_component.window_event( event.clone());
 
+        // Paused apps draw a frame on user input, so the gui and the components can react to it and resume
+        if self.power_mode == PowerMode::Paused && is_user_input(&event) {
+            self.paused_redraw = true;
+            self.window.winit_window().request_redraw();
+        }
+
+        let redraw = matches!(event, WindowEvent::RedrawRequested);
+        let paused = self.power_mode == PowerMode::Paused && !(redraw && std::mem::take(&mut self.paused_redraw));
         match event {
+            // Paused apps keep showing the last frame
+            WindowEvent::RedrawRequested if paused => (),
             WindowEvent::RedrawRequested => {
+                // Updates don't run in between frames when only drawing on events
+                if self.redraw_mode == RedrawMode::OnEvent {
+                    self.update();
+                }
                 self.draw();
 
+                if self.soak_test.is_some_and(|frames| self.renderer.stats.frames_rendered >= frames) {
+                    self.renderer.window.request_exit();
+                }
+
                 if self.log_fps {
                     let current_frame_time = SystemTime::now();
                     let elapsed = current_frame_time.duration_since(self.last_print_time).unwrap();
@@ -125,25 +587,99 @@ impl Engine {
                 }
             },
             WindowEvent::Resized( .. ) => {
-                let window_state = WindowState {
-                    window_handle: self.window.window_handle(),
-                    display_handle: self.window.display_handle(),
-                    extent2d: self.window.get_extent(),
-                    scale_factor: self.window.scale_factor(),
-                };
-                self.renderer.on_window_recreation(&mut self.gui_system.gui_data, window_state);
+                self.recreate_swapchain();
             },
             WindowEvent::ScaleFactorChanged { .. } => {
-                let window_state = WindowState {
-                    window_handle: self.window.window_handle(),
-                    display_handle: self.window.display_handle(),
-                    extent2d: self.window.get_extent(),
-                    scale_factor: self.window.scale_factor(),
-                };
-                self.renderer.on_window_recreation(&mut self.gui_system.gui_data, window_state);
+                self.recreate_swapchain();
+                self.renderer.clock.set_refresh_rate(self.window.refresh_rate());
+            }
+            WindowEvent::KeyboardInput { event: KeyEvent { logical_key: Key::Named(key), state: ElementState::Pressed, repeat: false, .. }, .. }
+                if !consumed && Some(key) == self.fps_overlay_hotkey => {
+                self.fps_overlay.toggle();
+            }
+            WindowEvent::Moved( .. ) => {
+                // The window may have moved to a monitor with a different refresh rate or color space
+                self.renderer.clock.set_refresh_rate(self.window.refresh_rate());
+                if self.renderer.is_surface_outdated() {
+                    self.recreate_swapchain();
+                }
             }
             _ => (),
         }
+
+        self.renderer.window.apply(&self.window);
+        if self.renderer.window.take_exit_request() {
+            event_loop.exit();
+        }
+
+        // Resume right away when a component woke the app up, the frame rate of the low power mode is kept by `new_events`
+        if self.redraw_mode == RedrawMode::Continuous {
+            let previous = self.power_mode;
+            match self.power_mode() {
+                PowerMode::Active => event_loop.set_control_flow(ControlFlow::Poll),
+                PowerMode::LowPower { .. } if previous == PowerMode::Paused => {
+                    event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now()));
+                }
+                _ => (),
+            }
+        }
+
+        // Input and other events change what is shown, frames change it when the gui or a component asks for more
+        if self.redraw_mode == RedrawMode::OnEvent {
+            if !redraw {
+                self.window.winit_window().request_redraw();
+            } else {
+                self.schedule_redraw(event_loop);
+            }
+        }
+    }
+
+    /// Request the next frame of the main window after drawing one with [`RedrawMode::OnEvent`], right away or once
+    /// the gui wants to repaint.
+    fn schedule_redraw(&mut self, event_loop: &ActiveEventLoop) {
+        let loading = self.splash.is_some() || !self.renderer.loading.is_ready();
+        let repaint_delay = self.gui_system.repaint_delay();
+        if loading || self.renderer.window.take_redraw_request() || repaint_delay.is_zero() {
+            self.window.winit_window().request_redraw();
+            event_loop.set_control_flow(ControlFlow::Wait);
+        } else {
+            // Woken up with `StartCause::ResumeTimeReached`
+            event_loop.set_control_flow(match Instant::now().checked_add(repaint_delay) {
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            });
+        }
+    }
+
+    fn recreate_swapchain(&mut self) {
+        let extent = self.window.get_extent();
+
+        // Minimized windows have nothing to present to, keep the current swapchain until restored
+        if extent.width == 0 || extent.height == 0 {
+            return;
+        }
+
+        let window_state = WindowState {
+            window_handle: self.window.window_handle(),
+            display_handle: self.window.display_handle(),
+            extent2d: extent,
+            scale_factor: self.window.scale_factor(),
+            transparent: self.window.is_transparent(),
+        };
+        let previous_format = self.renderer.surface_format();
+        if self.renderer.on_window_recreation(&mut self.gui_system.gui_data, window_state) {
+            let extent = self.renderer.surface_sizes().render;
+            let app_component = &mut self.app_component;
+            self.renderer.run_single_time(|ctx| app_component.resized(ctx, extent));
+        }
+        let format = self.renderer.surface_format();
+        if format != previous_format {
+            let app_component = &mut self.app_component;
+            self.renderer.run_single_time(|ctx| app_component.surface_changed(ctx, format));
+        }
+
+        // Present a frame at the new extent right away instead of showing a stretched or cleared surface
+        self.window.winit_window().request_redraw();
     }
 
     pub fn user_event(&mut self, _: &ActiveEventLoop, event: UserEvent) {
@@ -151,41 +687,172 @@ impl Engine {
             | UserEvent::GlslUpdate(path) => {
                 debug!("Reloading shader: {:?}", path);
 
-                if let Err(e) = self.renderer.pipeline_context.pipeline_store.reload(&path) {
-                    error!("{}", e);
+                if let Err(errors) = self.renderer.pipeline_context.pipeline_store.reload(&path) {
+                    errors.iter().for_each(|e| error!("{}", e));
+                }
+                for window in self.windows.values_mut() {
+                    if let Err(errors) = window.renderer.pipeline_context.pipeline_store.reload(&path) {
+                        errors.iter().for_each(|e| error!("{}", e));
+                    }
+                }
+                if self.redraw_mode == RedrawMode::OnEvent {
+                    self.window.winit_window().request_redraw();
+                }
+            }
+            | UserEvent::Gamepad(event) => {
+                self.renderer.input.handle_gamepad_event(&event);
+                if let Some(input_component) = self.app_component.input_component() {
+                    dispatch_gamepad_event(input_component, &self.renderer.input, &event);
+                }
+                if self.redraw_mode == RedrawMode::OnEvent {
+                    self.window.winit_window().request_redraw();
                 }
             }
             _ => (),
         }
     }
     
-    pub fn new_events(&mut self, _: &ActiveEventLoop, cause: StartCause) {
-        match cause {
-            | StartCause::Poll => {
-                self.update();
+    pub fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if self.redraw_mode == RedrawMode::OnEvent {
+            // The gui asked to repaint after a delay, see `schedule_redraw`
+            if let StartCause::ResumeTimeReached { .. } = cause {
                 self.window.winit_window().request_redraw();
             }
-            _ => {}
+            return;
+        }
+        if let StartCause::Init = cause {
+            return;
+        }
+
+        // Wake up when the active hours start or end
+        let now = Instant::now();
+        let active_hours = self.active_hours;
+        let wake_up = |deadline: Option<Instant>| {
+            let change = active_hours.map(|hours| now + hours.next_change(SystemTime::now()));
+            match (deadline, change) {
+                (Some(deadline), Some(change)) => ControlFlow::WaitUntil(deadline.min(change)),
+                (deadline, change) => deadline.or(change).map_or(ControlFlow::Wait, ControlFlow::WaitUntil),
+            }
+        };
+        match self.power_mode() {
+            PowerMode::Active => event_loop.set_control_flow(ControlFlow::Poll),
+            PowerMode::LowPower { frame_rate } => {
+                let interval = Duration::from_secs_f64(1.0 / frame_rate.max(0.001));
+                if now < self.last_frame + interval {
+                    event_loop.set_control_flow(wake_up(Some(self.last_frame + interval)));
+                    return;
+                }
+                event_loop.set_control_flow(wake_up(Some(now + interval)));
+            }
+            PowerMode::Paused => {
+                event_loop.set_control_flow(wake_up(None));
+                return;
+            }
+        }
+
+        self.last_frame = now;
+        self.update();
+        self.window.winit_window().request_redraw();
+        for window in self.windows.values() {
+            window.window.winit_window().request_redraw();
         }
     }
 
-    fn update(&mut self) {
+    /// Power mode set by a component, or of the active hours. Logs when it changes.
+    fn power_mode(&mut self) -> PowerMode {
+        let power_mode = self.renderer.window.power_mode()
+            .or(self.active_hours.map(|hours| hours.mode_at(SystemTime::now())))
+            .unwrap_or_default();
+        if power_mode != self.power_mode {
+            info!("Power mode changed to {:?}", power_mode);
+            self.power_mode = power_mode;
+        }
+        power_mode
+    }
 
+    fn update(&mut self) {
+        // Exports update on the timeline of the exported frames
+        let now = if self.renderer.clock.is_offline() {
+            self.renderer.clock.frame_instant()
+        } else {
+            Instant::now()
+        };
+        // Disabled components skip their updates instead of catching up once enabled again
+        let enabled = self.app_toggle.is_enabled();
+        for dt in self.update_loop.tick(now) {
+            if let Some(update_component) = self.app_component.update_component().filter(|_| enabled) {
+                update_component.update(dt);
+            }
+        }
     }
     
     pub fn draw(&mut self) {
-        
+        trace_scope!("frame", index = self.renderer.clock.frame_index());
+
+        // Keep showing the splash until the components are done loading
+        if self.splash.is_some() && self.renderer.loading.is_ready() {
+            self.splash = None;
+        }
+        if let Some(splash) = &mut self.splash {
+            self.gui_system.update(
+                &mut self.renderer.gpu.gfx,
+                &mut self.renderer.image_context,
+                &mut self.renderer.window,
+                self.window.winit_window(),
+                &mut [&mut *splash as &mut dyn GuiComponent]
+            );
+            self.renderer.draw_frame(&mut self.gui_system, &mut [splash as &mut dyn RenderComponent]);
+            self.renderer.input.end_frame();
+            return;
+        }
+
+        if self.fps_overlay.visible {
+            // The frame scope spans all commands of the frame
+            let gpu_time = self.renderer.profiler.timings().iter()
+                .find(|timing| timing.depth == 0 && timing.name == "frame")
+                .map(|timing| timing.duration);
+            self.fps_overlay.set_frame_info(gpu_time, *self.renderer.surface_sizes(), self.renderer.presenter.present_mode());
+        }
+
         // Update our gui. Has to happen each frame or we will miss frames
-        let mut gui_components: Vec<&mut dyn GuiComponent> = vec![self.app_component.as_mut()];
+        let enabled = self.app_toggle.is_enabled();
+        let mut gui_components: Vec<&mut dyn GuiComponent> = Vec::new();
+        if enabled {
+            gui_components.push(self.app_component.as_mut());
+        }
+        if self.gpu_profiler_overlay {
+            gui_components.push(&mut self.renderer.profiler);
+        }
+        if let Some(overlay) = &mut self.memory_overlay {
+            gui_components.push(overlay);
+        }
+        if self.fps_overlay.visible {
+            gui_components.push(&mut self.fps_overlay);
+        }
+        if let Some(viewer) = &mut self.image_viewer {
+            gui_components.push(viewer);
+        }
+        if let Some(inspector) = &mut self.component_inspector {
+            gui_components.push(inspector);
+        }
         self.gui_system.update(
-            &mut self.renderer.graphics_context,
+            &mut self.renderer.gpu.gfx,
             &mut self.renderer.image_context,
+            &mut self.renderer.window,
             self.window.winit_window(),
             &mut gui_components
         );
 
         // Render all our components
-        let mut render_components: Vec<&mut dyn RenderComponent> = vec![self.app_component.as_mut()];
+        let mut render_components: Vec<&mut dyn RenderComponent> = Vec::new();
+        if enabled {
+            render_components.push(self.app_component.as_mut());
+        }
+        // Shows the images as the components left them
+        if let Some(viewer) = &mut self.image_viewer {
+            render_components.push(viewer);
+        }
         self.renderer.draw_frame(&mut self.gui_system, &mut render_components);
+        self.renderer.input.end_frame();
     }
 }
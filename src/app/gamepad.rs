@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "gamepad")]
+use log::warn;
+#[cfg(feature = "gamepad")]
+use winit::event_loop::EventLoopProxy;
+#[cfg(feature = "gamepad")]
+use crate::app::app::UserEvent;
+
+/// Identifies a connected gamepad. The id of a disconnected gamepad may be reused by a later connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GamepadId(pub usize);
+
+/// Buttons of a gamepad by their position on a standard layout, e.g. `South` is A on an Xbox and Cross on a
+/// PlayStation controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    /// Also reported as [`GamepadAxis::LeftTrigger`] on analog triggers.
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// Gamepad events from the gamepad thread of the `gamepad` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected { id: GamepadId, name: String },
+    Disconnected(GamepadId),
+    ButtonPressed(GamepadId, GamepadButton),
+    ButtonReleased(GamepadId, GamepadButton),
+    AxisChanged(GamepadId, GamepadAxis, f32),
+}
+
+/// State of a connected gamepad, see [`InputState::gamepads`](crate::app::InputState::gamepads).
+#[derive(Clone, Debug, Default)]
+pub struct Gamepad {
+    name: String,
+    buttons: HashSet<GamepadButton>,
+    buttons_pressed: HashSet<GamepadButton>,
+    buttons_released: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Gamepad {
+    pub(crate) fn new(name: String) -> Self {
+        Self { name, ..Default::default() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_button_down(&self, button: GamepadButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    /// Whether the button went down since the previous frame.
+    pub fn was_button_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Whether the button was released since the previous frame.
+    pub fn was_button_released(&self, button: GamepadButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Position of the axis, from -1 to 1 for sticks with up being positive, from 0 to 1 for triggers. Zero until
+    /// the axis first moves.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    pub fn left_stick(&self) -> (f32, f32) {
+        (self.axis(GamepadAxis::LeftStickX), self.axis(GamepadAxis::LeftStickY))
+    }
+
+    pub fn right_stick(&self) -> (f32, f32) {
+        (self.axis(GamepadAxis::RightStickX), self.axis(GamepadAxis::RightStickY))
+    }
+
+    pub(crate) fn handle_event(&mut self, event: &GamepadEvent) {
+        match event {
+            GamepadEvent::ButtonPressed(_, button) => {
+                if self.buttons.insert(*button) {
+                    self.buttons_pressed.insert(*button);
+                }
+            }
+            GamepadEvent::ButtonReleased(_, button) => {
+                self.buttons.remove(button);
+                self.buttons_released.insert(*button);
+            }
+            GamepadEvent::AxisChanged(_, axis, value) => {
+                self.axes.insert(*axis, *value);
+            }
+            GamepadEvent::Connected { .. } | GamepadEvent::Disconnected(_) => {}
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+    }
+}
+
+/// Poll the gamepads on a thread of their own and send their events to the event loop, so they wake up an app that
+/// only draws on events. Gamepads are unavailable with a warning when the platform has no support.
+#[cfg(feature = "gamepad")]
+pub(crate) fn spawn_gamepad_thread(proxy: EventLoopProxy<UserEvent>) {
+    let spawned = std::thread::Builder::new()
+        .name("cen-gamepad".to_string())
+        .spawn(move || {
+            let mut gilrs = match gilrs::Gilrs::new() {
+                Ok(gilrs) => gilrs,
+                Err(e) => {
+                    warn!("Gamepads are unavailable: {}", e);
+                    return;
+                }
+            };
+
+            // Gamepads that were connected before the start don't send a connection event
+            let connected = gilrs.gamepads()
+                .map(|(id, gamepad)| GamepadEvent::Connected { id: GamepadId(id.into()), name: gamepad.name().to_string() })
+                .collect::<Vec<_>>();
+            for event in connected {
+                if proxy.send_event(UserEvent::Gamepad(event)).is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let Some(gilrs::Event { id, event, .. }) = gilrs.next_event_blocking(None) else {
+                    continue;
+                };
+                let Some(event) = translate_event(&gilrs, id, event) else {
+                    continue;
+                };
+                // The event loop is gone once the app exits
+                if proxy.send_event(UserEvent::Gamepad(event)).is_err() {
+                    return;
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to spawn the gamepad thread: {}", e);
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn translate_event(gilrs: &gilrs::Gilrs, id: gilrs::GamepadId, event: gilrs::EventType) -> Option<GamepadEvent> {
+    use gilrs::EventType;
+
+    let gamepad = GamepadId(id.into());
+    match event {
+        EventType::Connected => Some(GamepadEvent::Connected { id: gamepad, name: gilrs.gamepad(id).name().to_string() }),
+        EventType::Disconnected => Some(GamepadEvent::Disconnected(gamepad)),
+        EventType::ButtonPressed(button, _) => translate_button(button).map(|button| GamepadEvent::ButtonPressed(gamepad, button)),
+        EventType::ButtonReleased(button, _) => translate_button(button).map(|button| GamepadEvent::ButtonReleased(gamepad, button)),
+        // Analog triggers report their position as a button value
+        EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => Some(GamepadEvent::AxisChanged(gamepad, GamepadAxis::LeftTrigger, value)),
+        EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => Some(GamepadEvent::AxisChanged(gamepad, GamepadAxis::RightTrigger, value)),
+        EventType::AxisChanged(axis, value, _) => translate_axis(axis).map(|axis| GamepadEvent::AxisChanged(gamepad, axis, value)),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn translate_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftThumb,
+        Button::RightThumb => GamepadButton::RightThumb,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "gamepad")]
+fn translate_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    use gilrs::Axis;
+
+    Some(match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        Axis::LeftZ => GamepadAxis::LeftTrigger,
+        Axis::RightZ => GamepadAxis::RightTrigger,
+        _ => return None,
+    })
+}
@@ -1,19 +1,20 @@
-use crate::app::{ImageFlags, ImageResource, Window};
+use crate::app::{ImageFlags, ImageResource, Window, WindowController};
 use crate::graphics::{GraphicsContext, ImageContext};
 use crate::graphics::renderer::RenderComponent;
 use crate::graphics::Renderer;
 use crate::vulkan::memory::GpuResource;
-use crate::vulkan::{DescriptorPool, Device, ImageConfig, ImageTrait};
+use crate::vulkan::{DescriptorAllocator, DescriptorSet, Device, ImageConfig, ImageTrait};
 use ash::vk;
-use ash::vk::{AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, ClearValue, DescriptorSet, DescriptorSetLayout, ImageLayout, Offset2D, PipelineStageFlags, Rect2D, RenderingAttachmentInfo};
-use egui::{Context, FullOutput, TextureId, ViewportId};
-use egui_ash_renderer::vulkan::{create_vulkan_descriptor_set, create_vulkan_descriptor_set_layout};
+use ash::vk::{AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, ClearValue, DescriptorSetLayout, ImageLayout, Offset2D, PipelineStageFlags, Rect2D, RenderingAttachmentInfo};
+use egui::{Context, FullOutput, RawInput, TextureId, ViewportId};
+use egui_ash_renderer::vulkan::create_vulkan_descriptor_set_layout;
 use egui_ash_renderer::{DynamicRendering, Options};
 use egui_winit::State;
 use log::{error, trace};
 use std::any::Any;
 use std::collections::HashMap;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use crate::app::engine::CenContext;
 use crate::graphics::image_store::{ImageKey, ImageStore};
 
@@ -36,6 +37,8 @@ pub struct GuiContext<'a> {
     gui_data: &'a mut GuiData,
     pub gfx: &'a mut GraphicsContext,
     pub images: &'a mut ImageContext,
+    /// Fullscreen, title and cursor of the window, see [`WindowController`].
+    pub window: &'a mut WindowController,
     used_textures: Vec<TextureKey>
 }
 
@@ -47,21 +50,129 @@ impl GuiContext<'_> {
 
 impl GuiData {
 
+    /// Gui data for an egui renderer drawing to `color_format` attachments.
+    pub(crate) fn new(gfx: &GraphicsContext, color_format: vk::Format, in_flight_frames: usize) -> Self {
+        let device = gfx.device.clone();
+        let descriptor_allocator = DescriptorAllocator::new(
+            &gfx.device,
+            &[(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1.0)]
+        );
+
+        let egui_renderer = egui_ash_renderer::Renderer::with_gpu_allocator(
+            gfx.allocator.inner.lock().unwrap().allocator.clone(),
+            gfx.device.handle().clone(),
+            DynamicRendering {
+                color_attachment_format: color_format,
+                depth_attachment_format: None,
+            },
+            Options {
+                in_flight_frames,
+                enable_depth_test: false,
+                enable_depth_write: false,
+                srgb_framebuffer: true
+            }
+        ).unwrap();
+
+        let texture_layout = create_vulkan_descriptor_set_layout(gfx.device.handle()).unwrap();
+
+        Self {
+            device,
+            descriptor_allocator,
+            textures: HashMap::new(),
+            egui_renderer,
+            texture_layout
+        }
+    }
+
+    /// Rebuild the egui pipeline for attachments of another format, after the swapchain format changed.
+    pub(crate) fn set_color_format(&mut self, color_format: vk::Format) {
+        self.egui_renderer.set_dynamic_rendering(DynamicRendering {
+            color_attachment_format: color_format,
+            depth_attachment_format: None,
+        }).expect("Failed to recreate the gui pipeline");
+    }
+
+    /// Remove the textures no component references anymore.
+    fn remove_unused_textures(&mut self) {
+        self.textures.retain(|handle, (texture, set, _)| {
+            match texture.upgrade() {
+                None => {
+                    // There are no more shared references to the texture, so it may be removed
+                    // The descriptor set is recycled by the allocator once dropped
+                    trace!("Destroyed texture {:?}", set.handle());
+                    self.egui_renderer.remove_user_texture(handle.id);
+                    false
+                }
+                Some(_) => { true }
+            }
+        });
+    }
+
+    /// Initialize the gui of newly created components, see [`GuiComponent::initialize_gui`].
+    pub(crate) fn initialize(
+        &mut self,
+        egui_ctx: &Context,
+        gfx: &mut GraphicsContext,
+        image_context: &mut ImageContext,
+        window_controller: &mut WindowController,
+        components: &mut [&mut dyn GuiComponent]
+    ) {
+        let mut gui_context = GuiContext {
+            gui_data: self,
+            gfx,
+            images: image_context,
+            window: window_controller,
+            used_textures: vec![]
+        };
+        for component in components {
+            component.initialize_gui(&mut gui_context, egui_ctx);
+        }
+    }
+
+    /// Run the components for one egui frame, returns its output and the textures the components drew.
+    pub(crate) fn run(
+        &mut self,
+        egui_ctx: &Context,
+        raw_input: RawInput,
+        gfx: &mut GraphicsContext,
+        image_context: &mut ImageContext,
+        window_controller: &mut WindowController,
+        components: &mut [&mut dyn GuiComponent]
+    ) -> (FullOutput, Vec<TextureKey>) {
+        self.remove_unused_textures();
+
+        let mut gui_context = GuiContext {
+            gui_data: self,
+            gfx,
+            images: image_context,
+            window: window_controller,
+            used_textures: vec![]
+        };
+
+        let output = egui_ctx.run(raw_input, |ctx| {
+            for component in &mut *components {
+                component.gui(&mut gui_context, ctx);
+            }
+        });
+
+        (output, gui_context.used_textures)
+    }
+
     pub fn create_texture(&mut self, image_store: &mut ImageStore, image: ImageKey) -> Option<TextureKey> {
         if let Some(si) = image_store.get_handle(&image) {
 
-            let device = self.device.handle();
-            let descriptor_set = create_vulkan_descriptor_set(
-                device,
-                self.texture_layout,
-                self.renderer_descriptor_pool.handle(),
-                si.image.image_view(),
-                si.image.sampler(),
-            ).unwrap();
+            let descriptor_set = self.descriptor_allocator.allocate_raw(self.texture_layout, &[(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)]);
+            let image_info = [si.image.binding(ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+            descriptor_set.write(&[
+                vk::WriteDescriptorSet::default()
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_info)
+            ]);
 
             let handle = TextureHandle {
                 image_key: image.clone(),
-                id: self.egui_renderer.add_user_texture(descriptor_set)
+                id: self.egui_renderer.add_user_texture(descriptor_set.handle())
             };
             let texture: TextureKey  = Arc::new(handle.clone());
 
@@ -101,6 +212,10 @@ impl GuiContext<'_> {
 }
 
 pub trait GuiComponent {
+    /// Called once after the component was created, before its first gui frame.
+    /// Create textures up front here, or configure egui, e.g. its fonts and style.
+    fn initialize_gui(&mut self, _gui: &mut GuiContext, _ctx: &Context) {}
+
     fn gui(&mut self, gui: &mut GuiContext, ctx: &Context);
 }
 
@@ -111,7 +226,7 @@ pub struct GuiData {
     pub textures: TextureMap,
     pub egui_renderer: egui_ash_renderer::Renderer,
     texture_layout: DescriptorSetLayout,
-    renderer_descriptor_pool: DescriptorPool,
+    descriptor_allocator: DescriptorAllocator,
 }
 
 
@@ -121,6 +236,8 @@ pub struct GuiSystem {
     pub gui_data: GuiData,
     used_textures: Vec<TextureKey>,
     egui_output: Option<FullOutput>,
+    // Delay after which egui wants to repaint, requested by the last update
+    repaint_delay: Duration,
 }
 
 impl GuiSystem {
@@ -129,18 +246,17 @@ impl GuiSystem {
     }
 }
 
-impl Drop for GuiSystem {
+impl Drop for GuiData {
     fn drop(&mut self) {
-        for (handle, (_, set, _)) in self.gui_data.textures.iter() {
-            self.gui_data.egui_renderer.remove_user_texture(handle.id);
-            unsafe {
-                self.gui_data.device.handle().free_descriptor_sets(self.gui_data.renderer_descriptor_pool.descriptor_pool, &[*set]).unwrap();
-            }
+        for handle in self.textures.keys() {
+            self.egui_renderer.remove_user_texture(handle.id);
             trace!("Destroyed user texture {:?}", handle.id);
         }
+        // Return the sets to the allocator before the layout is destroyed
+        self.textures.clear();
         unsafe {
-            self.gui_data.device.handle().destroy_descriptor_set_layout(self.gui_data.texture_layout, None);
-            trace!("Destroyed gui image descriptor set layout {:?}", self.gui_data.texture_layout);
+            self.device.handle().destroy_descriptor_set_layout(self.texture_layout, None);
+            trace!("Destroyed gui image descriptor set layout {:?}", self.texture_layout);
         }
     }
 }
@@ -156,101 +272,87 @@ impl GuiSystem {
         // image = { version = "0.25", features = ["png"] }
         egui_extras::install_image_loaders(&egui_ctx);
 
+        // egui follows the OS theme through the window, see `WindowController::set_theme`
         let egui_winit = egui_winit::State::new(
             egui_ctx.clone(),
             ViewportId::ROOT,
             &window.display_handle(),
             None,
-            None,
+            window.winit_window().theme(),
             None
         );
 
-        // Renderer values
-
-        let device = renderer.graphics_context.device.clone();
-        let renderer_descriptor_pool = DescriptorPool::new(&renderer.graphics_context.device, 10000);
-
-        let preferred_format = renderer.swapchain.get_format().format;
-
-        let egui_renderer = egui_ash_renderer::Renderer::with_gpu_allocator(
-            renderer.graphics_context.allocator.inner.lock().unwrap().allocator.clone(),
-            renderer.graphics_context.device.handle().clone(),
-            DynamicRendering {
-                color_attachment_format: preferred_format,
-                depth_attachment_format: None,
-            },
-            Options {
-                in_flight_frames: renderer.swapchain.get_image_count() as usize,
-                enable_depth_test: false,
-                enable_depth_write: false,
-                srgb_framebuffer: true
-            }
-        ).unwrap();
-
-        let texture_layout = create_vulkan_descriptor_set_layout(renderer.graphics_context.device.handle()).unwrap();
-
-        let gui_data = GuiData {
-            device,
-            renderer_descriptor_pool,
-            textures: HashMap::new(),
-            egui_renderer,
-            texture_layout
-        };
+        let gui_data = GuiData::new(
+            &renderer.gpu.gfx,
+            renderer.presenter.format(),
+            renderer.presenter.swapchain.get_image_count() as usize
+        );
 
         Self {
             egui_ctx,
             egui_winit,
             egui_output: None,
+            repaint_delay: Duration::MAX,
             gui_data,
             used_textures: vec![],
         }
     }
 
-    pub fn on_window_event(&mut self, window: &winit::window::Window, event: &winit::event::WindowEvent) {
-        let _ = self.egui_winit.on_window_event(window, event);
+    /// Returns whether egui consumed the event, e.g. a key press in a text field.
+    pub fn on_window_event(&mut self, window: &winit::window::Window, event: &winit::event::WindowEvent) -> bool {
+        self.egui_winit.on_window_event(window, event).consumed
     }
 
-    pub fn update(&mut self, gfx: &mut GraphicsContext, image_context: &mut ImageContext, window: &winit::window::Window, components: &mut [&mut dyn GuiComponent]) {
+    pub fn update(&mut self, gfx: &mut GraphicsContext, image_context: &mut ImageContext, window_controller: &mut WindowController, window: &winit::window::Window, components: &mut [&mut dyn GuiComponent]) {
 
-        // Remove unused images
-        self.gui_data.textures.retain(|handle, (texture, set, _)| {
-            match texture.upgrade() {
-                None => {
-                    // There are no more shared references to the texture, so it may be removed
-                    unsafe {
-                        trace!("Destroyed texture {:?}", *set);
-                        self.gui_data.device.handle().free_descriptor_sets(self.gui_data.renderer_descriptor_pool.descriptor_pool, &[*set]).unwrap();
-                    }
-                    self.gui_data.egui_renderer.remove_user_texture(handle.id);
-                    false
-                }
-                Some(_) => { true }
-            }
-        });
+        // Themes requested during the previous frame
+        let theme = window_controller.theme();
+        if self.egui_ctx.options(|options| options.theme_preference) != theme {
+            self.egui_ctx.set_theme(theme);
+        }
 
         let raw_input = self.egui_winit.take_egui_input(window);
+        let (mut output, used_textures) = self.gui_data.run(
+            &self.egui_ctx, raw_input, gfx, image_context, window_controller, components
+        );
 
-        let mut gui_context = GuiContext {
-            gui_data: &mut self.gui_data,
-            gfx,
-            images: image_context,
-            used_textures: vec![]
-        };
+        self.repaint_delay = output.viewport_output.get(&ViewportId::ROOT)
+            .map_or(Duration::MAX, |viewport| viewport.repaint_delay);
 
-        self.egui_output = Some(self.egui_ctx.run(raw_input, |ctx| {
-            for component in &mut *components {
-                component.gui(&mut gui_context, ctx);
-            }
-        }));
+        // The previous output was never rendered, e.g. because the frame was skipped. Keep its texture updates.
+        if let Some(previous) = self.egui_output.take() {
+            let mut textures_delta = previous.textures_delta;
+            textures_delta.append(output.textures_delta);
+            output.textures_delta = textures_delta;
+        }
+        self.egui_output = Some(output);
+
+        self.used_textures = used_textures;
+    }
+
+    /// Delay after which the gui wants to be drawn again, e.g. zero while animating. `Duration::MAX` if it is idle.
+    pub(crate) fn repaint_delay(&self) -> Duration {
+        self.repaint_delay
+    }
+
+    /// Customize the egui style of the dark and the light theme, used whenever the theme changes.
+    pub fn set_style(&self, style: &dyn Fn(&mut egui::Style, egui::Theme)) {
+        for theme in [egui::Theme::Dark, egui::Theme::Light] {
+            self.egui_ctx.style_mut_of(theme, |egui_style| style(egui_style, theme));
+        }
+    }
 
-        self.used_textures = gui_context.used_textures;
+    /// Initialize the gui of newly created components, see [`GuiComponent::initialize_gui`].
+    pub fn initialize(&mut self, gfx: &mut GraphicsContext, image_context: &mut ImageContext, window_controller: &mut WindowController, components: &mut [&mut dyn GuiComponent]) {
+        self.gui_data.initialize(&self.egui_ctx, gfx, image_context, window_controller, components);
     }
 
-    pub fn context<'a>(&'a mut self, gfx: &'a mut GraphicsContext, image_context: &'a mut ImageContext) -> GuiContext<'a> {
+    pub fn context<'a>(&'a mut self, gfx: &'a mut GraphicsContext, image_context: &'a mut ImageContext, window_controller: &'a mut WindowController) -> GuiContext<'a> {
         GuiContext {
             gui_data: &mut self.gui_data,
             gfx,
             images: image_context,
+            window: window_controller,
             used_textures: vec![]
         }
     }
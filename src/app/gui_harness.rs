@@ -0,0 +1,206 @@
+use ash::vk;
+use egui::{ClippedPrimitive, Context, Event, FullOutput, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2};
+use crate::app::gui::{GuiComponent, GuiData};
+use crate::app::WindowController;
+use crate::graphics::{GpuContext, GraphicsContext, ImageContext};
+
+/// Output of a frame run by a [`GuiHarness`].
+pub struct GuiFrame {
+    pub output: FullOutput,
+    /// The draw data of the frame, as the renderer would draw it.
+    pub primitives: Vec<ClippedPrimitive>,
+}
+
+/// Runs [`GuiComponent`]s headless, without a window, for automated tests.
+///
+/// Synthetic input is queued with [`pointer_move`](Self::pointer_move), [`click`](Self::click),
+/// [`key`](Self::key) and [`text`](Self::text) and fed to the components by the next [`run`](Self::run).
+/// Widgets react to input on their position of the previous frame, so run a frame before clicking them.
+///
+/// ```ignore
+/// let mut harness = GuiHarness::new(Vec2::new(800.0, 600.0));
+/// harness.run(&mut [&mut component]);
+/// harness.click(component.button_rect.center());
+/// harness.run(&mut [&mut component]);
+/// assert_eq!(component.clicks, 1);
+/// ```
+pub struct GuiHarness {
+    egui_ctx: Context,
+    input: RawInput,
+    time: f64,
+    window: WindowController,
+    // Dropped before the device
+    gui_data: GuiData,
+    images: ImageContext,
+    gpu: GpuContext,
+}
+
+impl GuiHarness {
+
+    /// Harness with a screen of `size` points, on a device without a surface.
+    pub fn new(size: Vec2) -> Self {
+        let gpu = GpuContext::headless(&[]);
+        let gui_data = GuiData::new(&gpu.gfx, vk::Format::B8G8R8A8_UNORM, 1);
+        let images = ImageContext::new(0);
+
+        let egui_ctx = Context::default();
+        egui_extras::install_image_loaders(&egui_ctx);
+
+        let mut harness = Self {
+            egui_ctx,
+            input: RawInput::default(),
+            time: 0.0,
+            window: WindowController::default(),
+            gui_data,
+            images,
+            gpu,
+        };
+        harness.set_screen_size(size);
+        harness
+    }
+
+    pub fn set_screen_size(&mut self, size: Vec2) {
+        self.input.screen_rect = Some(Rect::from_min_size(Pos2::ZERO, size));
+    }
+
+    /// Queue an event for the next frame.
+    pub fn event(&mut self, event: Event) {
+        self.input.events.push(event);
+    }
+
+    pub fn pointer_move(&mut self, pos: Pos2) {
+        self.event(Event::PointerMoved(pos));
+    }
+
+    pub fn pointer_button(&mut self, pos: Pos2, button: PointerButton, pressed: bool) {
+        self.event(Event::PointerButton { pos, button, pressed, modifiers: self.input.modifiers });
+    }
+
+    /// Move the pointer to `pos`, then press and release the primary button.
+    pub fn click(&mut self, pos: Pos2) {
+        self.pointer_move(pos);
+        self.pointer_button(pos, PointerButton::Primary, true);
+        self.pointer_button(pos, PointerButton::Primary, false);
+    }
+
+    /// Press and release a key.
+    pub fn key(&mut self, key: Key) {
+        for pressed in [true, false] {
+            self.event(Event::Key { key, physical_key: None, pressed, repeat: false, modifiers: self.input.modifiers });
+        }
+    }
+
+    /// Type text, e.g. into the focused text field.
+    pub fn text(&mut self, text: &str) {
+        self.event(Event::Text(text.to_string()));
+    }
+
+    /// Modifiers held for the following events and frames.
+    pub fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.input.modifiers = modifiers;
+    }
+
+    /// Initialize the gui of the components, as the engine does once after creating them.
+    pub fn initialize(&mut self, components: &mut [&mut dyn GuiComponent]) {
+        self.gui_data.initialize(&self.egui_ctx, &mut self.gpu.gfx, &mut self.images, &mut self.window, components);
+    }
+
+    /// Run the components for one frame with the queued input. Frames are 1/60 seconds apart.
+    pub fn run(&mut self, components: &mut [&mut dyn GuiComponent]) -> GuiFrame {
+        let dt = 1.0 / 60.0;
+        self.time += dt as f64;
+
+        let mut raw_input = self.input.take();
+        raw_input.time = Some(self.time);
+        raw_input.predicted_dt = dt;
+
+        // The harness doesn't draw, referenced textures only have to outlive the frame
+        let (output, _used_textures) = self.gui_data.run(
+            &self.egui_ctx, raw_input, &mut self.gpu.gfx, &mut self.images, &mut self.window, components
+        );
+        self.images.cleanup();
+
+        let primitives = self.egui_ctx.tessellate(output.shapes.clone(), output.pixels_per_point);
+        GuiFrame { output, primitives }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.egui_ctx
+    }
+
+    pub fn gfx(&mut self) -> &mut GraphicsContext {
+        &mut self.gpu.gfx
+    }
+
+    pub fn images(&mut self) -> &mut ImageContext {
+        &mut self.images
+    }
+
+    /// Window changes the components requested. They are not applied without a window.
+    pub fn window(&self) -> &WindowController {
+        &self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::TextureId;
+    use crate::app::gui::GuiContext;
+    use crate::app::{ImageFlags, ImageResource};
+    use crate::vulkan::{Format, ImageConfig, ImageUsage};
+
+    #[derive(Default)]
+    struct Form {
+        clicks: u32,
+        name: String,
+        button: Rect,
+        field: Rect,
+        icon: Option<(ImageResource, TextureId)>,
+    }
+
+    impl GuiComponent for Form {
+        fn initialize_gui(&mut self, gui: &mut GuiContext, _ctx: &Context) {
+            let mut image = gui.create_image(ImageConfig::new(16, 16, Format::Rgba8Unorm, ImageUsage::SAMPLED), ImageFlags::empty());
+            let texture = gui.get_texture(&mut image);
+            self.icon = Some((image, texture));
+        }
+
+        fn gui(&mut self, gui: &mut GuiContext, ctx: &Context) {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let button = ui.button("Fullscreen");
+                if button.clicked() {
+                    self.clicks += 1;
+                    gui.window.toggle_fullscreen();
+                }
+                self.button = button.rect;
+                self.field = ui.text_edit_singleline(&mut self.name).rect;
+            });
+        }
+    }
+
+    #[test]
+    fn click_and_type() {
+        let mut harness = GuiHarness::new(Vec2::new(320.0, 240.0));
+        let mut form = Form::default();
+        harness.initialize(&mut [&mut form]);
+        assert!(form.icon.is_some());
+        assert_eq!(harness.gui_data.textures.len(), 1);
+
+        let frame = harness.run(&mut [&mut form]);
+        assert!(!frame.primitives.is_empty());
+
+        harness.click(form.button.center());
+        harness.run(&mut [&mut form]);
+        assert_eq!(form.clicks, 1);
+        assert!(harness.window().is_fullscreen());
+
+        harness.click(form.field.center());
+        harness.run(&mut [&mut form]);
+        harness.text("cen");
+        harness.key(Key::Backspace);
+        harness.run(&mut [&mut form]);
+        assert_eq!(form.name, "ce");
+        assert_eq!(form.clicks, 1);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashSet};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use crate::app::gamepad::{Gamepad, GamepadButton, GamepadEvent, GamepadId};
+
+/// Pixels per line for converting pixel scroll deltas to lines.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Scroll distance in lines.
+fn scroll_lines(delta: &MouseScrollDelta) -> (f32, f32) {
+    match delta {
+        MouseScrollDelta::LineDelta(x, y) => (*x, *y),
+        MouseScrollDelta::PixelDelta(position) => {
+            (position.x as f32 / PIXELS_PER_LINE, position.y as f32 / PIXELS_PER_LINE)
+        }
+    }
+}
+
+/// Keyboard, mouse and gamepad state, updated from the window events before each frame.
+///
+/// Keys are identified by their physical location, so WASD controls work on any keyboard layout. Gamepads are
+/// polled with the `gamepad` feature.
+/// "Pressed this frame" and delta values cover the events since the previous frame.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    keys: HashSet<KeyCode>,
+    keys_pressed: HashSet<KeyCode>,
+    keys_released: HashSet<KeyCode>,
+    mouse_buttons: HashSet<MouseButton>,
+    mouse_buttons_pressed: HashSet<MouseButton>,
+    mouse_position: Option<PhysicalPosition<f64>>,
+    mouse_delta: (f64, f64),
+    scroll_delta: (f32, f32),
+    modifiers: ModifiersState,
+    gamepads: BTreeMap<GamepadId, Gamepad>,
+}
+
+impl InputState {
+
+    /// Whether the key is held down.
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    /// Whether the key went down since the previous frame.
+    pub fn was_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    /// Whether the key was released since the previous frame.
+    pub fn was_key_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains(&button)
+    }
+
+    /// Whether the mouse button went down since the previous frame.
+    pub fn was_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_pressed.contains(&button)
+    }
+
+    /// Cursor position in physical pixels, `None` while the cursor is outside the window.
+    pub fn mouse_position(&self) -> Option<PhysicalPosition<f64>> {
+        self.mouse_position
+    }
+
+    /// Cursor movement in physical pixels since the previous frame.
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    /// Scroll distance in lines since the previous frame.
+    pub fn scroll_delta(&self) -> (f32, f32) {
+        self.scroll_delta
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn gamepad(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(&id)
+    }
+
+    /// Connected gamepads, ordered by id.
+    pub fn gamepads(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.gamepads.iter().map(|(id, gamepad)| (*id, gamepad))
+    }
+
+    pub(crate) fn handle_gamepad_event(&mut self, event: &GamepadEvent) {
+        match event {
+            GamepadEvent::Connected { id, name } => {
+                self.gamepads.insert(*id, Gamepad::new(name.clone()));
+            }
+            GamepadEvent::Disconnected(id) => {
+                self.gamepads.remove(id);
+            }
+            GamepadEvent::ButtonPressed(id, _) | GamepadEvent::ButtonReleased(id, _) | GamepadEvent::AxisChanged(id, _, _) => {
+                if let Some(gamepad) = self.gamepads.get_mut(id) {
+                    gamepad.handle_event(event);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(key) = event.physical_key else {
+                    return;
+                };
+                match event.state {
+                    ElementState::Pressed => {
+                        // Ignore key repeats
+                        if self.keys.insert(key) {
+                            self.keys_pressed.insert(key);
+                        }
+                    }
+                    ElementState::Released => {
+                        self.keys.remove(&key);
+                        self.keys_released.insert(key);
+                    }
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => {
+                        self.mouse_buttons.insert(*button);
+                        self.mouse_buttons_pressed.insert(*button);
+                    }
+                    ElementState::Released => {
+                        self.mouse_buttons.remove(button);
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(previous) = self.mouse_position {
+                    self.mouse_delta.0 += position.x - previous.x;
+                    self.mouse_delta.1 += position.y - previous.y;
+                }
+                self.mouse_position = Some(*position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.mouse_position = None;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = scroll_lines(delta);
+                self.scroll_delta.0 += x;
+                self.scroll_delta.1 += y;
+            }
+            WindowEvent::Focused(false) => {
+                // Releases that happen while unfocused are never delivered
+                self.keys.clear();
+                self.mouse_buttons.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Reset the per-frame state once a frame has been rendered.
+    pub(crate) fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.mouse_buttons_pressed.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = (0.0, 0.0);
+        self.gamepads.values_mut().for_each(Gamepad::end_frame);
+    }
+}
+
+/// Callbacks for input events. All methods do nothing by default. Components opt in with
+/// [`AppComponent::input_component`](crate::app::app::AppComponent::input_component).
+/// The current [`InputState`] is also available from the [`CenContext`](crate::app::engine::CenContext).
+pub trait InputComponent {
+    fn key_pressed(&mut self, _key: KeyCode, _input: &InputState) {}
+    fn key_released(&mut self, _key: KeyCode, _input: &InputState) {}
+    fn mouse_button_pressed(&mut self, _button: MouseButton, _input: &InputState) {}
+    fn mouse_button_released(&mut self, _button: MouseButton, _input: &InputState) {}
+    fn mouse_moved(&mut self, _position: PhysicalPosition<f64>, _input: &InputState) {}
+    fn scrolled(&mut self, _delta: (f32, f32), _input: &InputState) {}
+    fn gamepad_connected(&mut self, _id: GamepadId, _input: &InputState) {}
+    fn gamepad_disconnected(&mut self, _id: GamepadId, _input: &InputState) {}
+    fn gamepad_button_pressed(&mut self, _id: GamepadId, _button: GamepadButton, _input: &InputState) {}
+    fn gamepad_button_released(&mut self, _id: GamepadId, _button: GamepadButton, _input: &InputState) {}
+}
+
+/// Forward a window event to the callbacks of an input component, after the state has been updated.
+pub(crate) fn dispatch_input_event(component: &mut dyn InputComponent, input: &InputState, event: &WindowEvent) {
+    match event {
+        WindowEvent::KeyboardInput { event, .. } => {
+            if let PhysicalKey::Code(key) = event.physical_key {
+                match event.state {
+                    ElementState::Pressed if !event.repeat => component.key_pressed(key, input),
+                    ElementState::Released => component.key_released(key, input),
+                    _ => {}
+                }
+            }
+        }
+        WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => component.mouse_button_pressed(*button, input),
+        WindowEvent::MouseInput { state: ElementState::Released, button, .. } => component.mouse_button_released(*button, input),
+        WindowEvent::CursorMoved { position, .. } => component.mouse_moved(*position, input),
+        WindowEvent::MouseWheel { delta, .. } => {
+            component.scrolled(scroll_lines(delta), input);
+        }
+        _ => {}
+    }
+}
+
+/// Forward a gamepad event to the callbacks of an input component, after the state has been updated.
+pub(crate) fn dispatch_gamepad_event(component: &mut dyn InputComponent, input: &InputState, event: &GamepadEvent) {
+    match event {
+        GamepadEvent::Connected { id, .. } => component.gamepad_connected(*id, input),
+        GamepadEvent::Disconnected(id) => component.gamepad_disconnected(*id, input),
+        GamepadEvent::ButtonPressed(id, button) => component.gamepad_button_pressed(*id, *button, input),
+        GamepadEvent::ButtonReleased(id, button) => component.gamepad_button_released(*id, *button, input),
+        GamepadEvent::AxisChanged(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_state_is_tracked_per_frame() {
+        let mut input = InputState::default();
+        input.handle_event(&WindowEvent::MouseWheel {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            delta: MouseScrollDelta::LineDelta(0.0, 2.0),
+            phase: winit::event::TouchPhase::Moved,
+        });
+        input.handle_event(&WindowEvent::MouseInput {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+        });
+        assert_eq!(input.scroll_delta(), (0.0, 2.0));
+        assert!(input.is_mouse_button_down(MouseButton::Left));
+        assert!(input.was_mouse_button_pressed(MouseButton::Left));
+
+        input.end_frame();
+        assert_eq!(input.scroll_delta(), (0.0, 0.0));
+        assert!(input.is_mouse_button_down(MouseButton::Left));
+        assert!(!input.was_mouse_button_pressed(MouseButton::Left));
+    }
+
+    #[test]
+    fn gamepad_state_is_tracked_per_frame() {
+        use crate::app::gamepad::GamepadAxis;
+
+        let mut input = InputState::default();
+        let id = GamepadId(0);
+        input.handle_gamepad_event(&GamepadEvent::Connected { id, name: "Pad".to_string() });
+        input.handle_gamepad_event(&GamepadEvent::ButtonPressed(id, GamepadButton::South));
+        input.handle_gamepad_event(&GamepadEvent::AxisChanged(id, GamepadAxis::LeftStickX, 0.5));
+        // Events of gamepads that aren't connected are dropped
+        input.handle_gamepad_event(&GamepadEvent::ButtonPressed(GamepadId(1), GamepadButton::South));
+
+        let gamepad = input.gamepad(id).unwrap();
+        assert_eq!(gamepad.name(), "Pad");
+        assert!(gamepad.was_button_pressed(GamepadButton::South));
+        assert_eq!(gamepad.left_stick(), (0.5, 0.0));
+        assert_eq!(input.gamepads().count(), 1);
+
+        input.end_frame();
+        let gamepad = input.gamepad(id).unwrap();
+        assert!(gamepad.is_button_down(GamepadButton::South));
+        assert!(!gamepad.was_button_pressed(GamepadButton::South));
+
+        input.handle_gamepad_event(&GamepadEvent::Disconnected(id));
+        assert!(input.gamepad(id).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+use crate::vulkan::{LOG_TARGET, OBJECTS_LOG_TARGET, SWAPCHAIN_LOG_TARGET};
+
+/// Subsystems of cen whose log output can be filtered separately, see
+/// [`AppConfig::log_level`](crate::app::app::AppConfig::log_level).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LogSubsystem {
+    /// Creation and destruction traces of Vulkan objects, by far the most verbose output.
+    Objects,
+    /// Device setup, validation messages and shader compilation, without the other Vulkan subsystems.
+    Vulkan,
+    /// Swapchain creation and recreation.
+    Swapchain,
+    /// Pipeline recreation and shader hot reloading of the pipeline store.
+    Pipelines,
+    Gui,
+}
+
+impl LogSubsystem {
+    /// The log target of the subsystem, e.g. for filters of a custom logger.
+    pub fn target(&self) -> &'static str {
+        match self {
+            LogSubsystem::Objects => OBJECTS_LOG_TARGET,
+            LogSubsystem::Vulkan => LOG_TARGET,
+            LogSubsystem::Swapchain => SWAPCHAIN_LOG_TARGET,
+            LogSubsystem::Pipelines => "cen::graphics::pipeline_store",
+            LogSubsystem::Gui => "cen::app::gui",
+        }
+    }
+}
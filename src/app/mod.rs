@@ -1,12 +1,28 @@
 pub mod app;
 pub mod window;
 pub mod gui;
+pub mod gui_harness;
 pub mod engine;
+pub mod update;
+pub mod input;
+pub mod gamepad;
+pub mod splash;
+pub mod logging;
+pub mod power;
+pub mod components;
 mod image_resource;
 
 pub use self::app::Cen;
-pub use self::window::Window;
+pub use self::window::{Window, WindowController, WindowOptions};
 pub use self::gui::TextureKey;
+pub use self::gui_harness::{GuiFrame, GuiHarness};
+pub use self::update::UpdateComponent;
+pub use self::input::{InputComponent, InputState};
+pub use self::gamepad::{Gamepad, GamepadAxis, GamepadButton, GamepadEvent, GamepadId};
+pub use self::splash::{LoadingProgress, Splash};
+pub use self::logging::LogSubsystem;
+pub use self::power::{ActiveHours, PowerMode};
+pub use self::components::{ComponentRegistry, ComponentToggle};
 pub use self::image_resource::ImageFlags;
 pub use self::image_resource::ImageResource;
 pub(crate) use self::image_resource::WeakImageResource;
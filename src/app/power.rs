@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// How much of the machine the app uses, see
+/// [`WindowController::set_power_mode`](crate::app::WindowController::set_power_mode) and
+/// [`AppConfig::active_hours`](crate::app::app::AppConfig::active_hours).
+///
+/// Only applies to the continuous redraw mode, see [`RedrawMode`](crate::app::app::RedrawMode).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PowerMode {
+    /// Update and draw as fast as presenting allows.
+    #[default]
+    Active,
+    /// Update and draw at most `frame_rate` times per second.
+    LowPower { frame_rate: f64 },
+    /// Neither update nor draw, while the process and its GPU resources stay alive to resume instantly. The window
+    /// keeps showing the last frame, and input still reaches the components. User input draws a single frame, so the
+    /// gui and the components can resume with
+    /// [`WindowController::set_power_mode`](crate::app::WindowController::set_power_mode).
+    Paused,
+}
+
+/// Daily hours in which the app runs, for installations that stay on around the clock. Outside of them the app
+/// drops to the `idle` power mode.
+///
+/// ```ignore
+/// // Open from 10:00 to 18:00 in UTC+1, paused overnight
+/// AppConfig::default().active_hours(ActiveHours::new(10, 18).utc_offset(1));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActiveHours {
+    /// Start of the active hours, since midnight. Hours that wrap past midnight start after they end.
+    pub start: Duration,
+    pub end: Duration,
+    /// Offset of the local time to UTC, in seconds.
+    pub utc_offset: i64,
+    /// Power mode outside of the active hours.
+    pub idle: PowerMode,
+}
+
+impl ActiveHours {
+    /// Active from `start_hour` until `end_hour` in UTC, paused otherwise.
+    pub fn new(start_hour: u32, end_hour: u32) -> Self {
+        Self {
+            start: Duration::from_secs(start_hour as u64 * 60 * 60),
+            end: Duration::from_secs(end_hour as u64 * 60 * 60),
+            utc_offset: 0,
+            idle: PowerMode::Paused,
+        }
+    }
+
+    /// Offset of the local time to UTC in hours, e.g. `1` for CET.
+    pub fn utc_offset(mut self, hours: i64) -> Self {
+        self.utc_offset = hours * 60 * 60;
+        self
+    }
+
+    pub fn idle(mut self, idle: PowerMode) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    // Local time since midnight, in seconds
+    fn time_of_day(&self, time: SystemTime) -> u64 {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        (since_epoch + self.utc_offset).rem_euclid(DAY as i64) as u64
+    }
+
+    pub fn is_active(&self, time: SystemTime) -> bool {
+        let (start, end, now) = (self.start.as_secs() % DAY, self.end.as_secs() % DAY, self.time_of_day(time));
+        if start <= end {
+            (start..end).contains(&now)
+        } else {
+            now >= start || now < end
+        }
+    }
+
+    /// Power mode at `time`.
+    pub fn mode_at(&self, time: SystemTime) -> PowerMode {
+        if self.is_active(time) { PowerMode::Active } else { self.idle }
+    }
+
+    /// Time from `time` until the active hours start or end.
+    pub fn next_change(&self, time: SystemTime) -> Duration {
+        let now = self.time_of_day(time);
+        let until = |boundary: Duration| match (boundary.as_secs() % DAY + DAY - now) % DAY {
+            0 => DAY,
+            seconds => seconds,
+        };
+        Duration::from_secs(until(self.start).min(until(self.end)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u64, minute: u64) -> SystemTime {
+        // Some day well after the epoch
+        UNIX_EPOCH + Duration::from_secs(20_000 * DAY + hour * 60 * 60 + minute * 60)
+    }
+
+    #[test]
+    fn active_hours() {
+        let hours = ActiveHours::new(10, 18);
+        assert!(!hours.is_active(at(9, 59)));
+        assert!(hours.is_active(at(10, 0)));
+        assert!(!hours.is_active(at(18, 0)));
+        assert_eq!(hours.mode_at(at(3, 0)), PowerMode::Paused);
+        assert_eq!(hours.next_change(at(9, 30)), Duration::from_secs(30 * 60));
+        assert_eq!(hours.next_change(at(18, 0)), Duration::from_secs(16 * 60 * 60));
+
+        // Past midnight
+        let night = ActiveHours::new(20, 2).idle(PowerMode::LowPower { frame_rate: 1.0 });
+        assert!(night.is_active(at(23, 0)) && night.is_active(at(1, 0)));
+        assert_eq!(night.mode_at(at(12, 0)), PowerMode::LowPower { frame_rate: 1.0 });
+
+        // 10:00 in UTC+2 is 8:00 in UTC
+        let local = ActiveHours::new(10, 18).utc_offset(2);
+        assert!(local.is_active(at(8, 0)));
+        assert!(!local.is_active(at(16, 30)));
+    }
+}
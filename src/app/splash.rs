@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use ash::vk;
+use egui::Context;
+use crate::app::engine::CenContext;
+use crate::app::gui::{GuiComponent, GuiContext};
+use crate::app::{ImageFlags, ImageResource};
+use crate::graphics::renderer::RenderComponent;
+use crate::vulkan::{ImageConfig, ImageTrait};
+
+/// What the engine shows while the components are loading, see [`AppConfig::splash`](crate::app::app::AppConfig::splash).
+#[derive(Clone, Debug)]
+pub enum Splash {
+    /// Fill the window with a linear RGBA color.
+    Color([f32; 4]),
+    /// Show tightly packed RGBA8 sRGB pixels, scaled to fit the window, on a linear RGBA background color.
+    Image {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        background: [f32; 4],
+    },
+}
+
+impl Default for Splash {
+    fn default() -> Self {
+        Splash::Color([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+#[derive(Default)]
+struct LoadingState {
+    tracked: AtomicBool,
+    fraction: AtomicU32,
+}
+
+/// Loading progress of the components, shared with e.g. background loading threads.
+///
+/// Components are rendered right after they were created, unless they report progress during creation.
+/// Once progress is reported, the engine shows the splash with a progress bar until it reaches 1.
+#[derive(Clone, Default)]
+pub struct LoadingProgress {
+    state: Arc<LoadingState>,
+}
+
+impl LoadingProgress {
+
+    /// Report progress in the range [0, 1].
+    pub fn set(&self, fraction: f32) {
+        self.state.fraction.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+        self.state.tracked.store(true, Ordering::Release);
+    }
+
+    /// Report that loading is complete.
+    pub fn finish(&self) {
+        self.set(1.0);
+    }
+
+    /// Reported progress, `None` if nothing was reported.
+    pub fn fraction(&self) -> Option<f32> {
+        self.state.tracked.load(Ordering::Acquire)
+            .then(|| f32::from_bits(self.state.fraction.load(Ordering::Relaxed)))
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.fraction().is_none_or(|fraction| fraction >= 1.0)
+    }
+}
+
+/// Destination region of an image of `source` extent, scaled to fit and centered in `target`.
+fn fit_region(source: vk::Extent2D, target: vk::Extent2D) -> [vk::Offset3D; 2] {
+    let scale = (target.width as f32 / source.width as f32).min(target.height as f32 / source.height as f32);
+    let width = (source.width as f32 * scale).round() as i32;
+    let height = (source.height as f32 * scale).round() as i32;
+    let x = (target.width as i32 - width) / 2;
+    let y = (target.height as i32 - height) / 2;
+    [
+        vk::Offset3D { x, y, z: 0 },
+        vk::Offset3D { x: x + width, y: y + height, z: 1 },
+    ]
+}
+
+/// Renders the splash and the loading progress in place of the components.
+pub(crate) struct SplashScreen {
+    splash: Splash,
+    progress: LoadingProgress,
+    image: Option<ImageResource>,
+}
+
+impl SplashScreen {
+    pub(crate) fn new(splash: Splash, progress: LoadingProgress) -> Self {
+        Self {
+            splash,
+            progress,
+            image: None,
+        }
+    }
+}
+
+impl RenderComponent for SplashScreen {
+    fn render(&mut self, ctx: &mut CenContext) {
+        let Some(swapchain_image) = ctx.swapchain_image else {
+            return;
+        };
+
+        let background = match &self.splash {
+            Splash::Color(color) => *color,
+            Splash::Image { width, height, pixels, background } => {
+                if self.image.is_none() {
+                    let image = ctx.create_image(
+                        ImageConfig {
+                            extent: vk::Extent3D { width: *width, height: *height, depth: 1 },
+                            format: vk::Format::R8G8B8A8_SRGB,
+                            image_usage_flags: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+                            ..Default::default()
+                        },
+                        ImageFlags::empty()
+                    );
+                    ctx.upload_image(&image, pixels);
+                    self.image = Some(image);
+                }
+                *background
+            }
+        };
+
+        ctx.command_buffer.transition(swapchain_image, vk::ImageLayout::PRESENT_SRC_KHR, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        ctx.command_buffer.clear_color_image(swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, background);
+
+        if let Some(image) = &self.image {
+            let image = ctx.images.get(image);
+            let subresource = vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(1);
+
+            ctx.command_buffer.transition(image, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            ctx.command_buffer.blit_image(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageBlit::default()
+                    .src_offsets([
+                        vk::Offset3D::default(),
+                        vk::Offset3D { x: image.width() as i32, y: image.height() as i32, z: 1 },
+                    ])
+                    .dst_offsets(fit_region(image.extent(), swapchain_image.extent()))
+                    .src_subresource(subresource)
+                    .dst_subresource(subresource)],
+                vk::Filter::LINEAR,
+            );
+            ctx.command_buffer.transition(image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        }
+
+        ctx.command_buffer.transition(swapchain_image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+}
+
+impl GuiComponent for SplashScreen {
+    fn gui(&mut self, _: &mut GuiContext, context: &Context) {
+        let Some(fraction) = self.progress.fraction() else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("cen_loading_progress"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -48.0])
+            .show(context, |ui| {
+                ui.add(egui::ProgressBar::new(fraction).desired_width(320.0).show_percentage());
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_progress() {
+        let progress = LoadingProgress::default();
+        assert!(progress.is_ready());
+        assert_eq!(progress.fraction(), None);
+
+        let shared = progress.clone();
+        shared.set(0.5);
+        assert!(!progress.is_ready());
+        assert_eq!(progress.fraction(), Some(0.5));
+
+        shared.finish();
+        assert!(progress.is_ready());
+    }
+
+    #[test]
+    fn splash_image_fits_window() {
+        let extent = |width, height| vk::Extent2D { width, height };
+        assert_eq!(
+            fit_region(extent(100, 50), extent(400, 400)),
+            [vk::Offset3D { x: 0, y: 100, z: 0 }, vk::Offset3D { x: 400, y: 300, z: 1 }]
+        );
+        assert_eq!(
+            fit_region(extent(100, 100), extent(400, 200)),
+            [vk::Offset3D { x: 100, y: 0, z: 0 }, vk::Offset3D { x: 300, y: 200, z: 1 }]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+/// Simulation logic that runs decoupled from rendering, see [`AppConfig::fixed_timestep`](crate::app::app::AppConfig::fixed_timestep).
+/// Apps opt in with [`AppComponent::update_component`](crate::app::app::AppComponent::update_component).
+pub trait UpdateComponent {
+    /// Advance the simulation by `dt`.
+    fn update(&mut self, _dt: Duration) {}
+}
+
+/// Maximum number of fixed steps per frame, so a slow frame doesn't snowball into ever more steps.
+const MAX_STEPS: u32 = 8;
+
+/// Decides how often and with which timestep to update.
+pub(crate) struct UpdateLoop {
+    fixed_timestep: Option<Duration>,
+    last_update: Option<Instant>,
+    accumulator: Duration,
+}
+
+impl UpdateLoop {
+    pub(crate) fn new(fixed_timestep: Option<Duration>) -> Self {
+        Self {
+            fixed_timestep,
+            last_update: None,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// Timesteps of the updates to run at `now`.
+    pub(crate) fn tick(&mut self, now: Instant) -> Vec<Duration> {
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_update = Some(now);
+
+        let Some(step) = self.fixed_timestep else {
+            return vec![elapsed];
+        };
+
+        self.accumulator += elapsed;
+        let steps = (self.accumulator.as_nanos() / step.as_nanos().max(1)).min(MAX_STEPS as u128) as u32;
+        self.accumulator = self.accumulator.saturating_sub(step * steps);
+
+        // Drop the time that couldn't be caught up with
+        if steps == MAX_STEPS {
+            self.accumulator = self.accumulator.min(step);
+        }
+
+        vec![step; steps as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variable_timestep() {
+        let start = Instant::now();
+        let mut update_loop = UpdateLoop::new(None);
+        assert_eq!(update_loop.tick(start), vec![Duration::ZERO]);
+        assert_eq!(update_loop.tick(start + Duration::from_millis(16)), vec![Duration::from_millis(16)]);
+    }
+
+    #[test]
+    fn fixed_timestep() {
+        let start = Instant::now();
+        let step = Duration::from_millis(10);
+        let mut update_loop = UpdateLoop::new(Some(step));
+        assert!(update_loop.tick(start).is_empty());
+        assert_eq!(update_loop.tick(start + Duration::from_millis(25)), vec![step; 2]);
+        // The remaining 5ms carry over
+        assert_eq!(update_loop.tick(start + Duration::from_millis(30)), vec![step]);
+        // Long stalls are capped
+        assert_eq!(update_loop.tick(start + Duration::from_secs(10)).len(), MAX_STEPS as usize);
+        assert!(update_loop.tick(start + Duration::from_secs(10)).len() <= 1);
+    }
+}
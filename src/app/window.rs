@@ -1,38 +1,301 @@
 use ash::vk::Extent2D;
+use egui::ThemePreference;
+use log::warn;
 use winit::event::WindowEvent;
 use winit::event::{ElementState, KeyEvent};
 use winit::event_loop::{ActiveEventLoop};
 use winit::keyboard::{Key, NamedKey};
 use winit::raw_window_handle::{DisplayHandle, HasDisplayHandle, HasWindowHandle, WindowHandle};
-use winit::window::WindowAttributes;
+use winit::window::{CursorGrabMode, Fullscreen, Icon, Theme, WindowAttributes, WindowLevel};
+use crate::app::power::PowerMode;
 
 pub struct WindowInner {
 }
 
+/// Attributes a window is created with, see [`Window::create`].
+/// Sizes and positions are in logical pixels.
+#[derive(Clone, Debug)]
+pub struct WindowOptions {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub resizable: bool,
+    /// Title bar and borders.
+    pub decorations: bool,
+    pub always_on_top: bool,
+    pub min_size: Option<(u32, u32)>,
+    pub max_size: Option<(u32, u32)>,
+    /// Position of the top left corner of the window, picked by the platform when `None`.
+    pub position: Option<(i32, i32)>,
+    /// Let the desktop show through where the swapchain image has an alpha below one.
+    /// Requires a surface that supports alpha compositing, otherwise the window stays opaque.
+    pub transparent: bool,
+    pub icon: Option<Icon>,
+    /// Theme of the title bar and the gui, following the OS preference by default.
+    pub theme: ThemePreference,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            title: "cen".to_string(),
+            width: 1000,
+            height: 1000,
+            fullscreen: false,
+            resizable: false,
+            decorations: true,
+            always_on_top: false,
+            min_size: None,
+            max_size: None,
+            position: None,
+            transparent: false,
+            icon: None,
+            theme: ThemePreference::System,
+        }
+    }
+}
+
+enum WindowCommand {
+    Fullscreen(bool),
+    Title(String),
+    CursorVisible(bool),
+    CursorGrab(CursorGrabMode),
+    Theme(ThemePreference),
+}
+
+/// Changes components request to their window, see [`CenContext::window`](crate::app::engine::CenContext::window).
+/// They are applied by the engine once the current callback returned.
+pub struct WindowController {
+    commands: Vec<WindowCommand>,
+    fullscreen: bool,
+    cursor_visible: bool,
+    cursor_grab: CursorGrabMode,
+    theme: ThemePreference,
+    exit_requested: bool,
+    redraw_requested: bool,
+    power_mode: Option<PowerMode>,
+}
+
+impl Default for WindowController {
+    fn default() -> Self {
+        Self {
+            commands: Vec::new(),
+            fullscreen: false,
+            cursor_visible: true,
+            cursor_grab: CursorGrabMode::None,
+            theme: ThemePreference::System,
+            exit_requested: false,
+            redraw_requested: false,
+            power_mode: None,
+        }
+    }
+}
+
+impl WindowController {
+
+    /// Whether the window is fullscreen, including requested changes that weren't applied yet.
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    /// Switch between windowed and borderless fullscreen, see [`Window::set_fullscreen`].
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+        self.commands.push(WindowCommand::Fullscreen(fullscreen));
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.set_fullscreen(!self.fullscreen);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.commands.push(WindowCommand::Title(title.to_string()));
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Show or hide the cursor while it is over the window.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        self.commands.push(WindowCommand::CursorVisible(visible));
+    }
+
+    pub fn cursor_grab(&self) -> CursorGrabMode {
+        self.cursor_grab
+    }
+
+    /// Confine the cursor to the window or lock it in place, e.g. for mouse look. Platforms that only support
+    /// one of the two modes fall back to the other.
+    pub fn set_cursor_grab(&mut self, mode: CursorGrabMode) {
+        self.cursor_grab = mode;
+        self.commands.push(WindowCommand::CursorGrab(mode));
+    }
+
+    pub fn theme(&self) -> ThemePreference {
+        self.theme
+    }
+
+    /// Switch the title bar and the gui to a dark or light theme, or follow the OS preference again.
+    pub fn set_theme(&mut self, theme: ThemePreference) {
+        self.theme = theme;
+        self.commands.push(WindowCommand::Theme(theme));
+    }
+
+    /// Close the window, which exits the app for the main window.
+    pub fn request_exit(&mut self) {
+        self.exit_requested = true;
+    }
+
+    /// Draw another frame with [`RedrawMode::OnEvent`](crate::app::app::RedrawMode::OnEvent), e.g. while an
+    /// animation or a background task is running. Frames are drawn continuously otherwise.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    /// The power mode set with [`set_power_mode`](Self::set_power_mode), `None` while following the active hours.
+    pub fn power_mode(&self) -> Option<PowerMode> {
+        self.power_mode
+    }
+
+    /// Drop to a low frame rate or pause, e.g. when nobody is in front of an installation, or resume with
+    /// [`PowerMode::Active`]. Overrides the [active hours](crate::app::app::AppConfig::active_hours) until
+    /// [`follow_active_hours`](Self::follow_active_hours) is called. Only the controller of the main window
+    /// changes the mode, which applies to all windows.
+    pub fn set_power_mode(&mut self, power_mode: PowerMode) {
+        self.power_mode = Some(power_mode);
+    }
+
+    pub fn follow_active_hours(&mut self) {
+        self.power_mode = None;
+    }
+
+    /// Apply the requested changes to the window.
+    pub(crate) fn apply(&mut self, window: &Window) {
+        let winit_window = window.winit_window();
+        for command in self.commands.drain(..) {
+            match command {
+                WindowCommand::Fullscreen(fullscreen) => window.set_fullscreen(fullscreen),
+                WindowCommand::Title(title) => winit_window.set_title(&title),
+                WindowCommand::CursorVisible(visible) => winit_window.set_cursor_visible(visible),
+                WindowCommand::CursorGrab(mode) => {
+                    let fallback = match mode {
+                        CursorGrabMode::Locked => CursorGrabMode::Confined,
+                        CursorGrabMode::Confined => CursorGrabMode::Locked,
+                        CursorGrabMode::None => CursorGrabMode::None,
+                    };
+                    if let Err(e) = winit_window.set_cursor_grab(mode).or_else(|_| winit_window.set_cursor_grab(fallback)) {
+                        warn!("Failed to set the cursor grab mode to {:?}: {}", mode, e);
+                    }
+                }
+                WindowCommand::Theme(theme) => winit_window.set_theme(window_theme(theme)),
+            }
+        }
+        // The fullscreen hotkey changes the window directly
+        self.fullscreen = window.is_fullscreen();
+    }
+
+    /// Whether [`request_exit`](Self::request_exit) was called since the last time this was checked.
+    pub(crate) fn take_exit_request(&mut self) -> bool {
+        std::mem::take(&mut self.exit_requested)
+    }
+
+    /// Whether [`request_redraw`](Self::request_redraw) was called since the last time this was checked.
+    pub(crate) fn take_redraw_request(&mut self) -> bool {
+        std::mem::take(&mut self.redraw_requested)
+    }
+}
+
+/// Theme of a winit window, `None` follows the OS.
+fn window_theme(theme: ThemePreference) -> Option<Theme> {
+    match theme {
+        ThemePreference::Dark => Some(Theme::Dark),
+        ThemePreference::Light => Some(Theme::Light),
+        ThemePreference::System => None,
+    }
+}
+
 /// System window wrapper.
 /// Handles window events i.e. close, redraw, keyboard input.
 pub struct Window {
     window: winit::window::Window,
+    fullscreen_hotkey: Option<NamedKey>,
+    exit_on_escape: bool,
+    transparent: bool,
 }
 
 impl Window {
-    pub fn create(event_loop: &ActiveEventLoop, window_title: &str, width: u32, height: u32, fullscreen: bool, resizable: bool) -> Window {
+    pub fn create(event_loop: &ActiveEventLoop, options: &WindowOptions) -> Window {
         let mut attributes = WindowAttributes::default()
-            .with_title(window_title)
-            .with_resizable(resizable)
-            .with_inner_size(winit::dpi::LogicalSize::new(width, height));
+            .with_title(&options.title)
+            .with_resizable(options.resizable)
+            .with_inner_size(winit::dpi::LogicalSize::new(options.width, options.height))
+            .with_decorations(options.decorations)
+            .with_transparent(options.transparent)
+            .with_window_icon(options.icon.clone());
 
-        if fullscreen {
-            attributes = attributes.with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+        if options.fullscreen {
+            attributes = attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        attributes = attributes.with_theme(window_theme(options.theme));
+        if options.always_on_top {
+            attributes = attributes.with_window_level(WindowLevel::AlwaysOnTop);
+        }
+        if let Some((width, height)) = options.min_size {
+            attributes = attributes.with_min_inner_size(winit::dpi::LogicalSize::new(width, height));
+        }
+        if let Some((width, height)) = options.max_size {
+            attributes = attributes.with_max_inner_size(winit::dpi::LogicalSize::new(width, height));
+        }
+        if let Some((x, y)) = options.position {
+            attributes = attributes.with_position(winit::dpi::LogicalPosition::new(x, y));
         }
 
         let window = event_loop.create_window(attributes).expect("Failed to create window");
 
         Window {
             window,
+            fullscreen_hotkey: None,
+            exit_on_escape: true,
+            transparent: options.transparent,
         }
     }
 
+    /// Whether the window was created with [`WindowOptions::transparent`].
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    /// Key that toggles fullscreen when pressed, disabled when `None`.
+    pub fn set_fullscreen_hotkey(&mut self, key: Option<NamedKey>) {
+        self.fullscreen_hotkey = key;
+    }
+
+    /// Close the app when Escape is pressed.
+    pub fn set_exit_on_escape(&mut self, exit_on_escape: bool) {
+        self.exit_on_escape = exit_on_escape;
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.window.fullscreen().is_some()
+    }
+
+    /// Switch between windowed and borderless fullscreen on the current monitor.
+    /// The swapchain follows through the resulting resize event.
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        if fullscreen == self.is_fullscreen() {
+            return;
+        }
+        let mode = if fullscreen { Some(Fullscreen::Borderless(self.window.current_monitor())) } else { None };
+        self.window.set_fullscreen(mode);
+    }
+
+    pub fn toggle_fullscreen(&self) {
+        self.set_fullscreen(!self.is_fullscreen());
+    }
+
     pub fn winit_window(&self) -> &winit::window::Window {
         &self.window
     }
@@ -55,6 +318,13 @@ impl Window {
         self.window.scale_factor()
     }
 
+    /// Refresh rate in Hz of the monitor the window currently resides on.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.window.current_monitor()
+            .and_then(|monitor| monitor.refresh_rate_millihertz())
+            .map(|millihertz| millihertz as f64 / 1000.0)
+    }
+
     pub fn window_event(&mut self, event: WindowEvent, event_loop: &ActiveEventLoop) {
         match event {
             WindowEvent::CloseRequested => {
@@ -68,8 +338,10 @@ impl Window {
                         ..
                     },
                 ..
-            } => if let Key::Named(NamedKey::Escape) = key.as_ref() {
-                event_loop.exit();
+            } => match key.as_ref() {
+                Key::Named(NamedKey::Escape) if self.exit_on_escape => event_loop.exit(),
+                Key::Named(named) if Some(named) == self.fullscreen_hotkey => self.toggle_fullscreen(),
+                _ => {}
             },
             _ => {}
         }
@@ -0,0 +1,310 @@
+//! Headless GPU benchmarks.
+//!
+//! A [`Bench`] owns its own device without a window. It records a workload many times on fixed-size resources,
+//! measures each submission with timestamp queries and collects the timings into a [`BenchReport`], which can be
+//! written as JSON to track shader performance across changes.
+//!
+//! ```ignore
+//! use cen::bench::{Bench, BenchConfig};
+//!
+//! let mut bench = Bench::new(BenchConfig::default());
+//! let pipeline = ComputePipeline::new(bench.device(), config)?;
+//! let image = bench.create_image(ImageConfig { .. });
+//! bench.run_compute("blur 1080p", &pipeline, [1920, 1080, 1], |command_buffer, pipeline| {
+//!     command_buffer.bind_push_descriptor_image(pipeline, 0, &image);
+//! });
+//! bench.report().write_json("target/bench.json")?;
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::warn;
+use crate::graphics::context::GraphicsContext;
+use crate::graphics::gpu::GpuContext;
+use crate::vulkan::event_log::json_string;
+use crate::vulkan::{Buffer, CommandBuffer, ComputePipeline, Device, DevicePlugin, Image, ImageConfig, QueryPool};
+
+/// How often a workload is run.
+#[derive(Clone, Copy, Debug)]
+pub struct BenchConfig {
+    /// Runs before measuring, to warm up caches and clocks.
+    pub warmup: u32,
+    /// Measured runs.
+    pub iterations: u32,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            warmup: 5,
+            iterations: 100,
+        }
+    }
+}
+
+/// Summary of the GPU time of a workload's runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BenchStats {
+    pub mean: Duration,
+    pub median: Duration,
+    pub stddev: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl BenchStats {
+    /// Statistics of the samples, zero without any.
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let count = sorted.len();
+        let median = if count % 2 == 0 {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2
+        } else {
+            sorted[count / 2]
+        };
+
+        let seconds: Vec<f64> = sorted.iter().map(Duration::as_secs_f64).collect();
+        let mean = seconds.iter().sum::<f64>() / count as f64;
+        let variance = seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count as f64;
+
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            median,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            min: sorted[0],
+            max: sorted[count - 1],
+        }
+    }
+}
+
+/// Measured runs of a named workload.
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub name: String,
+    /// GPU time of every measured run, in submission order.
+    pub samples: Vec<Duration>,
+    pub stats: BenchStats,
+}
+
+impl BenchResult {
+    pub fn new(name: &str, samples: Vec<Duration>) -> Self {
+        Self {
+            name: name.to_string(),
+            stats: BenchStats::from_samples(&samples),
+            samples,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let ms = |duration: Duration| format!("{:.6}", duration.as_secs_f64() * 1000.0);
+        format!(
+            r#"{{"name":{},"iterations":{},"mean_ms":{},"median_ms":{},"stddev_ms":{},"min_ms":{},"max_ms":{}}}"#,
+            json_string(&self.name),
+            self.samples.len(),
+            ms(self.stats.mean),
+            ms(self.stats.median),
+            ms(self.stats.stddev),
+            ms(self.stats.min),
+            ms(self.stats.max),
+        )
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{}: {:.3?} mean, {:.3?} median, {:.3?} stddev, {:.3?} min, {:.3?} max over {} runs",
+            self.name, self.stats.mean, self.stats.median, self.stats.stddev, self.stats.min, self.stats.max, self.samples.len()
+        )
+    }
+}
+
+/// Results of a [`Bench`] together with the device they were measured on.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub device: String,
+    pub driver: String,
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    pub fn to_json(&self) -> String {
+        let results: Vec<String> = self.results.iter().map(BenchResult::to_json).collect();
+        format!(
+            r#"{{"device":{},"driver":{},"results":[{}]}}"#,
+            json_string(&self.device),
+            json_string(&self.driver),
+            results.join(","),
+        )
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.device, self.driver)?;
+        for result in &self.results {
+            write!(f, "\n{}", result)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs GPU workloads on a device without a window and times them, see the [module documentation](self).
+pub struct Bench {
+    config: BenchConfig,
+    results: Vec<BenchResult>,
+    gpu: GpuContext,
+}
+
+impl Bench {
+    pub fn new(config: BenchConfig) -> Self {
+        Self::with_plugins(config, &[])
+    }
+
+    /// Bench on a device with the extensions and features of `plugins`, see [`DevicePlugin`].
+    pub fn with_plugins(config: BenchConfig, plugins: &[Box<dyn DevicePlugin>]) -> Self {
+        Self {
+            config,
+            results: Vec::new(),
+            gpu: GpuContext::headless(plugins),
+        }
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.gpu.gfx.device
+    }
+
+    /// The device, queue and allocators the workloads run with, e.g. to upload input data with
+    /// [`run_immediate`](GraphicsContext::run_immediate).
+    pub fn gfx(&mut self) -> &mut GraphicsContext {
+        &mut self.gpu.gfx
+    }
+
+    pub fn create_buffer(&mut self, location: MemoryLocation, size: vk::DeviceSize, usage: vk::BufferUsageFlags) -> Buffer {
+        Buffer::new(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, location, size, usage)
+    }
+
+    pub fn create_image(&mut self, config: ImageConfig) -> Image {
+        Image::new(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, config)
+    }
+
+    /// Time the commands `record` records. Every run is submitted and waited on separately.
+    /// No samples are collected when the device doesn't support timestamps.
+    pub fn run(&mut self, name: &str, mut record: impl FnMut(&mut CommandBuffer)) -> &BenchResult {
+        let limits = self.gpu.gfx.device.limits();
+        let mut samples = Vec::with_capacity(self.config.iterations as usize);
+        if limits.timestamp_compute_and_graphics != vk::TRUE {
+            warn!("Timestamp queries are not supported, benchmark {} isn't measured", name);
+        } else {
+            let query_pool = QueryPool::new_timestamp(&self.gpu.gfx.device, 2);
+            for iteration in 0..self.config.warmup + self.config.iterations {
+                self.gpu.gfx.run_immediate(|command_buffer| {
+                    command_buffer.reset_query_pool(&query_pool, 0, 2);
+                    command_buffer.write_timestamp(&query_pool, vk::PipelineStageFlags::TOP_OF_PIPE, 0);
+                    record(command_buffer);
+                    command_buffer.write_timestamp(&query_pool, vk::PipelineStageFlags::BOTTOM_OF_PIPE, 1);
+                });
+
+                if iteration < self.config.warmup {
+                    continue;
+                }
+                match query_pool.results(0, 2) {
+                    Some(timestamps) => {
+                        let nanoseconds = timestamps[1].saturating_sub(timestamps[0]) as f64 * limits.timestamp_period as f64;
+                        samples.push(Duration::from_nanos(nanoseconds as u64));
+                    }
+                    None => warn!("Timestamps of benchmark {} are not available", name),
+                }
+            }
+        }
+
+        self.results.push(BenchResult::new(name, samples));
+        self.results.last().unwrap()
+    }
+
+    /// Time a compute pipeline dispatched over `extent` invocations, see [`CommandBuffer::dispatch_extent`].
+    /// `bind` binds its descriptor sets and push constants.
+    pub fn run_compute(
+        &mut self,
+        name: &str,
+        pipeline: &ComputePipeline,
+        extent: [u32; 3],
+        mut bind: impl FnMut(&mut CommandBuffer, &ComputePipeline)
+    ) -> &BenchResult {
+        self.run(name, |command_buffer| {
+            command_buffer.bind_pipeline(pipeline);
+            bind(command_buffer, pipeline);
+            command_buffer.dispatch_extent(pipeline, extent);
+        })
+    }
+
+    pub fn results(&self) -> &[BenchResult] {
+        &self.results
+    }
+
+    pub fn report(&self) -> BenchReport {
+        let info = self.gpu.gfx.device.driver_info();
+        BenchReport {
+            device: info.device_name.clone(),
+            driver: format!("{} {}", info.driver_name, info.driver_version),
+            results: self.results.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    #[test]
+    fn sample_statistics() {
+        let stats = BenchStats::from_samples(&[ms(4), ms(2), ms(6), ms(8)]);
+        assert_eq!(stats.mean, ms(5));
+        assert_eq!(stats.median, ms(5));
+        assert_eq!(stats.min, ms(2));
+        assert_eq!(stats.max, ms(8));
+        assert!((stats.stddev.as_secs_f64() - 5f64.sqrt() / 1000.0).abs() < 1e-8);
+
+        assert_eq!(BenchStats::from_samples(&[ms(3), ms(1), ms(2)]).median, ms(2));
+        assert_eq!(BenchStats::from_samples(&[]), BenchStats::default());
+    }
+
+    #[test]
+    fn report_json() {
+        let report = BenchReport {
+            device: "gpu \"0\"".to_string(),
+            driver: "driver 1.0.0".to_string(),
+            results: vec![BenchResult::new("blur", vec![ms(1), ms(3)])],
+        };
+        let json = report.to_json();
+        assert!(json.starts_with(r#"{"device":"gpu \"0\"","driver":"driver 1.0.0","results":[{"name":"blur","iterations":2,"#));
+        assert!(json.contains(r#""min_ms":1.000000,"max_ms":3.000000}]}"#));
+    }
+
+    #[test]
+    fn time_commands() {
+        let mut bench = Bench::new(BenchConfig { warmup: 1, iterations: 4 });
+        let buffer = bench.create_buffer(MemoryLocation::GpuOnly, 1024, vk::BufferUsageFlags::TRANSFER_DST);
+        let result = bench.run("fill", |command_buffer| command_buffer.fill_buffer(&buffer, 0, vk::WHOLE_SIZE, 0));
+        if bench.device().limits().timestamp_compute_and_graphics == vk::TRUE {
+            assert_eq!(result.samples.len(), 4);
+        }
+        assert_eq!(bench.report().results.len(), 1);
+    }
+}
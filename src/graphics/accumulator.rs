@@ -0,0 +1,220 @@
+use std::any::Any;
+use std::collections::HashMap;
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::{compile_shader_code, glsl_image_format, ComputePipeline, DescriptorSetLayout, Image, ImageConfig, ImageTrait};
+
+const ACCUMULATE_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 8, local_size_y = 8, local_size_z = 1 ) in;
+
+layout( binding = 0, SAMPLE_FORMAT ) uniform readonly image2D sample_image;
+layout( binding = 1, rgba32f ) uniform image2D accumulation_image;
+
+layout( push_constant ) uniform PushConstants {
+    uint sample_index;
+} pc;
+
+void main()
+{
+    ivec2 p = ivec2( gl_GlobalInvocationID.xy );
+    ivec2 size = imageSize( accumulation_image );
+    if( p.x >= size.x || p.y >= size.y )
+    {
+        return;
+    }
+
+    vec4 s = imageLoad( sample_image, p );
+    if( pc.sample_index == 0 )
+    {
+        imageStore( accumulation_image, p, s );
+        return;
+    }
+
+    // Running average of all samples
+    vec4 a = imageLoad( accumulation_image, p );
+    imageStore( accumulation_image, p, mix( a, s, 1.0f / float( pc.sample_index + 1 ) ) );
+}
+"#;
+
+/// Temporal accumulation of per-frame samples into a running average, as used by progressive path tracers.
+///
+/// Each [`accumulate`](Accumulator::accumulate) call blends a sample image into an `R32G32B32A32_SFLOAT`
+/// accumulation image. The average restarts whenever [`reset`](Accumulator::reset) is called,
+/// the watched state passed to [`reset_on_change`](Accumulator::reset_on_change) changes, or the image is resized.
+pub struct Accumulator {
+    image: ImageResource,
+    pipeline: ComputePipeline,
+    _layout: DescriptorSetLayout,
+    sample_count: u32,
+    max_samples: Option<u32>,
+    extent: vk::Extent2D,
+    state: Option<Box<dyn Any>>,
+}
+
+impl Accumulator {
+
+    /// Create an accumulator for samples of the given format, one of
+    /// `R8G8B8A8_UNORM`, `R16G16B16A16_SFLOAT` or `R32G32B32A32_SFLOAT`.
+    /// The accumulation image is created with `extent` and `flags`, e.g. [`ImageFlags::MATCH_SWAPCHAIN_EXTENT`].
+    pub fn new(ctx: &mut CenContext, sample_format: impl Into<vk::Format>, extent: vk::Extent2D, flags: ImageFlags) -> Self {
+        let sample_format = sample_format.into();
+        let image = ctx.create_image(
+            ImageConfig {
+                extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                image_usage_flags: vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                ..Default::default()
+            },
+            flags
+        );
+
+        let layout_bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &layout_bindings);
+
+        let sample_format = glsl_image_format(sample_format)
+            .unwrap_or_else(|| panic!("Unsupported accumulation sample format: {:?}", sample_format));
+        let macros = HashMap::from([("SAMPLE_FORMAT".to_string(), sample_format.to_string())]);
+        let code = compile_shader_code(ACCUMULATE_SHADER, "cen/accumulate.comp", shaderc::ShaderKind::Compute, &macros)
+            .expect("Failed to compile accumulation shader");
+        let pipeline = ComputePipeline::from_spirv(
+            &ctx.gfx.device,
+            code,
+            &[layout.clone()],
+            &[vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .size(std::mem::size_of::<u32>() as u32)]
+        );
+
+        Self {
+            image,
+            pipeline,
+            _layout: layout,
+            sample_count: 0,
+            max_samples: None,
+            extent: vk::Extent2D::default(),
+            state: None,
+        }
+    }
+
+    /// Stop accumulating once `max_samples` samples have been blended in.
+    pub fn set_max_samples(&mut self, max_samples: Option<u32>) {
+        self.max_samples = max_samples;
+    }
+
+    /// Restart the average at the next accumulated sample.
+    pub fn reset(&mut self) {
+        self.sample_count = 0;
+    }
+
+    /// Reset when `state` differs from the state passed in the previous call, e.g. camera parameters.
+    /// Returns whether the accumulation was reset.
+    pub fn reset_on_change<T: PartialEq + Clone + 'static>(&mut self, state: &T) -> bool {
+        let previous = self.state.as_ref().and_then(|previous| previous.downcast_ref::<T>());
+        let changed = previous != Some(state);
+        if changed {
+            self.state = Some(Box::new(state.clone()));
+            self.reset();
+        }
+        changed
+    }
+
+    /// Number of samples in the current average.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.max_samples.is_some_and(|max| self.sample_count >= max)
+    }
+
+    /// The accumulation image, kept in `ImageLayout::GENERAL`.
+    pub fn image(&self) -> &ImageResource {
+        &self.image
+    }
+
+    /// Blend a sample into the running average.
+    /// The sample image must be in `ImageLayout::GENERAL` and have the format passed at creation.
+    pub fn accumulate(&mut self, ctx: &mut CenContext, sample: &Image) {
+        let image = ctx.images.get(&self.image);
+
+        // Resized images start over
+        if image.width() != self.extent.width || image.height() != self.extent.height {
+            self.extent = image.extent();
+            self.reset();
+        }
+
+        if self.is_converged() {
+            return;
+        }
+
+        // A reset discards the previous contents
+        let old_layout = if self.sample_count == 0 { vk::ImageLayout::UNDEFINED } else { vk::ImageLayout::GENERAL };
+        ctx.command_buffer.image_barrier(
+            image,
+            old_layout,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+        ctx.command_buffer.image_barrier(
+            sample,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+
+        ctx.command_buffer.bind_pipeline(&self.pipeline);
+
+        ctx.command_buffer.track(sample);
+        ctx.command_buffer.track(image);
+        let sample_binding = [sample.binding(vk::ImageLayout::GENERAL)];
+        let accumulation_binding = [image.binding(vk::ImageLayout::GENERAL)];
+        ctx.command_buffer.push_descriptor_set(&self.pipeline, 0, &[
+            vk::WriteDescriptorSet::default()
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&sample_binding),
+            vk::WriteDescriptorSet::default()
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&accumulation_binding),
+        ]);
+        ctx.command_buffer.push_constants(&self.pipeline, vk::ShaderStageFlags::COMPUTE, 0, &self.sample_count.to_ne_bytes());
+        ctx.command_buffer.dispatch(self.extent.width.div_ceil(8), self.extent.height.div_ceil(8), 1);
+
+        // Make the result visible to whoever reads the accumulation image next
+        ctx.command_buffer.image_barrier(
+            image,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::MEMORY_READ,
+        );
+
+        self.sample_count += 1;
+    }
+}
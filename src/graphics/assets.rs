@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::error;
+use crate::app::engine::CenContext;
+use crate::app::splash::LoadingProgress;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::{Buffer, ImageConfig};
+
+enum AssetState<T> {
+    Loading,
+    Ready(T),
+    Failed(String),
+}
+
+/// Handle to an asset that is loaded in the background, see [`CenContext::load_image`] and [`CenContext::load_buffer`].
+pub struct Asset<T> {
+    state: Arc<Mutex<AssetState<T>>>,
+}
+
+impl<T> Clone for Asset<T> {
+    fn clone(&self) -> Self {
+        Self { state: self.state.clone() }
+    }
+}
+
+impl<T: Clone> Asset<T> {
+    fn new() -> Self {
+        Self { state: Arc::new(Mutex::new(AssetState::Loading)) }
+    }
+
+    fn set(&self, state: AssetState<T>) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    /// The asset, once it was uploaded and the upload has executed.
+    pub fn get(&self) -> Option<T> {
+        match &*self.state.lock().unwrap() {
+            AssetState::Ready(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        matches!(*self.state.lock().unwrap(), AssetState::Loading)
+    }
+
+    /// Error of the load function, if it failed.
+    pub fn error(&self) -> Option<String> {
+        match &*self.state.lock().unwrap() {
+            AssetState::Failed(error) => Some(error.clone()),
+            _ => None,
+        }
+    }
+}
+
+enum Target {
+    Image { config: ImageConfig, flags: ImageFlags, asset: Asset<ImageResource> },
+    Buffer { usage: vk::BufferUsageFlags, asset: Asset<Buffer> },
+}
+
+enum Completion {
+    Image(Asset<ImageResource>, ImageResource),
+    Buffer(Asset<Buffer>, Buffer),
+}
+
+type LoadResult = (u64, io::Result<Vec<u8>>);
+type LoadJob = (u64, Box<dyn FnOnce() -> io::Result<Vec<u8>> + Send>);
+
+/// Number of threads loading assets, many requests at once queue up on them.
+const WORKER_COUNT: usize = 4;
+
+/// Loads assets on a few background threads and uploads them in the frames after.
+/// The threads are started with the first request.
+///
+/// An asset becomes ready once the command buffer of its upload has executed. Loads requested while
+/// the components initialize keep the splash up until they are complete, see [`LoadingProgress`].
+pub struct AssetLoader {
+    sender: mpsc::Sender<LoadResult>,
+    receiver: mpsc::Receiver<LoadResult>,
+    jobs: mpsc::Sender<LoadJob>,
+    /// Shared by the workers, each takes the next job once it is done with its last one.
+    job_receiver: Arc<Mutex<mpsc::Receiver<LoadJob>>>,
+    workers: usize,
+    next_id: u64,
+    loading: HashMap<u64, Target>,
+    /// Uploads with the frame slot whose command buffer records them
+    uploads: Vec<(usize, Completion)>,
+    requested: usize,
+    completed: usize,
+    progress: LoadingProgress,
+}
+
+impl AssetLoader {
+    pub fn new(progress: LoadingProgress) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (jobs, job_receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            jobs,
+            job_receiver: Arc::new(Mutex::new(job_receiver)),
+            workers: 0,
+            next_id: 0,
+            loading: HashMap::new(),
+            uploads: Vec::new(),
+            requested: 0,
+            completed: 0,
+            progress,
+        }
+    }
+
+    /// Number of assets that are not ready or failed yet.
+    pub fn pending(&self) -> usize {
+        self.requested - self.completed
+    }
+
+    fn request(&mut self, target: Target, load: impl FnOnce() -> io::Result<Vec<u8>> + Send + 'static) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.loading.insert(id, target);
+
+        match self.spawn_workers() {
+            Ok(()) => self.jobs.send((id, Box::new(load))).unwrap(),
+            // Reported like a failed load when the uploads are recorded
+            Err(e) => self.sender.send((id, Err(e))).unwrap(),
+        }
+
+        self.requested += 1;
+        self.update_progress();
+    }
+
+    /// Start the missing workers. Fails if none is running.
+    fn spawn_workers(&mut self) -> io::Result<()> {
+        while self.workers < WORKER_COUNT {
+            let jobs = self.job_receiver.clone();
+            let results = self.sender.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("cen-asset-loader-{}", self.workers))
+                .spawn(move || load_assets(jobs, results));
+            match spawned {
+                Ok(_) => self.workers += 1,
+                // The loads queue up on the workers that are running
+                Err(_) if self.workers > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self) {
+        self.completed += 1;
+        self.update_progress();
+    }
+
+    fn update_progress(&self) {
+        if self.requested > 0 {
+            self.progress.set(self.completed as f32 / self.requested as f32);
+        }
+    }
+
+    /// The command buffer of the frame slot has executed, so the uploads it recorded are complete.
+    pub(crate) fn frame_completed(&mut self, frame_index: usize) {
+        let (done, uploads) = std::mem::take(&mut self.uploads)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(slot, _)| *slot == frame_index);
+        self.uploads = uploads;
+
+        for (_, completion) in done {
+            match completion {
+                Completion::Image(asset, image) => asset.set(AssetState::Ready(image)),
+                Completion::Buffer(asset, buffer) => asset.set(AssetState::Ready(buffer)),
+            }
+            self.complete();
+        }
+    }
+
+    /// Record the uploads of the assets whose loading finished into the command buffer of the frame slot.
+    pub(crate) fn record_uploads(ctx: &mut CenContext, frame_index: usize) {
+        let finished = ctx.assets.receiver.try_iter().collect::<Vec<_>>();
+        for (id, result) in finished {
+            let Some(target) = ctx.assets.loading.remove(&id) else {
+                continue;
+            };
+
+            let data = match result {
+                Ok(data) => data,
+                Err(e) => {
+                    error!("Failed to load asset: {}", e);
+                    match target {
+                        Target::Image { asset, .. } => asset.set(AssetState::Failed(e.to_string())),
+                        Target::Buffer { asset, .. } => asset.set(AssetState::Failed(e.to_string())),
+                    }
+                    ctx.assets.complete();
+                    continue;
+                }
+            };
+
+            let completion = match target {
+                Target::Image { config, flags, asset } => {
+                    let image = ctx.create_image(config, flags);
+                    ctx.upload_image(&image, &data);
+                    Completion::Image(asset, image)
+                }
+                Target::Buffer { usage, asset } => {
+                    let buffer = Buffer::new(
+                        &ctx.gfx.device,
+                        &mut ctx.gfx.allocator,
+                        MemoryLocation::GpuOnly,
+                        data.len().max(1) as vk::DeviceSize,
+                        usage
+                    );
+                    ctx.upload_buffer(&buffer, &data);
+                    Completion::Buffer(asset, buffer)
+                }
+            };
+            ctx.assets.uploads.push((frame_index, completion));
+        }
+    }
+}
+
+/// Run the jobs of the loader until it is dropped.
+fn load_assets(jobs: Arc<Mutex<mpsc::Receiver<LoadJob>>>, results: mpsc::Sender<LoadResult>) {
+    loop {
+        // The lock is only held while waiting for a job, so the loads run in parallel
+        let job = jobs.lock().unwrap().recv();
+        let Ok((id, load)) = job else {
+            return;
+        };
+        // The loader may be gone by now, e.g. when the app exits during loading
+        if results.send((id, load())).is_err() {
+            return;
+        }
+    }
+}
+
+impl CenContext<'_> {
+
+    /// Create an image from tightly packed texel data produced by `load` on a background thread, e.g. a decoded file.
+    /// The data is uploaded to the first mip level of all layers, the image ends up in `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn load_image(
+        &mut self,
+        mut config: ImageConfig,
+        flags: ImageFlags,
+        load: impl FnOnce() -> io::Result<Vec<u8>> + Send + 'static
+    ) -> Asset<ImageResource> {
+        config.image_usage_flags |= vk::ImageUsageFlags::TRANSFER_DST;
+        let asset = Asset::new();
+        self.assets.request(Target::Image { config, flags, asset: asset.clone() }, load);
+        asset
+    }
+
+    /// Create a `GpuOnly` buffer with the data produced by `load` on a background thread.
+    pub fn load_buffer(
+        &mut self,
+        usage: vk::BufferUsageFlags,
+        load: impl FnOnce() -> io::Result<Vec<u8>> + Send + 'static
+    ) -> Asset<Buffer> {
+        let asset = Asset::new();
+        self.assets.request(Target::Buffer { usage: usage | vk::BufferUsageFlags::TRANSFER_DST, asset: asset.clone() }, load);
+        asset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_on_worker_pool() {
+        let mut loader = AssetLoader::new(LoadingProgress::default());
+        for _ in 0..16 {
+            let target = Target::Buffer { usage: vk::BufferUsageFlags::STORAGE_BUFFER, asset: Asset::new() };
+            loader.request(target, || Ok(vec![1, 2, 3]));
+        }
+        assert_eq!(loader.workers, WORKER_COUNT);
+        assert_eq!(loader.pending(), 16);
+
+        let mut ids = (0..16)
+            .map(|_| loader.receiver.recv().unwrap())
+            .map(|(id, data)| {
+                assert_eq!(data.unwrap(), vec![1, 2, 3]);
+                id
+            })
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, (0..16).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,102 @@
+use ash::vk;
+use crate::vulkan::{CommandBuffer, CommandPool, Device};
+
+/// Command buffers for the dedicated compute queue, for work that can overlap with graphics work.
+///
+/// Compute work recorded during a frame is submitted right before the graphics work of that frame,
+/// and the graphics submission waits on it. Buffers and images that are used on both queues must be
+/// created concurrent, see [`QueueFamilies`](crate::vulkan::QueueFamilies), or be transferred between the queue
+/// families with [`CommandBuffer::release_image`] and [`CommandBuffer::acquire_image`].
+pub struct AsyncCompute {
+    device: Device,
+    queue: vk::Queue,
+    queue_family_index: u32,
+    command_buffers: Vec<CommandBuffer>,
+    semaphores: Vec<vk::Semaphore>,
+    frame_index: usize,
+    recording: bool,
+    // Dropped after the command buffers that were allocated from it
+    _command_pool: CommandPool,
+}
+
+impl AsyncCompute {
+
+    /// Returns `None` when the device has no dedicated compute queue.
+    pub fn new(device: &Device, frames_in_flight: usize) -> Option<Self> {
+        let queue = device.get_compute_queue()?;
+        let queue_family_index = device.compute_queue_family_index()?;
+
+        let command_pool = CommandPool::new(device, queue_family_index);
+        let command_buffers = (0..frames_in_flight)
+            .map(|_| CommandBuffer::new(device, &command_pool, true))
+            .collect();
+        let semaphores = (0..frames_in_flight)
+            .map(|_| device.create_semaphore())
+            .collect();
+
+        Some(Self {
+            device: device.clone(),
+            queue,
+            queue_family_index,
+            command_buffers,
+            semaphores,
+            frame_index: 0,
+            recording: false,
+            _command_pool: command_pool,
+        })
+    }
+
+    pub fn queue(&self) -> vk::Queue {
+        self.queue
+    }
+
+    pub fn queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    /// Command buffer for the compute work of the current frame. It is begun on first use.
+    pub fn command_buffer(&mut self) -> &mut CommandBuffer {
+        let command_buffer = &mut self.command_buffers[self.frame_index];
+        if !self.recording {
+            // The previous submission from this slot must have finished before re-recording
+            self.device.wait_for_fence(command_buffer.fence());
+            command_buffer.begin();
+            self.recording = true;
+        }
+        command_buffer
+    }
+
+    /// Whether compute work was recorded for the current frame.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub(crate) fn begin_frame(&mut self, frame_index: usize) {
+        self.frame_index = frame_index % self.command_buffers.len();
+    }
+
+    /// Submit the recorded work, returns the semaphore that is signaled once it has executed.
+    pub(crate) fn submit(&mut self) -> Option<vk::Semaphore> {
+        if !self.recording {
+            return None;
+        }
+        self.recording = false;
+
+        let command_buffer = &self.command_buffers[self.frame_index];
+        let semaphore = self.semaphores[self.frame_index];
+        command_buffer.end();
+        self.device.reset_fence(command_buffer.fence());
+        self.device.submit_command_buffer_with(&self.queue, &[], &[semaphore], command_buffer);
+
+        Some(semaphore)
+    }
+}
+
+impl Drop for AsyncCompute {
+    fn drop(&mut self) {
+        self.device.wait_idle();
+        for semaphore in &self.semaphores {
+            self.device.destroy_semaphore(*semaphore);
+        }
+    }
+}
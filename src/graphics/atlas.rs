@@ -0,0 +1,323 @@
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::ImageConfig;
+
+/// Region of an atlas in texels.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    pub fn offset(&self) -> vk::Offset2D {
+        vk::Offset2D { x: self.x as i32, y: self.y as i32 }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D { width: self.width, height: self.height }
+    }
+
+    /// Normalized texture coordinates of the region as `[min_u, min_v, max_u, max_v]`.
+    pub fn uv(&self, atlas_width: u32, atlas_height: u32) -> [f32; 4] {
+        let (width, height) = (atlas_width as f32, atlas_height as f32);
+        [
+            self.x as f32 / width,
+            self.y as f32 / height,
+            (self.x + self.width) as f32 / width,
+            (self.y + self.height) as f32 / height,
+        ]
+    }
+}
+
+/// Top edge of the packed area over a span of columns.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// Packs rectangles into a fixed area with the skyline bottom-left heuristic.
+///
+/// The packer only tracks the top edge of the packed area, so allocation is fast and needs little memory, at the
+/// cost of never reusing space below that edge. Rectangles can't be freed individually, [`clear`](Self::clear)
+/// starts over.
+#[derive(Clone, Debug)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    padding: u32,
+    skyline: Vec<Segment>,
+    used_area: u64,
+}
+
+impl SkylinePacker {
+    /// Packer for a `width`x`height` area, keeping `padding` texels free to the right and below every rectangle
+    /// so filtering doesn't bleed between neighbours.
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        Self {
+            width,
+            height,
+            padding,
+            skyline: vec![Segment { x: 0, y: 0, width }],
+            used_area: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Reserve a `width`x`height` region, or `None` when it doesn't fit anymore.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRect> {
+        if width == 0 || height == 0 {
+            return Some(AtlasRect { x: 0, y: 0, width, height });
+        }
+
+        let padded_width = width.checked_add(self.padding)?;
+        let padded_height = height.checked_add(self.padding)?;
+
+        // Lowest position, the leftmost one on ties
+        let mut best: Option<(usize, u32)> = None;
+        for index in 0..self.skyline.len() {
+            if let Some(y) = self.fit(index, padded_width, padded_height) {
+                if best.is_none_or(|(_, best_y)| y < best_y) {
+                    best = Some((index, y));
+                }
+            }
+        }
+        let (index, y) = best?;
+
+        let x = self.skyline[index].x;
+        self.insert(index, Segment { x, y: y + padded_height, width: padded_width });
+        self.used_area += padded_width as u64 * padded_height as u64;
+
+        Some(AtlasRect { x, y, width, height })
+    }
+
+    /// Forget all rectangles.
+    pub fn clear(&mut self) {
+        self.skyline = vec![Segment { x: 0, y: 0, width: self.width }];
+        self.used_area = 0;
+    }
+
+    /// Fraction of the area covered by rectangles and their padding.
+    pub fn occupancy(&self) -> f32 {
+        let area = self.width as u64 * self.height as u64;
+        if area == 0 {
+            return 0.0;
+        }
+        (self.used_area as f64 / area as f64) as f32
+    }
+
+    /// Height a rectangle placed at the start of the segment would be at, if it fits.
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].x;
+        if width > self.width - x {
+            return None;
+        }
+
+        let end = x + width;
+        let y = self.skyline[index..].iter()
+            .take_while(|segment| segment.x < end)
+            .map(|segment| segment.y)
+            .max()?;
+
+        (height <= self.height - y.min(self.height)).then_some(y)
+    }
+
+    fn insert(&mut self, index: usize, segment: Segment) {
+        self.skyline.insert(index, segment);
+
+        // Shrink or remove the segments the new one covers
+        let end = segment.x + segment.width;
+        let next = index + 1;
+        while next < self.skyline.len() {
+            let covered = &mut self.skyline[next];
+            if covered.x >= end {
+                break;
+            }
+            let overlap = end - covered.x;
+            if overlap < covered.width {
+                covered.x += overlap;
+                covered.width -= overlap;
+                break;
+            }
+            self.skyline.remove(next);
+        }
+
+        // Merge neighbours at the same height
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].width += self.skyline[i + 1].width;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// An image that many small textures, e.g. glyphs, sprites or lookup tiles, are packed into, so they can be
+/// sampled through a single binding. Create it with [`CenContext::create_atlas`] and fill it with
+/// [`CenContext::upload_to_atlas`].
+///
+/// The image is kept in `SHADER_READ_ONLY_OPTIMAL`, regions that weren't uploaded to are zero.
+pub struct Atlas {
+    image: ImageResource,
+    format: vk::Format,
+    packer: SkylinePacker,
+}
+
+impl Atlas {
+    pub fn image(&self) -> &ImageResource {
+        &self.image
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.packer.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.packer.height()
+    }
+
+    /// Reserve a region without uploading to it, e.g. to render into it.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<AtlasRect> {
+        self.packer.allocate(width, height)
+    }
+
+    /// Normalized texture coordinates of a region, see [`AtlasRect::uv`].
+    pub fn uv(&self, rect: &AtlasRect) -> [f32; 4] {
+        rect.uv(self.width(), self.height())
+    }
+
+    pub fn occupancy(&self) -> f32 {
+        self.packer.occupancy()
+    }
+
+    /// Forget all regions. The image contents are kept until they are overwritten.
+    pub fn clear(&mut self) {
+        self.packer.clear();
+    }
+}
+
+impl CenContext<'_> {
+
+    /// Create an empty atlas, see [`SkylinePacker::new`] for the padding.
+    /// The image is cleared by the current command buffer.
+    pub fn create_atlas(&mut self, width: u32, height: u32, format: impl Into<vk::Format>, padding: u32) -> Atlas {
+        let format = format.into();
+        let image = self.create_image(
+            ImageConfig {
+                extent: vk::Extent3D { width, height, depth: 1 },
+                format,
+                image_usage_flags: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                ..Default::default()
+            },
+            ImageFlags::empty()
+        );
+
+        let target = self.images.get(&image);
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        self.command_buffer.clear_color_image(target, vk::ImageLayout::TRANSFER_DST_OPTIMAL, [0.0; 4]);
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+
+        Atlas {
+            image,
+            format,
+            packer: SkylinePacker::new(width, height, padding),
+        }
+    }
+
+    /// Pack a `width`x`height` texture into the atlas and upload its tightly packed texel data.
+    /// Returns `None` when the atlas is full.
+    pub fn upload_to_atlas(&mut self, atlas: &mut Atlas, width: u32, height: u32, data: &[u8]) -> Option<AtlasRect> {
+        let rect = atlas.allocate(width, height)?;
+        if width > 0 && height > 0 {
+            self.upload_image_region(&atlas.image, rect.offset(), rect.extent(), data);
+        }
+        Some(rect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn pack_without_overlap() {
+        let mut packer = SkylinePacker::new(64, 64, 1);
+        let mut rects = Vec::new();
+        for i in 0..40 {
+            let (width, height) = (3 + i % 7, 2 + i % 5);
+            let rect = packer.allocate(width, height).expect("Atlas is full");
+            assert!(rect.x + rect.width <= 64 && rect.y + rect.height <= 64);
+            rects.push(rect);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        assert!(packer.occupancy() > 0.0 && packer.occupancy() <= 1.0);
+    }
+
+    #[test]
+    fn fill_and_clear() {
+        let mut packer = SkylinePacker::new(16, 16, 0);
+        for _ in 0..16 {
+            assert!(packer.allocate(4, 4).is_some());
+        }
+        assert_eq!(packer.occupancy(), 1.0);
+        assert_eq!(packer.allocate(1, 1), None);
+        assert_eq!(packer.allocate(17, 1), None);
+
+        packer.clear();
+        assert_eq!(packer.allocate(16, 16), Some(AtlasRect { x: 0, y: 0, width: 16, height: 16 }));
+    }
+
+    #[test]
+    fn place_lowest_first() {
+        let mut packer = SkylinePacker::new(16, 16, 0);
+        packer.allocate(8, 8);
+        packer.allocate(8, 2);
+        // Stacks on the short rectangle, rather than on top of the tall one
+        assert_eq!(packer.allocate(8, 4), Some(AtlasRect { x: 8, y: 2, width: 8, height: 4 }));
+        assert_eq!(packer.allocate(8, 8), Some(AtlasRect { x: 8, y: 6, width: 8, height: 8 }));
+        assert_eq!(packer.allocate(8, 8), Some(AtlasRect { x: 0, y: 8, width: 8, height: 8 }));
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+/// Animation clock of the renderer.
+/// The time is sampled once at the start of each frame, so all components see the same value.
+/// When quantization is enabled, the animation time is snapped to the refresh interval of the
+/// monitor the window resides on. This keeps time-based shaders smooth on 60/120/144 Hz displays.
+///
+/// An offline clock ignores the wall-clock time, every frame is exactly one frame interval after the previous one.
+pub struct AnimationClock {
+    start_time: Instant,
+    frame_time: Instant,
+    refresh_rate: Option<f64>,
+    quantize: bool,
+    /// Frame rate and number of frames of an offline clock.
+    offline: Option<(f64, u64)>,
+    /// Elapsed time at the start of the previous frame.
+    previous_elapsed: Option<Duration>,
+    delta_time: Duration,
+    frame_count: u64,
+}
+
+impl AnimationClock {
+    pub fn new(refresh_rate: Option<f64>, quantize: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            start_time: now,
+            frame_time: now,
+            refresh_rate,
+            quantize,
+            offline: None,
+            previous_elapsed: None,
+            delta_time: Duration::ZERO,
+            frame_count: 0,
+        }
+    }
+
+    /// Clock that advances by exactly `1 / frame_rate` seconds per frame, regardless of how long frames take.
+    pub fn offline(frame_rate: f64) -> Self {
+        assert!(frame_rate > 0.0, "The frame rate has to be positive");
+        Self {
+            offline: Some((frame_rate, 0)),
+            ..Self::new(Some(frame_rate), false)
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.is_some()
+    }
+
+    /// Sample the time for a new frame. Offline clocks only move with [`advance`](Self::advance).
+    pub(crate) fn tick(&mut self) {
+        if self.offline.is_none() {
+            self.frame_time = Instant::now();
+        }
+        let elapsed = self.elapsed();
+        self.delta_time = self.previous_elapsed.map_or(Duration::ZERO, |previous| elapsed.saturating_sub(previous));
+        self.previous_elapsed = Some(elapsed);
+        self.frame_count += 1;
+    }
+
+    /// Move an offline clock to the next frame.
+    pub(crate) fn advance(&mut self) {
+        if let Some((_, frames)) = &mut self.offline {
+            *frames += 1;
+            self.frame_time = self.start_time + self.elapsed();
+        }
+    }
+
+    /// Start of the current frame, on the virtual timeline for offline clocks.
+    pub fn frame_instant(&self) -> Instant {
+        self.frame_time
+    }
+
+    pub(crate) fn set_refresh_rate(&mut self, refresh_rate: Option<f64>) {
+        // The frame rate of an offline clock is fixed
+        if self.offline.is_none() {
+            self.refresh_rate = refresh_rate;
+        }
+    }
+
+    pub fn set_quantize(&mut self, quantize: bool) {
+        self.quantize = quantize;
+    }
+
+    /// Refresh rate of the current monitor in Hz, if the platform reports it.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.refresh_rate
+    }
+
+    /// Duration of a single refresh of the current monitor.
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_rate
+            .filter(|hz| *hz > 0.0)
+            .map(|hz| Duration::from_secs_f64(1.0 / hz))
+    }
+
+    /// Wall-clock time between the clock creation and the start of the current frame.
+    /// For offline clocks, the number of frames times the frame interval.
+    pub fn elapsed(&self) -> Duration {
+        match self.offline {
+            // Exact, instead of accumulating rounding errors
+            Some((frame_rate, frames)) => Duration::from_secs_f64(frames as f64 / frame_rate),
+            None => self.frame_time.duration_since(self.start_time),
+        }
+    }
+
+    /// Time between the starts of the previous and the current frame, zero for the first frame.
+    pub fn delta_time(&self) -> Duration {
+        self.delta_time
+    }
+
+    /// Number of frames started before the current one, increasing by one every frame.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_count.saturating_sub(1)
+    }
+
+    /// Time in seconds to drive animations with.
+    /// Snapped to the monitor refresh interval when quantization is enabled.
+    pub fn animation_time(&self) -> f64 {
+        let time = self.elapsed().as_secs_f64();
+        match self.refresh_rate {
+            Some(hz) if self.quantize => quantize_time(time, hz),
+            _ => time,
+        }
+    }
+}
+
+/// Snap a time in seconds to the nearest refresh interval of a display running at `refresh_rate` Hz.
+pub fn quantize_time(time: f64, refresh_rate: f64) -> f64 {
+    if refresh_rate <= 0.0 {
+        return time;
+    }
+    (time * refresh_rate).round() / refresh_rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantize_snaps_to_refresh_interval() {
+        let interval = 1.0 / 60.0;
+        assert!((quantize_time(interval * 10.4, 60.0) - interval * 10.0).abs() < 1e-9);
+        assert!((quantize_time(interval * 10.6, 60.0) - interval * 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn offline_clock_steps_exactly() {
+        let mut clock = AnimationClock::offline(60.0);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+        clock.tick();
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        for _ in 0..120 {
+            clock.advance();
+        }
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+        assert_eq!(clock.animation_time(), 2.0);
+        clock.set_refresh_rate(Some(144.0));
+        assert_eq!(clock.refresh_rate(), Some(60.0));
+    }
+
+    #[test]
+    fn delta_time_and_frame_index() {
+        let mut clock = AnimationClock::offline(50.0);
+        clock.tick();
+        assert_eq!((clock.frame_index(), clock.delta_time()), (0, Duration::ZERO));
+
+        clock.advance();
+        clock.tick();
+        assert_eq!((clock.frame_index(), clock.delta_time()), (1, Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn quantize_ignores_invalid_refresh_rate() {
+        assert_eq!(quantize_time(1.2345, 0.0), 1.2345);
+    }
+}
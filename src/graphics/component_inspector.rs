@@ -0,0 +1,35 @@
+use egui::Context;
+use crate::app::components::ComponentRegistry;
+use crate::app::gui::{GuiComponent, GuiContext};
+
+/// Debug window with a checkbox per registered component to disable it at runtime, see
+/// [`AppConfig::component_inspector`](crate::app::app::AppConfig::component_inspector) and [`ComponentRegistry`].
+pub struct ComponentInspector {
+    registry: ComponentRegistry,
+}
+
+impl ComponentInspector {
+    pub fn new(registry: ComponentRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+impl GuiComponent for ComponentInspector {
+    fn gui(&mut self, _: &mut GuiContext, context: &Context) {
+        egui::Window::new("Components")
+            .id(egui::Id::new("cen_component_inspector"))
+            .default_open(false)
+            .show(context, |ui| {
+                let toggles = self.registry.toggles();
+                if toggles.is_empty() {
+                    ui.label("No components registered");
+                }
+                for (name, toggle) in toggles {
+                    let mut enabled = toggle.is_enabled();
+                    if ui.checkbox(&mut enabled, name).changed() {
+                        toggle.set_enabled(enabled);
+                    }
+                }
+            });
+    }
+}
@@ -1,36 +1,174 @@
+use ash::vk;
 use ash::vk::Queue;
+use gpu_allocator::vulkan::AllocatorCreateDesc;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, Weak};
 use crate::app::{ImageFlags, ImageResource, WeakImageResource};
+use crate::app::engine::CenContext;
+use crate::graphics::history::HistoryData;
 use crate::graphics::image_store::ImageStore;
-use crate::graphics::pipeline_store::{IntoPipelineHandle, PipelineKey, PipelineStore};
-use crate::vulkan::{Allocator, CommandPool, Device, Image, ImageConfig, Pipeline, PipelineErr};
+use crate::graphics::pipeline_store::{IntoPipelineHandle, PipelineEntry, PipelineKey, PipelineNamespace, PipelineStore};
+use crate::vulkan::{is_float_sampleable, Allocator, Buffer, CommandBuffer, CommandBufferPool, CommandPool, DescriptorAllocator, Device, DevicePlugin, Image, ImageConfig, ImageError, Instance, Pipeline, PipelineErr, DEFAULT_POOL_RATIOS};
 
 pub struct GraphicsContext {
     pub command_pool: CommandPool,
+    /// Single-time command buffers of `command_pool` that are recycled after they completed.
+    pub command_buffers: CommandBufferPool,
     pub queue: Queue,
     pub allocator: Allocator,
+    pub descriptor_allocator: DescriptorAllocator,
     pub device: Device,
 }
 
+impl GraphicsContext {
+
+    /// Context on the best device for offscreen work, without a surface. The instance has to outlive it.
+    pub(crate) fn offscreen(instance: &Instance, plugins: &[Box<dyn DevicePlugin>]) -> Self {
+        let (physical_device, queue_family_index) = instance.create_physical_device_offscreen();
+        let device = Device::with_plugins(instance, physical_device, queue_family_index, plugins);
+        let queue = device.get_queue(0);
+        let command_pool = CommandPool::new(&device, queue_family_index);
+        let allocator = Allocator::new(
+            &device,
+            &AllocatorCreateDesc {
+                instance: instance.handle().clone(),
+                device: device.handle().clone(),
+                physical_device,
+                debug_settings: Default::default(),
+                buffer_device_address: device.inner.buffer_device_address,
+                allocation_sizes: Default::default(),
+            }
+        );
+        let descriptor_allocator = DescriptorAllocator::new(&device, DEFAULT_POOL_RATIOS);
+
+        Self {
+            device,
+            allocator,
+            descriptor_allocator,
+            queue,
+            command_pool,
+            command_buffers: CommandBufferPool::new(),
+        }
+    }
+
+    /// Record commands into an auxiliary command buffer, submit it on the graphics queue and wait for it to execute.
+    ///
+    /// The command buffer has its own fence. Resources it tracks stay alive until it has executed, so they can be
+    /// dropped inside `f`. Within a frame, the commands execute before the ones of the frame, which is submitted later.
+    pub fn run_immediate<R>(&mut self, f: impl FnOnce(&mut CommandBuffer) -> R) -> R {
+        let mut command_buffer = self.command_buffers.acquire(&self.device, &self.command_pool);
+        command_buffer.begin();
+        let result = f(&mut command_buffer);
+        command_buffer.end();
+        self.submit_and_wait(command_buffer);
+        result
+    }
+
+    /// Submit a recorded command buffer on the graphics queue and wait for it to execute.
+    /// Command buffers of the [`command_buffers`](Self::command_buffers) pool are recycled once nothing else references them.
+    pub fn submit_and_wait(&mut self, command_buffer: CommandBuffer) {
+        self.device.submit_single_time_command(self.queue, &command_buffer);
+        self.device.wait_for_fence(command_buffer.fence());
+
+        // Reuse the command buffer and its fence for the next single-time submission
+        if command_buffer.is_unique() {
+            self.command_buffers.recycle(&self.device, command_buffer);
+        }
+    }
+}
+
+impl Buffer {
+    /// Replace the buffer with one of `new_size` bytes, copying the start of its contents over in the frame's command
+    /// buffer with `preserve`. The old buffer is destroyed once the frames in flight executed, see
+    /// [`resized_with`](Self::resized_with).
+    ///
+    /// ```ignore
+    /// if self.points.size() < required {
+    ///     self.points = self.points.resized(ctx, required, true);
+    /// }
+    /// ```
+    pub fn resized(&self, ctx: &mut CenContext, new_size: vk::DeviceSize, preserve: bool) -> Buffer {
+        self.resized_with(&ctx.gfx.device, &mut ctx.gfx.allocator, ctx.command_buffer, new_size, preserve)
+    }
+}
+
 pub struct ImageContext {
     pub image_store: ImageStore,
     pub images: Vec<(WeakImageResource, ImageFlags)>,
+    pub(crate) histories: Vec<Weak<Mutex<HistoryData>>>,
+    /// Images registered for debugging by name, with the layout they're left in, see [`register`](Self::register).
+    pub(crate) registered: BTreeMap<String, (WeakImageResource, vk::ImageLayout)>,
+    /// Epoch of the current swapchain, images matching its extent are tied to it.
+    pub(crate) swapchain_epoch: u64,
 }
 
 impl ImageContext {
 
+    /// No images yet, images created with the swapchain extent are tied to the swapchain of `swapchain_epoch`.
+    pub(crate) fn new(swapchain_epoch: u64) -> Self {
+        Self {
+            image_store: ImageStore::new(),
+            images: Vec::new(),
+            histories: Vec::new(),
+            registered: BTreeMap::new(),
+            swapchain_epoch,
+        }
+    }
+
     pub fn create_image(&mut self, gfx: &mut GraphicsContext, config: ImageConfig, flags: ImageFlags) -> ImageResource {
-        let image_key = self.image_store.insert(Image::new(&gfx.device, &mut gfx.allocator, config));
+        self.try_create_image(gfx, config, flags)
+            .unwrap_or_else(|e| panic!("Failed to create image: {}", e))
+    }
+
+    /// Create an image, or fail when the device can't use its format with its usage, see [`Image::try_new`].
+    pub fn try_create_image(&mut self, gfx: &mut GraphicsContext, config: ImageConfig, flags: ImageFlags) -> Result<ImageResource, ImageError> {
+        let image = Image::try_new(&gfx.device, &mut gfx.allocator, config)?;
+        if flags.contains(ImageFlags::MATCH_SWAPCHAIN_EXTENT) {
+            image.set_swapchain_epoch(self.swapchain_epoch);
+        }
+        let image_key = self.image_store.insert(image);
         let resource = ImageResource::new(image_key);
         self.images.push((resource.downgrade(), flags));
-        resource
+        Ok(resource)
     }
 
     pub fn get(&self, resource: &ImageResource) -> &Image {
         self.image_store.get(&resource.image_key())
     }
 
+    /// Make an image available to debug tools by name, e.g. the [`ImageViewer`](crate::graphics::ImageViewer).
+    /// `layout` is the layout the image is in once the components rendered the frame. Registering a name again
+    /// replaces its image, and images are unregistered once dropped.
+    ///
+    /// Panics unless the image is a single sampled 2D color image of a format sampled as floats, e.g. not an
+    /// integer or depth format, or when `layout` is `UNDEFINED`.
+    pub fn register(&mut self, name: impl Into<String>, resource: &ImageResource, layout: vk::ImageLayout) {
+        let name = name.into();
+        let config = self.get(resource).config();
+        let format = config.view_format.unwrap_or(config.format);
+        assert_ne!(layout, vk::ImageLayout::UNDEFINED, "Image '{}' is registered in the UNDEFINED layout, its contents can't be read", name);
+        assert!(is_float_sampleable(format), "Image '{}' has format {:?}, debug tools can only show color formats sampled as floats", name, format);
+        assert_eq!(config.view_type(), vk::ImageViewType::TYPE_2D, "Image '{}' needs a 2D view without layers to be shown", name);
+        assert_eq!(config.samples, vk::SampleCountFlags::TYPE_1, "Image '{}' is multisampled, resolve it before registering", name);
+        self.registered.insert(name, (resource.downgrade(), layout));
+    }
+
+    /// Names of the registered images that are still alive, in order.
+    pub fn registered_names(&self) -> impl Iterator<Item = &str> {
+        self.registered.iter()
+            .filter(|(_, (resource, _))| resource.upgrade().is_some())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The image registered under `name`, with its layout.
+    pub fn registered(&self, name: &str) -> Option<(ImageResource, vk::ImageLayout)> {
+        let (resource, layout) = self.registered.get(name)?;
+        resource.upgrade().map(|resource| (resource, *layout))
+    }
+
     pub(crate) fn cleanup(&mut self) {
         self.images.retain(|(resource, _)| resource.upgrade().is_some());
+        self.registered.retain(|_, (resource, _)| resource.upgrade().is_some());
         self.image_store.cleanup();
     }
 }
@@ -47,15 +185,51 @@ impl PipelineContext {
     pub fn create_pipeline(&mut self, handle: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
         self.pipeline_store.insert(handle)
     }
+
+    /// Create a pipeline other components can look up by name, see [`PipelineStore::insert_named`].
+    pub fn create_named_pipeline(&mut self, name: impl Into<String>, handle: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.pipeline_store.insert_named(name, handle)
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<&dyn Pipeline> {
+        self.pipeline_store.get_named(name)
+    }
+
+    pub fn named_key(&self, name: &str) -> Option<PipelineKey> {
+        self.pipeline_store.named_key(name)
+    }
+
+    /// All loaded pipelines, e.g. for a debug ui, see [`PipelineStore::entries`].
+    pub fn entries(&self) -> impl Iterator<Item = PipelineEntry<'_>> {
+        self.pipeline_store.entries()
+    }
+
+    /// Remove a pipeline, see [`PipelineStore::remove`].
+    pub fn remove_pipeline(&mut self, key: PipelineKey) -> bool {
+        self.pipeline_store.remove(key)
+    }
+
+    pub fn create_namespace(&mut self) -> PipelineNamespace {
+        self.pipeline_store.create_namespace()
+    }
+
+    pub fn create_pipeline_in(&mut self, namespace: PipelineNamespace, handle: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.pipeline_store.insert_into(namespace, handle)
+    }
+
+    /// Remove all pipelines of the namespace, see [`PipelineStore::unload_namespace`].
+    pub fn unload_namespace(&mut self, namespace: PipelineNamespace) {
+        self.pipeline_store.unload_namespace(namespace)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use ash::Entry;
     use ash::vk;
-    use gpu_allocator::vulkan::AllocatorCreateDesc;
+    use gpu_allocator::MemoryLocation;
     use super::*;
-    use crate::vulkan::{CommandPool, Device, ImageTrait, Instance};
+    use crate::vulkan::{DescriptorSetLayout, DescriptorWriter, ImageTrait, TransientDescriptorAllocator};
 
     // PipelineContext is not tested here: PipelineStore::new requires a winit
     // EventLoopProxy, which needs a display connection unavailable in CI.
@@ -78,7 +252,8 @@ mod tests {
                 allocation_sizes: Default::default(),
             },
         );
-        let gfx = GraphicsContext { device, allocator, queue, command_pool };
+        let descriptor_allocator = DescriptorAllocator::new(&device, DEFAULT_POOL_RATIOS);
+        let gfx = GraphicsContext { device, allocator, descriptor_allocator, queue, command_pool, command_buffers: CommandBufferPool::new() };
         (entry, instance, physical_device, gfx)
     }
 
@@ -87,10 +262,68 @@ mod tests {
         let (_entry, _instance, _physical_device, _gfx) = make_graphics_context();
     }
 
+    #[test]
+    fn run_immediate_recycles_command_buffer() {
+        let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
+        let buffer = Buffer::new(&gfx.device, &mut gfx.allocator, MemoryLocation::GpuToCpu, 16, vk::BufferUsageFlags::TRANSFER_DST);
+
+        for value in [1, 2] {
+            gfx.run_immediate(|command_buffer| command_buffer.fill_buffer(&buffer, 0, 16, value));
+            assert_eq!(buffer.read_to_vec::<u32>().unwrap(), vec![value; 4]);
+        }
+        assert_eq!(gfx.command_buffers.free_count(), 1);
+        assert_eq!(gfx.command_buffers.recycled_count(), 2);
+        assert_eq!(gfx.device.object_stats().fences_in_flight, 0);
+    }
+
+    #[test]
+    fn resized_buffer_preserves_contents() {
+        let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
+        let buffer = Buffer::new(&gfx.device, &mut gfx.allocator, MemoryLocation::GpuToCpu, 16, vk::BufferUsageFlags::TRANSFER_SRC);
+        buffer.write_slice(0, &[1u32, 2, 3, 4]).unwrap();
+
+        let device = gfx.device.clone();
+        let mut allocator = gfx.allocator.clone();
+        let grown = gfx.run_immediate(|command_buffer| buffer.resized_with(&device, &mut allocator, command_buffer, 32, true));
+        assert_eq!(grown.size(), 32);
+        assert!(grown.usage().contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST));
+        assert_eq!(&grown.read_to_vec::<u32>().unwrap()[..4], &[1, 2, 3, 4]);
+
+        let shrunk = gfx.run_immediate(|command_buffer| grown.resized_with(&device, &mut allocator, command_buffer, 8, true));
+        assert_eq!(shrunk.read_to_vec::<u32>().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn transient_descriptors_grow_and_reset() {
+        let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
+        let buffer = Buffer::new(&gfx.device, &mut gfx.allocator, MemoryLocation::CpuToGpu, 16, vk::BufferUsageFlags::STORAGE_BUFFER);
+        let layout = DescriptorSetLayout::new(&gfx.device, &[vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)]);
+        let writer = DescriptorWriter::new().storage_buffer(0, &buffer);
+
+        let mut transient = TransientDescriptorAllocator::new(&gfx.device, DEFAULT_POOL_RATIOS);
+        for _ in 0..100 {
+            transient.allocate(&layout, &writer);
+        }
+        assert_eq!(transient.allocated_count(), 100);
+        assert_eq!(transient.pool_count(), 2);
+
+        // The pools are kept across resets
+        transient.reset();
+        for _ in 0..100 {
+            transient.allocate(&layout, &writer);
+        }
+        assert_eq!(transient.allocated_count(), 100);
+        assert_eq!(transient.pool_count(), 2);
+    }
+
     #[test]
     fn image_context_create_image() {
         let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
-        let mut image_ctx = ImageContext { image_store: ImageStore::new(), images: Vec::new() };
+        let mut image_ctx = ImageContext::new(0);
 
         let config = ImageConfig {
             extent: vk::Extent3D { width: 64, height: 64, depth: 1 },
@@ -107,7 +340,7 @@ mod tests {
     #[test]
     fn image_context_cleanup_drops_unreferenced_images() {
         let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
-        let mut image_ctx = ImageContext { image_store: ImageStore::new(), images: Vec::new() };
+        let mut image_ctx = ImageContext::new(0);
 
         let config = ImageConfig {
             extent: vk::Extent3D { width: 64, height: 64, depth: 1 },
@@ -122,4 +355,29 @@ mod tests {
         image_ctx.cleanup();
         assert_eq!(image_ctx.images.len(), 0);
     }
+
+    #[test]
+    fn history_images_swap_each_frame() {
+        let (_entry, _instance, _physical_device, mut gfx) = make_graphics_context();
+        let mut image_ctx = ImageContext::new(0);
+
+        let config = ImageConfig {
+            extent: vk::Extent3D { width: 64, height: 64, depth: 1 },
+            image_usage_flags: vk::ImageUsageFlags::STORAGE,
+            ..Default::default()
+        };
+
+        let history = image_ctx.create_history_image(&mut gfx, config, ImageFlags::empty());
+        let current = image_ctx.get(&history.current()).handle();
+        let previous = image_ctx.get(&history.previous()).handle();
+        assert_ne!(current, previous);
+
+        image_ctx.advance_histories();
+        assert_eq!(image_ctx.get(&history.current()).handle(), previous);
+        assert_eq!(image_ctx.get(&history.previous()).handle(), current);
+
+        drop(history);
+        image_ctx.advance_histories();
+        assert!(image_ctx.histories.is_empty());
+    }
 }
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::panic;
+use std::sync::{Arc, Mutex, Weak};
+use log::error;
+use crate::vulkan::{Allocator, AllocatorInner};
+
+const MAX_LABELS: usize = 16;
+
+#[derive(Default)]
+struct CrashStateInner {
+    frame: u64,
+    frame_index: usize,
+    image_index: Option<usize>,
+    // Frame id submitted on each command buffer slot that has not been waited on yet
+    in_flight: Vec<Option<u64>>,
+    labels: VecDeque<String>,
+}
+
+/// Snapshot of the renderer state, kept up to date each frame so it can be dumped when the app panics.
+#[derive(Clone, Default)]
+pub struct CrashState {
+    inner: Arc<Mutex<CrashStateInner>>,
+    allocator: Option<Allocator>,
+}
+
+impl CrashState {
+
+    pub fn new(allocator: &Allocator) -> Self {
+        Self {
+            inner: Default::default(),
+            allocator: Some(allocator.clone()),
+        }
+    }
+
+    pub(crate) fn begin_frame(&self, frame: u64, frame_index: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.frame = frame;
+        state.frame_index = frame_index;
+        state.image_index = None;
+        if state.in_flight.len() <= frame_index {
+            state.in_flight.resize(frame_index + 1, None);
+        }
+        // The fence of this slot has been waited on
+        state.in_flight[frame_index] = None;
+    }
+
+    pub(crate) fn acquired(&self, image_index: usize) {
+        self.inner.lock().unwrap().image_index = Some(image_index);
+    }
+
+    pub(crate) fn submitted(&self) {
+        let mut state = self.inner.lock().unwrap();
+        let (frame, frame_index) = (state.frame, state.frame_index);
+        state.in_flight[frame_index] = Some(frame);
+    }
+
+    /// Record a label of the work that is currently being done. The most recent labels are part of the dump.
+    pub fn label(&self, label: &str) {
+        let mut state = self.inner.lock().unwrap();
+        if state.labels.len() == MAX_LABELS {
+            state.labels.pop_front();
+        }
+        state.labels.push_back(label.to_string());
+    }
+
+    /// Human-readable dump of the state.
+    /// Never blocks: state that is locked by the panicking thread is reported as unavailable.
+    pub fn dump(&self) -> String {
+        let mut out = self.dump_frame();
+        let _ = writeln!(out, "allocator: {}", self.allocator_stats().unwrap_or_else(|| "unavailable".to_string()));
+        out
+    }
+
+    fn dump_frame(&self) -> String {
+        let mut out = String::new();
+
+        match self.inner.try_lock() {
+            Ok(state) => {
+                let _ = writeln!(out, "frame: {} (slot {}, swapchain image {:?})", state.frame, state.frame_index, state.image_index);
+                let in_flight = state.in_flight.iter()
+                    .enumerate()
+                    .filter_map(|(slot, frame)| frame.map(|f| format!("slot {}: frame {}", slot, f)))
+                    .collect::<Vec<_>>();
+                let _ = writeln!(out, "in-flight submissions: [{}]", in_flight.join(", "));
+                let _ = writeln!(out, "last labels: [{}]", state.labels.iter().cloned().collect::<Vec<_>>().join(" > "));
+            }
+            Err(_) => {
+                let _ = writeln!(out, "frame state: unavailable");
+            }
+        }
+        out
+    }
+
+    fn allocator_stats(&self) -> Option<String> {
+        allocator_stats(&self.allocator.as_ref()?.inner)
+    }
+
+    fn downgrade(&self) -> WeakCrashState {
+        WeakCrashState {
+            inner: Arc::downgrade(&self.inner),
+            allocator: self.allocator.as_ref().map(|allocator| Arc::downgrade(&allocator.inner)),
+        }
+    }
+}
+
+/// Crash state as seen by the panic hook, which must not keep the allocator, and with it the device, alive.
+struct WeakCrashState {
+    inner: Weak<Mutex<CrashStateInner>>,
+    allocator: Option<Weak<Mutex<AllocatorInner>>>,
+}
+
+impl WeakCrashState {
+    /// Dump of the state, if the renderer is still alive.
+    fn dump(&self) -> Option<String> {
+        let state = CrashState {
+            inner: self.inner.upgrade()?,
+            allocator: None,
+        };
+        let mut out = state.dump_frame();
+        let stats = self.allocator.as_ref()
+            .and_then(Weak::upgrade)
+            .and_then(|inner| allocator_stats(&inner));
+        let _ = writeln!(out, "allocator: {}", stats.unwrap_or_else(|| "unavailable".to_string()));
+        Some(out)
+    }
+}
+
+fn allocator_stats(inner: &Mutex<AllocatorInner>) -> Option<String> {
+    let inner = inner.try_lock().ok()?;
+    let allocator = inner.allocator.try_lock().ok()?;
+    let report = allocator.generate_report();
+    Some(format!(
+        "{} allocations, {} / {} bytes in {} blocks",
+        report.allocations.len(),
+        report.total_allocated_bytes,
+        report.total_capacity_bytes,
+        report.blocks.len()
+    ))
+}
+
+/// Restores the panic hook that was installed before [`install_panic_hook`] when dropped.
+pub struct PanicHook {
+    previous: Arc<Box<dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send + 'static>>,
+}
+
+impl Drop for PanicHook {
+    fn drop(&mut self) {
+        // Setting the hook panics on a panicking thread, the installed hook aborts before that matters anyway
+        if std::thread::panicking() {
+            return;
+        }
+        let previous = self.previous.clone();
+        panic::set_hook(Box::new(move |info| previous(info)));
+    }
+}
+
+/// Install a panic hook that dumps the GPU state after the regular panic message and aborts the process.
+///
+/// The hook only holds on to the state weakly, so the device can still be destroyed. Once the renderer is gone it
+/// behaves like the previous hook, which is restored when the returned [`PanicHook`] is dropped.
+pub fn install_panic_hook(state: &CrashState) -> PanicHook {
+    let previous = Arc::new(panic::take_hook());
+    let state = state.downgrade();
+    let hook = PanicHook { previous: previous.clone() };
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        let Some(dump) = state.dump() else {
+            return;
+        };
+        error!("GPU state at panic:\n{}", dump);
+
+        // Unwinding through live vulkan objects would try to destroy in-use resources
+        std::process::abort();
+    }));
+    hook
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_tracks_submissions() {
+        let state = CrashState::default();
+        state.begin_frame(7, 1);
+        state.acquired(2);
+        state.label("render");
+        state.submitted();
+
+        let dump = state.dump();
+        assert!(dump.contains("frame: 7 (slot 1, swapchain image Some(2))"));
+        assert!(dump.contains("slot 1: frame 7"));
+        assert!(dump.contains("last labels: [render]"));
+
+        // Waiting on the slot again retires the submission
+        state.begin_frame(9, 1);
+        assert!(state.dump().contains("in-flight submissions: []"));
+    }
+
+    #[test]
+    fn weak_dump_follows_state() {
+        let state = CrashState::default();
+        let weak = state.downgrade();
+        state.begin_frame(3, 0);
+        assert!(weak.dump().unwrap().contains("frame: 3 (slot 0, swapchain image None)"));
+
+        drop(state);
+        assert!(weak.dump().is_none());
+    }
+}
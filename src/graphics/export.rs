@@ -0,0 +1,250 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use log::{error, info};
+use crate::vulkan::{Allocator, Buffer, CommandBuffer, Device, Image, ImageConfig, ImageTrait, SwapchainImage};
+
+/// A rendered frame of an offline export, see [`AppConfig::export`](crate::app::app::AppConfig::export).
+pub struct ExportedFrame<'a> {
+    /// Index of the frame in the export, starting at zero.
+    pub index: u64,
+    /// Exact animation time the frame was rendered at, `index` frame intervals after the start.
+    pub time: Duration,
+    pub width: u32,
+    pub height: u32,
+    /// Format of the surface, typically a four byte BGRA or RGBA format.
+    pub format: vk::Format,
+    /// Tightly packed texels, row by row.
+    pub pixels: &'a [u8],
+}
+
+/// Receives the frames of an offline export, e.g. to encode them into a movie.
+pub trait FrameSink {
+    fn frame(&mut self, frame: ExportedFrame);
+
+    /// Called once after the last frame of an export with a frame count.
+    fn finish(&mut self) {}
+}
+
+/// A frame kept by a [`MovieBuffer`].
+#[derive(Clone, Debug)]
+pub struct MovieFrame {
+    pub time: Duration,
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub pixels: Vec<u8>,
+}
+
+/// [`FrameSink`] that keeps all frames in memory.
+///
+/// Clones share the frames, so a clone can be given to the export and the frames read from the other once it
+/// finished.
+#[derive(Clone, Default)]
+pub struct MovieBuffer {
+    frames: Arc<Mutex<Vec<MovieFrame>>>,
+}
+
+impl MovieBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Take the frames received so far.
+    pub fn take_frames(&self) -> Vec<MovieFrame> {
+        std::mem::take(&mut self.frames.lock().unwrap())
+    }
+}
+
+impl FrameSink for MovieBuffer {
+    fn frame(&mut self, frame: ExportedFrame) {
+        self.frames.lock().unwrap().push(MovieFrame {
+            time: frame.time,
+            width: frame.width,
+            height: frame.height,
+            format: frame.format,
+            pixels: frame.pixels.to_vec(),
+        });
+    }
+}
+
+/// Settings of an offline export, see [`AppConfig::export`](crate::app::app::AppConfig::export).
+pub struct ExportConfig {
+    /// Frames per second of animation time.
+    pub frame_rate: f64,
+    /// Frames to render before the app exits, unlimited when `None`.
+    pub frame_count: Option<u64>,
+    pub sink: Box<dyn FrameSink>,
+}
+
+/// Offscreen surface frames are rendered to instead of the swapchain during an export, and read back from.
+pub(crate) struct OfflineExport {
+    frame_count: Option<u64>,
+    frames_exported: u64,
+    sink: Box<dyn FrameSink>,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    // The view wraps the image, so it is dropped first
+    target: SwapchainImage,
+    _image: Image,
+    readback: Buffer,
+}
+
+impl OfflineExport {
+    pub(crate) fn new(config: ExportConfig, device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) -> Self {
+        info!("Exporting frames offscreen at {} fps", config.frame_rate);
+        let (image, target, readback) = Self::create_surface(device, allocator, format, extent);
+        Self {
+            frame_count: config.frame_count,
+            frames_exported: 0,
+            sink: config.sink,
+            format,
+            extent,
+            target,
+            _image: image,
+            readback,
+        }
+    }
+
+    fn create_surface(device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) -> (Image, SwapchainImage, Buffer) {
+        // Usable like a swapchain image, which components clear, blit and render to
+        let image = Image::new(device, allocator, ImageConfig {
+            extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+            format,
+            image_usage_flags: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+            ..Default::default()
+        });
+        let target = SwapchainImage::from_raw(device, image.handle(), format, extent);
+        let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * texel_size(format);
+        let readback = Buffer::new(device, allocator, MemoryLocation::GpuToCpu, size, vk::BufferUsageFlags::TRANSFER_DST);
+        (image, target, readback)
+    }
+
+    /// Follow the swapchain to a new extent or format.
+    pub(crate) fn resize(&mut self, device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) {
+        if format == self.format && extent == self.extent {
+            return;
+        }
+        let (image, target, readback) = Self::create_surface(device, allocator, format, extent);
+        self.target = target;
+        self._image = image;
+        self.readback = readback;
+        self.format = format;
+        self.extent = extent;
+    }
+
+    pub(crate) fn target(&self) -> &SwapchainImage {
+        &self.target
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.frame_count.is_some_and(|count| self.frames_exported >= count)
+    }
+
+    /// Copy the rendered frame, which is in the presentation layout like a swapchain image, to the readback buffer.
+    pub(crate) fn record_readback(&self, command_buffer: &mut CommandBuffer) {
+        command_buffer.image_barrier(
+            &self.target,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        command_buffer.copy_image_to_buffer(
+            &self.target,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            &self.readback,
+            &[vk::BufferImageCopy::default()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image_extent(vk::Extent3D { width: self.extent.width, height: self.extent.height, depth: 1 })],
+        );
+        command_buffer.image_barrier(
+            &self.target,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::empty(),
+        );
+        command_buffer.track(&self.readback);
+    }
+
+    /// Hand the frame that was read back to the sink. The frame's commands have to be executed.
+    pub(crate) fn deliver(&mut self, time: Duration) {
+        let pixels = match self.readback.mapped() {
+            Ok(pixels) => pixels,
+            Err(e) => {
+                error!("Failed to read back exported frame {}: {}", self.frames_exported, e);
+                return;
+            }
+        };
+        self.sink.frame(ExportedFrame {
+            index: self.frames_exported,
+            time,
+            width: self.extent.width,
+            height: self.extent.height,
+            format: self.format,
+            pixels: &pixels.as_slice()[..self.readback.size() as usize],
+        });
+        drop(pixels);
+
+        self.frames_exported += 1;
+        if self.is_finished() {
+            info!("Exported {} frames", self.frames_exported);
+            self.sink.finish();
+        }
+    }
+}
+
+/// Bytes per texel of the color formats surfaces use.
+fn texel_size(format: vk::Format) -> vk::DeviceSize {
+    match format {
+        vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movie_buffer_shares_frames() {
+        let movie = MovieBuffer::new();
+        let mut sink = movie.clone();
+        for index in 0..3 {
+            sink.frame(ExportedFrame {
+                index,
+                time: Duration::from_secs(index) / 60,
+                width: 1,
+                height: 1,
+                format: vk::Format::B8G8R8A8_UNORM,
+                pixels: &[index as u8; 4],
+            });
+        }
+        assert_eq!(movie.len(), 3);
+
+        let frames = movie.take_frames();
+        assert_eq!(frames[2].time, Duration::from_secs(2) / 60);
+        assert_eq!(frames[2].pixels, vec![2; 4]);
+        assert!(movie.is_empty());
+    }
+}
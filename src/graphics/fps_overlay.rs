@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use ash::vk;
+use egui::{Context, Sense};
+use crate::app::gui::{GuiComponent, GuiContext};
+use crate::graphics::SurfaceSizes;
+
+/// Frames the graph and the average frame time cover.
+const HISTORY_FRAMES: usize = 120;
+
+/// Small overlay with the frame rate, a graph of the recent frame times, the GPU time of the frame, the resolution
+/// and the present mode, see [`AppConfig::fps_overlay`](crate::app::app::AppConfig::fps_overlay).
+///
+/// The GPU time is only shown with the [GPU profiler](crate::app::app::AppConfig::gpu_profiler) enabled.
+pub struct FpsOverlay {
+    pub visible: bool,
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>,
+    gpu_time: Option<Duration>,
+    sizes: Option<SurfaceSizes>,
+    present_mode: vk::PresentModeKHR,
+}
+
+impl Default for FpsOverlay {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            frame_times: VecDeque::with_capacity(HISTORY_FRAMES),
+            last_frame: None,
+            gpu_time: None,
+            sizes: None,
+            present_mode: vk::PresentModeKHR::FIFO,
+        }
+    }
+}
+
+impl FpsOverlay {
+    /// Show or hide the overlay. The frame times start over, as hidden frames are not measured.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.frame_times.clear();
+        self.last_frame = None;
+    }
+
+    /// Average time between the recent frames.
+    pub fn average_frame_time(&self) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        Some(self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32)
+    }
+
+    pub(crate) fn set_frame_info(&mut self, gpu_time: Option<Duration>, sizes: SurfaceSizes, present_mode: vk::PresentModeKHR) {
+        self.gpu_time = gpu_time;
+        self.sizes = Some(sizes);
+        self.present_mode = present_mode;
+    }
+
+    fn record_frame(&mut self, now: Instant) {
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == HISTORY_FRAMES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now - last_frame);
+        }
+        self.last_frame = Some(now);
+    }
+
+    fn frame_time_graph(&self, ui: &mut egui::Ui) {
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(HISTORY_FRAMES as f32 * 1.5, 40.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+        // Scaled to the slowest frame, but to at least 30 fps so small variations don't fill the graph
+        let slowest = self.frame_times.iter().max().copied().unwrap_or_default();
+        let scale = slowest.max(Duration::from_secs(1) / 30).as_secs_f32();
+        let points = self.frame_times.iter()
+            .enumerate()
+            .map(|(index, frame_time)| egui::pos2(
+                rect.left() + rect.width() * index as f32 / (HISTORY_FRAMES - 1) as f32,
+                rect.bottom() - rect.height() * frame_time.as_secs_f32() / scale,
+            ))
+            .collect();
+        painter.line(points, ui.visuals().selection.stroke);
+        response.on_hover_text(format!("Slowest frame {:.2} ms", slowest.as_secs_f64() * 1000.0));
+    }
+}
+
+impl GuiComponent for FpsOverlay {
+    fn gui(&mut self, _: &mut GuiContext, context: &Context) {
+        self.record_frame(Instant::now());
+
+        egui::Window::new("Frame")
+            .id(egui::Id::new("cen_fps_overlay"))
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .show(context, |ui| {
+                match self.average_frame_time() {
+                    Some(frame_time) => ui.label(format!("{:.0} fps, {:.2} ms", 1.0 / frame_time.as_secs_f64(), frame_time.as_secs_f64() * 1000.0)),
+                    None => ui.label("Measuring"),
+                };
+                self.frame_time_graph(ui);
+                if let Some(gpu_time) = self.gpu_time {
+                    ui.label(format!("GPU {:.2} ms", gpu_time.as_secs_f64() * 1000.0));
+                }
+                if let Some(sizes) = &self.sizes {
+                    if sizes.is_scaled() {
+                        ui.label(format!("{}x{} on {}x{}", sizes.render.width, sizes.render.height, sizes.surface.width, sizes.surface.height));
+                    } else {
+                        ui.label(format!("{}x{}", sizes.render.width, sizes.render.height));
+                    }
+                }
+                ui.label(format!("{:?}", self.present_mode));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_history() {
+        let mut overlay = FpsOverlay::default();
+        let start = Instant::now();
+        overlay.record_frame(start);
+        assert_eq!(overlay.average_frame_time(), None);
+
+        for frame in 1..=HISTORY_FRAMES as u32 + 10 {
+            overlay.record_frame(start + Duration::from_millis(10) * frame);
+        }
+        assert_eq!(overlay.frame_times.len(), HISTORY_FRAMES);
+        assert_eq!(overlay.average_frame_time(), Some(Duration::from_millis(10)));
+
+        overlay.toggle();
+        assert!(overlay.visible);
+        assert_eq!(overlay.average_frame_time(), None);
+    }
+}
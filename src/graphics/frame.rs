@@ -0,0 +1,143 @@
+use ash::vk;
+use crate::graphics::renderer::FrameFlags;
+use crate::vulkan::{CommandBuffer, CommandPool, Device, TransientDescriptorAllocator, DEFAULT_POOL_RATIOS};
+
+/// Number of frames recorded ahead of the GPU, unless configured with
+/// [`AppConfig::frames_in_flight`](crate::app::app::AppConfig::frames_in_flight).
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Runs once the frame it was queued in has executed, see [`CenContext::run_on_finish`](crate::app::engine::CenContext::run_on_finish).
+pub type FrameCallback = Box<dyn FnOnce()>;
+
+/// A frame between [`Renderer::begin_frame`](crate::graphics::Renderer::begin_frame) and
+/// [`Renderer::end_frame`](crate::graphics::Renderer::end_frame), with the synchronization of cen.
+///
+/// Work submitted to other queues in between cooperates through semaphores: the frame's submission waits on
+/// [`wait_semaphore`](Self::wait_semaphore)s, e.g. of a simulation step, and signals
+/// [`signal_semaphore`](Self::signal_semaphore)s for work that consumes the frame.
+pub struct Frame {
+    /// Identifies the frame in the event log and crash reports, counting up from one.
+    pub id: u64,
+    /// Frame in flight slot, see [`FrameResources`].
+    pub frame_index: usize,
+    /// Swapchain image the frame renders to and presents.
+    pub image_index: usize,
+    /// Recorded by [`Renderer::record_frame`](crate::graphics::Renderer::record_frame).
+    pub command_buffer: CommandBuffer,
+    /// Signaled once the swapchain image was acquired, the frame's submission waits on it.
+    pub image_available: vk::Semaphore,
+    /// Signaled by the frame's submission, presentation waits on it.
+    pub render_finished: vk::Semaphore,
+    /// Signaled once the frame's submission executed.
+    pub fence: vk::Fence,
+    pub(crate) wait_semaphores: Vec<(vk::Semaphore, vk::PipelineStageFlags)>,
+    pub(crate) signal_semaphores: Vec<vk::Semaphore>,
+}
+
+impl Frame {
+    /// Have the frame's submission wait on `semaphore` before `stage`.
+    pub fn wait_semaphore(&mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) {
+        self.wait_semaphores.push((semaphore, stage));
+    }
+
+    /// Have the frame's submission signal `semaphore` once it executed.
+    pub fn signal_semaphore(&mut self, semaphore: vk::Semaphore) {
+        self.signal_semaphores.push(semaphore);
+    }
+}
+
+/// Resources of one frame in flight, reused once the GPU finished that frame.
+///
+/// Frames in flight are independent of the swapchain images they render to, the number of images
+/// is up to the driver while the number of frames is configured.
+pub struct FrameResources {
+    pub command_buffer: CommandBuffer,
+    /// Signaled once the swapchain image of the frame was acquired.
+    pub image_available: vk::Semaphore,
+    on_finish: Vec<FrameCallback>,
+    /// Throwaway descriptor sets of the frame, reset once it executed.
+    pub transient_descriptors: TransientDescriptorAllocator,
+    /// Frame of the device's deletion queue the resources dropped while recording belong to.
+    pub(crate) deletion_frame: u64,
+    /// Whether the pre-clear of the swapchain image was recorded, see [`image_available_stages`].
+    pub(crate) cleared: bool,
+}
+
+impl FrameResources {
+    pub(crate) fn new(device: &Device, command_pool: &CommandPool) -> Self {
+        Self {
+            command_buffer: CommandBuffer::new(device, command_pool, true),
+            image_available: device.create_semaphore(),
+            on_finish: Vec::new(),
+            transient_descriptors: TransientDescriptorAllocator::new(device, DEFAULT_POOL_RATIOS),
+            deletion_frame: 0,
+            cleared: false,
+        }
+    }
+
+    /// Signaled once the frame's commands have executed.
+    pub fn fence(&self) -> vk::Fence {
+        self.command_buffer.fence()
+    }
+
+    /// Callbacks and transient descriptors used while recording the frame.
+    pub(crate) fn recording(&mut self) -> (&mut Vec<FrameCallback>, &mut TransientDescriptorAllocator) {
+        (&mut self.on_finish, &mut self.transient_descriptors)
+    }
+
+    /// The frame's fence was waited on, run its callbacks and free its transient descriptor sets.
+    pub(crate) fn finished(&mut self) {
+        for callback in self.on_finish.drain(..) {
+            callback();
+        }
+        self.transient_descriptors.reset();
+    }
+
+    pub(crate) fn destroy(&mut self, device: &Device) {
+        device.destroy_semaphore(self.image_available);
+    }
+}
+
+/// Color of the pre-clear recorded for a frame, `None` without a clear color or when a component skips it.
+pub(crate) fn frame_clear(clear_color: Option<[f32; 4]>, frame_flags: FrameFlags) -> Option<[f32; 4]> {
+    clear_color.filter(|_| !frame_flags.contains(FrameFlags::SKIP_CLEAR))
+}
+
+/// Stages of the frame's submission that wait for the swapchain image to be acquired. The pre-clear is the first
+/// write to it when recorded, otherwise components may write it first from a render or compute pass.
+pub(crate) fn image_available_stages(cleared: bool) -> vk::PipelineStageFlags {
+    if cleared {
+        vk::PipelineStageFlags::TRANSFER
+    } else {
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::COMPUTE_SHADER
+            | vk::PipelineStageFlags::TRANSFER
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_for_image_without_clear() {
+        assert_eq!(image_available_stages(true), vk::PipelineStageFlags::TRANSFER);
+
+        let stages = image_available_stages(false);
+        assert!(stages.contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT));
+        assert!(stages.contains(vk::PipelineStageFlags::COMPUTE_SHADER));
+        assert!(stages.contains(vk::PipelineStageFlags::TRANSFER));
+    }
+
+    #[test]
+    fn skip_clear() {
+        let color = Some([0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(frame_clear(color, FrameFlags::empty()), color);
+        assert_eq!(frame_clear(color, FrameFlags::SKIP_CLEAR), None);
+        assert_eq!(frame_clear(None, FrameFlags::empty()), None);
+
+        // Without a clear color the components write the swapchain image first, as with a skipped clear
+        let cleared = frame_clear(None, FrameFlags::empty()).is_some();
+        assert_eq!(image_available_stages(cleared), image_available_stages(false));
+    }
+}
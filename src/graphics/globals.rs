@@ -0,0 +1,148 @@
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use gpu_allocator::MemoryLocation;
+use winit::event::MouseButton;
+use crate::app::input::InputState;
+use crate::graphics::clock::AnimationClock;
+use crate::graphics::surface_size::SurfaceSizes;
+use crate::vulkan::{Allocator, Buffer, CommandBuffer, DescriptorAllocator, DescriptorSet, DescriptorSetLayout, Device, Pipeline};
+
+/// Values of the globals uniform buffer, declared as `cen_globals` by `#include <cen/globals.glsl>`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct GlobalUniforms {
+    /// Animation time in seconds, see [`AnimationClock::animation_time`].
+    pub time: f32,
+    /// Animation time since the previous frame in seconds.
+    pub delta_time: f32,
+    /// Number of frames rendered before this one.
+    pub frame: u32,
+    /// Random seed of the frame, see [`RandomProvider`](crate::graphics::RandomProvider).
+    pub random_seed: u32,
+    /// Extent the components render at in pixels, see [`SurfaceSizes::render`].
+    pub resolution: [f32; 2],
+    /// Cursor position in pixels of the rendered frame, negative while the cursor is outside of it.
+    pub mouse: [f32; 2],
+    /// Held mouse buttons: bit 0 is left, bit 1 right and bit 2 middle.
+    pub mouse_buttons: u32,
+    _padding: [u32; 3],
+}
+
+/// Engine-provided uniforms that are updated every frame: time, resolution, frame number, mouse and random seed.
+///
+/// Pipelines opt in by listing [`layout`](Self::layout) as their first descriptor set layout, binding the
+/// set with [`bind`](Self::bind), and declaring it with `#include <cen/globals.glsl>`:
+///
+/// ```glsl
+/// #include <cen/globals.glsl>
+///
+/// void main() {
+///     vec2 uv = gl_FragCoord.xy / cen_globals.resolution;
+///     float t = cen_globals.time;
+/// }
+/// ```
+pub struct FrameGlobals {
+    layout: DescriptorSetLayout,
+    // One buffer and set per frame in flight, so the values of in-flight frames aren't overwritten
+    buffers: Vec<Buffer>,
+    sets: Vec<DescriptorSet>,
+    current: usize,
+    uniforms: GlobalUniforms,
+    updated: bool,
+}
+
+impl FrameGlobals {
+    pub(crate) fn new(device: &Device, allocator: &mut Allocator, descriptor_allocator: &DescriptorAllocator, frames_in_flight: usize) -> Self {
+        let layout = DescriptorSetLayout::new(device, &[vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::ALL)]);
+
+        let buffers = (0..frames_in_flight).map(|_| {
+            Buffer::new(
+                device,
+                allocator,
+                MemoryLocation::CpuToGpu,
+                size_of::<GlobalUniforms>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER
+            )
+        }).collect::<Vec<_>>();
+
+        let sets = buffers.iter().map(|buffer| {
+            let set = descriptor_allocator.allocate(&layout);
+            let buffer_binding = [buffer.binding()];
+            set.write(&[vk::WriteDescriptorSet::default()
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&buffer_binding)]);
+            set
+        }).collect();
+
+        Self {
+            layout,
+            buffers,
+            sets,
+            current: 0,
+            uniforms: GlobalUniforms::default(),
+            updated: false,
+        }
+    }
+
+    /// Write the values of the frame using the given frame in flight slot.
+    pub(crate) fn update(&mut self, frame_index: usize, clock: &AnimationClock, input: &InputState, sizes: &SurfaceSizes, random_seed: u32) {
+        let time = clock.animation_time() as f32;
+        let mouse = input.mouse_position()
+            .and_then(|position| sizes.surface_to_render(position.x, position.y))
+            .map_or([-1.0, -1.0], |(x, y)| [x as f32, y as f32]);
+        let mouse_buttons = [MouseButton::Left, MouseButton::Right, MouseButton::Middle].iter().enumerate()
+            .filter(|(_, button)| input.is_mouse_button_down(**button))
+            .fold(0, |bits, (bit, _)| bits | 1 << bit);
+
+        self.uniforms = GlobalUniforms {
+            time,
+            delta_time: if self.updated { time - self.uniforms.time } else { 0.0 },
+            frame: if self.updated { self.uniforms.frame + 1 } else { 0 },
+            random_seed,
+            resolution: [sizes.render.width as f32, sizes.render.height as f32],
+            mouse,
+            mouse_buttons,
+            _padding: [0; 3],
+        };
+
+        self.updated = true;
+
+        self.current = frame_index % self.buffers.len();
+        self.buffers[self.current].write_slice(0, &[self.uniforms]).expect("Failed to write globals");
+    }
+
+    /// Layout of the globals set, list it first in the `descriptor_set_layouts` of pipelines that use it.
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Values of the current frame.
+    pub fn uniforms(&self) -> &GlobalUniforms {
+        &self.uniforms
+    }
+
+    /// Bind the globals of the current frame as set 0 of a bound pipeline.
+    pub fn bind(&self, command_buffer: &mut CommandBuffer, pipeline: &dyn Pipeline) {
+        command_buffer.bind_descriptor_set(pipeline, 0, &self.sets[self.current]);
+        command_buffer.track(&self.buffers[self.current]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+    use super::*;
+
+    #[test]
+    fn std140_layout() {
+        assert_eq!(offset_of!(GlobalUniforms, resolution), 16);
+        assert_eq!(offset_of!(GlobalUniforms, mouse), 24);
+        assert_eq!(offset_of!(GlobalUniforms, mouse_buttons), 32);
+        assert_eq!(size_of::<GlobalUniforms>(), 48);
+    }
+}
@@ -0,0 +1,156 @@
+use ash::vk;
+use gpu_allocator::vulkan::AllocatorCreateDesc;
+use log::info;
+use crate::app::app::AppConfig;
+use crate::graphics::context::GraphicsContext;
+use crate::vulkan::{Allocator, CommandBufferPool, CommandPool, DescriptorAllocator, Device, DevicePlugin, Instance, Surface, WindowState, DEFAULT_POOL_RATIOS};
+
+/// The instance, device and allocators, without a surface or a frame loop.
+///
+/// A [`Renderer`](crate::graphics::Renderer) runs the frames of a window on one together with a
+/// [`Presenter`](crate::graphics::Presenter). Headless work, e.g. tests, benchmarks and tools embedded in other
+/// applications, only needs the context.
+pub struct GpuContext {
+    // Dropped before the instance
+    pub gfx: GraphicsContext,
+    pub physical_device: vk::PhysicalDevice,
+    pub instance: Instance,
+    pub entry: ash::Entry,
+    /// Whether the queue of `gfx` is a compute family that presents, see
+    /// [`AppConfig::present_from_compute`](crate::app::app::AppConfig::present_from_compute).
+    presents_from_compute: bool,
+    /// Whether the device belongs to another context, see [`share`](Self::share).
+    shared: bool,
+}
+
+impl GpuContext {
+
+    /// Context on the best device for offscreen work, without a surface.
+    pub fn headless(plugins: &[Box<dyn DevicePlugin>]) -> Self {
+        let entry = ash::Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let gfx = GraphicsContext::offscreen(&instance, plugins);
+        Self {
+            physical_device: gfx.device.inner.physical_device,
+            gfx,
+            instance,
+            entry,
+            presents_from_compute: false,
+            shared: false,
+        }
+    }
+
+    /// Context on the device that presents best to the window, along with the surface of the window, which has to be
+    /// dropped before the context.
+    pub fn with_surface(window: &WindowState, app_config: &AppConfig) -> (Self, Surface) {
+        let entry = ash::Entry::linked();
+        let instance = Instance::with_config(&entry, Some(window), &app_config.instance_config);
+        let surface = Surface::new(&entry, &instance, window);
+        let (physical_device, graphics_queue_family_index) = instance.create_physical_device(&entry, &surface);
+
+        // Compute-only apps can run the whole frame on a compute family that presents, away from the graphics queue
+        let compute_present_family = if app_config.present_from_compute {
+            let family = instance.find_compute_present_queue_family(physical_device, &surface);
+            match family {
+                Some(index) => info!("Presenting from compute queue family {}", index),
+                None => info!("No compute queue family can present, using the graphics queue"),
+            }
+            family
+        } else {
+            None
+        };
+        let queue_family_index = compute_present_family.unwrap_or(graphics_queue_family_index);
+
+        // Some devices only present from a family without graphics support
+        let present_queue_family_index = instance.find_present_queue_family(physical_device, &surface, queue_family_index)
+            .expect("No queue family can present to the surface");
+        if present_queue_family_index != queue_family_index {
+            info!("Presenting from separate queue family {}", present_queue_family_index);
+        }
+        let device = Device::with_present_queue(&instance, physical_device, queue_family_index, present_queue_family_index, &app_config.device_config, &app_config.device_plugins)
+            .unwrap_or_else(|err| panic!("{}", err));
+        let queue = device.get_queue(0);
+        let command_pool = CommandPool::new(&device, queue_family_index);
+
+        let allocator = Allocator::new(
+            &device,
+            &AllocatorCreateDesc {
+                instance: instance.handle().clone(),
+                device: device.handle().clone(),
+                physical_device,
+                debug_settings: app_config.allocator_debug_settings,
+                buffer_device_address: device.inner.buffer_device_address,
+                allocation_sizes: Default::default(),
+            }
+        );
+
+        let descriptor_allocator = DescriptorAllocator::new(&device, DEFAULT_POOL_RATIOS);
+
+        let gfx = GraphicsContext {
+            device,
+            allocator,
+            descriptor_allocator,
+            queue,
+            command_pool,
+            command_buffers: CommandBufferPool::new(),
+        };
+
+        let context = Self {
+            gfx,
+            physical_device,
+            instance,
+            entry,
+            presents_from_compute: compute_present_family.is_some(),
+            shared: false,
+        };
+        (context, surface)
+    }
+
+    /// Context on the same device, queue and allocator, with a command pool and descriptor allocator of its own,
+    /// e.g. for the renderer of another window. The device is destroyed once all contexts sharing it are dropped.
+    pub fn share(&self) -> Self {
+        let device = self.gfx.device.clone();
+        let gfx = GraphicsContext {
+            command_pool: CommandPool::new(&device, device.inner.queue_family_index),
+            command_buffers: CommandBufferPool::new(),
+            queue: self.gfx.queue,
+            allocator: self.gfx.allocator.clone(),
+            descriptor_allocator: DescriptorAllocator::new(&device, DEFAULT_POOL_RATIOS),
+            device,
+        };
+        Self {
+            gfx,
+            physical_device: self.physical_device,
+            instance: self.instance.clone(),
+            entry: self.entry.clone(),
+            presents_from_compute: self.presents_from_compute,
+            shared: true,
+        }
+    }
+
+    /// Surface of another window, which the device has to be able to present to.
+    pub fn create_surface(&self, window: &WindowState) -> Surface {
+        let surface = Surface::new(&self.entry, &self.instance, window);
+        let device = &self.gfx.device;
+        let present_queue_family_index = device.present_queue_family_index().unwrap_or(device.inner.queue_family_index);
+        assert!(
+            surface.supports_present(&self.physical_device, present_queue_family_index),
+            "The device can't present to the window from queue family {}", present_queue_family_index
+        );
+        surface
+    }
+
+    /// Whether the device belongs to another context, see [`share`](Self::share).
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.gfx.device
+    }
+
+    /// Whether the context's queue is a compute family that presents.
+    pub fn presents_from_compute(&self) -> bool {
+        self.presents_from_compute
+    }
+}
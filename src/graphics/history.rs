@@ -0,0 +1,123 @@
+use std::sync::{Arc, Mutex, Weak};
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::graphics::image_store::ImageKey;
+use crate::graphics::{GraphicsContext, ImageContext};
+use crate::vulkan::{CommandBuffer, ImageConfig};
+
+pub(crate) struct HistoryData {
+    images: [ImageResource; 2],
+    current: usize,
+    // Image that was last cleared, resized images are recreated with undefined contents
+    cleared: Option<ImageKey>,
+}
+
+/// A pair of images that swap roles every frame, giving access to the previous frame's output
+/// for feedback effects, temporal anti-aliasing or motion blur.
+///
+/// Both images are kept in `ImageLayout::GENERAL`. They are cleared to zero when created and after
+/// they have been resized, so the previous image never holds undefined contents.
+#[derive(Clone)]
+pub struct HistoryImage(Arc<Mutex<HistoryData>>);
+
+impl HistoryImage {
+
+    /// The image to write the current frame to.
+    pub fn current(&self) -> ImageResource {
+        let data = self.0.lock().unwrap();
+        data.images[data.current].clone()
+    }
+
+    /// The image that was written to in the previous frame.
+    pub fn previous(&self) -> ImageResource {
+        let data = self.0.lock().unwrap();
+        data.images[1 - data.current].clone()
+    }
+
+    pub(crate) fn downgrade(&self) -> Weak<Mutex<HistoryData>> {
+        Arc::downgrade(&self.0)
+    }
+
+    pub(crate) fn swap(data: &Mutex<HistoryData>) {
+        let mut data = data.lock().unwrap();
+        data.current = 1 - data.current;
+    }
+
+    /// Clear both images if they haven't been cleared since they were created.
+    pub(crate) fn clear_if_needed(data: &Mutex<HistoryData>, images: &ImageContext, command_buffer: &mut CommandBuffer) {
+        let mut data = data.lock().unwrap();
+        let key = data.images[0].image_key();
+        if data.cleared.as_ref() == Some(&key) {
+            return;
+        }
+        data.cleared = Some(key);
+
+        for resource in &data.images {
+            let image = images.get(resource);
+            command_buffer.image_barrier(
+                image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::NONE,
+                vk::AccessFlags::TRANSFER_WRITE,
+            );
+            command_buffer.clear_color_image(image, vk::ImageLayout::GENERAL, [0.0, 0.0, 0.0, 0.0]);
+            command_buffer.image_barrier(
+                image,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            );
+        }
+    }
+}
+
+impl ImageContext {
+
+    /// Create a pair of history images. `TRANSFER_DST` usage is added for the initial clears.
+    pub fn create_history_image(&mut self, gfx: &mut GraphicsContext, mut config: ImageConfig, flags: ImageFlags) -> HistoryImage {
+        config.image_usage_flags |= vk::ImageUsageFlags::TRANSFER_DST;
+        let images = [
+            self.create_image(gfx, config, flags),
+            self.create_image(gfx, config, flags),
+        ];
+        let history = HistoryImage(Arc::new(Mutex::new(HistoryData {
+            images,
+            current: 0,
+            cleared: None,
+        })));
+        self.histories.push(history.downgrade());
+        history
+    }
+
+    /// Swap the current and previous images of all live history images.
+    pub(crate) fn advance_histories(&mut self) {
+        self.histories.retain(|history| {
+            history.upgrade().map(|data| HistoryImage::swap(&data)).is_some()
+        });
+    }
+
+    /// Clear history images that were created or resized since the last frame.
+    pub(crate) fn clear_histories(&self, command_buffer: &mut CommandBuffer) {
+        for data in self.histories.iter().filter_map(|history| history.upgrade()) {
+            HistoryImage::clear_if_needed(&data, self, command_buffer);
+        }
+    }
+}
+
+impl CenContext<'_> {
+
+    /// Create a pair of history images, see [`HistoryImage`].
+    /// Both images are cleared right away, so they can be used in the current frame.
+    pub fn create_history_image(&mut self, config: ImageConfig, flags: ImageFlags) -> HistoryImage {
+        let history = self.images.create_history_image(self.gfx, config, flags);
+        HistoryImage::clear_if_needed(&history.0, self.images, self.command_buffer);
+        history
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use ash::vk;
+use egui::Context;
+use crate::app::engine::CenContext;
+use crate::app::gui::{GuiComponent, GuiContext};
+use crate::app::{ImageFlags, ImageResource};
+use crate::graphics::renderer::RenderComponent;
+use crate::vulkan::{compile_shader_code, ComputePipeline, DescriptorSetLayout, DescriptorWriter, ImageConfig, ImageTrait};
+
+const VIEWER_SHADER: &str = r#"
+#version 450
+
+#include <cen/color.glsl>
+
+layout ( local_size_x = 8, local_size_y = 8, local_size_z = 1 ) in;
+
+layout( binding = 0 ) uniform sampler2D source_image;
+layout( binding = 1, rgba8 ) uniform writeonly image2D display_image;
+
+layout( push_constant ) uniform PushConstants {
+    uint channel;
+    uint colormap;
+    float range_min;
+    float range_max;
+} pc;
+
+void main()
+{
+    ivec2 p = ivec2( gl_GlobalInvocationID.xy );
+    ivec2 size = imageSize( display_image );
+    if( p.x >= size.x || p.y >= size.y )
+    {
+        return;
+    }
+
+    vec4 value = ( texelFetch( source_image, p, 0 ) - pc.range_min ) / max( pc.range_max - pc.range_min, 1e-6 );
+    if( pc.channel == 0 )
+    {
+        imageStore( display_image, p, vec4( clamp( value.rgb, 0.0, 1.0 ), 1.0 ) );
+        return;
+    }
+
+    float t = clamp( value[ pc.channel - 1 ], 0.0, 1.0 );
+    vec3 color = pc.colormap == 1 ? viridis( t ) : pc.colormap == 2 ? turbo( t ) : vec3( t );
+    imageStore( display_image, p, vec4( color, 1.0 ) );
+}
+"#;
+
+/// Channels of the image shown by the [`ImageViewer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ViewChannel {
+    #[default]
+    Rgb,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+/// Colors of a single channel shown by the [`ImageViewer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    #[default]
+    Grayscale,
+    Viridis,
+    Turbo,
+}
+
+/// Debug window showing any image registered with [`CenContext::register_image`], e.g. the intermediate targets of
+/// compute passes, see [`AppConfig::image_viewer`](crate::app::app::AppConfig::image_viewer).
+///
+/// Values in `range` are mapped to [0, 1], a single channel through a colormap. The shown image needs `SAMPLED`
+/// usage, and is converted after the components rendered the frame. Only 2D color images sampled as floats can be
+/// registered, see [`ImageContext::register`](crate::graphics::ImageContext::register).
+pub struct ImageViewer {
+    /// Name of the shown image.
+    pub selected: Option<String>,
+    pub channel: ViewChannel,
+    pub colormap: Colormap,
+    pub range: [f32; 2],
+    pipeline: Option<(ComputePipeline, DescriptorSetLayout)>,
+    display: Option<ImageResource>,
+    // Whether the window is open this frame, the conversion is skipped otherwise
+    visible: bool,
+    error: Option<String>,
+}
+
+impl Default for ImageViewer {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            channel: ViewChannel::Rgb,
+            colormap: Colormap::Grayscale,
+            range: [0.0, 1.0],
+            pipeline: None,
+            display: None,
+            visible: false,
+            error: None,
+        }
+    }
+}
+
+impl ImageViewer {
+    fn create_pipeline(ctx: &mut CenContext) -> (ComputePipeline, DescriptorSetLayout) {
+        let layout_bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &layout_bindings);
+
+        let code = compile_shader_code(VIEWER_SHADER, "cen/image_viewer.comp", shaderc::ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile image viewer shader");
+        let pipeline = ComputePipeline::from_spirv(
+            &ctx.gfx.device,
+            code,
+            &[layout.clone()],
+            &[vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .size(4 * std::mem::size_of::<u32>() as u32)]
+        );
+        (pipeline, layout)
+    }
+
+    fn push_constants(&self) -> [u32; 4] {
+        let channel = match self.channel {
+            ViewChannel::Rgb => 0,
+            ViewChannel::Red => 1,
+            ViewChannel::Green => 2,
+            ViewChannel::Blue => 3,
+            ViewChannel::Alpha => 4,
+        };
+        let colormap = match self.colormap {
+            Colormap::Grayscale => 0,
+            Colormap::Viridis => 1,
+            Colormap::Turbo => 2,
+        };
+        [channel, colormap, self.range[0].to_bits(), self.range[1].to_bits()]
+    }
+}
+
+impl RenderComponent for ImageViewer {
+    fn render(&mut self, ctx: &mut CenContext) {
+        if !self.visible {
+            return;
+        }
+        let Some((resource, layout)) = self.selected.as_deref().and_then(|name| ctx.images.registered(name)) else {
+            self.display = None;
+            return;
+        };
+
+        let (extent, usage) = {
+            let image = ctx.images.get(&resource);
+            (image.extent(), image.config().image_usage_flags)
+        };
+        if !usage.contains(vk::ImageUsageFlags::SAMPLED) {
+            self.error = Some("The image needs SAMPLED usage to be shown".to_string());
+            self.display = None;
+            return;
+        }
+        self.error = None;
+
+        if self.pipeline.is_none() {
+            self.pipeline = Some(Self::create_pipeline(ctx));
+        }
+        if !self.display.as_ref().is_some_and(|display| ctx.images.get(display).extent() == extent) {
+            self.display = Some(ctx.create_image(
+                ImageConfig::new(extent.width, extent.height, vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED),
+                ImageFlags::empty()
+            ));
+        }
+
+        // Sample the image in place if its layout allows it
+        let read_layout = match layout {
+            vk::ImageLayout::GENERAL | vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => layout,
+            _ => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let source = ctx.images.get(&resource);
+        let display = ctx.images.get(self.display.as_ref().unwrap());
+        ctx.command_buffer.image_barrier(
+            source,
+            layout,
+            read_layout,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+        // The gui of the previous frame may still sample the display image
+        ctx.command_buffer.image_barrier(
+            display,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::SHADER_WRITE,
+        );
+
+        let (pipeline, _) = self.pipeline.as_ref().unwrap();
+        ctx.command_buffer.bind_pipeline(pipeline);
+        ctx.command_buffer.push_descriptors(pipeline, 0, &DescriptorWriter::new()
+            .sampled_image(0, source, read_layout)
+            .storage_image(1, display));
+        ctx.command_buffer.push_constants(pipeline, vk::ShaderStageFlags::COMPUTE, 0, bytemuck::cast_slice(&self.push_constants()));
+        ctx.command_buffer.dispatch(extent.width.div_ceil(8), extent.height.div_ceil(8), 1);
+
+        ctx.command_buffer.image_barrier(
+            source,
+            read_layout,
+            layout,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+        );
+        ctx.command_buffer.image_barrier(
+            display,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+    }
+}
+
+impl GuiComponent for ImageViewer {
+    fn gui(&mut self, gui: &mut GuiContext, context: &Context) {
+        let response = egui::Window::new("Images")
+            .id(egui::Id::new("cen_image_viewer"))
+            .default_open(false)
+            .show(context, |ui| {
+                let names = gui.images.registered_names().map(str::to_string).collect::<Vec<_>>();
+                egui::ComboBox::from_label("Image")
+                    .selected_text(self.selected.as_deref().unwrap_or("None"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.selected, None, "None");
+                        for name in names {
+                            ui.selectable_value(&mut self.selected, Some(name.clone()), name);
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    for (channel, label) in [
+                        (ViewChannel::Rgb, "RGB"),
+                        (ViewChannel::Red, "R"),
+                        (ViewChannel::Green, "G"),
+                        (ViewChannel::Blue, "B"),
+                        (ViewChannel::Alpha, "A"),
+                    ] {
+                        ui.selectable_value(&mut self.channel, channel, label);
+                    }
+                });
+                ui.add_enabled_ui(self.channel != ViewChannel::Rgb, |ui| {
+                    egui::ComboBox::from_label("Colormap")
+                        .selected_text(format!("{:?}", self.colormap))
+                        .show_ui(ui, |ui| {
+                            for colormap in [Colormap::Grayscale, Colormap::Viridis, Colormap::Turbo] {
+                                ui.selectable_value(&mut self.colormap, colormap, format!("{:?}", colormap));
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Range");
+                    ui.add(egui::DragValue::new(&mut self.range[0]).speed(0.01));
+                    ui.add(egui::DragValue::new(&mut self.range[1]).speed(0.01));
+                });
+
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                } else if let Some(display) = &mut self.display {
+                    let extent = gui.images.get(display).extent();
+                    let size = egui::vec2(extent.width as f32, extent.height as f32);
+                    let texture = gui.get_texture(display);
+                    ui.image((texture, size * (ui.available_width() / size.x).min(1.0)));
+                }
+            });
+        self.visible = response.is_some_and(|response| response.inner.is_some());
+    }
+}
@@ -0,0 +1,260 @@
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::ImageConfig;
+
+/// Largest size of [`Lut::BlueNoise`]. Generating the ranks takes O(size^4) steps, tile smaller tables instead.
+pub const MAX_BLUE_NOISE_SIZE: u32 = 128;
+
+/// Lookup textures that are commonly needed by generative shaders.
+/// All tables are generated deterministically from fixed seeds, so they are identical between runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Lut {
+    /// `size`x`size` blue noise ranks as `R8_UNORM`, generated with the void-and-cluster method.
+    /// `size` is at most [`MAX_BLUE_NOISE_SIZE`].
+    BlueNoise(u32),
+    /// `size`x`size` ordered dithering thresholds as `R8_UNORM`. `size` must be a power of two.
+    Bayer(u32),
+    /// `size`x`size` random unit vectors in `xyz` and a uniform random value in `w`, as `R32G32B32A32_SFLOAT`.
+    RandomVectors(u32),
+}
+
+impl Lut {
+    pub fn size(&self) -> u32 {
+        match *self {
+            Lut::BlueNoise(size) | Lut::Bayer(size) | Lut::RandomVectors(size) => size,
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        match self {
+            Lut::BlueNoise(_) | Lut::Bayer(_) => vk::Format::R8_UNORM,
+            Lut::RandomVectors(_) => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    /// Texel data of the lookup texture, tightly packed in row-major order.
+    pub fn data(&self) -> Vec<u8> {
+        match *self {
+            Lut::BlueNoise(size) => normalize_ranks(&blue_noise_ranks(size)),
+            Lut::Bayer(size) => normalize_ranks(&bayer_ranks(size)),
+            Lut::RandomVectors(size) => random_vectors(size).iter()
+                .flat_map(|v| v.iter().flat_map(|c| c.to_ne_bytes()))
+                .collect(),
+        }
+    }
+}
+
+impl CenContext<'_> {
+
+    /// Create and upload a lookup texture. The image is left in `SHADER_READ_ONLY_OPTIMAL`
+    /// once the current command buffer has executed.
+    pub fn create_lut(&mut self, lut: Lut) -> ImageResource {
+        let size = lut.size();
+        let data = lut.data();
+
+        let image = self.create_image(
+            ImageConfig {
+                extent: vk::Extent3D { width: size, height: size, depth: 1 },
+                format: lut.format(),
+                image_usage_flags: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                ..Default::default()
+            },
+            ImageFlags::empty()
+        );
+
+        self.upload_image(&image, &data);
+
+        image
+    }
+}
+
+/// Small permuted congruential generator, used to generate reproducible tables.
+pub(crate) struct Pcg32 {
+    state: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+    const INCREMENT: u64 = 1442695040888963407;
+
+    pub(crate) fn new(seed: u64) -> Self {
+        let mut rng = Pcg32 { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state.wrapping_mul(Self::MULTIPLIER).wrapping_add(Self::INCREMENT);
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rot = (state >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform value in [0, 1).
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Map ranks in [0, n) to the full u8 range.
+fn normalize_ranks(ranks: &[u32]) -> Vec<u8> {
+    let count = ranks.len() as u64;
+    ranks.iter().map(|rank| (*rank as u64 * 256 / count) as u8).collect()
+}
+
+fn bayer_ranks(size: u32) -> Vec<u32> {
+    assert!(size.is_power_of_two(), "Bayer matrix size must be a power of two");
+
+    let mut matrix = vec![0u32];
+    let mut n = 1;
+    while n < size {
+        let mut next = vec![0u32; (4 * n * n) as usize];
+        for y in 0..n {
+            for x in 0..n {
+                let v = 4 * matrix[(y * n + x) as usize];
+                let m = 2 * n;
+                next[(y * m + x) as usize] = v;
+                next[(y * m + x + n) as usize] = v + 2;
+                next[((y + n) * m + x) as usize] = v + 3;
+                next[((y + n) * m + x + n) as usize] = v + 1;
+            }
+        }
+        matrix = next;
+        n *= 2;
+    }
+    matrix
+}
+
+fn blue_noise_ranks(size: u32) -> Vec<u32> {
+    assert!(size <= MAX_BLUE_NOISE_SIZE, "Blue noise size must be at most {}", MAX_BLUE_NOISE_SIZE);
+    let n = size as usize;
+    let count = n * n;
+    let sigma = 1.5f32;
+
+    // Toroidal gaussian kernel, indexed by offset
+    let kernel = (0..count).map(|i| {
+        let (dx, dy) = ((i % n).min(n - i % n) as f32, (i / n).min(n - i / n) as f32);
+        (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+    }).collect::<Vec<f32>>();
+
+    // The kernel is negligible past three standard deviations, so only the window around a pixel is updated
+    let radius = ((3.0 * sigma).ceil() as usize).min((n - 1) / 2);
+    let splat = |energy: &mut [f32], p: usize, sign: f32| {
+        let (px, py) = (p % n, p / n);
+        for dy in 0..=2 * radius {
+            for dx in 0..=2 * radius {
+                let (x, y) = ((px + n + dx - radius) % n, (py + n + dy - radius) % n);
+                let k = ((y + n - py) % n) * n + (x + n - px) % n;
+                energy[y * n + x] += sign * kernel[k];
+            }
+        }
+    };
+    let tightest_cluster = |energy: &[f32], pattern: &[bool]| {
+        (0..count).filter(|i| pattern[*i]).max_by(|a, b| energy[*a].total_cmp(&energy[*b])).unwrap()
+    };
+    let largest_void = |energy: &[f32], pattern: &[bool]| {
+        (0..count).filter(|i| !pattern[*i]).min_by(|a, b| energy[*a].total_cmp(&energy[*b])).unwrap()
+    };
+
+    // Initial random pattern with ~10% of the pixels set
+    let mut rng = Pcg32::new(0x5eed);
+    let mut pattern = vec![false; count];
+    let initial = (count / 10).max(1);
+    let mut ones = 0;
+    while ones < initial {
+        let p = rng.next_u32() as usize % count;
+        if !pattern[p] {
+            pattern[p] = true;
+            ones += 1;
+        }
+    }
+    let mut energy = vec![0f32; count];
+    for p in (0..count).filter(|i| pattern[*i]) {
+        splat(&mut energy, p, 1.0);
+    }
+
+    // Spread the initial pattern evenly by moving the tightest cluster into the largest void
+    loop {
+        let cluster = tightest_cluster(&energy, &pattern);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&energy, &pattern);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0u32; count];
+
+    // Rank the initial pattern by removing clusters
+    {
+        let mut pattern = pattern.clone();
+        let mut energy = energy.clone();
+        for rank in (0..ones).rev() {
+            let cluster = tightest_cluster(&energy, &pattern);
+            pattern[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+            ranks[cluster] = rank as u32;
+        }
+    }
+
+    // Rank the remaining pixels by filling voids
+    for rank in ones..count {
+        let void = largest_void(&energy, &pattern);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        ranks[void] = rank as u32;
+    }
+
+    ranks
+}
+
+fn random_vectors(size: u32) -> Vec<[f32; 4]> {
+    let mut rng = Pcg32::new(0x7ec7);
+    (0..size * size).map(|_| {
+        // Uniformly distributed on the unit sphere
+        let z = rng.next_f32() * 2.0 - 1.0;
+        let phi = rng.next_f32() * std::f32::consts::TAU;
+        let r = (1.0 - z * z).sqrt();
+        [r * phi.cos(), r * phi.sin(), z, rng.next_f32()]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_permutation(ranks: &[u32]) -> bool {
+        let mut sorted = ranks.to_vec();
+        sorted.sort();
+        sorted.iter().enumerate().all(|(i, rank)| i as u32 == *rank)
+    }
+
+    #[test]
+    fn bayer_matrix() {
+        assert_eq!(bayer_ranks(2), vec![0, 2, 3, 1]);
+        assert!(is_permutation(&bayer_ranks(8)));
+    }
+
+    #[test]
+    fn blue_noise_is_deterministic_permutation() {
+        let ranks = blue_noise_ranks(16);
+        assert!(is_permutation(&ranks));
+        assert_eq!(ranks, blue_noise_ranks(16));
+    }
+
+    #[test]
+    fn random_vectors_are_normalized() {
+        for v in random_vectors(8) {
+            let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            assert!((length - 1.0).abs() < 1e-4);
+            assert!((0.0..1.0).contains(&v[3]));
+        }
+    }
+}
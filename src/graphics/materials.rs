@@ -0,0 +1,180 @@
+use std::ffi::CStr;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::app::engine::CenContext;
+use crate::app::ImageResource;
+use crate::vulkan::{Buffer, DescriptorAllocator, DescriptorSet, DescriptorSetLayout, DevicePlugin, FeatureChain, ImageTrait, Pipeline};
+
+/// Enables the descriptor indexing features a [`MaterialTable`] relies on,
+/// add it with [`AppConfig::device_plugin`](crate::app::app::AppConfig::device_plugin).
+pub struct DescriptorIndexingPlugin;
+
+impl DevicePlugin for DescriptorIndexingPlugin {
+    fn extensions(&self) -> Vec<&'static CStr> {
+        // Core in Vulkan 1.2, listed for drivers that still report the extension separately
+        vec![ash::ext::descriptor_indexing::NAME]
+    }
+
+    fn features(&self, chain: &mut FeatureChain) {
+        chain.push(vk::PhysicalDeviceDescriptorIndexingFeatures::default()
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_update_unused_while_pending(true)
+            .descriptor_binding_partially_bound(true)
+            .runtime_descriptor_array(true));
+    }
+}
+
+/// A table of materials in a storage buffer, together with a bindless array of the textures they reference.
+///
+/// Materials are plain bytes of a fixed size, laid out as an std430 array, and refer to textures by the index
+/// returned from [`add_texture`](Self::add_texture). Both are bound as a single descriptor set:
+/// - binding 0: `readonly buffer { Material materials[]; }`
+/// - binding 1: `uniform sampler2D textures[]`, indexed with `nonuniformEXT`
+///
+/// `#include <cen/materials.glsl>` declares both, see its documentation. Requires the [`DescriptorIndexingPlugin`].
+pub struct MaterialTable {
+    _allocator: DescriptorAllocator,
+    layout: DescriptorSetLayout,
+    set: DescriptorSet,
+    buffer: Buffer,
+    material_size: usize,
+    material_count: u32,
+    max_materials: u32,
+    textures: Vec<ImageResource>,
+    max_textures: u32,
+}
+
+impl MaterialTable {
+
+    /// Create a table for up to `max_materials` materials of `material_size` bytes, the std430 size of the
+    /// material struct, and up to `max_textures` textures.
+    pub fn new(ctx: &mut CenContext, material_size: usize, max_materials: u32, max_textures: u32) -> Self {
+        assert!(material_size > 0 && material_size % 4 == 0, "The material size must be a positive multiple of 4");
+        assert!(max_materials > 0 && max_textures > 0, "A material table needs room for materials and textures");
+
+        let stages = vk::ShaderStageFlags::ALL;
+        let layout_bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(stages),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(max_textures)
+                .stage_flags(stages),
+        ];
+        let layout = DescriptorSetLayout::with_binding_flags(
+            &ctx.gfx.device,
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            &layout_bindings,
+            &[
+                vk::DescriptorBindingFlags::empty(),
+                vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                    | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                    | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+            ]
+        );
+
+        // The table only needs a single set, size the first pool of the allocator for exactly that
+        let sets_per_pool = 64.0;
+        let allocator = DescriptorAllocator::with_flags(
+            &ctx.gfx.device,
+            &[
+                (vk::DescriptorType::STORAGE_BUFFER, 1.0 / sets_per_pool),
+                (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, max_textures as f32 / sets_per_pool),
+            ],
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+        );
+        let set = allocator.allocate(&layout);
+
+        let buffer = Buffer::new(
+            &ctx.gfx.device,
+            &mut ctx.gfx.allocator,
+            MemoryLocation::CpuToGpu,
+            (material_size * max_materials as usize) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+        );
+        let buffer_binding = [buffer.binding()];
+        set.write(&[vk::WriteDescriptorSet::default()
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_binding)]);
+
+        Self {
+            _allocator: allocator,
+            layout,
+            set,
+            buffer,
+            material_size,
+            material_count: 0,
+            max_materials,
+            textures: Vec::new(),
+            max_textures,
+        }
+    }
+
+    /// Layout of the table's descriptor set, to put into the `descriptor_set_layouts` of pipelines that use it.
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Add a texture with `SAMPLED` usage that stays in `SHADER_READ_ONLY_OPTIMAL`, as left by
+    /// [`upload_image`](CenContext::upload_image). Returns its index in the texture array.
+    ///
+    /// The descriptor is written once, so the image shouldn't be recreated, e.g. by matching the swapchain extent.
+    pub fn add_texture(&mut self, ctx: &CenContext, image: &ImageResource) -> u32 {
+        assert!((self.textures.len() as u32) < self.max_textures, "The material table is limited to {} textures", self.max_textures);
+        let index = self.textures.len() as u32;
+
+        // Unused by frames in flight, so it can be written while they execute
+        let image_binding = [ctx.images.get(image).binding(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        self.set.write(&[vk::WriteDescriptorSet::default()
+            .dst_binding(1)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_binding)]);
+
+        self.textures.push(image.clone());
+        index
+    }
+
+    pub fn texture_count(&self) -> u32 {
+        self.textures.len() as u32
+    }
+
+    /// Add a material of `material_size` bytes. Returns its index in the material array.
+    pub fn add_material(&mut self, material: &[u8]) -> u32 {
+        assert!(self.material_count < self.max_materials, "The material table is limited to {} materials", self.max_materials);
+        let index = self.material_count;
+        self.material_count += 1;
+        self.set_material(index, material);
+        index
+    }
+
+    /// Overwrite a material. The buffer is host visible and written directly,
+    /// so frames that are still in flight may see the new contents.
+    pub fn set_material(&mut self, index: u32, material: &[u8]) {
+        assert!(index < self.material_count, "Material {} was not added", index);
+        assert_eq!(material.len(), self.material_size, "Materials are {} bytes", self.material_size);
+
+        let offset = index as usize * self.material_size;
+        self.buffer.mapped().expect("Failed to map material buffer").as_mut_slice()[offset..offset + self.material_size]
+            .copy_from_slice(material);
+    }
+
+    pub fn material_count(&self) -> u32 {
+        self.material_count
+    }
+
+    /// Bind the table as descriptor set `set` of a bound pipeline that was created with [`layout`](Self::layout).
+    pub fn bind(&self, ctx: &mut CenContext, pipeline: &dyn Pipeline, set: u32) {
+        ctx.command_buffer.bind_descriptor_set(pipeline, set, &self.set);
+        ctx.command_buffer.track(&self.buffer);
+        for texture in &self.textures {
+            ctx.command_buffer.track(ctx.images.get(texture));
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use egui::{Context, Rect, Sense};
+use crate::app::gui::{GuiComponent, GuiContext};
+use crate::vulkan::format_bytes;
+
+/// Debug window with the memory usage of the allocator, see
+/// [`AppConfig::memory_overlay`](crate::app::app::AppConfig::memory_overlay).
+///
+/// Shows the usage per heap, and every memory block as a bar with its allocations, hover a bar for their names.
+#[derive(Default)]
+pub struct MemoryOverlay;
+
+impl GuiComponent for MemoryOverlay {
+    fn gui(&mut self, gui: &mut GuiContext, context: &Context) {
+        let report = gui.gfx.allocator.memory_report();
+        egui::Window::new("GPU memory")
+            .id(egui::Id::new("cen_memory_overlay"))
+            .default_open(false)
+            .show(context, |ui| {
+                egui::Grid::new("cen_memory_heaps").striped(true).show(ui, |ui| {
+                    for (index, heap) in report.heaps.iter().enumerate() {
+                        ui.label(format!("Heap {}{}", index, if heap.device_local { " (device local)" } else { "" }));
+                        ui.add(egui::ProgressBar::new(heap.allocated_bytes as f32 / heap.size.max(1) as f32)
+                            .desired_width(160.0)
+                            .text(format!("{} / {}", format_bytes(heap.allocated_bytes), format_bytes(heap.size))));
+                        ui.label(format!("{} allocations", heap.allocation_count));
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.label(format!("{} of {} in {} blocks",
+                    format_bytes(report.allocated_bytes()), format_bytes(report.capacity_bytes()), report.allocator.blocks.len()));
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for block in &report.allocator.blocks {
+                        let (rect, response) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 12.0), Sense::hover());
+                        let painter = ui.painter_at(rect);
+                        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+                        let allocations = &report.allocator.allocations[block.allocations.clone()];
+                        let x = |offset: u64| rect.left() + rect.width() * (offset as f64 / block.size.max(1) as f64) as f32;
+                        let mut hovered = None;
+                        for allocation in allocations {
+                            // Keep tiny allocations visible
+                            let (left, right) = (x(allocation.offset), x(allocation.offset + allocation.size));
+                            let allocation_rect = Rect::from_x_y_ranges(left..=right.max(left + 1.0), rect.y_range());
+                            painter.rect_filled(allocation_rect, 0.0, ui.visuals().selection.bg_fill);
+                            if response.hover_pos().is_some_and(|pos| allocation_rect.x_range().contains(pos.x)) {
+                                hovered = Some(allocation);
+                            }
+                        }
+
+                        let free = block.size - allocations.iter().map(|allocation| allocation.size).sum::<u64>();
+                        response.on_hover_text(match hovered {
+                            Some(allocation) => format!("{}: {}", allocation.name, format_bytes(allocation.size)),
+                            None => format!("Block of {}, {} free", format_bytes(block.size), format_bytes(free)),
+                        });
+                    }
+                });
+            });
+    }
+}
@@ -2,6 +2,68 @@ pub mod renderer;
 pub mod context;
 pub mod pipeline_store;
 pub mod image_store;
+pub mod clock;
+pub mod accumulator;
+pub mod lut;
+pub mod random;
+pub mod crash;
+pub mod stats;
+pub mod history;
+pub mod async_compute;
+pub mod prefix_sum;
+pub mod radix_sort;
+pub mod point_cloud;
+pub mod volume;
+pub mod upload;
+pub mod post_process;
+pub mod assets;
+pub mod materials;
+pub mod profiler;
+pub mod frame;
+pub mod globals;
+pub mod atlas;
+pub mod workgroup;
+pub mod export;
+pub mod gpu;
+pub mod presenter;
+pub mod memory;
+pub mod render_graph;
+pub mod image_viewer;
+pub mod surface_size;
+pub mod texture;
+pub mod fps_overlay;
+pub mod component_inspector;
 
-pub use self::renderer::Renderer;
-pub use self::context::{GraphicsContext, ImageContext, PipelineContext};
\ No newline at end of file
+pub use self::renderer::{FrameFlags, Renderer};
+pub use self::context::{GraphicsContext, ImageContext, PipelineContext};
+pub use self::gpu::GpuContext;
+pub use self::presenter::Presenter;
+pub use self::clock::AnimationClock;
+pub use self::export::{ExportConfig, ExportedFrame, FrameSink, MovieBuffer, MovieFrame};
+pub use self::accumulator::Accumulator;
+pub use self::lut::{Lut, MAX_BLUE_NOISE_SIZE};
+pub use self::random::RandomProvider;
+pub use self::crash::CrashState;
+pub use self::stats::FrameStats;
+pub use self::history::HistoryImage;
+pub use self::async_compute::AsyncCompute;
+pub use self::prefix_sum::PrefixSum;
+pub use self::radix_sort::RadixSort;
+pub use self::point_cloud::{Point, PointCloud, PointCloudRenderer};
+pub use self::volume::VolumeRenderer;
+pub use self::upload::Uploader;
+pub use self::post_process::PostProcessPipeline;
+pub use self::assets::{Asset, AssetLoader};
+pub use self::materials::{DescriptorIndexingPlugin, MaterialTable};
+pub use self::profiler::{GpuProfiler, GpuTiming};
+pub use self::memory::MemoryOverlay;
+pub use self::fps_overlay::FpsOverlay;
+pub use self::component_inspector::ComponentInspector;
+pub use self::image_viewer::{Colormap, ImageViewer, ViewChannel};
+pub use self::surface_size::{SurfaceSizePolicy, SurfaceSizes};
+pub use self::texture::{TextureData, TextureError, TextureOptions};
+pub use self::render_graph::{BufferAccess, GraphBuffer, GraphExport, GraphImage, ImageAccess, PassBuilder, RenderGraph};
+pub use self::frame::{Frame, FrameCallback, FrameResources, DEFAULT_FRAMES_IN_FLIGHT};
+pub use self::globals::{FrameGlobals, GlobalUniforms};
+pub use self::atlas::{Atlas, AtlasRect, SkylinePacker};
+pub use self::workgroup::{PipelineStatisticsPlugin, WorkgroupReport, WorkgroupTiming};
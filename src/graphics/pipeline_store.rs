@@ -1,15 +1,20 @@
-use std::path::{PathBuf};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use log::error;
+use log::{error, warn};
 use notify::{RecommendedWatcher, RecursiveMode};
 use notify_debouncer_mini::{DebounceEventResult, Debouncer};
 use notify_debouncer_mini::DebouncedEventKind::Any;
 use slotmap::{new_key_type, SlotMap};
 use winit::event_loop::{EventLoopProxy};
 use crate::app::app::UserEvent;
-use crate::vulkan::{GraphicsPipelineConfig, ComputePipeline, Device, GraphicsPipeline, Pipeline, PipelineErr, ComputePipelineConfig};
+use crate::vulkan::{is_builtin_shader, GraphicsPipelineConfig, ComputePipeline, Device, FrameEvent, GraphicsPipeline, Pipeline, PipelineErr, ComputePipelineConfig, ShaderSource};
 
-new_key_type! { pub struct PipelineKey; }
+new_key_type! {
+    pub struct PipelineKey;
+    /// Group of pipelines that share a lifetime, e.g. the pipelines of one scene or plugin.
+    pub struct PipelineNamespace;
+}
 
 pub enum PipelineHandle {
     Graphics(GraphicsPipelineConfig, GraphicsPipeline),
@@ -19,6 +24,11 @@ pub enum PipelineHandle {
 pub trait IntoPipelineHandle {
     fn into_pipeline_handle(self, device: &Device) -> Result<PipelineHandle, PipelineErr>;
     fn shader_paths(&self) -> Vec<&PathBuf>;
+    /// The shader sources, to resolve their paths before loading them. Paths of handles without any are used as
+    /// they are.
+    fn shader_sources_mut(&mut self) -> Vec<&mut ShaderSource> {
+        Vec::new()
+    }
 }
 
 impl IntoPipelineHandle for GraphicsPipelineConfig {
@@ -32,7 +42,13 @@ impl IntoPipelineHandle for GraphicsPipelineConfig {
     }
 
     fn shader_paths(&self) -> Vec<&PathBuf> {
-        vec![&self.fragment_shader_source, &self.vertex_shader_source]
+        [&self.fragment_shader_source, &self.vertex_shader_source].into_iter()
+            .filter_map(ShaderSource::path)
+            .collect()
+    }
+
+    fn shader_sources_mut(&mut self) -> Vec<&mut ShaderSource> {
+        vec![&mut self.fragment_shader_source, &mut self.vertex_shader_source]
     }
 }
 
@@ -47,29 +63,132 @@ impl IntoPipelineHandle for ComputePipelineConfig {
     }
 
     fn shader_paths(&self) -> Vec<&PathBuf> {
-        vec![&self.shader_source]
+        self.shader_source.path().into_iter().collect()
     }
+
+    fn shader_sources_mut(&mut self) -> Vec<&mut ShaderSource> {
+        vec![&mut self.shader_source]
+    }
+}
+
+/// Where shader files are loaded from, for deployments where the shaders live in another directory than during
+/// development. Pipelines load and watch the resolved paths, so hot reloading follows the remapped files.
+///
+/// ```ignore
+/// // Edit the shaders in the source tree, while the config names the installed ones
+/// let paths = ShaderPaths::default()
+///     .remap("/opt/app/shaders", "/home/dev/app/shaders")
+///     .root("assets");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShaderPaths {
+    roots: Vec<PathBuf>,
+    remaps: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ShaderPaths {
+    /// Look up relative shader paths that don't exist from the working directory in `root`. Roots are searched in
+    /// the order they were added.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Load shaders under the `from` directory from the `to` directory instead. The first matching rule applies.
+    pub fn remap(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.remaps.push((from.into(), to.into()));
+        self
+    }
+
+    /// The path a shader at `path` is loaded from: remapped first, then searched in the roots. Paths that aren't
+    /// found anywhere stay as they are, so loading them reports the original path.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        // Built-in shaders aren't files
+        if is_builtin_shader(path) {
+            return path.to_path_buf();
+        }
+        let path = self.remaps.iter()
+            .find_map(|(from, to)| path.strip_prefix(from).ok().map(|rest| to.join(rest)))
+            .unwrap_or_else(|| path.to_path_buf());
+        if path.is_absolute() || path.exists() {
+            return path;
+        }
+        self.roots.iter()
+            .map(|root| root.join(&path))
+            .find(|candidate| candidate.exists())
+            .unwrap_or(path)
+    }
+
+    fn resolve_sources(&self, config: &mut impl IntoPipelineHandle) {
+        for source in config.shader_sources_mut() {
+            if let ShaderSource::File(path) = source {
+                *path = self.resolve(path);
+            }
+        }
+    }
+}
+
+struct StoredPipeline {
+    handle: PipelineHandle,
+    namespace: PipelineNamespace,
+    name: Option<String>,
+}
+
+/// A pipeline of the store, for listing them in a debug ui, see [`PipelineStore::entries`].
+pub struct PipelineEntry<'a> {
+    pub key: PipelineKey,
+    /// Name it was registered under with [`PipelineStore::insert_named`].
+    pub name: Option<&'a str>,
+    pub namespace: PipelineNamespace,
+    pub pipeline: &'a dyn Pipeline,
+    /// Shader files it loads, without includes.
+    pub shader_paths: Vec<&'a PathBuf>,
+}
+
+#[derive(Default)]
+struct Namespace {
+    pipelines: Vec<PipelineKey>,
+    watch_roots: Vec<PathBuf>,
 }
 
 pub struct PipelineStore {
     device: Device,
-    pipelines: SlotMap<PipelineKey, PipelineHandle>,
+    pipelines: SlotMap<PipelineKey, StoredPipeline>,
+    namespaces: SlotMap<PipelineNamespace, Namespace>,
+    default_namespace: PipelineNamespace,
+    names: HashMap<String, PipelineKey>,
+    // Number of pipelines and watch roots that need each watched path
+    watched: HashMap<PathBuf, usize>,
     watcher: Debouncer<RecommendedWatcher>,
+    shader_paths: ShaderPaths,
 }
 
 impl PipelineStore {
     pub fn new(device: &Device, proxy: EventLoopProxy<UserEvent>) -> PipelineStore {
+        Self::with_watch_callback(device, Self::watch_callback(proxy))
+    }
+
+    /// Store that passes the changes of the watched shader files to `callback`, e.g. without an event loop in tests.
+    fn with_watch_callback(device: &Device, callback: impl FnMut(DebounceEventResult) + Send + 'static) -> PipelineStore {
 
         // Register file watching for the shaders
         let watcher = notify_debouncer_mini::new_debouncer(
                 Duration::from_millis(250),
-                Self::watch_callback(proxy)
+                callback
             ).expect("Failed to create file watcher");
 
+        let mut namespaces = SlotMap::with_key();
+        let default_namespace = namespaces.insert(Namespace::default());
+
         PipelineStore {
             watcher,
             device: device.clone(),
             pipelines: SlotMap::with_key(),
+            namespaces,
+            default_namespace,
+            names: HashMap::new(),
+            watched: HashMap::new(),
+            shader_paths: ShaderPaths::default(),
         }
     }
 
@@ -90,22 +209,197 @@ impl PipelineStore {
         }
     }
 
+    fn watch(&mut self, path: &Path) {
+        // Built-in shaders never change
+        if is_builtin_shader(path) {
+            return;
+        }
+        let count = self.watched.entry(path.to_path_buf()).or_insert(0);
+        if *count == 0 {
+            self.watcher.watcher().watch(path, RecursiveMode::Recursive).unwrap_or_else(|_|{
+                panic!("Failed to find path {:?}", path);
+            });
+        }
+        *count += 1;
+    }
+
+    fn unwatch(&mut self, path: &Path) {
+        let Some(count) = self.watched.get_mut(path) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.watched.remove(path);
+            if let Err(e) = self.watcher.watcher().unwatch(path) {
+                warn!("Failed to unwatch {:?}: {}", path, e);
+            }
+        }
+    }
+
+    /// Shader files and the files they include, the include graph of a pipeline.
+    fn handle_paths(handle: &PipelineHandle) -> Vec<PathBuf> {
+        let (shaders, includes) = match handle {
+            PipelineHandle::Graphics(config, pipeline) => (config.shader_paths(), pipeline.includes()),
+            PipelineHandle::Compute(config, pipeline) => (config.shader_paths(), pipeline.includes()),
+        };
+        shaders.into_iter().chain(includes).cloned().collect()
+    }
+
+    /// Whether the pipeline loads `path`, which may be the absolute path of a changed file.
+    fn depends_on(handle: &PipelineHandle, path: &Path) -> bool {
+        let (loads_source, includes) = match handle {
+            PipelineHandle::Graphics(config, pipeline) => (
+                config.vertex_shader_source.is_loaded_from(path) || config.fragment_shader_source.is_loaded_from(path),
+                pipeline.includes(),
+            ),
+            PipelineHandle::Compute(config, pipeline) => (config.shader_source.is_loaded_from(path), pipeline.includes()),
+        };
+        loads_source || includes.iter().any(|include| path.ends_with(include))
+    }
+
+    /// Swap in a recreated pipeline, watching the files it uses instead of the old ones.
+    fn replace_handle(&mut self, key: PipelineKey, handle: PipelineHandle) {
+        // Watch first, so files both use stay watched
+        for path in Self::handle_paths(&handle) {
+            self.watch(&path);
+        }
+        let stored = self.pipelines.get_mut(key).expect("Key not found");
+        let old = std::mem::replace(&mut stored.handle, handle);
+        for path in Self::handle_paths(&old) {
+            self.unwatch(&path);
+        }
+    }
+
+    /// Resolve the shader paths of pipelines inserted from now on with `shader_paths`.
+    pub fn set_shader_paths(&mut self, shader_paths: ShaderPaths) {
+        self.shader_paths = shader_paths;
+    }
+
+    pub fn shader_paths(&self) -> &ShaderPaths {
+        &self.shader_paths
+    }
+
+    /// The namespace pipelines are inserted into by [`insert`](PipelineStore::insert).
+    pub fn default_namespace(&self) -> PipelineNamespace {
+        self.default_namespace
+    }
+
+    /// Create an empty namespace, its pipelines can be unloaded together with [`unload_namespace`](PipelineStore::unload_namespace).
+    pub fn create_namespace(&mut self) -> PipelineNamespace {
+        self.namespaces.insert(Namespace::default())
+    }
+
+    /// Additionally watch a directory for shader changes for as long as the namespace holds it,
+    /// e.g. a directory with shared include files.
+    pub fn add_watch_root(&mut self, namespace: PipelineNamespace, path: impl Into<PathBuf>) {
+        let path = self.shader_paths.resolve(&path.into());
+        assert!(self.namespaces.contains_key(namespace), "Namespace not found");
+        self.watch(&path);
+        self.namespaces[namespace].watch_roots.push(path);
+    }
+
+    /// Remove all pipelines and watch roots of the namespace. The namespace itself stays valid.
+    /// Pipelines that are still referenced by in-flight command buffers are destroyed once those have executed.
+    pub fn unload_namespace(&mut self, namespace: PipelineNamespace) {
+        let Some(data) = self.namespaces.get_mut(namespace) else {
+            return;
+        };
+        let pipelines = std::mem::take(&mut data.pipelines);
+        let watch_roots = std::mem::take(&mut data.watch_roots);
+
+        for key in pipelines {
+            self.remove_pipeline(key);
+        }
+        for path in watch_roots {
+            self.unwatch(&path);
+        }
+    }
+
+    /// Namespace the pipeline was inserted into.
+    pub fn namespace(&self, key: PipelineKey) -> Option<PipelineNamespace> {
+        self.pipelines.get(key).map(|stored| stored.namespace)
+    }
+
+    /// Number of pipelines in the namespace.
+    pub fn namespace_len(&self, namespace: PipelineNamespace) -> usize {
+        self.namespaces.get(namespace).map_or(0, |data| data.pipelines.len())
+    }
+
     pub fn insert(&mut self, config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.insert_into(self.default_namespace, config)
+    }
+
+    pub fn insert_into(&mut self, namespace: PipelineNamespace, config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.insert_stored(namespace, None, config)
+    }
+
+    /// Insert a pipeline other components can look up by name with [`get_named`](Self::get_named), instead of
+    /// passing its key around. Fails if the name is taken, until the pipeline of that name is removed.
+    pub fn insert_named(&mut self, name: impl Into<String>, config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.insert_stored(self.default_namespace, Some(name.into()), config)
+    }
+
+    fn insert_stored(&mut self, namespace: PipelineNamespace, name: Option<String>, mut config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        if let Some(name) = name.as_ref().filter(|name| self.names.contains_key(*name)) {
+            return Err(PipelineErr::DuplicateName(name.clone()));
+        }
+        assert!(self.namespaces.contains_key(namespace), "Namespace not found");
+
+        self.shader_paths.resolve_sources(&mut config);
+        let handle = config.into_pipeline_handle(&self.device)?;
 
         // Watch for file changes
-        config.shader_paths().iter().for_each(|path| {
-            self.watcher.watcher().watch(path.as_path(), RecursiveMode::Recursive).unwrap_or_else(|_|{
-                panic!("Failed to find path {:?}", path.as_path());
-            });
-        });
+        for path in Self::handle_paths(&handle) {
+            self.watch(&path);
+        }
+
+        let key = self.pipelines.insert(StoredPipeline { handle, namespace, name: name.clone() });
+        if let Some(name) = name {
+            self.names.insert(name, key);
+        }
+        self.namespaces[namespace].pipelines.push(key);
+        Ok(key)
+    }
+
+    fn remove_pipeline(&mut self, key: PipelineKey) -> Option<StoredPipeline> {
+        let stored = self.pipelines.remove(key)?;
+        if let Some(name) = &stored.name {
+            self.names.remove(name);
+        }
+        for path in Self::handle_paths(&stored.handle) {
+            self.unwatch(&path);
+        }
+        Some(stored)
+    }
+
+    /// Remove a pipeline and stop watching its shaders if no other pipeline uses them.
+    /// Returns whether the key was present.
+    ///
+    /// Command buffers keep the pipelines they bound alive, so the Vulkan objects are only
+    /// destroyed once no in-flight command buffer references them anymore.
+    pub fn remove(&mut self, key: PipelineKey) -> bool {
+        let Some(stored) = self.remove_pipeline(key) else {
+            return false;
+        };
+        if let Some(namespace) = self.namespaces.get_mut(stored.namespace) {
+            namespace.pipelines.retain(|k| *k != key);
+        }
+        true
+    }
 
-        Ok(self.pipelines.insert(config.into_pipeline_handle(&self.device)?))
+    /// Number of pipelines in the store.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
     }
 
     pub fn get(&self, key: PipelineKey) -> Option<&dyn Pipeline> {
         self.pipelines.get(key)
-            .map(|handle| {
-                match handle {
+            .map(|stored| {
+                match &stored.handle {
                     PipelineHandle::Graphics(_, pipeline) => {
                         pipeline as &dyn Pipeline
                     }
@@ -116,35 +410,110 @@ impl PipelineStore {
             })
     }
 
-    pub fn write(&mut self, key: PipelineKey, config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
-        *self.pipelines.get_mut(key).expect("Key not found") = config.into_pipeline_handle(&self.device)?;
+    /// Key of the pipeline registered under `name`.
+    pub fn named_key(&self, name: &str) -> Option<PipelineKey> {
+        self.names.get(name).copied()
+    }
+
+    /// The pipeline registered under `name` with [`insert_named`](Self::insert_named).
+    pub fn get_named(&self, name: &str) -> Option<&dyn Pipeline> {
+        self.named_key(name).and_then(|key| self.get(key))
+    }
+
+    /// All pipelines of the store, in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = PipelineEntry<'_>> {
+        self.pipelines.iter().map(|(key, stored)| {
+            let (pipeline, shader_paths) = match &stored.handle {
+                PipelineHandle::Graphics(config, pipeline) => (pipeline as &dyn Pipeline, config.shader_paths()),
+                PipelineHandle::Compute(config, pipeline) => (pipeline as &dyn Pipeline, config.shader_paths()),
+            };
+            PipelineEntry { key, name: stored.name.as_deref(), namespace: stored.namespace, pipeline, shader_paths }
+        })
+    }
+
+    pub fn write(&mut self, key: PipelineKey, mut config: impl IntoPipelineHandle) -> Result<PipelineKey, PipelineErr> {
+        self.shader_paths.resolve_sources(&mut config);
+        let handle = config.into_pipeline_handle(&self.device)?;
+
+        // The new config may use different shader files
+        self.replace_handle(key, handle);
+
         Ok(key)
     }
 
-    pub fn reload(&mut self, path: &PathBuf) -> Result<(), PipelineErr> {
-        // Look through all shaders with the given path and recreate them
-        for (_, handle) in self.pipelines.iter_mut() {
-            match handle {
-                PipelineHandle::Graphics(config, pipeline) => {
-                    if path.ends_with(&config.vertex_shader_source) || path.ends_with(&config.fragment_shader_source) {
-                        *pipeline = GraphicsPipeline::new(
-                            &self.device,
-                            config.clone()
-                        )?;
-                    }
-                }
-                PipelineHandle::Compute(config, pipeline) => {
-                    if path.ends_with(&config.shader_source) {
-                        *pipeline = ComputePipeline::new(
-                            &self.device,
-                            config.clone()
-                        )?;
-                    }
-                }
+    /// Recreate all pipelines that load the changed file, either as a shader or through an include. The path is
+    /// remapped like the shader paths, see [`ShaderPaths`].
+    ///
+    /// Pipelines that fail to compile keep their previous version, while the others are still recreated. Returns
+    /// the errors of all failed pipelines.
+    pub fn reload(&mut self, path: &PathBuf) -> Result<(), Vec<PipelineErr>> {
+        let path = &self.shader_paths.resolve(path);
+        let keys: Vec<PipelineKey> = self.pipelines.iter()
+            .filter(|(_, stored)| Self::depends_on(&stored.handle, path))
+            .map(|(key, _)| key)
+            .collect();
+
+        let mut errors = Vec::new();
+        for key in keys {
+            let result = match &self.pipelines[key].handle {
+                PipelineHandle::Graphics(config, _) => config.clone().into_pipeline_handle(&self.device),
+                PipelineHandle::Compute(config, _) => config.clone().into_pipeline_handle(&self.device),
+            };
+            self.device.event_log().record(FrameEvent::PipelineReloaded {
+                path: path.clone(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+            });
+
+            // Edits to the includes may have changed what the pipeline includes
+            match result {
+                Ok(handle) => self.replace_handle(key, handle),
+                Err(e) => errors.push(e),
             }
         }
 
+        if !errors.is_empty() {
+            return Err(errors);
+        }
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::Entry;
+    use super::*;
+    use crate::vulkan::{compile_shader_code, Instance, FULLSCREEN_VERTEX_SHADER};
+
+    #[test]
+    fn resolve_shader_paths() {
+        let paths = ShaderPaths::default()
+            .remap("/opt/app/shaders", "/home/dev/app/shaders")
+            .root("does/not/exist")
+            .root("examples");
+
+        assert_eq!(paths.resolve(Path::new("/opt/app/shaders/blur.comp")), PathBuf::from("/home/dev/app/shaders/blur.comp"));
+        assert_eq!(paths.resolve(Path::new("compute/shader.comp")), PathBuf::from("examples/compute/shader.comp"));
+        // Found from the working directory, or nowhere
+        assert_eq!(paths.resolve(Path::new("examples/compute/shader.comp")), PathBuf::from("examples/compute/shader.comp"));
+        assert_eq!(paths.resolve(Path::new("missing.comp")), PathBuf::from("missing.comp"));
+        assert_eq!(paths.resolve(Path::new(FULLSCREEN_VERTEX_SHADER)), PathBuf::from(FULLSCREEN_VERTEX_SHADER));
+    }
+
+    #[test]
+    fn reject_duplicate_names() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+        let mut store = PipelineStore::with_watch_callback(&device, |_| {});
+
+        let code = compile_shader_code("#version 450\nvoid main() {}\n", "empty.comp", shaderc::ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile shader");
+        let config = || ComputePipelineConfig { shader_source: ShaderSource::SpirV(code.clone()), ..Default::default() };
+
+        let key = store.insert_named("empty", config()).expect("Failed to create pipeline");
+        assert!(matches!(store.insert_named("empty", config()), Err(PipelineErr::DuplicateName(name)) if name == "empty"));
+        assert_eq!(store.named_key("empty"), Some(key));
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::{compile_shader_code, glsl_image_format, Buffer, ComputePipeline, DescriptorSetLayout, ImageConfig, ImageTrait, STORAGE_COLOR_FORMATS};
+
+const SPLAT_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 256, local_size_y = 1, local_size_z = 1 ) in;
+
+struct Point {
+    vec3 position;
+    uint color;
+};
+
+layout( std430, binding = 0 ) readonly buffer Points { Point points[]; };
+layout( binding = 1, r32ui ) uniform uimage2D depth_image;
+layout( binding = 2, COLOR_FORMAT ) uniform writeonly image2D color_image;
+
+layout( push_constant ) uniform PushConstants {
+    mat4 view_projection;
+    uint count;
+    uint stride;
+} pc;
+
+void main()
+{
+    uint invocation = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 256u + gl_GlobalInvocationID.x;
+    uint i = invocation * pc.stride;
+    if( i >= pc.count )
+    {
+        return;
+    }
+
+    Point point = points[i];
+    vec4 clip = pc.view_projection * vec4( point.position, 1.0 );
+
+    // Frustum culling
+    if( clip.w <= 0.0 )
+    {
+        return;
+    }
+    vec3 ndc = clip.xyz / clip.w;
+    if( any( lessThan( ndc, vec3( -1.0, -1.0, 0.0 ) ) ) || any( greaterThan( ndc, vec3( 1.0 ) ) ) )
+    {
+        return;
+    }
+
+    ivec2 size = imageSize( depth_image );
+    ivec2 pixel = min( ivec2( ( ndc.xy * 0.5 + 0.5 ) * vec2( size ) ), size - 1 );
+
+    // Non-negative floats order the same as their bit patterns
+    uint depth = floatBitsToUint( ndc.z );
+
+#ifdef COLOR_PASS
+    if( imageLoad( depth_image, pixel ).r == depth )
+    {
+        imageStore( color_image, pixel, unpackUnorm4x8( point.color ) );
+    }
+#else
+    imageAtomicMin( depth_image, pixel, depth );
+#endif
+}
+"#;
+
+const WORKGROUP_SIZE: u32 = 256;
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// A point as stored in the point buffer: a position and an `RGBA8` color packed with red in the lowest byte.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point {
+    pub position: [f32; 3],
+    pub color: u32,
+}
+
+impl Point {
+    pub fn new(position: [f32; 3], color: [u8; 4]) -> Self {
+        Self { position, color: u32::from_le_bytes(color) }
+    }
+}
+
+/// Points in a storage buffer.
+pub struct PointCloud {
+    buffer: Buffer,
+    count: u32,
+}
+
+impl PointCloud {
+
+    /// Upload points to a new storage buffer.
+    pub fn new(ctx: &mut CenContext, points: &[Point]) -> Self {
+        let size = std::mem::size_of_val(points).max(std::mem::size_of::<Point>());
+        let buffer = Buffer::new(
+            &ctx.gfx.device,
+            &mut ctx.gfx.allocator,
+            MemoryLocation::CpuToGpu,
+            size as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+        );
+        {
+            let mut mapped = buffer.mapped().expect("Failed to map point buffer");
+            for (chunk, point) in mapped.as_mut_slice().chunks_exact_mut(std::mem::size_of::<Point>()).zip(points) {
+                chunk[0..4].copy_from_slice(&point.position[0].to_ne_bytes());
+                chunk[4..8].copy_from_slice(&point.position[1].to_ne_bytes());
+                chunk[8..12].copy_from_slice(&point.position[2].to_ne_bytes());
+                chunk[12..16].copy_from_slice(&point.color.to_ne_bytes());
+            }
+        }
+
+        Self { buffer, count: points.len() as u32 }
+    }
+
+    /// Use points that are already in a storage buffer, laid out as [`Point`], e.g. written by a simulation.
+    pub fn from_buffer(buffer: Buffer, count: u32) -> Self {
+        Self { buffer, count }
+    }
+
+    /// Load the vertices of a PLY file, see [`load_ply`].
+    pub fn from_ply(ctx: &mut CenContext, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(ctx, &load_ply(path)?))
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+/// Renders point clouds with compute shaders instead of the rasterizer, which scales to many millions of points.
+///
+/// Points outside the view frustum are culled. With a point budget, the cloud is decimated uniformly
+/// so no more than the budget is splatted per frame. The result is written to an `R8G8B8A8_UNORM` storage image,
+/// or a float RGBA one on devices without storage support for it, that can be copied or blitted to the swapchain, or
+/// composed in a later pass.
+pub struct PointCloudRenderer {
+    depth_pipeline: ComputePipeline,
+    color_pipeline: ComputePipeline,
+    _layout: DescriptorSetLayout,
+    depth_image: ImageResource,
+    color_image: ImageResource,
+    point_budget: Option<u32>,
+    background: [f32; 4],
+}
+
+impl PointCloudRenderer {
+
+    /// The target images are created with `extent` and `flags`, e.g. [`ImageFlags::MATCH_SWAPCHAIN_EXTENT`].
+    pub fn new(ctx: &mut CenContext, extent: vk::Extent2D, flags: ImageFlags) -> Self {
+        let color_usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC;
+        let color_format = ctx.gfx.device
+            .select_format(&STORAGE_COLOR_FORMATS, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST | color_usage)
+            .expect("No RGBA storage image format is supported");
+        let mut create_image = |format: vk::Format, usage: vk::ImageUsageFlags| ctx.create_image(
+            ImageConfig {
+                extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+                format,
+                image_usage_flags: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST | usage,
+                ..Default::default()
+            },
+            flags
+        );
+        let depth_image = create_image(vk::Format::R32_UINT, vk::ImageUsageFlags::empty());
+        let color_image = create_image(color_format, color_usage);
+
+        let layout_bindings = [
+            (0, vk::DescriptorType::STORAGE_BUFFER),
+            (1, vk::DescriptorType::STORAGE_IMAGE),
+            (2, vk::DescriptorType::STORAGE_IMAGE),
+        ].map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &layout_bindings);
+
+        let compile = |macros: HashMap<String, String>| {
+            let code = compile_shader_code(SPLAT_SHADER, "cen/point_splat.comp", shaderc::ShaderKind::Compute, &macros)
+                .expect("Failed to compile point splatting shader");
+            ComputePipeline::from_spirv(
+                &ctx.gfx.device,
+                code,
+                &[layout.clone()],
+                &[vk::PushConstantRange::default()
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .size(72)]
+            )
+        };
+        let color_format = glsl_image_format(color_format).unwrap().to_string();
+        let depth_pipeline = compile(HashMap::from([("COLOR_FORMAT".to_string(), color_format.clone())]));
+        let color_pipeline = compile(HashMap::from([
+            ("COLOR_FORMAT".to_string(), color_format),
+            ("COLOR_PASS".to_string(), "1".to_string()),
+        ]));
+
+        Self {
+            depth_pipeline,
+            color_pipeline,
+            _layout: layout,
+            depth_image,
+            color_image,
+            point_budget: None,
+            background: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    /// Maximum number of points to splat per frame, `None` renders all points.
+    pub fn set_point_budget(&mut self, point_budget: Option<u32>) {
+        self.point_budget = point_budget;
+    }
+
+    /// Color of pixels without points.
+    pub fn set_background(&mut self, background: [f32; 4]) {
+        self.background = background;
+    }
+
+    /// The rendered image, left in `ImageLayout::GENERAL`.
+    pub fn image(&self) -> &ImageResource {
+        &self.color_image
+    }
+
+    /// Render the point cloud with a column-major view-projection matrix. Depth is expected in the range [0, 1].
+    pub fn render(&self, ctx: &mut CenContext, cloud: &PointCloud, view_projection: [[f32; 4]; 4]) {
+        let depth_image = ctx.images.get(&self.depth_image);
+        let color_image = ctx.images.get(&self.color_image);
+
+        // Clear the targets
+        ctx.command_buffer.image_barriers(
+            &[depth_image, color_image],
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_READ,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        ctx.command_buffer.clear_color_image_u32(depth_image, vk::ImageLayout::GENERAL, [u32::MAX; 4]);
+        ctx.command_buffer.clear_color_image(color_image, vk::ImageLayout::GENERAL, self.background);
+        ctx.command_buffer.image_barriers(
+            &[depth_image, color_image],
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        // Decimate uniformly to stay within the budget
+        let stride = match self.point_budget {
+            Some(budget) => cloud.count.div_ceil(budget.max(1)).max(1),
+            None => 1,
+        };
+        let invocations = cloud.count.div_ceil(stride);
+        let workgroups = invocations.div_ceil(WORKGROUP_SIZE).max(1);
+        let (groups_x, groups_y) = (workgroups.min(MAX_WORKGROUPS), workgroups.div_ceil(MAX_WORKGROUPS));
+
+        let mut push_constants = view_projection.iter().flatten().flat_map(|v| v.to_ne_bytes()).collect::<Vec<u8>>();
+        push_constants.extend_from_slice(&cloud.count.to_ne_bytes());
+        push_constants.extend_from_slice(&stride.to_ne_bytes());
+
+        let point_binding = [cloud.buffer.binding()];
+        let depth_binding = [depth_image.binding(vk::ImageLayout::GENERAL)];
+        let color_binding = [color_image.binding(vk::ImageLayout::GENERAL)];
+        ctx.command_buffer.track(&cloud.buffer);
+
+        for pipeline in [&self.depth_pipeline, &self.color_pipeline] {
+            ctx.command_buffer.bind_pipeline(pipeline);
+            ctx.command_buffer.push_descriptor_set(pipeline, 0, &[
+                vk::WriteDescriptorSet::default()
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&point_binding),
+                vk::WriteDescriptorSet::default()
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&depth_binding),
+                vk::WriteDescriptorSet::default()
+                    .dst_binding(2)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&color_binding),
+            ]);
+            ctx.command_buffer.push_constants(pipeline, vk::ShaderStageFlags::COMPUTE, 0, &push_constants);
+            ctx.command_buffer.dispatch(groups_x, groups_y, 1);
+
+            // The color pass reads the resolved depth, later passes read the color
+            ctx.command_buffer.image_barriers(
+                &[depth_image, color_image],
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::MEMORY_READ,
+            );
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum PlyType {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+
+    fn read(&self, bytes: &[u8], format: PlyFormat) -> f64 {
+        macro_rules! from_bytes {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                (if format == PlyFormat::BinaryBigEndian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }) as f64
+            }};
+        }
+        match self {
+            PlyType::I8 => from_bytes!(i8),
+            PlyType::U8 => from_bytes!(u8),
+            PlyType::I16 => from_bytes!(i16),
+            PlyType::U16 => from_bytes!(u16),
+            PlyType::I32 => from_bytes!(i32),
+            PlyType::U32 => from_bytes!(u32),
+            PlyType::F32 => from_bytes!(f32),
+            PlyType::F64 => from_bytes!(f64),
+        }
+    }
+
+    /// Scale of a color channel of this type to the [0, 255] range.
+    fn color_scale(&self) -> f64 {
+        match self {
+            PlyType::F32 | PlyType::F64 => 255.0,
+            PlyType::U16 => 255.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Load the vertices of a PLY file as points, in ASCII or binary encoding.
+/// Uses the `x`, `y` and `z` properties and the `red`, `green`, `blue` and `alpha` properties if present.
+/// Points without color are white.
+pub fn load_ply(path: impl AsRef<Path>) -> io::Result<Vec<Point>> {
+    parse_ply(BufReader::new(File::open(path)?))
+}
+
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<()> {
+    line.clear();
+    if reader.read_line(line)? == 0 {
+        return Err(invalid_data("Unexpected end of PLY file"));
+    }
+    Ok(())
+}
+
+fn parse_ply(mut reader: impl BufRead) -> io::Result<Vec<Point>> {
+    let mut line = String::new();
+
+    read_line(&mut reader, &mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data("Not a PLY file"));
+    }
+
+    let mut format = None;
+    // Elements before the vertices need to be skipped, they are only supported in ASCII files
+    let mut elements_before_vertices = 0;
+    let mut vertex_count = None;
+    let mut in_vertex_element = false;
+    let mut properties: Vec<(String, PlyType)> = vec![];
+
+    loop {
+        read_line(&mut reader, &mut line)?;
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(PlyFormat::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(PlyFormat::BinaryBigEndian),
+            ["element", name, count] => {
+                let count = count.parse::<usize>().map_err(|_| invalid_data("Invalid element count"))?;
+                in_vertex_element = *name == "vertex";
+                if in_vertex_element {
+                    vertex_count = Some(count);
+                } else if vertex_count.is_none() {
+                    elements_before_vertices += count;
+                }
+            }
+            ["property", "list", ..] if in_vertex_element => {
+                return Err(invalid_data("List properties on vertices are not supported"));
+            }
+            ["property", ty, name] if in_vertex_element => {
+                let ty = PlyType::parse(ty).ok_or_else(|| invalid_data(format!("Unknown property type {}", ty)))?;
+                properties.push((name.to_string(), ty));
+            }
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("Missing PLY format"))?;
+    let vertex_count = vertex_count.ok_or_else(|| invalid_data("PLY file has no vertices"))?;
+    let index_of = |name: &str| properties.iter().position(|(n, _)| n == name);
+    let (x, y, z) = match (index_of("x"), index_of("y"), index_of("z")) {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => return Err(invalid_data("PLY vertices have no position")),
+    };
+    let colors = [index_of("red"), index_of("green"), index_of("blue"), index_of("alpha")];
+
+    let to_point = |values: &[f64]| {
+        let mut color = [255u8; 4];
+        for (channel, index) in colors.iter().enumerate() {
+            if let Some(index) = index {
+                color[channel] = (values[*index] * properties[*index].1.color_scale()).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Point::new([values[x] as f32, values[y] as f32, values[z] as f32], color)
+    };
+
+    let mut points = Vec::with_capacity(vertex_count);
+    let mut values = vec![0f64; properties.len()];
+
+    if format == PlyFormat::Ascii {
+        for _ in 0..elements_before_vertices {
+            read_line(&mut reader, &mut line)?;
+        }
+        for _ in 0..vertex_count {
+            read_line(&mut reader, &mut line)?;
+            for (value, token) in values.iter_mut().zip(line.split_whitespace()) {
+                *value = token.parse().map_err(|_| invalid_data(format!("Invalid PLY value {}", token)))?;
+            }
+            points.push(to_point(&values));
+        }
+    } else {
+        if elements_before_vertices > 0 {
+            return Err(invalid_data("Binary PLY files with elements before the vertices are not supported"));
+        }
+        let stride = properties.iter().map(|(_, ty)| ty.size()).sum::<usize>();
+        let mut vertex = vec![0u8; stride];
+        for _ in 0..vertex_count {
+            reader.read_exact(&mut vertex)?;
+            let mut offset = 0;
+            for (value, (_, ty)) in values.iter_mut().zip(&properties) {
+                *value = ty.read(&vertex[offset..offset + ty.size()], format);
+                offset += ty.size();
+            }
+            points.push(to_point(&values));
+        }
+    }
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ascii_ply() {
+        let ply = "ply\nformat ascii 1.0\ncomment test\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 0\nproperty list uchar int vertex_indices\nend_header\n\
+            0 1 2 255 0 0\n-1.5 0 3 0 128 255\n";
+        let points = parse_ply(ply.as_bytes()).unwrap();
+        assert_eq!(points, vec![
+            Point::new([0.0, 1.0, 2.0], [255, 0, 0, 255]),
+            Point::new([-1.5, 0.0, 3.0], [0, 128, 255, 255]),
+        ]);
+    }
+
+    #[test]
+    fn parse_binary_ply() {
+        let mut ply = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty double z\nend_header\n".to_vec();
+        ply.extend_from_slice(&1.0f32.to_le_bytes());
+        ply.extend_from_slice(&2.0f32.to_le_bytes());
+        ply.extend_from_slice(&3.0f64.to_le_bytes());
+        let points = parse_ply(ply.as_slice()).unwrap();
+        assert_eq!(points, vec![Point::new([1.0, 2.0, 3.0], [255; 4])]);
+    }
+
+    #[test]
+    fn reject_invalid_ply() {
+        assert!(parse_ply("obj\n".as_bytes()).is_err());
+        assert!(parse_ply("ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n0\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::ImageResource;
+use crate::graphics::pipeline_store::PipelineKey;
+use crate::vulkan::{BlendMode, ComputePipelineConfig, DescriptorSetLayout, GraphicsPipelineConfig, ImageTrait, PipelineErr, FULLSCREEN_VERTEX_SHADER};
+
+/// Size of the push constant range available to post-processing shaders.
+const PUSH_CONSTANT_SIZE: u32 = 128;
+
+/// Workgroup size compute post-processing shaders are dispatched with.
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PassKind {
+    Fragment,
+    Compute,
+}
+
+/// Image-to-image pass from a single shader, with the vertex shader, layout and barriers provided by cen.
+/// The shader is hot reloaded like other pipelines.
+///
+/// The inputs are bound as `sampler2D`s at bindings `0..inputs` of set 0. Both shader kinds can use up to 128 bytes of push constants.
+/// - `.frag` shaders are drawn as a fullscreen triangle into the output, which is color attachment 0.
+///   The texture coordinate is passed at location 0.
+/// - `.comp` shaders write the output as `rgba8` storage image at binding `inputs`, with
+///   `local_size_x = 8, local_size_y = 8` and one invocation per pixel.
+pub struct PostProcessPipeline {
+    pipeline: PipelineKey,
+    _layout: DescriptorSetLayout,
+    kind: PassKind,
+    inputs: u32,
+}
+
+impl PostProcessPipeline {
+
+    /// Create a pass reading `inputs` images. Fragment passes render into images of `output_format`,
+    /// compute passes declare the output format in the shader.
+    pub fn new(ctx: &mut CenContext, shader: impl Into<PathBuf>, inputs: u32, output_format: impl Into<vk::Format>) -> Result<Self, PipelineErr> {
+        let shader = shader.into();
+        let output_format = output_format.into();
+        let kind = match shader.extension().and_then(|e| e.to_str()) {
+            Some("frag") => PassKind::Fragment,
+            Some("comp") => PassKind::Compute,
+            _ => panic!("Post-processing shaders must be .frag or .comp shaders: {:?}", shader),
+        };
+        let stage = match kind {
+            PassKind::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            PassKind::Compute => vk::ShaderStageFlags::COMPUTE,
+        };
+
+        let mut layout_bindings = (0..inputs).map(|binding| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(stage)
+        }).collect::<Vec<_>>();
+        if kind == PassKind::Compute {
+            layout_bindings.push(vk::DescriptorSetLayoutBinding::default()
+                .binding(inputs)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(stage));
+        }
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &layout_bindings);
+        let push_constant_ranges = vec![vk::PushConstantRange::default()
+            .stage_flags(stage)
+            .size(PUSH_CONSTANT_SIZE)];
+
+        let pipeline = match kind {
+            PassKind::Fragment => ctx.create_pipeline(GraphicsPipelineConfig {
+                color_formats: vec![output_format],
+                vertex_shader_source: FULLSCREEN_VERTEX_SHADER.into(),
+                fragment_shader_source: shader.into(),
+                descriptor_set_layouts: vec![layout.clone()],
+                push_constant_ranges,
+                blend: BlendMode::Opaque,
+                ..Default::default()
+            })?,
+            PassKind::Compute => ctx.create_pipeline(ComputePipelineConfig {
+                shader_source: shader.into(),
+                descriptor_set_layouts: vec![layout.clone()],
+                push_constant_ranges,
+                ..Default::default()
+            })?,
+        };
+
+        Ok(Self {
+            pipeline,
+            _layout: layout,
+            kind,
+            inputs,
+        })
+    }
+
+    /// The pipeline in the pipeline store.
+    pub fn pipeline(&self) -> PipelineKey {
+        self.pipeline
+    }
+
+    /// Run the pass. The inputs need `SAMPLED` usage and to be in `ImageLayout::GENERAL`, as left by most passes.
+    /// The output needs `COLOR_ATTACHMENT` or `STORAGE` usage for fragment or compute passes, its previous contents are discarded.
+    /// The output is left in `ImageLayout::GENERAL`.
+    pub fn run(&self, ctx: &mut CenContext, inputs: &[&ImageResource], output: &ImageResource) {
+        self.run_with_push_constants(ctx, inputs, output, &[]);
+    }
+
+    /// Run the pass with up to 128 bytes of push constants, see [`run`](Self::run).
+    pub fn run_with_push_constants(&self, ctx: &mut CenContext, inputs: &[&ImageResource], output: &ImageResource, push_constants: &[u8]) {
+        assert_eq!(inputs.len(), self.inputs as usize, "The pass expects {} inputs", self.inputs);
+        assert!(push_constants.len() <= PUSH_CONSTANT_SIZE as usize, "At most {} bytes of push constants are supported", PUSH_CONSTANT_SIZE);
+
+        let Some(pipeline) = ctx.pipelines.get(self.pipeline) else {
+            return;
+        };
+        let stage = match self.kind {
+            PassKind::Fragment => vk::PipelineStageFlags::FRAGMENT_SHADER,
+            PassKind::Compute => vk::PipelineStageFlags::COMPUTE_SHADER,
+        };
+
+        let inputs = inputs.iter().map(|input| ctx.images.get(input)).collect::<Vec<_>>();
+        let output = ctx.images.get(output);
+
+        // Make earlier writes to the inputs visible
+        for input in &inputs {
+            ctx.command_buffer.image_barrier(
+                *input,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                stage,
+                vk::AccessFlags::MEMORY_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            );
+        }
+
+        let input_bindings = inputs.iter()
+            .map(|input| [input.binding(vk::ImageLayout::GENERAL)])
+            .collect::<Vec<_>>();
+        let mut writes = input_bindings.iter().enumerate().map(|(binding, info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(info)
+        }).collect::<Vec<_>>();
+        let output_binding = [output.binding(vk::ImageLayout::GENERAL)];
+
+        match self.kind {
+            PassKind::Fragment => {
+                ctx.command_buffer.image_barrier(
+                    output,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::MEMORY_READ,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                );
+
+                let extent = output.extent();
+                let color_attachments = [vk::RenderingAttachmentInfo::default()
+                    .image_view(output.image_view())
+                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE)];
+                let rendering_info = vk::RenderingInfo::default()
+                    .render_area(vk::Rect2D::default().extent(extent))
+                    .layer_count(1)
+                    .color_attachments(&color_attachments);
+
+                ctx.command_buffer.begin_rendering(&rendering_info);
+                ctx.command_buffer.bind_pipeline(pipeline);
+                ctx.command_buffer.set_viewport(vk::Viewport::default()
+                    .width(extent.width as f32)
+                    .height(extent.height as f32)
+                    .max_depth(1.0));
+                ctx.command_buffer.set_scissor(vk::Rect2D::default().extent(extent));
+                if !writes.is_empty() {
+                    ctx.command_buffer.push_descriptor_set(pipeline, 0, &writes);
+                }
+                if !push_constants.is_empty() {
+                    ctx.command_buffer.push_constants(pipeline, vk::ShaderStageFlags::FRAGMENT, 0, push_constants);
+                }
+                ctx.command_buffer.draw(3, 1, 0, 0);
+                ctx.command_buffer.end_rendering();
+
+                ctx.command_buffer.image_barrier(
+                    output,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::MEMORY_READ,
+                );
+            }
+            PassKind::Compute => {
+                ctx.command_buffer.image_barrier(
+                    output,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::MEMORY_READ,
+                    vk::AccessFlags::SHADER_WRITE,
+                );
+
+                writes.push(vk::WriteDescriptorSet::default()
+                    .dst_binding(self.inputs)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&output_binding));
+
+                ctx.command_buffer.bind_pipeline(pipeline);
+                ctx.command_buffer.push_descriptor_set(pipeline, 0, &writes);
+                if !push_constants.is_empty() {
+                    ctx.command_buffer.push_constants(pipeline, vk::ShaderStageFlags::COMPUTE, 0, push_constants);
+                }
+                ctx.command_buffer.dispatch(output.width().div_ceil(WORKGROUP_SIZE), output.height().div_ceil(WORKGROUP_SIZE), 1);
+
+                ctx.command_buffer.image_barrier(
+                    output,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::GENERAL,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::MEMORY_READ,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::app::engine::CenContext;
+use crate::vulkan::{compile_shader_code, Buffer, CommandBuffer, ComputePipeline, DescriptorSetLayout, Device};
+
+pub(crate) const BLOCK_SIZE: u32 = 256;
+
+const SCAN_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 256, local_size_y = 1, local_size_z = 1 ) in;
+
+layout( std430, binding = 0 ) buffer Data { uint data[]; };
+layout( std430, binding = 1 ) writeonly buffer BlockSums { uint block_sums[]; };
+
+layout( push_constant ) uniform PushConstants {
+    uint count;
+} pc;
+
+shared uint temp[256];
+
+void main()
+{
+    uint local = gl_LocalInvocationID.x;
+    uint i = gl_GlobalInvocationID.x;
+
+    uint value = i < pc.count ? data[i] : 0u;
+    temp[local] = value;
+    barrier();
+
+    // Inclusive Hillis-Steele scan of the block
+    for( uint offset = 1u; offset < 256u; offset <<= 1u )
+    {
+        uint add = local >= offset ? temp[local - offset] : 0u;
+        barrier();
+        temp[local] += add;
+        barrier();
+    }
+
+    if( i < pc.count )
+    {
+        data[i] = temp[local] - value;
+    }
+    if( local == 255u )
+    {
+        block_sums[gl_WorkGroupID.x] = temp[255];
+    }
+}
+"#;
+
+const ADD_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 256, local_size_y = 1, local_size_z = 1 ) in;
+
+layout( std430, binding = 0 ) buffer Data { uint data[]; };
+layout( std430, binding = 1 ) readonly buffer BlockSums { uint block_sums[]; };
+
+layout( push_constant ) uniform PushConstants {
+    uint count;
+} pc;
+
+void main()
+{
+    uint i = gl_GlobalInvocationID.x;
+    if( i < pc.count )
+    {
+        data[i] += block_sums[gl_WorkGroupID.x];
+    }
+}
+"#;
+
+/// Number of elements of each level of block sums for a scan over `count` elements.
+fn level_counts(count: u32) -> Vec<u32> {
+    let mut levels = vec![];
+    let mut count = count.max(1);
+    loop {
+        count = count.div_ceil(BLOCK_SIZE);
+        levels.push(count);
+        if count == 1 {
+            return levels;
+        }
+    }
+}
+
+pub(crate) fn compute_barrier(command_buffer: &mut CommandBuffer, buffer: &Buffer) {
+    command_buffer.buffer_barrier(
+        buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        0,
+        vk::WHOLE_SIZE
+    );
+}
+
+pub(crate) fn compile_compute(device: &Device, source: &str, name: &str, layout: &DescriptorSetLayout, push_constant_size: u32) -> ComputePipeline {
+    let code = compile_shader_code(source, name, shaderc::ShaderKind::Compute, &HashMap::new())
+        .unwrap_or_else(|e| panic!("Failed to compile {}: {}", name, e));
+    ComputePipeline::from_spirv(
+        device,
+        code,
+        &[layout.clone()],
+        &[vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .size(push_constant_size)]
+    )
+}
+
+pub(crate) fn storage_buffer_bindings(count: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+    (0..count).map(|binding| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+    }).collect()
+}
+
+/// Exclusive prefix sum over `u32` values in a user buffer, computed in place on the GPU.
+///
+/// Scans blocks of 256 values, recursively scans the block sums and adds them back.
+/// The intermediate buffers are sized for `capacity` values at creation.
+pub struct PrefixSum {
+    scan_pipeline: ComputePipeline,
+    add_pipeline: ComputePipeline,
+    _layout: DescriptorSetLayout,
+    block_sums: Vec<Buffer>,
+    capacity: u32,
+}
+
+impl PrefixSum {
+
+    pub fn new(ctx: &mut CenContext, capacity: u32) -> Self {
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &storage_buffer_bindings(2));
+        let scan_pipeline = compile_compute(&ctx.gfx.device, SCAN_SHADER, "cen/prefix_sum_scan.comp", &layout, 4);
+        let add_pipeline = compile_compute(&ctx.gfx.device, ADD_SHADER, "cen/prefix_sum_add.comp", &layout, 4);
+
+        let block_sums = level_counts(capacity).iter().map(|count| {
+            Buffer::new(
+                &ctx.gfx.device,
+                &mut ctx.gfx.allocator,
+                MemoryLocation::GpuOnly,
+                (*count as usize * std::mem::size_of::<u32>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC
+            )
+        }).collect();
+
+        Self {
+            scan_pipeline,
+            add_pipeline,
+            _layout: layout,
+            block_sums,
+            capacity,
+        }
+    }
+
+    /// Maximum number of values that can be scanned.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Buffer whose first value holds the sum of all scanned values once the scan has executed,
+    /// e.g. the number of elements left after a stream compaction.
+    pub fn total(&self) -> &Buffer {
+        self.block_sums.last().unwrap()
+    }
+
+    /// Record an exclusive prefix sum over the first `count` values of `buffer`.
+    /// The buffer needs `STORAGE_BUFFER` usage, writes to it before the scan are made visible.
+    pub fn scan(&self, command_buffer: &mut CommandBuffer, buffer: &Buffer, count: u32) {
+        assert!(count <= self.capacity, "Prefix sum of {} values exceeds the capacity of {}", count, self.capacity);
+
+        let levels = level_counts(count);
+        let data = |level: usize| if level == 0 { buffer } else { &self.block_sums[level - 1] };
+        let count_of = |level: usize| if level == 0 { count } else { levels[level - 1] };
+
+        compute_barrier(command_buffer, buffer);
+
+        // Scan every level, the block sums of each level are the input of the next
+        command_buffer.bind_pipeline(&self.scan_pipeline);
+        for level in 0..levels.len() {
+            self.dispatch(command_buffer, &self.scan_pipeline, data(level), &self.block_sums[level], count_of(level), levels[level]);
+            compute_barrier(command_buffer, &self.block_sums[level]);
+            compute_barrier(command_buffer, data(level));
+        }
+
+        // Add the scanned block sums back down, the top level is a single block and complete already
+        command_buffer.bind_pipeline(&self.add_pipeline);
+        for level in (0..levels.len() - 1).rev() {
+            self.dispatch(command_buffer, &self.add_pipeline, data(level), &self.block_sums[level], count_of(level), levels[level]);
+            compute_barrier(command_buffer, data(level));
+        }
+    }
+
+    fn dispatch(&self, command_buffer: &mut CommandBuffer, pipeline: &ComputePipeline, data: &Buffer, block_sums: &Buffer, count: u32, blocks: u32) {
+        let data_binding = [data.binding()];
+        let block_sums_binding = [block_sums.binding()];
+        command_buffer.track(data);
+        command_buffer.track(block_sums);
+        command_buffer.push_descriptor_set(pipeline, 0, &[
+            vk::WriteDescriptorSet::default()
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&data_binding),
+            vk::WriteDescriptorSet::default()
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&block_sums_binding),
+        ]);
+        command_buffer.push_constants(pipeline, vk::ShaderStageFlags::COMPUTE, 0, &count.to_ne_bytes());
+        command_buffer.dispatch(blocks, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_sum_levels() {
+        assert_eq!(level_counts(0), vec![1]);
+        assert_eq!(level_counts(256), vec![1]);
+        assert_eq!(level_counts(257), vec![2, 1]);
+        assert_eq!(level_counts(256 * 256 + 1), vec![257, 2, 1]);
+    }
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+use ash::vk;
+use log::info;
+use crate::graphics::gpu::GpuContext;
+use crate::vulkan::{CommandBuffer, CommandPool, Device, FrameEvent, Surface, Swapchain, SwapchainImage, WindowState, SWAPCHAIN_LOG_TARGET};
+
+/// Queue of a separate present family, see [`Device::with_present_queue`].
+///
+/// The frames release the swapchain image to the present family, which acquires it in a submission of its own
+/// before presenting. Frames discard the image contents by starting from `UNDEFINED`, so the image isn't transferred
+/// back.
+struct PresentQueue {
+    queue_family_index: u32,
+    queue: vk::Queue,
+    /// Acquire the ownership of a swapchain image, one per image.
+    command_buffers: Vec<CommandBuffer>,
+    /// Signaled once the present queue acquired a swapchain image, one per image.
+    acquired_semaphores: Vec<vk::Semaphore>,
+    // Dropped after the command buffers
+    command_pool: CommandPool,
+}
+
+impl PresentQueue {
+    fn new(device: &Device, queue_family_index: u32, queue: vk::Queue) -> Self {
+        Self {
+            queue_family_index,
+            queue,
+            command_buffers: Vec::new(),
+            acquired_semaphores: Vec::new(),
+            command_pool: CommandPool::new(device, queue_family_index),
+        }
+    }
+
+    /// Record the acquire of every image of the swapchain. The device has to be idle.
+    fn record(&mut self, device: &Device, swapchain: &Swapchain, src_queue_family_index: u32) {
+        let images = swapchain.get_images();
+        while self.command_buffers.len() < images.len() {
+            self.command_buffers.push(CommandBuffer::new(device, &self.command_pool, true));
+        }
+        if self.acquired_semaphores.len() != images.len() {
+            for semaphore in self.acquired_semaphores.drain(..) {
+                device.destroy_semaphore(semaphore);
+            }
+            self.acquired_semaphores = images.iter()
+                .map(|_| device.create_semaphore())
+                .collect();
+        }
+
+        for (command_buffer, image) in self.command_buffers.iter_mut().zip(images) {
+            command_buffer.begin();
+            command_buffer.acquire_image(
+                image,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                src_queue_family_index,
+                self.queue_family_index,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+            );
+            command_buffer.end();
+        }
+    }
+}
+
+/// The surface and swapchain of a window, and the semaphores presentation waits on.
+///
+/// Has to be dropped before the [`GpuContext`] it was created with, and only once the device is idle.
+pub struct Presenter {
+    pub swapchain: Swapchain,
+    /// Signaled once the frame rendering to a swapchain image was executed, one per image.
+    render_finished_semaphores: Vec<vk::Semaphore>,
+    // Dropped after the swapchain
+    pub surface: Surface,
+    present_mode: vk::PresentModeKHR,
+    acquire_timeout: Duration,
+    /// Set when the frames run on a queue family that can't present.
+    present_queue: Option<PresentQueue>,
+    device: Device,
+}
+
+impl Presenter {
+    pub fn new(gpu: &GpuContext, window: &WindowState, surface: Surface, present_mode: vk::PresentModeKHR, acquire_timeout: Duration) -> Self {
+        info!(target: SWAPCHAIN_LOG_TARGET, "Creating initial swapchain");
+        let device = gpu.device().clone();
+        let swapchain = Swapchain::new(&gpu.instance, &gpu.physical_device, &device, window, &surface, present_mode, None);
+        let render_finished_semaphores = (0..swapchain.get_image_count())
+            .map(|_| device.create_semaphore())
+            .collect();
+
+        let present_queue = device.present_queue_family_index()
+            .zip(device.get_present_queue())
+            .map(|(queue_family_index, queue)| {
+                let mut present_queue = PresentQueue::new(&device, queue_family_index, queue);
+                present_queue.record(&device, &swapchain, device.inner.queue_family_index);
+                present_queue
+            });
+
+        Self {
+            swapchain,
+            render_finished_semaphores,
+            surface,
+            present_mode,
+            acquire_timeout,
+            present_queue,
+            device,
+        }
+    }
+
+    /// Recreate the swapchain for the new window state. The device has to be idle.
+    pub fn recreate(&mut self, gpu: &GpuContext, window_state: &WindowState) {
+        info!(target: SWAPCHAIN_LOG_TARGET, "Recreating swapchain");
+        self.swapchain = Swapchain::new(&gpu.instance, &gpu.physical_device, &self.device, window_state, &self.surface, self.present_mode, Some(self.swapchain.handle()));
+        self.device.event_log().record(FrameEvent::SwapchainRecreated {
+            width: self.swapchain.get_extent().width,
+            height: self.swapchain.get_extent().height,
+            image_count: self.swapchain.get_image_count(),
+        });
+
+        // Presentation waits per swapchain image, and the new swapchain may have a different number of images
+        let image_count = self.swapchain.get_image_count() as usize;
+        if self.render_finished_semaphores.len() != image_count {
+            for semaphore in self.render_finished_semaphores.drain(..) {
+                self.device.destroy_semaphore(semaphore);
+            }
+            self.render_finished_semaphores = (0..image_count)
+                .map(|_| self.device.create_semaphore())
+                .collect();
+        }
+
+        if let Some(present_queue) = &mut self.present_queue {
+            present_queue.record(&self.device, &self.swapchain, self.device.inner.queue_family_index);
+        }
+    }
+
+    /// Acquire the next swapchain image, signaling `image_available` once it can be rendered to.
+    /// `None` when the presentation engine doesn't hand out an image within the acquire timeout.
+    pub fn acquire(&self, image_available: vk::Semaphore) -> Option<usize> {
+        self.swapchain.acquire_next_image(image_available, self.acquire_timeout)
+            .map(|image_index| image_index as usize)
+    }
+
+    pub fn acquire_timeout(&self) -> Duration {
+        self.acquire_timeout
+    }
+
+    pub fn image(&self, image_index: usize) -> &SwapchainImage {
+        &self.swapchain.get_images()[image_index]
+    }
+
+    /// Semaphore the frame rendering to the image signals, and its presentation waits on.
+    pub fn render_finished(&self, image_index: usize) -> vk::Semaphore {
+        self.render_finished_semaphores[image_index]
+    }
+
+    /// Release the image to the present queue family, recorded last by the frame rendering to it. Does nothing when
+    /// `queue` of [`present`](Self::present) presents itself.
+    pub fn release(&self, command_buffer: &mut CommandBuffer, image_index: usize) {
+        if let Some(present_queue) = &self.present_queue {
+            command_buffer.release_image(
+                self.image(image_index),
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                self.device.inner.queue_family_index,
+                present_queue.queue_family_index,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::MEMORY_WRITE,
+            );
+        }
+    }
+
+    /// Present the image once the frame rendering to it executed. With a separate present family, the present
+    /// queue acquires the image first, and presents it instead of `queue`.
+    pub fn present(&self, queue: vk::Queue, image_index: usize) {
+        let Some(present_queue) = &self.present_queue else {
+            self.swapchain.queue_present(queue, self.render_finished_semaphores[image_index], image_index as u32);
+            return;
+        };
+
+        // The previous acquire of the image executed before the image was presented, so this doesn't block
+        let command_buffer = &present_queue.command_buffers[image_index];
+        self.device.wait_for_fence(command_buffer.fence());
+        self.device.reset_fence(command_buffer.fence());
+        self.device.submit_command_buffer_with(
+            &present_queue.queue,
+            &[(self.render_finished_semaphores[image_index], vk::PipelineStageFlags::ALL_COMMANDS)],
+            &[present_queue.acquired_semaphores[image_index]],
+            command_buffer
+        );
+        self.swapchain.queue_present(present_queue.queue, present_queue.acquired_semaphores[image_index], image_index as u32);
+    }
+
+    /// Queue family presenting the images, when the frames run on a family that can't present.
+    pub fn present_queue_family_index(&self) -> Option<u32> {
+        self.present_queue.as_ref().map(|present_queue| present_queue.queue_family_index)
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.swapchain.get_extent()
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.swapchain.get_format().format
+    }
+
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.swapchain.get_present_mode()
+    }
+
+    /// Whether the swapchain has to be recreated to follow the format the surface prefers, see
+    /// [`Swapchain::is_format_outdated`].
+    pub fn is_format_outdated(&self, gpu: &GpuContext) -> bool {
+        self.swapchain.is_format_outdated(&gpu.physical_device, &self.surface)
+    }
+}
+
+impl Drop for Presenter {
+    fn drop(&mut self) {
+        for semaphore in &self.render_finished_semaphores {
+            self.device.destroy_semaphore(*semaphore);
+        }
+        if let Some(present_queue) = &self.present_queue {
+            for semaphore in &present_queue.acquired_semaphores {
+                self.device.destroy_semaphore(*semaphore);
+            }
+        }
+    }
+}
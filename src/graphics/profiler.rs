@@ -0,0 +1,227 @@
+use std::time::Duration;
+use ash::vk;
+use egui::Context;
+use log::{info, warn};
+use crate::app::engine::CenContext;
+use crate::app::gui::{GuiComponent, GuiContext};
+use crate::vulkan::{CommandBuffer, Device, Instance, QueryPool};
+
+/// Maximum number of scopes per frame, further scopes are not measured.
+pub const MAX_GPU_SCOPES: u32 = 256;
+
+/// GPU time of a scope in the last completed frame.
+#[derive(Clone, Debug)]
+pub struct GpuTiming {
+    pub name: String,
+    /// Number of scopes this one is nested in.
+    pub depth: u32,
+    pub duration: Duration,
+}
+
+struct Scope {
+    name: String,
+    depth: u32,
+}
+
+struct FrameQueries {
+    pool: QueryPool,
+    scopes: Vec<Scope>,
+}
+
+/// Measures GPU time with timestamp queries, per render component and per scope opened with
+/// [`CenContext::begin_gpu_scope`]. Results are read once the frame's fence was waited on, so they lag a few frames behind.
+///
+/// Enable it with [`AppConfig::gpu_profiler`](crate::app::app::AppConfig::gpu_profiler). When disabled, or when the
+/// device doesn't support timestamps on the graphics queue, scopes are ignored and there are no timings.
+pub struct GpuProfiler {
+    /// Queries per frame in flight, empty when disabled
+    frames: Vec<FrameQueries>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f64,
+    current: Option<usize>,
+    /// Open scopes, `None` for scopes beyond `MAX_GPU_SCOPES`
+    open: Vec<Option<u32>>,
+    timings: Vec<GpuTiming>,
+}
+
+impl GpuProfiler {
+    pub(crate) fn new(instance: &Instance, physical_device: vk::PhysicalDevice, device: &Device, frames_in_flight: usize, enabled: bool) -> Self {
+        let limits = unsafe { instance.handle().get_physical_device_properties(physical_device).limits };
+
+        let supported = limits.timestamp_compute_and_graphics == vk::TRUE;
+        if enabled && !supported {
+            info!("Timestamp queries are not supported, the GPU profiler is disabled");
+        }
+
+        let frames = if enabled && supported {
+            (0..frames_in_flight).map(|_| FrameQueries {
+                pool: QueryPool::new_timestamp(device, MAX_GPU_SCOPES * 2),
+                scopes: Vec::new(),
+            }).collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            frames,
+            timestamp_period: limits.timestamp_period as f64,
+            current: None,
+            open: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Timings of the last completed frame, in the order the scopes were opened.
+    pub fn timings(&self) -> &[GpuTiming] {
+        &self.timings
+    }
+
+    /// Start measuring into the queries of the frame slot, at the start of its command buffer.
+    pub(crate) fn begin_frame(&mut self, frame_index: usize, command_buffer: &mut CommandBuffer) {
+        let Some(frame) = self.frames.get_mut(frame_index) else {
+            return;
+        };
+        frame.scopes.clear();
+        command_buffer.reset_query_pool(&frame.pool, 0, frame.pool.query_count());
+        self.current = Some(frame_index);
+        self.open.clear();
+    }
+
+    /// Stop measuring, scopes that are still open are closed.
+    pub(crate) fn end_frame(&mut self, command_buffer: &mut CommandBuffer) {
+        if self.current.is_none() {
+            return;
+        }
+        if !self.open.is_empty() {
+            warn!("{} GPU scopes were not ended", self.open.len());
+            while !self.open.is_empty() {
+                self.end_scope(command_buffer);
+            }
+        }
+        self.current = None;
+    }
+
+    /// The command buffer of the frame slot has executed, read its timestamps.
+    pub(crate) fn frame_completed(&mut self, frame_index: usize) {
+        let Some(frame) = self.frames.get_mut(frame_index) else {
+            return;
+        };
+        // The frame was skipped, or the slot wasn't used yet
+        if frame.scopes.is_empty() {
+            return;
+        }
+
+        let scopes = std::mem::take(&mut frame.scopes);
+        let Some(timestamps) = frame.pool.results(0, scopes.len() as u32 * 2) else {
+            warn!("GPU timestamps of frame slot {} are not available", frame_index);
+            return;
+        };
+
+        self.timings = scopes.into_iter().zip(timestamps.chunks_exact(2)).map(|(scope, timestamps)| {
+            let ticks = timestamps[1].saturating_sub(timestamps[0]);
+            GpuTiming {
+                name: scope.name,
+                depth: scope.depth,
+                duration: Duration::from_nanos((ticks as f64 * self.timestamp_period) as u64),
+            }
+        }).collect();
+    }
+
+    pub(crate) fn begin_scope(&mut self, command_buffer: &mut CommandBuffer, name: &str) {
+        let Some(frame_index) = self.current else {
+            return;
+        };
+        let frame = &mut self.frames[frame_index];
+
+        let index = frame.scopes.len() as u32;
+        if index >= MAX_GPU_SCOPES {
+            self.open.push(None);
+            return;
+        }
+
+        command_buffer.write_timestamp(&frame.pool, vk::PipelineStageFlags::TOP_OF_PIPE, index * 2);
+        frame.scopes.push(Scope {
+            name: name.to_string(),
+            depth: self.open.len() as u32,
+        });
+        self.open.push(Some(index));
+    }
+
+    pub(crate) fn end_scope(&mut self, command_buffer: &mut CommandBuffer) {
+        let Some(frame_index) = self.current else {
+            return;
+        };
+        let Some(scope) = self.open.pop() else {
+            warn!("Ended a GPU scope that was not begun");
+            return;
+        };
+        if let Some(index) = scope {
+            command_buffer.write_timestamp(&self.frames[frame_index].pool, vk::PipelineStageFlags::BOTTOM_OF_PIPE, index * 2 + 1);
+        }
+    }
+}
+
+/// Overlay with the timings of the last completed frame.
+impl GuiComponent for GpuProfiler {
+    fn gui(&mut self, _: &mut GuiContext, context: &Context) {
+        egui::Window::new("GPU")
+            .id(egui::Id::new("cen_gpu_profiler"))
+            .resizable(false)
+            .show(context, |ui| {
+                if !self.is_enabled() {
+                    ui.label("Timestamp queries are unavailable");
+                    return;
+                }
+                egui::Grid::new("cen_gpu_timings").striped(true).show(ui, |ui| {
+                    for timing in &self.timings {
+                        ui.label(format!("{}{}", "  ".repeat(timing.depth as usize), timing.name));
+                        ui.label(format!("{:.3} ms", timing.duration.as_secs_f64() * 1000.0));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+/// Type name without module paths, e.g. `Renderer` for `my_app::render::Renderer<f32>`.
+pub(crate) fn short_type_name(name: &'static str) -> &'static str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+impl CenContext<'_> {
+
+    /// Start measuring the GPU time of the commands recorded until the matching [`end_gpu_scope`](Self::end_gpu_scope).
+    /// Scopes can be nested, and show up as labeled regions in graphics debuggers. Timings are only measured
+    /// during frames when the profiler is enabled.
+    pub fn begin_gpu_scope(&mut self, name: &str) {
+        self.command_buffer.begin_label(name, [0.0; 4]);
+        self.profiler.begin_scope(self.command_buffer, name);
+    }
+
+    pub fn end_gpu_scope(&mut self) {
+        self.profiler.end_scope(self.command_buffer);
+        self.command_buffer.end_label();
+    }
+
+    /// GPU timings of the last completed frame, see [`GpuProfiler`].
+    pub fn gpu_timings(&self) -> &[GpuTiming] {
+        self.profiler.timings()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_type_names() {
+        assert_eq!(short_type_name("my_app::render::Renderer"), "Renderer");
+        assert_eq!(short_type_name("my_app::Renderer<alloc::vec::Vec<f32>>"), "Renderer");
+        assert_eq!(short_type_name("App"), "App");
+    }
+}
@@ -0,0 +1,232 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::app::engine::CenContext;
+use crate::graphics::prefix_sum::{compile_compute, compute_barrier, storage_buffer_bindings, PrefixSum, BLOCK_SIZE};
+use crate::vulkan::{Buffer, CommandBuffer, ComputePipeline, DescriptorSetLayout};
+
+const RADIX_BITS: u32 = 4;
+const RADIX: u32 = 1 << RADIX_BITS;
+
+const HISTOGRAM_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 256, local_size_y = 1, local_size_z = 1 ) in;
+
+layout( std430, binding = 0 ) readonly buffer KeysIn { uint keys_in[]; };
+layout( std430, binding = 4 ) writeonly buffer Histogram { uint histogram[]; };
+
+layout( push_constant ) uniform PushConstants {
+    uint count;
+    uint shift;
+    uint num_blocks;
+    uint has_values;
+} pc;
+
+shared uint bins[16];
+
+void main()
+{
+    uint local = gl_LocalInvocationID.x;
+    uint block = gl_WorkGroupID.x;
+    uint i = gl_GlobalInvocationID.x;
+
+    if( local < 16u )
+    {
+        bins[local] = 0u;
+    }
+    barrier();
+
+    if( i < pc.count )
+    {
+        atomicAdd( bins[( keys_in[i] >> pc.shift ) & 15u], 1u );
+    }
+    barrier();
+
+    // Digit-major, so a prefix sum over the histogram gives the output offset of each digit in each block
+    if( local < 16u )
+    {
+        histogram[local * pc.num_blocks + block] = bins[local];
+    }
+}
+"#;
+
+const SCATTER_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 256, local_size_y = 1, local_size_z = 1 ) in;
+
+layout( std430, binding = 0 ) readonly buffer KeysIn { uint keys_in[]; };
+layout( std430, binding = 1 ) writeonly buffer KeysOut { uint keys_out[]; };
+layout( std430, binding = 2 ) readonly buffer ValuesIn { uint values_in[]; };
+layout( std430, binding = 3 ) writeonly buffer ValuesOut { uint values_out[]; };
+layout( std430, binding = 4 ) readonly buffer Histogram { uint histogram[]; };
+
+layout( push_constant ) uniform PushConstants {
+    uint count;
+    uint shift;
+    uint num_blocks;
+    uint has_values;
+} pc;
+
+shared uint digits[256];
+shared uint ranks[256];
+
+void main()
+{
+    uint local = gl_LocalInvocationID.x;
+    uint block = gl_WorkGroupID.x;
+    uint i = gl_GlobalInvocationID.x;
+    uint block_length = min( 256u, pc.count - block * 256u );
+
+    uint key = 0u;
+    if( i < pc.count )
+    {
+        key = keys_in[i];
+        digits[local] = ( key >> pc.shift ) & 15u;
+    }
+    barrier();
+
+    // One invocation per digit ranks the keys with that digit in order, which keeps the sort stable
+    if( local < 16u )
+    {
+        uint rank = 0u;
+        for( uint j = 0u; j < block_length; j++ )
+        {
+            if( digits[j] == local )
+            {
+                ranks[j] = rank;
+                rank++;
+            }
+        }
+    }
+    barrier();
+
+    if( i < pc.count )
+    {
+        uint destination = histogram[digits[local] * pc.num_blocks + block] + ranks[local];
+        keys_out[destination] = key;
+        if( pc.has_values != 0u )
+        {
+            values_out[destination] = values_in[i];
+        }
+    }
+}
+"#;
+
+/// Stable GPU radix sort of `u32` keys in a user buffer, optionally carrying `u32` values along,
+/// e.g. particle indices sorted by depth or cell id.
+///
+/// Sorts 4 bits per pass, the number of passes follows from the number of key bits to sort.
+/// Scratch buffers are sized for `capacity` elements at creation, up to `256 * 65535` elements are supported.
+pub struct RadixSort {
+    histogram_pipeline: ComputePipeline,
+    scatter_pipeline: ComputePipeline,
+    _layout: DescriptorSetLayout,
+    prefix_sum: PrefixSum,
+    histogram: Buffer,
+    keys: Buffer,
+    values: Buffer,
+    capacity: u32,
+}
+
+impl RadixSort {
+
+    pub fn new(ctx: &mut CenContext, capacity: u32) -> Self {
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &storage_buffer_bindings(5));
+        let histogram_pipeline = compile_compute(&ctx.gfx.device, HISTOGRAM_SHADER, "cen/radix_sort_histogram.comp", &layout, 16);
+        let scatter_pipeline = compile_compute(&ctx.gfx.device, SCATTER_SHADER, "cen/radix_sort_scatter.comp", &layout, 16);
+
+        let histogram_count = RADIX * capacity.max(1).div_ceil(BLOCK_SIZE);
+        let prefix_sum = PrefixSum::new(ctx, histogram_count);
+
+        let mut create_buffer = |count: u32| Buffer::new(
+            &ctx.gfx.device,
+            &mut ctx.gfx.allocator,
+            MemoryLocation::GpuOnly,
+            (count.max(1) as usize * std::mem::size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+        );
+        let histogram = create_buffer(histogram_count);
+        let keys = create_buffer(capacity);
+        let values = create_buffer(capacity);
+
+        Self {
+            histogram_pipeline,
+            scatter_pipeline,
+            _layout: layout,
+            prefix_sum,
+            histogram,
+            keys,
+            values,
+            capacity,
+        }
+    }
+
+    /// Maximum number of elements that can be sorted.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Record a sort of the first `count` keys in ascending order. All keys must be smaller than `2^key_bits`,
+    /// fewer bits take fewer passes. `values` are reordered along with the keys.
+    /// The sorted result ends up in the passed buffers.
+    pub fn sort(&self, command_buffer: &mut CommandBuffer, keys: &Buffer, values: Option<&Buffer>, count: u32, key_bits: u32) {
+        assert!(count <= self.capacity, "Radix sort of {} elements exceeds the capacity of {}", count, self.capacity);
+        assert!(key_bits <= 32, "Keys have at most 32 bits");
+
+        // An even number of passes, so the result ends up back in the user buffers
+        let passes = key_bits.div_ceil(RADIX_BITS).next_multiple_of(2);
+        let num_blocks = count.max(1).div_ceil(BLOCK_SIZE);
+
+        // Without values the key buffers are bound in their place, the shader doesn't access them
+        let (values_in, values_out) = match values {
+            Some(values) => (values, &self.values),
+            None => (keys, &self.keys),
+        };
+        let mut buffers = [keys, &self.keys, values_in, values_out];
+
+        compute_barrier(command_buffer, keys);
+        if let Some(values) = values {
+            compute_barrier(command_buffer, values);
+        }
+
+        for pass in 0..passes {
+            let push_constants = [count, pass * RADIX_BITS, num_blocks, values.is_some() as u32];
+            let push_constants = push_constants.iter().flat_map(|c| c.to_ne_bytes()).collect::<Vec<u8>>();
+
+            command_buffer.bind_pipeline(&self.histogram_pipeline);
+            self.bind(command_buffer, &self.histogram_pipeline, &buffers);
+            command_buffer.push_constants(&self.histogram_pipeline, vk::ShaderStageFlags::COMPUTE, 0, &push_constants);
+            command_buffer.dispatch(num_blocks, 1, 1);
+
+            self.prefix_sum.scan(command_buffer, &self.histogram, RADIX * num_blocks);
+
+            command_buffer.bind_pipeline(&self.scatter_pipeline);
+            self.bind(command_buffer, &self.scatter_pipeline, &buffers);
+            command_buffer.push_constants(&self.scatter_pipeline, vk::ShaderStageFlags::COMPUTE, 0, &push_constants);
+            command_buffer.dispatch(num_blocks, 1, 1);
+
+            compute_barrier(command_buffer, buffers[1]);
+            compute_barrier(command_buffer, buffers[3]);
+            buffers.swap(0, 1);
+            buffers.swap(2, 3);
+        }
+    }
+
+    fn bind(&self, command_buffer: &mut CommandBuffer, pipeline: &ComputePipeline, buffers: &[&Buffer; 4]) {
+        let bindings = buffers.iter()
+            .chain(std::iter::once(&&self.histogram))
+            .map(|buffer| {
+                command_buffer.track(*buffer);
+                [buffer.binding()]
+            })
+            .collect::<Vec<_>>();
+        let writes = bindings.iter().enumerate().map(|(binding, info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(info)
+        }).collect::<Vec<_>>();
+        command_buffer.push_descriptor_set(pipeline, 0, &writes);
+    }
+}
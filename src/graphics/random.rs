@@ -0,0 +1,74 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::graphics::lut::Pcg32;
+use crate::vulkan::{Allocator, Buffer, Device};
+
+/// Per-frame random seeds and an optional storage buffer of random `u32` values, refreshed every frame.
+///
+/// Shaders can use the helpers in `#include <cen/random.glsl>`.
+/// Defining `CEN_RANDOM_BINDING` (and optionally `CEN_RANDOM_SET`) declares the `cen_random` buffer at that binding.
+pub struct RandomProvider {
+    rng: Pcg32,
+    seed: u32,
+    // One buffer per frame in flight, so the values of in-flight frames aren't overwritten
+    buffers: Vec<Buffer>,
+    current: usize,
+}
+
+impl RandomProvider {
+
+    /// `buffer_size` is the number of random values in the buffer, zero disables the buffer.
+    pub fn new(device: &Device, allocator: &mut Allocator, seed: u64, buffer_size: usize, frames_in_flight: usize) -> Self {
+        let buffers = if buffer_size > 0 {
+            (0..frames_in_flight).map(|_| {
+                Buffer::new(
+                    device,
+                    allocator,
+                    MemoryLocation::CpuToGpu,
+                    (buffer_size * std::mem::size_of::<u32>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::STORAGE_BUFFER
+                )
+            }).collect()
+        } else {
+            vec![]
+        };
+
+        let mut rng = Pcg32::new(seed);
+        let seed = rng.next_u32();
+        Self {
+            rng,
+            seed,
+            buffers,
+            current: 0,
+        }
+    }
+
+    /// Draw new values for the frame using the given frame in flight slot.
+    pub(crate) fn advance(&mut self, frame_index: usize) {
+        self.seed = self.rng.next_u32();
+
+        if self.buffers.is_empty() {
+            return;
+        }
+        self.current = frame_index % self.buffers.len();
+        let mut mapped = self.buffers[self.current].mapped().expect("Failed to map random buffer");
+        for chunk in mapped.as_mut_slice().chunks_exact_mut(std::mem::size_of::<u32>()) {
+            chunk.copy_from_slice(&self.rng.next_u32().to_ne_bytes());
+        }
+    }
+
+    /// Random seed of the current frame, pass it to shaders e.g. through push constants.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Buffer with random values of the current frame, if enabled.
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffers.get(self.current)
+    }
+
+    /// Descriptor info of the random buffer of the current frame, if enabled.
+    pub fn buffer_binding(&self) -> Option<vk::DescriptorBufferInfo> {
+        self.buffer().map(|buffer| buffer.binding())
+    }
+}
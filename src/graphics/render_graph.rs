@@ -0,0 +1,661 @@
+use std::fmt::Write;
+use std::path::Path;
+use ash::vk;
+use ash::vk::{AccessFlags as A, PipelineStageFlags as S};
+use crate::vulkan::{Buffer, CommandBuffer, ImageTrait};
+use crate::vulkan::event_log::json_string;
+
+/// How a pass uses an image. The usage decides the layout the image is transitioned to before the pass.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ImageAccess {
+    /// Rendered to as a color attachment.
+    ColorAttachment,
+    /// Depth tested and written as a depth attachment.
+    DepthAttachment,
+    /// Depth tested without writing, or sampled as a depth texture.
+    DepthRead,
+    /// Sampled in shaders.
+    Sampled,
+    /// Loaded from as a storage image.
+    StorageRead,
+    /// Stored to, and possibly loaded from, as a storage image.
+    StorageWrite,
+    /// Source of copies and blits.
+    TransferSrc,
+    /// Destination of copies, blits and clears.
+    TransferDst,
+}
+
+/// How a pass uses a buffer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BufferAccess {
+    Vertex,
+    Index,
+    Indirect,
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+}
+
+/// Layout, stages and accesses of one use of a resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Usage {
+    layout: vk::ImageLayout,
+    stages: S,
+    access: A,
+    write: bool,
+}
+
+impl Usage {
+    fn merge(self, other: Usage) -> Usage {
+        assert_eq!(self.layout, other.layout, "An image can only be used in one layout within a pass");
+        Usage {
+            layout: self.layout,
+            stages: self.stages | other.stages,
+            access: self.access | other.access,
+            write: self.write || other.write,
+        }
+    }
+}
+
+impl From<ImageAccess> for Usage {
+    fn from(access: ImageAccess) -> Self {
+        use vk::ImageLayout as L;
+        let (layout, stages, access, write) = match access {
+            ImageAccess::ColorAttachment =>
+                (L::COLOR_ATTACHMENT_OPTIMAL, S::COLOR_ATTACHMENT_OUTPUT, A::COLOR_ATTACHMENT_READ | A::COLOR_ATTACHMENT_WRITE, true),
+            ImageAccess::DepthAttachment =>
+                (L::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS,
+                 A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE, true),
+            ImageAccess::DepthRead =>
+                (L::DEPTH_STENCIL_READ_ONLY_OPTIMAL, S::ALL_COMMANDS, A::DEPTH_STENCIL_ATTACHMENT_READ | A::SHADER_READ, false),
+            ImageAccess::Sampled =>
+                (L::SHADER_READ_ONLY_OPTIMAL, S::ALL_COMMANDS, A::SHADER_READ, false),
+            ImageAccess::StorageRead =>
+                (L::GENERAL, S::ALL_COMMANDS, A::SHADER_READ, false),
+            ImageAccess::StorageWrite =>
+                (L::GENERAL, S::ALL_COMMANDS, A::SHADER_READ | A::SHADER_WRITE, true),
+            ImageAccess::TransferSrc =>
+                (L::TRANSFER_SRC_OPTIMAL, S::TRANSFER, A::TRANSFER_READ, false),
+            ImageAccess::TransferDst =>
+                (L::TRANSFER_DST_OPTIMAL, S::TRANSFER, A::TRANSFER_WRITE, true),
+        };
+        Usage { layout, stages, access, write }
+    }
+}
+
+impl From<BufferAccess> for Usage {
+    fn from(access: BufferAccess) -> Self {
+        let (stages, access, write) = match access {
+            BufferAccess::Vertex => (S::VERTEX_INPUT, A::VERTEX_ATTRIBUTE_READ, false),
+            BufferAccess::Index => (S::VERTEX_INPUT, A::INDEX_READ, false),
+            BufferAccess::Indirect => (S::DRAW_INDIRECT, A::INDIRECT_COMMAND_READ, false),
+            BufferAccess::Uniform => (S::ALL_COMMANDS, A::UNIFORM_READ, false),
+            BufferAccess::StorageRead => (S::ALL_COMMANDS, A::SHADER_READ, false),
+            BufferAccess::StorageWrite => (S::ALL_COMMANDS, A::SHADER_READ | A::SHADER_WRITE, true),
+            BufferAccess::TransferSrc => (S::TRANSFER, A::TRANSFER_READ, false),
+            BufferAccess::TransferDst => (S::TRANSFER, A::TRANSFER_WRITE, true),
+        };
+        Usage { layout: vk::ImageLayout::UNDEFINED, stages, access, write }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Barrier {
+    src_stages: S,
+    dst_stages: S,
+    src_access: A,
+    dst_access: A,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+}
+
+/// What a resource went through since its last write, to find the barrier the next use needs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SyncState {
+    layout: vk::ImageLayout,
+    write_stages: S,
+    write_access: A,
+    // Reads since the last write, which a write has to wait for
+    read_stages: S,
+    // Reads the last write was made visible to
+    visible_stages: S,
+    visible_access: A,
+}
+
+impl SyncState {
+    /// State of an imported resource. Unless its contents are discarded, earlier commands might have written it.
+    fn imported(layout: vk::ImageLayout, discard: bool) -> Self {
+        let (write_stages, write_access) = if discard {
+            (S::empty(), A::empty())
+        } else {
+            (S::ALL_COMMANDS, A::MEMORY_WRITE)
+        };
+        SyncState {
+            layout,
+            write_stages,
+            write_access,
+            read_stages: S::empty(),
+            visible_stages: S::empty(),
+            visible_access: A::empty(),
+        }
+    }
+
+    /// The barrier needed before `usage`, if any, moving the state past it.
+    fn access(&mut self, usage: Usage) -> Option<Barrier> {
+        let transition = usage.layout != self.layout;
+        let barrier = |src_stages: S, src_access: A| Barrier {
+            src_stages: if src_stages.is_empty() { S::TOP_OF_PIPE } else { src_stages },
+            dst_stages: usage.stages,
+            src_access,
+            dst_access: usage.access,
+            old_layout: self.layout,
+            new_layout: usage.layout,
+        };
+
+        if transition || usage.write {
+            // Layout transitions and writes wait for the last write and all reads since
+            let barrier = barrier(self.write_stages | self.read_stages, self.write_access);
+            *self = if usage.write {
+                SyncState { write_stages: usage.stages, write_access: usage.access, ..SyncState::imported(usage.layout, true) }
+            } else {
+                // The transition itself is a write, later reads in other stages wait for it
+                SyncState {
+                    layout: usage.layout,
+                    write_stages: usage.stages,
+                    write_access: A::empty(),
+                    read_stages: usage.stages,
+                    visible_stages: usage.stages,
+                    visible_access: usage.access,
+                }
+            };
+            return Some(barrier);
+        }
+
+        self.read_stages |= usage.stages;
+        let visible = self.visible_stages.contains(usage.stages) && self.visible_access.contains(usage.access);
+        if self.write_stages.is_empty() || visible {
+            return None;
+        }
+        let barrier = barrier(self.write_stages, self.write_access);
+        self.visible_stages |= usage.stages;
+        self.visible_access |= usage.access;
+        Some(barrier)
+    }
+}
+
+/// An image imported into a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct GraphImage(usize);
+
+/// A buffer imported into a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct GraphBuffer(usize);
+
+/// A resource of the graph, for the barriers of the schedule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Resource {
+    Image(usize),
+    Buffer(usize),
+}
+
+struct ImageNode<'a> {
+    image: &'a dyn ImageTrait,
+    name: Option<String>,
+    state: SyncState,
+    final_layout: Option<vk::ImageLayout>,
+}
+
+struct BufferNode<'a> {
+    buffer: &'a Buffer,
+    name: Option<String>,
+    state: SyncState,
+}
+
+/// Barriers recorded before each pass, and after the last one to reach the final layouts.
+struct Schedule {
+    passes: Vec<Vec<(Resource, Barrier)>>,
+    final_barriers: Vec<(Resource, Barrier)>,
+}
+
+struct Pass<'a> {
+    name: String,
+    images: Vec<(GraphImage, Usage)>,
+    buffers: Vec<(GraphBuffer, Usage)>,
+    record: Box<dyn FnOnce(&mut CommandBuffer) + 'a>,
+}
+
+/// Passes of a frame that declare which images and buffers they read and write. The graph records the barriers
+/// and layout transitions between them, instead of every component writing them by hand.
+///
+/// Passes run in the order they are added, with each pass waiting on the earlier passes that touched the same
+/// resources. Imported images return to the layout they were imported in after the last pass, so graphs of
+/// different components compose: the swapchain image, for one, is received and left in `PRESENT_SRC_KHR`. The
+/// first use of an imported resource conservatively waits on all earlier commands that could have written it.
+///
+/// ```ignore
+/// let mut graph = RenderGraph::new();
+/// let output = graph.import_image(image, Layout::General);
+/// let target = graph.import_image(ctx.swapchain_image.unwrap(), Layout::Present);
+///
+/// graph.pass("trace")
+///     .image(output, ImageAccess::StorageWrite)
+///     .record(|command_buffer| {
+///         command_buffer.bind_pipeline(pipeline);
+///         command_buffer.dispatch(width / 16, height / 16, 1);
+///     });
+/// graph.pass("blit")
+///     .image(output, ImageAccess::TransferSrc)
+///     .image(target, ImageAccess::TransferDst)
+///     .record(|command_buffer| command_buffer.blit_image(image, Layout::TransferSrc, swapchain_image, Layout::TransferDst, &regions, Filter::Nearest));
+///
+/// graph.execute(ctx.command_buffer);
+/// ```
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<ImageNode<'a>>,
+    buffers: Vec<BufferNode<'a>>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an image in the layout it currently is in. `Layout::Undefined` discards its contents, and leaves it in
+    /// the layout of its last pass.
+    pub fn import_image(&mut self, image: &'a dyn ImageTrait, layout: impl Into<vk::ImageLayout>) -> GraphImage {
+        let layout = layout.into();
+        let discard = layout == vk::ImageLayout::UNDEFINED;
+        self.images.push(ImageNode {
+            image,
+            name: None,
+            state: SyncState::imported(layout, discard),
+            final_layout: (!discard).then_some(layout),
+        });
+        GraphImage(self.images.len() - 1)
+    }
+
+    /// Leave the image in `layout` after the last pass instead of the layout it was imported in.
+    pub fn final_layout(&mut self, image: GraphImage, layout: impl Into<vk::ImageLayout>) {
+        self.images[image.0].final_layout = Some(layout.into());
+    }
+
+    pub fn import_buffer(&mut self, buffer: &'a Buffer) -> GraphBuffer {
+        self.buffers.push(BufferNode {
+            buffer,
+            name: None,
+            state: SyncState::imported(vk::ImageLayout::UNDEFINED, false),
+        });
+        GraphBuffer(self.buffers.len() - 1)
+    }
+
+    /// Name the image in the [export](Self::export) of the graph.
+    pub fn name_image(&mut self, image: GraphImage, name: &str) {
+        self.images[image.0].name = Some(name.to_string());
+    }
+
+    /// Name the buffer in the [export](Self::export) of the graph.
+    pub fn name_buffer(&mut self, buffer: GraphBuffer, name: &str) {
+        self.buffers[buffer.0].name = Some(name.to_string());
+    }
+
+    /// Declare a pass, added to the graph by [`PassBuilder::record`].
+    pub fn pass<'g>(&'g mut self, name: &str) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            name: name.to_string(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Record the passes with the barriers in between into the command buffer. Each pass is wrapped in a debug
+    /// label with its name.
+    pub fn execute(self, command_buffer: &mut CommandBuffer) {
+        let schedule = self.schedule();
+        for (pass, barriers) in self.passes.into_iter().zip(schedule.passes) {
+            record_barriers(command_buffer, &self.images, &self.buffers, &barriers);
+
+            command_buffer.begin_label(&pass.name, [0.0; 4]);
+            (pass.record)(command_buffer);
+            command_buffer.end_label();
+        }
+
+        // Hand the images over in their final layouts
+        record_barriers(command_buffer, &self.images, &self.buffers, &schedule.final_barriers);
+    }
+
+    /// The passes, resources and barriers the graph would record, to inspect its scheduling decisions or attach
+    /// to a bug report. Call it before [`execute`](Self::execute), which consumes the graph.
+    ///
+    /// ```ignore
+    /// graph.export().write_dot("target/frame.dot")?;
+    /// ```
+    pub fn export(&self) -> GraphExport {
+        let image_names = self.images.iter()
+            .enumerate()
+            .map(|(index, node)| node.name.clone().unwrap_or_else(|| format!("image {}", index)));
+        let buffer_names = self.buffers.iter()
+            .enumerate()
+            .map(|(index, node)| node.name.clone().unwrap_or_else(|| format!("buffer {}", index)));
+        let resources = image_names.map(|name| ResourceExport { name, image: true })
+            .chain(buffer_names.map(|name| ResourceExport { name, image: false }))
+            .collect();
+
+        // Images come first in the exported resources
+        let index = |resource: Resource| match resource {
+            Resource::Image(index) => index,
+            Resource::Buffer(index) => self.images.len() + index,
+        };
+        let barriers = |barriers: Vec<(Resource, Barrier)>| barriers.into_iter()
+            .map(|(resource, barrier)| (index(resource), barrier))
+            .collect::<Vec<_>>();
+
+        let schedule = self.schedule();
+        let passes = self.passes.iter()
+            .zip(schedule.passes)
+            .map(|(pass, pass_barriers)| PassExport {
+                name: pass.name.clone(),
+                uses: pass.images.iter().map(|(image, usage)| (index(Resource::Image(image.0)), *usage))
+                    .chain(pass.buffers.iter().map(|(buffer, usage)| (index(Resource::Buffer(buffer.0)), *usage)))
+                    .collect(),
+                barriers: barriers(pass_barriers),
+            })
+            .collect();
+
+        GraphExport {
+            resources,
+            passes,
+            final_barriers: barriers(schedule.final_barriers),
+        }
+    }
+
+    /// Find the barriers of all passes, without touching the state of the resources.
+    fn schedule(&self) -> Schedule {
+        let mut images = self.images.iter().map(|node| node.state).collect::<Vec<_>>();
+        let mut buffers = self.buffers.iter().map(|node| node.state).collect::<Vec<_>>();
+
+        let passes = self.passes.iter()
+            .map(|pass| {
+                let images = pass.images.iter()
+                    .filter_map(|(image, usage)| Some((Resource::Image(image.0), images[image.0].access(*usage)?)));
+                let buffers = pass.buffers.iter()
+                    .filter_map(|(buffer, usage)| Some((Resource::Buffer(buffer.0), buffers[buffer.0].access(*usage)?)));
+                images.chain(buffers).collect()
+            })
+            .collect();
+
+        let final_barriers = self.images.iter()
+            .zip(images.iter_mut())
+            .enumerate()
+            .filter_map(|(index, (node, state))| {
+                let layout = node.final_layout.filter(|layout| *layout != state.layout)?;
+                let (stages, access) = if layout == vk::ImageLayout::PRESENT_SRC_KHR {
+                    (S::BOTTOM_OF_PIPE, A::empty())
+                } else {
+                    (S::ALL_COMMANDS, A::MEMORY_READ | A::MEMORY_WRITE)
+                };
+                let usage = Usage { layout, stages, access, write: false };
+                Some((Resource::Image(index), state.access(usage)?))
+            })
+            .collect();
+
+        Schedule { passes, final_barriers }
+    }
+}
+
+/// Merge the barriers before a pass into a single pipeline barrier.
+fn record_barriers(command_buffer: &mut CommandBuffer, image_nodes: &[ImageNode], buffer_nodes: &[BufferNode], barriers: &[(Resource, Barrier)]) {
+    if barriers.is_empty() {
+        return;
+    }
+    let (src_stages, dst_stages) = barriers.iter().fold((S::empty(), S::empty()), |(src, dst), (_, barrier)| {
+        (src | barrier.src_stages, dst | barrier.dst_stages)
+    });
+
+    let images = barriers.iter().filter_map(|(resource, barrier)| match resource {
+        Resource::Image(index) => Some((image_nodes[*index].image, vk::ImageMemoryBarrier::default()
+            .old_layout(barrier.old_layout)
+            .new_layout(barrier.new_layout)
+            .src_access_mask(barrier.src_access)
+            .dst_access_mask(barrier.dst_access))),
+        Resource::Buffer(_) => None,
+    }).collect::<Vec<_>>();
+    let buffers = barriers.iter().filter_map(|(resource, barrier)| match resource {
+        Resource::Buffer(index) => Some((buffer_nodes[*index].buffer, vk::BufferMemoryBarrier::default()
+            .src_access_mask(barrier.src_access)
+            .dst_access_mask(barrier.dst_access))),
+        Resource::Image(_) => None,
+    }).collect::<Vec<_>>();
+    command_buffer.pipeline_barrier(src_stages, dst_stages, &images, &buffers);
+}
+
+struct ResourceExport {
+    name: String,
+    image: bool,
+}
+
+struct PassExport {
+    name: String,
+    // Index of the resource and how the pass uses it
+    uses: Vec<(usize, Usage)>,
+    barriers: Vec<(usize, Barrier)>,
+}
+
+/// Snapshot of a [`RenderGraph`] for offline inspection, see [`RenderGraph::export`].
+pub struct GraphExport {
+    resources: Vec<ResourceExport>,
+    passes: Vec<PassExport>,
+    final_barriers: Vec<(usize, Barrier)>,
+}
+
+impl GraphExport {
+    /// GraphViz graph of the passes in order. Edges follow each resource from the pass that used it last, labeled
+    /// with the barrier in between, if any.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph render_graph {\n    rankdir=LR;\n");
+        for (index, resource) in self.resources.iter().enumerate() {
+            let shape = if resource.image { "ellipse" } else { "cylinder" };
+            let _ = writeln!(out, "    r{} [label={}, shape={}];", index, dot_string(&resource.name), shape);
+        }
+
+        // Node that last used each resource, the imported resource itself to begin with
+        let mut last = (0..self.resources.len()).map(|index| format!("r{}", index)).collect::<Vec<_>>();
+        let mut edge = |out: &mut String, resource: usize, to: String, barrier: Option<&Barrier>| {
+            let mut label = self.resources[resource].name.clone();
+            if let Some(barrier) = barrier {
+                label += &format!("\n{}", describe_barrier(barrier, self.resources[resource].image));
+            }
+            let _ = writeln!(out, "    {} -> {} [label={}];", last[resource], to, dot_string(&label));
+            last[resource] = to;
+        };
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let _ = writeln!(out, "    p{} [label={}, shape=box];", index, dot_string(&format!("{}: {}", index, pass.name)));
+            for (resource, _) in &pass.uses {
+                let barrier = pass.barriers.iter().find(|(used, _)| used == resource).map(|(_, barrier)| barrier);
+                edge(&mut out, *resource, format!("p{}", index), barrier);
+            }
+        }
+
+        if !self.final_barriers.is_empty() {
+            let _ = writeln!(out, "    end [label=\"final layouts\", shape=box, style=dashed];");
+            for (resource, barrier) in &self.final_barriers {
+                edge(&mut out, *resource, "end".to_string(), Some(barrier));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The resources, and the passes in order with their uses and barriers, as JSON.
+    pub fn to_json(&self) -> String {
+        let resources = self.resources.iter()
+            .map(|resource| format!(
+                r#"{{"name":{},"kind":"{}"}}"#,
+                json_string(&resource.name),
+                if resource.image { "image" } else { "buffer" }
+            ))
+            .collect::<Vec<_>>();
+        let passes = self.passes.iter()
+            .map(|pass| {
+                let uses = pass.uses.iter()
+                    .map(|(resource, usage)| format!(
+                        r#"{{"resource":{},"layout":"{:?}","stages":"{:?}","access":"{:?}","write":{}}}"#,
+                        resource, usage.layout, usage.stages, usage.access, usage.write
+                    ))
+                    .collect::<Vec<_>>();
+                format!(
+                    r#"{{"name":{},"uses":[{}],"barriers":[{}]}}"#,
+                    json_string(&pass.name),
+                    uses.join(","),
+                    json_barriers(&pass.barriers)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            r#"{{"resources":[{}],"passes":[{}],"final_barriers":[{}]}}"#,
+            resources.join(","),
+            passes.join(","),
+            json_barriers(&self.final_barriers)
+        )
+    }
+
+    pub fn write_dot(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_dot())
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+fn describe_barrier(barrier: &Barrier, image: bool) -> String {
+    let mut description = format!("{:?} -> {:?}", barrier.src_stages, barrier.dst_stages);
+    if image && barrier.old_layout != barrier.new_layout {
+        description += &format!("\n{:?} -> {:?}", barrier.old_layout, barrier.new_layout);
+    }
+    description
+}
+
+fn json_barriers(barriers: &[(usize, Barrier)]) -> String {
+    barriers.iter()
+        .map(|(resource, barrier)| format!(
+            r#"{{"resource":{},"src_stages":"{:?}","dst_stages":"{:?}","src_access":"{:?}","dst_access":"{:?}","old_layout":"{:?}","new_layout":"{:?}"}}"#,
+            resource, barrier.src_stages, barrier.dst_stages, barrier.src_access, barrier.dst_access, barrier.old_layout, barrier.new_layout
+        ))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quoted DOT string, JSON escaping is a valid subset.
+fn dot_string(value: &str) -> String {
+    json_string(value)
+}
+
+/// A pass being declared, see [`RenderGraph::pass`].
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    name: String,
+    images: Vec<(GraphImage, Usage)>,
+    buffers: Vec<(GraphBuffer, Usage)>,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// Use an image in the pass. Using it more than once merges the accesses, which need the same layout.
+    pub fn image(mut self, image: GraphImage, access: ImageAccess) -> Self {
+        let usage = Usage::from(access);
+        match self.images.iter_mut().find(|(used, _)| *used == image) {
+            Some((_, used)) => *used = used.merge(usage),
+            None => self.images.push((image, usage)),
+        }
+        self
+    }
+
+    pub fn buffer(mut self, buffer: GraphBuffer, access: BufferAccess) -> Self {
+        let usage = Usage::from(access);
+        match self.buffers.iter_mut().find(|(used, _)| *used == buffer) {
+            Some((_, used)) => *used = used.merge(usage),
+            None => self.buffers.push((buffer, usage)),
+        }
+        self
+    }
+
+    /// Add the pass to the graph, recording its commands with `record` when the graph executes.
+    pub fn record(self, record: impl FnOnce(&mut CommandBuffer) + 'a) {
+        self.graph.passes.push(Pass {
+            name: self.name,
+            images: self.images,
+            buffers: self.buffers,
+            record: Box::new(record),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barriers_between_passes() {
+        let mut state = SyncState::imported(vk::ImageLayout::UNDEFINED, true);
+
+        // Discarded contents only need the layout transition
+        let barrier = state.access(ImageAccess::StorageWrite.into()).unwrap();
+        assert_eq!(barrier.src_stages, S::TOP_OF_PIPE);
+        assert_eq!(barrier.src_access, A::empty());
+        assert_eq!(barrier.new_layout, vk::ImageLayout::GENERAL);
+
+        // Reading the storage image waits for the write, once
+        let barrier = state.access(ImageAccess::StorageRead.into()).unwrap();
+        assert_eq!((barrier.src_access, barrier.dst_access), (A::SHADER_READ | A::SHADER_WRITE, A::SHADER_READ));
+        assert_eq!(barrier.old_layout, barrier.new_layout);
+        assert_eq!(state.access(ImageAccess::StorageRead.into()), None);
+
+        // Copying from it transitions the layout after the reads
+        let barrier = state.access(ImageAccess::TransferSrc.into()).unwrap();
+        assert_eq!(barrier.src_stages, S::ALL_COMMANDS);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        assert_eq!(state.access(ImageAccess::TransferSrc.into()), None);
+
+        // Buffers written by earlier commands are waited on before the first read
+        let mut state = SyncState::imported(vk::ImageLayout::UNDEFINED, false);
+        let barrier = state.access(BufferAccess::Indirect.into()).unwrap();
+        assert_eq!((barrier.src_access, barrier.dst_stages), (A::MEMORY_WRITE, S::DRAW_INDIRECT));
+        assert_eq!(state.access(BufferAccess::Indirect.into()), None);
+        let barrier = state.access(BufferAccess::StorageWrite.into()).unwrap();
+        assert_eq!(barrier.src_stages, S::ALL_COMMANDS | S::DRAW_INDIRECT);
+    }
+
+    #[test]
+    fn export() {
+        let mut state = SyncState::imported(vk::ImageLayout::UNDEFINED, true);
+        let write = Usage::from(ImageAccess::StorageWrite);
+        let read = Usage::from(ImageAccess::Sampled);
+        let export = GraphExport {
+            resources: vec![ResourceExport { name: "output \"hdr\"".to_string(), image: true }],
+            passes: vec![
+                PassExport { name: "trace".to_string(), uses: vec![(0, write)], barriers: vec![(0, state.access(write).unwrap())] },
+                PassExport { name: "tonemap".to_string(), uses: vec![(0, read)], barriers: vec![(0, state.access(read).unwrap())] },
+            ],
+            final_barriers: vec![],
+        };
+
+        let dot = export.to_dot();
+        assert!(dot.contains(r#"r0 [label="output \"hdr\"", shape=ellipse];"#));
+        assert!(dot.contains(r#"p0 [label="0: trace", shape=box];"#));
+        assert!(dot.contains("r0 -> p0"));
+        assert!(dot.contains("p0 -> p1"));
+        assert!(dot.contains("GENERAL -> SHADER_READ_ONLY_OPTIMAL"));
+        assert!(!dot.contains("final layouts"));
+
+        let json = export.to_json();
+        assert!(json.starts_with(r#"{"resources":[{"name":"output \"hdr\"","kind":"image"}],"passes":[{"name":"trace","uses":[{"resource":0,"layout":"GENERAL""#));
+        assert!(json.contains(r#""old_layout":"GENERAL","new_layout":"SHADER_READ_ONLY_OPTIMAL""#));
+        assert!(json.ends_with(r#""final_barriers":[]}"#));
+    }
+}
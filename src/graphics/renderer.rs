@@ -1,133 +1,289 @@
-use log::{info};
+use bitflags::bitflags;
+use log::{info, warn};
 use std::time::Instant;
 use ash::vk;
-use ash::vk::{ImageLayout, PhysicalDevice};
-use gpu_allocator::vulkan::{AllocatorCreateDesc};
+use ash::vk::ImageLayout;
 use winit::event_loop::EventLoopProxy;
-use crate::app::app::UserEvent;
+use crate::app::app::{AppConfig, UserEvent};
 use crate::app::engine::{CenContext};
-use crate::app::ImageFlags;
+use crate::app::{ImageFlags, ImageResource};
+use crate::app::input::InputState;
+use crate::app::splash::LoadingProgress;
+use crate::app::components::ComponentRegistry;
 use crate::app::gui::{GuiData, GuiSystem};
-use crate::graphics::context::{GraphicsContext, ImageContext, PipelineContext};
-use crate::graphics::image_store::ImageStore;
+use crate::app::window::WindowController;
+use crate::graphics::context::{ImageContext, PipelineContext};
+use crate::graphics::gpu::GpuContext;
+use crate::graphics::presenter::Presenter;
+use crate::graphics::clock::AnimationClock;
+use crate::graphics::crash::CrashState;
+use crate::graphics::stats::FrameStats;
+use crate::graphics::random::RandomProvider;
+use crate::graphics::async_compute::AsyncCompute;
+use crate::graphics::frame::{frame_clear, image_available_stages, Frame, FrameResources};
+use crate::graphics::globals::FrameGlobals;
+use crate::graphics::assets::AssetLoader;
+use crate::graphics::profiler::{short_type_name, GpuProfiler};
+use crate::graphics::upload::{Uploader, DEFAULT_STAGING_CHUNK_SIZE};
+use crate::graphics::export::{ExportConfig, OfflineExport};
+use crate::graphics::surface_size::{RenderTarget, SurfaceSizePolicy, SurfaceSizes};
 use crate::graphics::pipeline_store::PipelineStore;
-use crate::vulkan::{Allocator, CommandBuffer, CommandPool, Device, Image, Instance, Surface, Swapchain, WindowState};
+use crate::vulkan::{CommandBuffer, FrameEvent, Image, ImageConfig, Surface, WindowState};
 
 // -- Traits --
 
+bitflags! {
+    /// Built-in work of the renderer that components can opt out of, see [`RenderComponent::frame_flags`].
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct FrameFlags: u32 {
+        /// Don't clear the swapchain image at the start of the frame. Components receive it in `UNDEFINED` layout
+        /// instead of `PRESENT_SRC_KHR`, and have to leave it in `PRESENT_SRC_KHR`. [`AppConfig::clear_color`] skips
+        /// the clear of all frames.
+        const SKIP_CLEAR = 1 << 0;
+        /// Don't draw the gui, so the swapchain image is presented exactly as the components left it,
+        /// without the gui pass transitioning it.
+        const SKIP_GUI = 1 << 1;
+    }
+}
+
 pub trait RenderComponent {
     fn render(&mut self, ctx: &mut CenContext);
+
+    /// Built-in work to skip for the next frame, asked before it is recorded.
+    /// The flags of all components of the frame are combined.
+    fn frame_flags(&self) -> FrameFlags {
+        FrameFlags::empty()
+    }
+
+    /// Name of the component's GPU scope, see [`GpuProfiler`].
+    fn name(&self) -> &str {
+        short_type_name(std::any::type_name::<Self>())
+    }
 }
 
 // -- Renderer --
 
 pub struct Renderer {
-    pub render_finished_semaphores: Vec<vk::Semaphore>,
-    pub image_available_semaphores: Vec<vk::Semaphore>,
-    pub command_buffers: Vec<CommandBuffer>,
-    pub swapchain: Swapchain,
-    pub entry: ash::Entry,
-    pub surface: Surface,
+    /// One per frame in flight.
+    pub frames: Vec<FrameResources>,
+    /// Surface and swapchain of the window, dropped before the device.
+    pub presenter: Presenter,
     pub frame_index: usize,
-    pub graphics_context: GraphicsContext,
     pub image_context: ImageContext,
+    /// Depth attachment matching the render extent, see [`AppConfig::depth_buffer`].
+    pub depth: Option<ImageResource>,
     pub pipeline_context: PipelineContext,
-    pub physical_device: PhysicalDevice,
-    pub instance: Instance,
     pub start_time: Instant,
-    present_mode: vk::PresentModeKHR,
+    pub clock: AnimationClock,
+    pub crash_state: CrashState,
+    pub stats: FrameStats,
+    pub random: RandomProvider,
+    pub async_compute: Option<AsyncCompute>,
+    pub input: InputState,
+    pub uploader: Uploader,
+    pub loading: LoadingProgress,
+    /// Components that can be disabled at runtime, shared by the windows of the app.
+    pub components: ComponentRegistry,
+    pub assets: AssetLoader,
+    pub profiler: GpuProfiler,
+    pub globals: FrameGlobals,
+    /// Window changes requested by components, applied by the engine.
+    pub window: WindowController,
+    /// Frames go to an offscreen surface instead of the swapchain while exporting.
+    export: Option<OfflineExport>,
+    /// Color of the pre-clear of the swapchain image, no pre-clear when `None`, see [`AppConfig::clear_color`].
+    pub clear_color: Option<[f32; 4]>,
+    /// Whether the command buffers of the components set the viewport to the render area, see
+    /// [`AppConfig::default_viewport`].
+    pub default_viewport: bool,
+    surface_size: SurfaceSizePolicy,
+    sizes: SurfaceSizes,
+    /// Components render to it instead of the swapchain when the render extent differs from the surface.
+    render_target: Option<RenderTarget>,
+    // Dropped last
+    pub gpu: GpuContext,
 }
 
 impl Renderer {
-    pub fn new(window: &WindowState, proxy: EventLoopProxy<UserEvent>, vsync: bool) -> Renderer {
-        let entry = ash::Entry::linked();
-        let instance = Instance::new(&entry, Some(window));
-        let surface = Surface::new(&entry, &instance, window);
-        let (physical_device, queue_family_index) = instance.create_physical_device(&entry, &surface);
-        let device = Device::new(&instance, physical_device, queue_family_index);
-        let queue = device.get_queue(0);
-        let command_pool = CommandPool::new(&device, queue_family_index);
-
-        let allocator = Allocator::new(
-            &device,
-            &AllocatorCreateDesc {
-                instance: instance.handle().clone(),
-                device: device.handle().clone(),
-                physical_device,
-                debug_settings: Default::default(),
-                buffer_device_address: false,  // Ideally, check the BufferDeviceAddressFeatures struct.
-                allocation_sizes: Default::default(),
-            }
-        );
+    pub fn new(window: &WindowState, proxy: EventLoopProxy<UserEvent>, app_config: &AppConfig) -> Renderer {
+        let (gpu, surface) = GpuContext::with_surface(window, app_config);
+        Self::with_gpu(gpu, surface, window, proxy, app_config)
+    }
+
+    /// Renderer of the window on an existing context, e.g. one [shared](GpuContext::share) with the renderer of
+    /// another window, presenting to `surface`.
+    pub fn with_gpu(mut gpu: GpuContext, surface: Surface, window: &WindowState, proxy: EventLoopProxy<UserEvent>, app_config: &AppConfig) -> Renderer {
+        let device = gpu.device().clone();
 
-        let present_mode = if vsync {
+        let present_mode = if app_config.vsync {
             vk::PresentModeKHR::FIFO
         } else {
             vk::PresentModeKHR::IMMEDIATE
         };
+        let presenter = Presenter::new(&gpu, window, surface, present_mode, app_config.acquire_timeout);
 
-        info!("Creating initial swapchain");
-        let swapchain = Swapchain::new(&instance, &physical_device, &device, window, &surface, present_mode, None);
-
-        let command_buffers = (0..swapchain.get_image_count()).map(|_| {
-            CommandBuffer::new(&device, &command_pool, true)
-        }).collect::<Vec<CommandBuffer>>();
-
-        let image_available_semaphores = (0..swapchain.get_image_count()).map(|_| unsafe {
-            let semaphore_create_info = vk::SemaphoreCreateInfo::default();
-            device.handle().create_semaphore(&semaphore_create_info, None)
-                .expect("Failed to create semaphore")
-        }).collect::<Vec<vk::Semaphore>>();
-
-        let render_finished_semaphores = (0..swapchain.get_image_count()).map(|_| unsafe {
-            let semaphore_create_info = vk::SemaphoreCreateInfo::default();
-            device.handle().create_semaphore(&semaphore_create_info, None)
-                .expect("Failed to create semaphore")
-        }).collect::<Vec<vk::Semaphore>>();
+        let frames_in_flight = app_config.frames_in_flight;
+        let frames = (0..frames_in_flight)
+            .map(|_| FrameResources::new(&device, &gpu.gfx.command_pool))
+            .collect::<Vec<FrameResources>>();
 
         let start_time = std::time::Instant::now();
 
-        let pipeline_store = PipelineStore::new( &device, proxy );
+        let mut pipeline_store = PipelineStore::new( &device, proxy );
+        pipeline_store.set_shader_paths(app_config.shader_paths.clone());
         let pipeline_context = PipelineContext {
             pipeline_store
         };
 
-        let image_store = ImageStore::new();
-        let image_context = ImageContext {
-            image_store,
-            images: Vec::new(),
+        let mut image_context = ImageContext::new(presenter.swapchain.epoch());
+
+        // Scaling the frame onto the surface blits, which compute queues can't do
+        let surface_size = if gpu.presents_from_compute() && app_config.surface_size != SurfaceSizePolicy::Surface {
+            warn!("Surface size {:?} is not supported when presenting from a compute queue, rendering at the surface extent", app_config.surface_size);
+            SurfaceSizePolicy::Surface
+        } else {
+            app_config.surface_size
         };
+        let sizes = SurfaceSizes::new(surface_size, presenter.extent(), window.scale_factor);
+        let depth = app_config.depth_format.map(|format| {
+            let extent = sizes.render;
+            let config = ImageConfig::depth(extent.width, extent.height, format);
+            image_context.create_image(&mut gpu.gfx, config, ImageFlags::MATCH_SWAPCHAIN_EXTENT)
+        });
+
+        let crash_state = CrashState::new(&gpu.gfx.allocator);
+
+        let random = RandomProvider::new(
+            &device,
+            &mut gpu.gfx.allocator,
+            app_config.random_seed,
+            app_config.random_buffer_size,
+            frames_in_flight
+        );
 
-        let graphics_context = GraphicsContext {
-            device,
-            allocator,
-            queue,
-            command_pool,
+        let globals = FrameGlobals::new(&device, &mut gpu.gfx.allocator, &gpu.gfx.descriptor_allocator, frames_in_flight);
+
+        // The frame already runs on the compute queue when presenting from it
+        let async_compute = if !gpu.presents_from_compute() {
+            AsyncCompute::new(&device, frames_in_flight)
+        } else {
+            None
         };
+        if async_compute.is_none() {
+            info!("No dedicated compute queue available, async compute is disabled");
+        }
+
+        let profiler = GpuProfiler::new(
+            &gpu.instance,
+            gpu.physical_device,
+            &device,
+            frames_in_flight,
+            app_config.gpu_profiler
+        );
 
-        Self {
-            entry,
-            graphics_context,
+        let loading = LoadingProgress::default();
+        let assets = AssetLoader::new(loading.clone());
+
+        let mut renderer = Self {
             image_context,
+            depth,
             pipeline_context,
-            physical_device,
-            instance,
-            surface,
-            swapchain,
-            render_finished_semaphores,
-            image_available_semaphores,
-            command_buffers,
+            presenter,
+            frames,
             frame_index: 0,
             start_time,
-            present_mode,
+            clock: AnimationClock::new(None, false),
+            crash_state,
+            stats: FrameStats::default(),
+            random,
+            async_compute,
+            input: InputState::default(),
+            uploader: Uploader::new(DEFAULT_STAGING_CHUNK_SIZE),
+            loading,
+            components: ComponentRegistry::default(),
+            assets,
+            profiler,
+            globals,
+            window: WindowController::default(),
+            export: None,
+            clear_color: app_config.clear_color,
+            default_viewport: app_config.default_viewport,
+            surface_size,
+            sizes,
+            render_target: None,
+            gpu,
+        };
+        renderer.update_render_target();
+        renderer
+    }
+
+    /// Sizes of the window, see [`CenContext::surface_sizes`].
+    pub fn surface_sizes(&self) -> &SurfaceSizes {
+        &self.sizes
+    }
+
+    fn update_render_target(&mut self) {
+        if !self.sizes.is_scaled() {
+            self.render_target = None;
+            return;
+        }
+        let (format, extent) = (self.presenter.format(), self.sizes.render);
+        match &mut self.render_target {
+            Some(render_target) => render_target.resize(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, format, extent),
+            None => self.render_target = Some(RenderTarget::new(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, format, extent)),
         }
     }
 
-    pub(crate) fn on_window_recreation(&mut self, gui_data: &mut GuiData, window_state: WindowState) {
+    /// Render all following frames offscreen and hand them to the sink of the export instead of presenting them.
+    /// Frames are only exported once the components finished loading, see [`LoadingProgress`].
+    /// The clock should be an [offline](AnimationClock::offline) one, so the frames are exactly one interval apart.
+    pub fn start_export(&mut self, config: ExportConfig) {
+        let format = self.presenter.format();
+        let extent = self.presenter.extent();
+        self.export = Some(OfflineExport::new(config, &self.gpu.gfx.device, &mut self.gpu.gfx.allocator, format, extent));
+    }
+
+    pub fn is_exporting(&self) -> bool {
+        self.export.is_some()
+    }
+
+    /// Whether frames run and present on a compute queue, see [`AppConfig::present_from_compute`].
+    /// Components can only record compute and transfer commands then, and the gui is not drawn.
+    pub fn presents_from_compute(&self) -> bool {
+        self.gpu.presents_from_compute()
+    }
+
+    /// Whether the surface prefers another format or color space than the swapchain, e.g. after the window moved to
+    /// another monitor, and the swapchain has to be recreated.
+    pub(crate) fn is_surface_outdated(&self) -> bool {
+        self.presenter.is_format_outdated(&self.gpu)
+    }
+
+    /// Format and color space of the swapchain.
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        self.presenter.swapchain.get_format()
+    }
+
+    /// Recreate the swapchain for the new window state. Returns whether the render extent changed.
+    pub(crate) fn on_window_recreation(&mut self, gui_data: &mut GuiData, window_state: WindowState) -> bool {
 
-        self.graphics_context.device.wait_idle();
-        info!("Recreating swapchain");
-        self.swapchain = Swapchain::new(&self.instance, &self.physical_device, &self.graphics_context.device, &window_state, &self.surface, self.present_mode, Some(self.swapchain.handle()));
+        let previous_extent = self.sizes.render;
+        let previous_format = self.presenter.format();
+
+        self.gpu.gfx.device.wait_idle();
+        self.presenter.recreate(&self.gpu, &window_state);
+        if self.presenter.format() != previous_format {
+            gui_data.set_color_format(self.presenter.format());
+        }
+
+        let epoch = self.presenter.swapchain.epoch();
+        self.image_context.swapchain_epoch = epoch;
+
+        if let Some(export) = &mut self.export {
+            export.resize(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, self.presenter.format(), self.presenter.extent());
+        }
+        self.sizes = SurfaceSizes::new(self.surface_size, self.presenter.extent(), window_state.scale_factor);
+        self.update_render_target();
 
         let resizeable: Vec<_> = self.image_context.images
             .iter()
@@ -140,15 +296,23 @@ impl Renderer {
             })
             .collect();
 
+        // Keep the user images when only e.g. the scale factor or the fullscreen state changed
+        if self.sizes.render == previous_extent {
+            for resource in &resizeable {
+                self.image_context.image_store.get(&resource.image_key()).set_swapchain_epoch(epoch);
+            }
+            return false;
+        }
+
         for resource in resizeable {
             let image = self.image_context.image_store.get(&resource.image_key());
             let mut config = image.config();
-            config.extent.width = self.swapchain.get_extent().width;
-            config.extent.height = self.swapchain.get_extent().height;
+            config.extent.width = self.sizes.render.width;
+            config.extent.height = self.sizes.render.height;
 
-            let image_key = self.image_context.image_store.insert(
-                Image::new(&self.graphics_context.device, &mut self.graphics_context.allocator, config)
-            );
+            let image = Image::new(&self.gpu.gfx.device, &mut self.gpu.gfx.allocator, config);
+            image.set_swapchain_epoch(epoch);
+            let image_key = self.image_context.image_store.insert(image);
 
             resource.set_image_key(image_key.clone());
             if resource.texture_key().is_some() {
@@ -156,116 +320,393 @@ impl Renderer {
                 resource.set_texture_key(texture);
             }
         }
+
+        true
     }
 
-    fn record_command_buffer<'a>(&mut self, gui: &mut GuiSystem, frame_index: usize, image_index: usize, render_components: &mut [&mut dyn RenderComponent]) {
+    /// Record commands through a [`CenContext`] outside of a frame, and wait for them to execute.
+    pub(crate) fn run_single_time<R>(&mut self, f: impl FnOnce(&mut CenContext) -> R) -> R {
+        let mut command_buffer = self.gpu.gfx.command_buffers.acquire(&self.gpu.gfx.device, &self.gpu.gfx.command_pool);
+        command_buffer.begin();
+        command_buffer.set_default_viewport(self.default_viewport);
+        let mut on_finish = Vec::new();
+        let swapchain_image_count = self.presenter.swapchain.get_image_count() as usize;
 
-        let mut command_buffer = self.command_buffers[frame_index].clone();
+        let mut ctx = CenContext {
+            gfx: &mut self.gpu.gfx,
+            images: &mut self.image_context,
+            pipelines: &mut self.pipeline_context,
+            command_buffer: &mut command_buffer,
+            swapchain_image: None,
+            clock: &self.clock,
+            random: &self.random,
+            input: &self.input,
+            uploader: &mut self.uploader,
+            loading: &self.loading,
+            components: &self.components,
+            assets: &mut self.assets,
+            profiler: &mut self.profiler,
+            globals: &self.globals,
+            window: &mut self.window,
+            async_compute: None,
+            depth: self.depth.as_ref(),
+            sizes: self.sizes,
+            swapchain_image_index: None,
+            swapchain_image_count,
+            on_finish: &mut on_finish,
+            transient_descriptors: None,
+        };
+        let result = f(&mut ctx);
+
+        command_buffer.end();
+        self.submit_single_time_command_buffer(command_buffer);
+        for callback in on_finish {
+            callback();
+        }
+
+        result
+    }
+
+    /// Record a frame rendering to a swapchain image, or to the export surface without an `image_index`.
+    fn record_command_buffer<'a>(&mut self, gui: &mut GuiSystem, frame_index: usize, image_index: Option<usize>, render_components: &mut [&mut dyn RenderComponent]) {
+
+        let mut command_buffer = self.frames[frame_index].command_buffer.clone();
+        let mut frame_flags = render_components.iter()
+            .fold(FrameFlags::empty(), |flags, rc| flags | rc.frame_flags());
+        // The gui is drawn with graphics commands
+        if self.gpu.presents_from_compute() {
+            frame_flags |= FrameFlags::SKIP_GUI;
+        }
 
         command_buffer.begin();
+        command_buffer.set_swapchain_epoch(self.presenter.swapchain.epoch());
+        command_buffer.set_default_viewport(self.default_viewport);
+        self.profiler.begin_frame(frame_index, &mut command_buffer);
+        self.profiler.begin_scope(&mut command_buffer, "frame");
 
         // Store any used textures in the command buffer lifetime
         gui.take_used_textures().iter().for_each(|tex| {
             command_buffer.track(tex);
         });
 
-        let swapchain_image = &self.swapchain.get_images()[image_index];
+        let output = match image_index {
+            Some(image_index) => self.presenter.image(image_index),
+            None => {
+                let target = self.export.as_ref().expect("No export surface").target();
+                target.set_swapchain_epoch(self.presenter.swapchain.epoch());
+                target
+            }
+        };
+        // Components render at the render extent, which is scaled to the output before the gui is drawn
+        let swapchain_image = match &self.render_target {
+            Some(render_target) => {
+                render_target.target().set_swapchain_epoch(self.presenter.swapchain.epoch());
+                render_target.target()
+            }
+            None => output,
+        };
 
         // Clear the swapchain image
-        command_buffer.image_barrier(
-            swapchain_image,
-            ImageLayout::UNDEFINED,
-            ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::AccessFlags::empty(),
-            vk::AccessFlags::MEMORY_WRITE,
-        );
-        command_buffer.clear_color_image(swapchain_image, ImageLayout::TRANSFER_DST_OPTIMAL, [0.0, 0.0, 0.0, 1.0]);
-        command_buffer.image_barrier(
-            swapchain_image,
-            ImageLayout::TRANSFER_DST_OPTIMAL,
-            ImageLayout::PRESENT_SRC_KHR,
-            vk::PipelineStageFlags::TRANSFER,
-            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-            vk::AccessFlags::MEMORY_WRITE,
-            vk::AccessFlags::empty(),
-        );
+        let clear_color = frame_clear(self.clear_color, frame_flags);
+        self.frames[frame_index].cleared = clear_color.is_some();
+        if let Some(clear_color) = clear_color {
+            command_buffer.image_barrier(
+                swapchain_image,
+                ImageLayout::UNDEFINED,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::MEMORY_WRITE,
+            );
+            command_buffer.clear_color_image(swapchain_image, ImageLayout::TRANSFER_DST_OPTIMAL, clear_color);
+            command_buffer.image_barrier(
+                swapchain_image,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::MEMORY_WRITE,
+                vk::AccessFlags::empty(),
+            );
+        }
 
+        // History images that were resized need to be cleared again
+        self.image_context.clear_histories(&mut command_buffer);
+
+        let swapchain_image_count = self.presenter.swapchain.get_image_count() as usize;
+
+        let (on_finish, transient_descriptors) = self.frames[frame_index].recording();
         let mut ctx = CenContext {
-            gfx: &mut self.graphics_context,
+            gfx: &mut self.gpu.gfx,
             images: &mut self.image_context,
             pipelines: &mut self.pipeline_context,
             command_buffer: &mut command_buffer,
             swapchain_image: Some(swapchain_image),
+            clock: &self.clock,
+            random: &self.random,
+            input: &self.input,
+            uploader: &mut self.uploader,
+            loading: &self.loading,
+            components: &self.components,
+            assets: &mut self.assets,
+            profiler: &mut self.profiler,
+            globals: &self.globals,
+            window: &mut self.window,
+            async_compute: self.async_compute.as_mut(),
+            depth: self.depth.as_ref(),
+            sizes: self.sizes,
+            swapchain_image_index: image_index,
+            swapchain_image_count,
+            on_finish,
+            transient_descriptors: Some(transient_descriptors),
         };
 
+        // Uploads of assets that finished loading on a background thread
+        AssetLoader::record_uploads(&mut ctx, frame_index);
+
+        self.crash_state.label("render components");
         for rc in render_components.iter_mut() {
+            trace_scope!("render", component = rc.name());
+            ctx.begin_gpu_scope(rc.name());
             rc.render( &mut ctx );
+            ctx.end_gpu_scope();
+        }
+
+        if let Some(render_target) = &self.render_target {
+            render_target.record_blit(&mut command_buffer, output, &self.sizes, self.clear_color.unwrap_or([0.0, 0.0, 0.0, 1.0]));
         }
 
+        let (on_finish, transient_descriptors) = self.frames[frame_index].recording();
         ctx = CenContext {
-            gfx: &mut self.graphics_context,
+            gfx: &mut self.gpu.gfx,
             images: &mut self.image_context,
             pipelines: &mut self.pipeline_context,
             command_buffer: &mut command_buffer,
-            swapchain_image: Some(swapchain_image),
+            swapchain_image: Some(output),
+            clock: &self.clock,
+            random: &self.random,
+            input: &self.input,
+            uploader: &mut self.uploader,
+            loading: &self.loading,
+            components: &self.components,
+            assets: &mut self.assets,
+            profiler: &mut self.profiler,
+            globals: &self.globals,
+            window: &mut self.window,
+            async_compute: self.async_compute.as_mut(),
+            depth: self.depth.as_ref(),
+            sizes: self.sizes,
+            swapchain_image_index: image_index,
+            swapchain_image_count,
+            on_finish,
+            transient_descriptors: Some(transient_descriptors),
         };
-        gui.render( &mut ctx );
+        self.crash_state.label("gui");
+        if !frame_flags.contains(FrameFlags::SKIP_GUI) {
+            trace_scope!("render", component = "gui");
+            ctx.begin_gpu_scope("gui");
+            gui.render( &mut ctx );
+            ctx.end_gpu_scope();
+        }
 
+        if let (None, Some(export)) = (image_index, &self.export) {
+            export.record_readback(&mut command_buffer);
+        }
+
+        // Hand the swapchain image over to the present queue family, if it's a different one
+        if let Some(image_index) = image_index {
+            self.presenter.release(&mut command_buffer, image_index);
+        }
+
+        self.profiler.end_scope(&mut command_buffer);
+        self.profiler.end_frame(&mut command_buffer);
         command_buffer.end();
     }
 
     pub fn draw_frame<'a>(&mut self, gui: &mut GuiSystem, render_components: &mut [&mut dyn RenderComponent]) {
 
+        // The app exits after the last exported frame
+        if self.export.as_ref().is_some_and(OfflineExport::is_finished) {
+            return;
+        }
+
+        let id = self.prepare_frame();
+
+        // The splash is presented as usual while the components load
+        if self.export.is_some() && self.loading.is_ready() {
+            let fence = self.frames[self.frame_index].fence();
+            self.export_frame(gui, render_components, fence);
+            return;
+        }
+
+        let Some(frame) = self.acquire_frame(id) else {
+            return;
+        };
+        self.record_frame(&frame, gui, render_components);
+        self.end_frame(frame);
+    }
+
+    /// Start a frame for an app that drives the renderer itself: wait until the frame in flight slot is free and
+    /// acquire a swapchain image. Submissions of its own can be interleaved until [`end_frame`](Self::end_frame),
+    /// after recording the frame exactly once with [`record_frame`](Self::record_frame).
+    ///
+    /// `None` when no swapchain image is available within the acquire timeout, the frame is skipped then.
+    /// Exports draw their frames with [`draw_frame`](Self::draw_frame) only.
+    pub fn begin_frame(&mut self) -> Option<Frame> {
+        assert!(self.export.is_none(), "Exports draw their frames with draw_frame");
+        let id = self.prepare_frame();
+        self.acquire_frame(id)
+    }
+
+    /// Record the components and the gui into the command buffer of the frame.
+    pub fn record_frame(&mut self, frame: &Frame, gui: &mut GuiSystem, render_components: &mut [&mut dyn RenderComponent]) {
+        self.record_command_buffer(gui, frame.frame_index, Some(frame.image_index), render_components);
+    }
+
+    /// Submit the frame and present its swapchain image.
+    pub fn end_frame(&mut self, frame: Frame) {
+        // Compute work goes first, the graphics work of this frame may consume its results
+        let image_available_stages = image_available_stages(self.frames[frame.frame_index].cleared);
+        let mut wait_semaphores = vec![(frame.image_available, image_available_stages)];
+        if let Some(semaphore) = self.async_compute.as_mut().and_then(|async_compute| async_compute.submit()) {
+            wait_semaphores.push((semaphore, vk::PipelineStageFlags::ALL_COMMANDS));
+        }
+        wait_semaphores.extend(frame.wait_semaphores);
+        let mut signal_semaphores = vec![frame.render_finished];
+        signal_semaphores.extend(frame.signal_semaphores);
+
+        self.gpu.gfx.device.reset_fence(frame.fence);
+        self.gpu.gfx.device.submit_command_buffer_with(
+            &self.gpu.gfx.queue,
+            &wait_semaphores,
+            &signal_semaphores,
+            &frame.command_buffer
+        );
+        self.crash_state.submitted();
+
+        self.presenter.present(self.gpu.gfx.queue, frame.image_index);
+
+        self.stats.frames_rendered += 1;
+        self.frame_index = ( self.frame_index + 1 ) % self.frames.len();
+    }
+
+    /// Wait until the current frame in flight slot executed and reset the per-frame state. Returns the frame id.
+    fn prepare_frame(&mut self) -> u64 {
         // Clean up the stores
         self.image_context.cleanup();
 
+        self.clock.tick();
+        let id = self.gpu.gfx.device.event_log().begin_frame();
+
         // Wait for the current frame's command buffer to finish executing.
-        let fence = self.command_buffers[self.frame_index].fence();
-        self.graphics_context.device.wait_for_fence(fence);
+        let fence = self.frames[self.frame_index].fence();
+        self.gpu.gfx.device.wait_for_fence(fence);
+        self.frames[self.frame_index].finished();
+
+        // Resources dropped until now are only used by this frame and the ones in flight
+        let deletion_queue = &self.gpu.gfx.device.inner.deletion_queue;
+        deletion_queue.frame_completed(self.frames[self.frame_index].deletion_frame);
+        self.frames[self.frame_index].deletion_frame = deletion_queue.begin_frame();
+        self.crash_state.begin_frame(id, self.frame_index);
+        self.random.advance(self.frame_index);
+        self.globals.update(self.frame_index, &self.clock, &self.input, &self.sizes, self.random.seed());
+        self.assets.frame_completed(self.frame_index);
+        self.profiler.frame_completed(self.frame_index);
+        id
+    }
 
+    /// Acquire the swapchain image of the frame, `None` when the frame is skipped.
+    fn acquire_frame(&mut self, id: u64) -> Option<Frame> {
         // Acquire image and signal the semaphore
-        let image_index = self.swapchain.acquire_next_image(self.image_available_semaphores[self.frame_index]) as usize;
+        // Skip the frame when the presentation engine doesn't hand out an image in time, instead of blocking the event loop
+        let acquire_start = Instant::now();
+        let image_index = match self.presenter.acquire(self.frames[self.frame_index].image_available) {
+            Some(image_index) => image_index,
+            None => {
+                let timeout = self.presenter.acquire_timeout();
+                warn!("Skipping frame, no swapchain image available after {:?}", timeout);
+                self.stats.record_stall(acquire_start.elapsed());
+                self.gpu.gfx.device.event_log().record(FrameEvent::AcquireStall { timeout });
+                // Nothing was submitted, so the fence doesn't tell when the resources dropped during this frame are unused
+                self.frames[self.frame_index].deletion_frame = 0;
+                return None;
+            }
+        };
+        self.crash_state.acquired(image_index);
+        self.image_context.advance_histories();
+        if let Some(async_compute) = &mut self.async_compute {
+            async_compute.begin_frame(self.frame_index);
+        }
 
-        self.record_command_buffer(gui, self.frame_index, image_index, render_components);
+        let resources = &self.frames[self.frame_index];
+        Some(Frame {
+            id,
+            frame_index: self.frame_index,
+            image_index,
+            command_buffer: resources.command_buffer.clone(),
+            image_available: resources.image_available,
+            render_finished: self.presenter.render_finished(image_index),
+            fence: resources.fence(),
+            wait_semaphores: Vec::new(),
+            signal_semaphores: Vec::new(),
+        })
+    }
 
-        self.graphics_context.device.reset_fence(fence);
-        self.graphics_context.device.submit_command_buffer(
-            &self.graphics_context.queue,
-            self.image_available_semaphores[self.frame_index],
-            self.render_finished_semaphores[image_index],
-            &self.command_buffers[self.frame_index]
-        );
+    /// Render a frame to the export surface and hand it to the sink once it executed.
+    fn export_frame(&mut self, gui: &mut GuiSystem, render_components: &mut [&mut dyn RenderComponent], fence: vk::Fence) {
+        self.image_context.advance_histories();
+        if let Some(async_compute) = &mut self.async_compute {
+            async_compute.begin_frame(self.frame_index);
+        }
+
+        self.record_command_buffer(gui, self.frame_index, None, render_components);
 
-        self.swapchain.queue_present(
-            self.graphics_context.queue,
-            self.render_finished_semaphores[image_index],
-            image_index as u32
+        let wait_semaphores: Vec<_> = self.async_compute.as_mut()
+            .and_then(|async_compute| async_compute.submit())
+            .map(|semaphore| (semaphore, vk::PipelineStageFlags::ALL_COMMANDS))
+            .into_iter()
+            .collect();
+
+        self.gpu.gfx.device.reset_fence(fence);
+        self.gpu.gfx.device.submit_command_buffer_with(
+            &self.gpu.gfx.queue,
+            &wait_semaphores,
+            &[],
+            &self.frames[self.frame_index].command_buffer
         );
+        self.crash_state.submitted();
+
+        // Exports don't run in real time, so wait for the frame instead of overlapping it with the next one
+        self.gpu.gfx.device.wait_for_fence(fence);
+        let export = self.export.as_mut().expect("No export surface");
+        export.deliver(self.clock.elapsed());
+        if export.is_finished() {
+            self.window.request_exit();
+        }
 
-        self.frame_index = ( self.frame_index + 1 ) % self.swapchain.get_image_views().len();
+        self.clock.advance();
+        self.stats.frames_rendered += 1;
+        self.frame_index = ( self.frame_index + 1 ) % self.frames.len();
     }
 
     pub fn submit_single_time_command_buffer(&mut self, command_buffer: CommandBuffer) {
-        self.graphics_context.device.submit_single_time_command(
-            self.graphics_context.queue,
-            &command_buffer
-        );
-        self.graphics_context.device.wait_for_fence(command_buffer.fence());
+        self.gpu.gfx.submit_and_wait(command_buffer);
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
-        unsafe {
-            self.graphics_context.device.handle().device_wait_idle().unwrap();
-            for semaphore in &self.render_finished_semaphores {
-                self.graphics_context.device.handle().destroy_semaphore(*semaphore, None);
-            }
-            for semaphore in &self.image_available_semaphores {
-                self.graphics_context.device.handle().destroy_semaphore(*semaphore, None);
-            }
+        self.gpu.gfx.device.wait_idle();
+        let deletion_queue = &self.gpu.gfx.device.inner.deletion_queue;
+        for frame in &mut self.frames {
+            frame.finished();
+            frame.destroy(&self.gpu.gfx.device);
+            deletion_queue.frame_completed(frame.deletion_frame);
+        }
+        // The queued resources hold on to the device, renderers sharing it leave them to the renderer owning it
+        if !self.gpu.is_shared() {
+            deletion_queue.flush();
         }
     }
 }
@@ -0,0 +1,21 @@
+use std::time::{Duration, Instant};
+
+/// Counters of the renderer frame loop.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    /// Frames that were submitted for presentation.
+    pub frames_rendered: u64,
+    /// Frames that were skipped because no swapchain image became available in time.
+    pub frames_skipped: u64,
+    /// Longest time spent waiting on a swapchain image acquisition that timed out.
+    pub longest_stall: Duration,
+    pub last_stall: Option<Instant>,
+}
+
+impl FrameStats {
+    pub(crate) fn record_stall(&mut self, waited: Duration) {
+        self.frames_skipped += 1;
+        self.longest_stall = self.longest_stall.max(waited);
+        self.last_stall = Some(Instant::now());
+    }
+}
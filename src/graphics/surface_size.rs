@@ -0,0 +1,239 @@
+use ash::vk;
+use crate::vulkan::{Allocator, CommandBuffer, Device, Image, ImageConfig, SwapchainImage};
+
+/// Extent the components render at, see [`AppConfig::surface_size`](crate::app::app::AppConfig::surface_size).
+///
+/// With fractional scaling the compositor reports a surface extent of the logical window size times the scale
+/// factor. Policies other than [`Surface`](Self::Surface) render to an intermediate image that is scaled onto the
+/// surface before the gui is drawn, so the gui stays sharp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurfaceSizePolicy {
+    /// Render at the extent the surface reports, in physical pixels.
+    #[default]
+    Surface,
+    /// Render at the logical window size, the surface extent divided by the scale factor, and stretch the frame to
+    /// the surface.
+    Logical,
+    /// Render at a fixed extent regardless of the window. With `letterbox` the frame keeps its aspect ratio and the
+    /// rest of the surface has the clear color, otherwise it is stretched to the surface.
+    Fixed { width: u32, height: u32, letterbox: bool },
+}
+
+/// Sizes of the window a frame renders to, see [`CenContext::surface_sizes`](crate::app::engine::CenContext::surface_sizes).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceSizes {
+    /// Extent of the swapchain, in physical pixels.
+    pub surface: vk::Extent2D,
+    /// Size of the window in logical pixels.
+    pub logical: vk::Extent2D,
+    pub scale_factor: f64,
+    /// Extent the components render at, that of [`CenContext::swapchain_image`](crate::app::engine::CenContext).
+    pub render: vk::Extent2D,
+    /// Area of the surface the rendered frame is scaled to.
+    pub viewport: vk::Rect2D,
+}
+
+impl SurfaceSizes {
+    pub fn new(policy: SurfaceSizePolicy, surface: vk::Extent2D, scale_factor: f64) -> Self {
+        let scale = |size: u32| ((size as f64 / scale_factor).round() as u32).max(1);
+        let logical = vk::Extent2D { width: scale(surface.width), height: scale(surface.height) };
+        let full = vk::Rect2D { offset: vk::Offset2D::default(), extent: surface };
+
+        let (render, viewport) = match policy {
+            SurfaceSizePolicy::Surface => (surface, full),
+            SurfaceSizePolicy::Logical => (logical, full),
+            SurfaceSizePolicy::Fixed { width, height, letterbox } => {
+                let render = vk::Extent2D { width: width.max(1), height: height.max(1) };
+                if !letterbox {
+                    (render, full)
+                } else {
+                    let fit = (surface.width as f64 / render.width as f64).min(surface.height as f64 / render.height as f64);
+                    let extent = vk::Extent2D {
+                        width: ((render.width as f64 * fit).round() as u32).clamp(1, surface.width),
+                        height: ((render.height as f64 * fit).round() as u32).clamp(1, surface.height),
+                    };
+                    let offset = vk::Offset2D {
+                        x: ((surface.width - extent.width) / 2) as i32,
+                        y: ((surface.height - extent.height) / 2) as i32,
+                    };
+                    (render, vk::Rect2D { offset, extent })
+                }
+            }
+        };
+
+        Self { surface, logical, scale_factor, render, viewport }
+    }
+
+    /// Whether the components render to an intermediate image that is scaled onto the surface.
+    pub fn is_scaled(&self) -> bool {
+        self.render != self.surface
+    }
+
+    /// Map a position on the surface in physical pixels, e.g. the cursor, to the rendered frame.
+    /// `None` on the bars of a letterboxed frame.
+    pub fn surface_to_render(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let u = (x - self.viewport.offset.x as f64) / self.viewport.extent.width as f64;
+        let v = (y - self.viewport.offset.y as f64) / self.viewport.extent.height as f64;
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some((u * self.render.width as f64, v * self.render.height as f64))
+    }
+}
+
+/// Intermediate image the components render to when the render extent differs from the surface, used like a
+/// swapchain image.
+pub(crate) struct RenderTarget {
+    format: vk::Format,
+    extent: vk::Extent2D,
+    // The view wraps the image, so it is dropped first
+    target: SwapchainImage,
+    _image: Image,
+}
+
+impl RenderTarget {
+    pub(crate) fn new(device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) -> Self {
+        let (image, target) = Self::create_image(device, allocator, format, extent);
+        Self { format, extent, target, _image: image }
+    }
+
+    fn create_image(device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) -> (Image, SwapchainImage) {
+        let image = Image::new(device, allocator, ImageConfig {
+            extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+            format,
+            image_usage_flags: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+            ..Default::default()
+        });
+        let target = SwapchainImage::from_raw(device, image.handle(), format, extent);
+        (image, target)
+    }
+
+    /// Follow the swapchain format and the render extent.
+    pub(crate) fn resize(&mut self, device: &Device, allocator: &mut Allocator, format: vk::Format, extent: vk::Extent2D) {
+        if format == self.format && extent == self.extent {
+            return;
+        }
+        let (image, target) = Self::create_image(device, allocator, format, extent);
+        self.target = target;
+        self._image = image;
+        self.format = format;
+        self.extent = extent;
+    }
+
+    pub(crate) fn target(&self) -> &SwapchainImage {
+        &self.target
+    }
+
+    /// Scale the rendered frame, in the presentation layout like a swapchain image, to the viewport of `output`, with
+    /// bars of `bar_color` around a letterboxed frame. Leaves `output` in the presentation layout for the gui.
+    pub(crate) fn record_blit(&self, command_buffer: &mut CommandBuffer, output: &SwapchainImage, sizes: &SurfaceSizes, bar_color: [f32; 4]) {
+        command_buffer.image_barrier(
+            &self.target,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        command_buffer.image_barrier(
+            output,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+
+        // The bars of a letterboxed frame
+        if sizes.viewport.extent != sizes.surface {
+            command_buffer.clear_color_image(output, vk::ImageLayout::TRANSFER_DST_OPTIMAL, bar_color);
+            command_buffer.memory_barrier(
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_WRITE,
+            );
+        }
+
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let viewport = sizes.viewport;
+        command_buffer.blit_image(
+            &self.target,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            output,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::ImageBlit::default()
+                .src_subresource(subresource)
+                .src_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D { x: self.extent.width as i32, y: self.extent.height as i32, z: 1 },
+                ])
+                .dst_subresource(subresource)
+                .dst_offsets([
+                    vk::Offset3D { x: viewport.offset.x, y: viewport.offset.y, z: 0 },
+                    vk::Offset3D {
+                        x: viewport.offset.x + viewport.extent.width as i32,
+                        y: viewport.offset.y + viewport.extent.height as i32,
+                        z: 1,
+                    },
+                ])],
+            vk::Filter::LINEAR,
+        );
+
+        command_buffer.image_barrier(
+            &self.target,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::empty(),
+        );
+        command_buffer.image_barrier(
+            output,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn surface_sizes() {
+        // 1.5 fractional scaling
+        let surface = SurfaceSizes::new(SurfaceSizePolicy::Surface, extent(1920, 1080), 1.5);
+        assert_eq!(surface.logical, extent(1280, 720));
+        assert_eq!(surface.render, extent(1920, 1080));
+        assert!(!surface.is_scaled());
+
+        let logical = SurfaceSizes::new(SurfaceSizePolicy::Logical, extent(1920, 1080), 1.5);
+        assert_eq!(logical.render, extent(1280, 720));
+        assert_eq!(logical.viewport.extent, extent(1920, 1080));
+        assert_eq!(logical.surface_to_render(960.0, 540.0), Some((640.0, 360.0)));
+
+        // A 4:3 frame on a 16:9 surface has bars left and right
+        let fixed = SurfaceSizes::new(SurfaceSizePolicy::Fixed { width: 640, height: 480, letterbox: true }, extent(1920, 1080), 1.0);
+        assert_eq!(fixed.viewport.extent, extent(1440, 1080));
+        assert_eq!(fixed.viewport.offset, vk::Offset2D { x: 240, y: 0 });
+        assert_eq!(fixed.surface_to_render(100.0, 500.0), None);
+        assert_eq!(fixed.surface_to_render(240.0, 0.0), Some((0.0, 0.0)));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{fmt, fs, io};
+use std::path::Path;
+use ash::vk;
+use log::warn;
+use crate::app::engine::CenContext;
+use crate::vulkan::{texel_size, Image, ImageConfig, ImageError, SamplerConfig};
+
+/// How [`Image::from_file`] and friends create an image.
+///
+/// ```ignore
+/// let options = TextureOptions::default().mips(true).sampler(SamplerConfig::default().filter(Filter::Linear));
+/// let albedo = Image::from_file(ctx, "textures/albedo.png", &options)?;
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    /// Whether 8 bit color is sRGB encoded, so sampling returns linear values. Enabled by default, disable it for
+    /// data like normal maps.
+    pub srgb: bool,
+    /// Generate the full mip chain. Skipped with a warning when the device can't blit the format linearly.
+    pub mips: bool,
+    /// Usage besides `SAMPLED` and the transfers of the upload.
+    pub usage: vk::ImageUsageFlags,
+    /// Samples all mip levels by default.
+    pub sampler: SamplerConfig,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            srgb: true,
+            mips: false,
+            usage: vk::ImageUsageFlags::empty(),
+            sampler: SamplerConfig::default().lod(0.0, vk::LOD_CLAMP_NONE),
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn mips(mut self, mips: bool) -> Self {
+        self.mips = mips;
+        self
+    }
+
+    pub fn usage(mut self, usage: impl Into<vk::ImageUsageFlags>) -> Self {
+        self.usage = usage.into();
+        self
+    }
+
+    pub fn sampler(mut self, sampler: SamplerConfig) -> Self {
+        self.sampler = sampler;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    /// The file couldn't be decoded, or its format isn't enabled.
+    Decode(String),
+    /// The pixels of [`TextureData`] don't match its extent and format.
+    InvalidData(String),
+    Image(ImageError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "Failed to read texture: {}", e),
+            TextureError::Decode(e) => write!(f, "Failed to decode texture: {}", e),
+            TextureError::InvalidData(e) => write!(f, "Invalid texture data: {}", e),
+            TextureError::Image(e) => write!(f, "Failed to create texture: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for TextureError {
+    fn from(e: io::Error) -> Self {
+        TextureError::Io(e)
+    }
+}
+
+/// Decoded texels of an image file, tightly packed row by row.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub pixels: Vec<u8>,
+}
+
+impl TextureData {
+    /// Decode a PNG, JPEG or OpenEXR file, each with the feature of the same name. 8 bit images become RGBA8, sRGB
+    /// encoded with `srgb`, 16 bit images RGBA16 and float images RGBA32F.
+    ///
+    /// Decoding doesn't touch the GPU, so it can run on a background thread, with the image created from the result
+    /// by [`Image::from_texture_data`] afterwards.
+    pub fn decode(bytes: &[u8], srgb: bool) -> Result<TextureData, TextureError> {
+        decode(bytes, srgb)
+    }
+
+    /// Read and decode a file, see [`decode`](Self::decode).
+    pub fn open(path: impl AsRef<Path>, srgb: bool) -> Result<TextureData, TextureError> {
+        Self::decode(&fs::read(path)?, srgb)
+    }
+
+    /// Check the pixels cover the extent with texels of the format.
+    fn check(&self) -> Result<(), TextureError> {
+        let texel_size = texel_size(self.format)
+            .ok_or_else(|| TextureError::InvalidData(format!("{:?} has no texel size", self.format)))?;
+        let expected = texel_size * self.width as u64 * self.height as u64;
+        if self.pixels.len() as u64 != expected {
+            return Err(TextureError::InvalidData(format!(
+                "{} bytes of pixels for {}x{} {:?}, expected {}", self.pixels.len(), self.width, self.height, self.format, expected
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "png", feature = "jpeg", feature = "exr"))]
+fn decode(bytes: &[u8], srgb: bool) -> Result<TextureData, TextureError> {
+    use image::DynamicImage;
+
+    let image = image::load_from_memory(bytes).map_err(|e| TextureError::Decode(e.to_string()))?;
+    let (width, height) = (image.width(), image.height());
+    let (format, pixels) = match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            (vk::Format::R32G32B32A32_SFLOAT, bytemuck::cast_slice(&image.to_rgba32f().into_raw()).to_vec())
+        }
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+            (vk::Format::R16G16B16A16_UNORM, bytemuck::cast_slice(&image.to_rgba16().into_raw()).to_vec())
+        }
+        _ => {
+            let format = if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM };
+            (format, image.to_rgba8().into_raw())
+        }
+    };
+    Ok(TextureData { width, height, format, pixels })
+}
+
+#[cfg(not(any(feature = "png", feature = "jpeg", feature = "exr")))]
+fn decode(_bytes: &[u8], _srgb: bool) -> Result<TextureData, TextureError> {
+    Err(TextureError::Decode("No image format is enabled, enable the png, jpeg or exr feature of cen".to_string()))
+}
+
+/// Mip levels of a full chain down to 1x1.
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+impl Image {
+    /// Create a sampled image from a PNG, JPEG or OpenEXR file, see [`TextureData::decode`]. The upload is recorded
+    /// into the current command buffer and the image is left in `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn from_file(ctx: &mut CenContext, path: impl AsRef<Path>, options: &TextureOptions) -> Result<Image, TextureError> {
+        let data = TextureData::open(path, options.srgb)?;
+        Image::from_texture_data(ctx, &data, options)
+    }
+
+    /// Create a sampled image from the contents of an image file, see [`from_file`](Self::from_file).
+    pub fn from_bytes(ctx: &mut CenContext, bytes: &[u8], options: &TextureOptions) -> Result<Image, TextureError> {
+        let data = TextureData::decode(bytes, options.srgb)?;
+        Image::from_texture_data(ctx, &data, options)
+    }
+
+    /// Create a sampled image from decoded texels, see [`from_file`](Self::from_file).
+    pub fn from_texture_data(ctx: &mut CenContext, data: &TextureData, options: &TextureOptions) -> Result<Image, TextureError> {
+        data.check()?;
+
+        let blit_features = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        let mips = options.mips && ctx.gfx.device.format_properties(data.format).optimal_tiling_features.contains(blit_features);
+        if options.mips && !mips {
+            warn!("{:?} can't be blitted linearly, the texture has no mips", data.format);
+        }
+
+        let mut usage = options.usage | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        if mips {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let config = ImageConfig {
+            mip_levels: if mips { mip_level_count(data.width, data.height) } else { 1 },
+            sampler: options.sampler,
+            ..ImageConfig::new(data.width, data.height, data.format, usage)
+        };
+        let image = Image::try_new(&ctx.gfx.device, &mut ctx.gfx.allocator, config).map_err(TextureError::Image)?;
+
+        let (staging, offset) = ctx.uploader.stage(&ctx.gfx.device, &mut ctx.gfx.allocator, &data.pixels);
+        ctx.command_buffer.image_barrier(
+            &image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        ctx.command_buffer.copy_buffer_to_image(
+            &staging,
+            &image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::BufferImageCopy::default()
+                .buffer_offset(offset)
+                .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1))
+                .image_extent(config.extent)]
+        );
+        if mips {
+            ctx.command_buffer.generate_mips(&image, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        } else {
+            ctx.command_buffer.image_barrier(
+                &image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            );
+        }
+
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_levels() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 17), 9);
+    }
+
+    #[test]
+    fn check_texture_data() {
+        let data = |pixels: usize, format| TextureData { width: 4, height: 2, format, pixels: vec![0; pixels] };
+        assert!(data(32, vk::Format::R8G8B8A8_SRGB).check().is_ok());
+        assert!(data(128, vk::Format::R32G32B32A32_SFLOAT).check().is_ok());
+        assert!(matches!(data(31, vk::Format::R8G8B8A8_SRGB).check(), Err(TextureError::InvalidData(_))));
+        assert!(matches!(data(32, vk::Format::BC1_RGB_UNORM_BLOCK).check(), Err(TextureError::InvalidData(_))));
+    }
+}
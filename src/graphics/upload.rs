@@ -0,0 +1,327 @@
+use std::ops::Range;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use crate::app::engine::CenContext;
+use crate::app::ImageResource;
+use crate::vulkan::{texel_size, Allocator, Buffer, Device, Image, ImageTrait};
+
+/// Default size of the staging chunks, larger uploads get a staging buffer of their own.
+pub const DEFAULT_STAGING_CHUNK_SIZE: vk::DeviceSize = 8 * 1024 * 1024;
+
+/// Offsets of staged data are aligned to this, which satisfies the copy alignment of all formats.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+/// Staging memory for uploads to `GpuOnly` images and buffers, see [`CenContext::upload_image`] and [`CenContext::upload_buffer`].
+///
+/// Data is written linearly into a ring of host visible chunks. The command buffers that copy from a chunk keep it
+/// referenced until they are recorded again, after which the chunk is reused. New chunks are only allocated when
+/// all existing ones are still in flight.
+pub struct Uploader {
+    chunks: Vec<Buffer>,
+    current: usize,
+    offset: vk::DeviceSize,
+    chunk_size: vk::DeviceSize,
+}
+
+impl Uploader {
+    pub fn new(chunk_size: vk::DeviceSize) -> Self {
+        Self {
+            chunks: Vec::new(),
+            current: 0,
+            offset: 0,
+            chunk_size,
+        }
+    }
+
+    /// Number of staging chunks allocated so far.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Copy `data` into staging memory. Returns the staging buffer and the offset of the data in it.
+    /// The buffer must be tracked by the command buffer that copies from it.
+    pub(crate) fn stage(&mut self, device: &Device, allocator: &mut Allocator, data: &[u8]) -> (Buffer, vk::DeviceSize) {
+        let size = data.len() as vk::DeviceSize;
+
+        if size > self.chunk_size {
+            let buffer = create_staging_buffer(device, allocator, size);
+            buffer.mapped().expect("Failed to map staging buffer").as_mut_slice()[..data.len()].copy_from_slice(data);
+            return (buffer, 0);
+        }
+
+        let mut offset = self.offset.next_multiple_of(STAGING_ALIGNMENT);
+        if self.chunks.is_empty() || offset + size > self.chunk_size {
+            self.next_chunk(device, allocator);
+            offset = 0;
+        }
+        self.offset = offset + size;
+
+        let chunk = &self.chunks[self.current];
+        chunk.mapped().expect("Failed to map staging buffer").as_mut_slice()[offset as usize..(offset + size) as usize]
+            .copy_from_slice(data);
+
+        (chunk.clone(), offset)
+    }
+
+    /// Move on to the next chunk that no command buffer references anymore.
+    fn next_chunk(&mut self, device: &Device, allocator: &mut Allocator) {
+        let count = self.chunks.len();
+        let free = (1..=count)
+            .map(|i| (self.current + i) % count)
+            .find(|&i| !self.chunks[i].in_use());
+
+        self.current = match free {
+            Some(index) => index,
+            None => {
+                self.chunks.push(create_staging_buffer(device, allocator, self.chunk_size));
+                self.chunks.len() - 1
+            }
+        };
+        self.offset = 0;
+    }
+}
+
+/// Bytes of tightly packed texel data that cover `extent` of `layers` layers of the image. Panics for formats without
+/// a texel size, like block compressed ones and combined depth/stencil ones, whose aspects are copied separately.
+fn upload_size(image: &Image, extent: vk::Extent3D, layers: u32) -> usize {
+    let format = image.config().format;
+    let texel_size = texel_size(format)
+        .unwrap_or_else(|| panic!("Uploads to {:?} images are not supported, only to uncompressed formats with a single aspect", format));
+    (texel_size * extent.width as u64 * extent.height as u64 * extent.depth as u64 * layers as u64) as usize
+}
+
+fn create_staging_buffer(device: &Device, allocator: &mut Allocator, size: vk::DeviceSize) -> Buffer {
+    Buffer::new(
+        device,
+        allocator,
+        MemoryLocation::CpuToGpu,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC
+    )
+}
+
+impl CenContext<'_> {
+
+    /// Upload tightly packed texel data to the first mip level of all layers of an image with `TRANSFER_DST` usage.
+    /// The copy is recorded into the current command buffer, the image is left in `SHADER_READ_ONLY_OPTIMAL`.
+    ///
+    /// Panics when `data` doesn't cover the image exactly, or for formats without a [`texel_size`], e.g. combined
+    /// depth/stencil formats.
+    pub fn upload_image(&mut self, image: &ImageResource, data: &[u8]) {
+        let target = self.images.get(image);
+        let config = target.config();
+        let size = upload_size(target, config.extent, config.array_layers);
+        assert_eq!(data.len(), size, "Upload of {} bytes to an image of {} bytes", data.len(), size);
+
+        let (staging, offset) = self.uploader.stage(&self.gfx.device, &mut self.gfx.allocator, data);
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_READ,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        self.command_buffer.copy_buffer_to_image(
+            &staging,
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::BufferImageCopy::default()
+                .buffer_offset(offset)
+                .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(target.aspect_mask())
+                    .layer_count(config.array_layers))
+                .image_extent(config.extent)]
+        );
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+    }
+
+    /// Upload tightly packed texel data to the first mip level of one layer of an image with `TRANSFER_DST` usage, e.g.
+    /// a face of a cubemap, keeping the other layers. The image has to be in `SHADER_READ_ONLY_OPTIMAL`, e.g. after
+    /// [`upload_image`](Self::upload_image), and is left in it.
+    pub fn upload_image_layer(&mut self, image: &ImageResource, layer: u32, data: &[u8]) {
+        let target = self.images.get(image);
+        let config = target.config();
+        assert!(layer < config.array_layers, "Layer {} out of range, the image has {} layers", layer, config.array_layers);
+        let size = upload_size(target, config.extent, 1);
+        assert_eq!(data.len(), size, "Upload of {} bytes to a layer of {} bytes", data.len(), size);
+
+        let (staging, offset) = self.uploader.stage(&self.gfx.device, &mut self.gfx.allocator, data);
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        self.command_buffer.copy_buffer_to_image(
+            &staging,
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::BufferImageCopy::default()
+                .buffer_offset(offset)
+                .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(target.aspect_mask())
+                    .base_array_layer(layer)
+                    .layer_count(1))
+                .image_extent(config.extent)]
+        );
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+    }
+
+    /// Upload tightly packed texel data to a region of the first mip level and layer of an image with `TRANSFER_DST`
+    /// usage, keeping the rest of its contents. The image has to be in `SHADER_READ_ONLY_OPTIMAL`, e.g. after
+    /// [`upload_image`](Self::upload_image), and is left in it.
+    pub fn upload_image_region(&mut self, image: &ImageResource, offset: vk::Offset2D, extent: vk::Extent2D, data: &[u8]) {
+        let target = self.images.get(image);
+        let config = target.config();
+        let inside = |offset: i32, size: u32, image_size: u32| {
+            u32::try_from(offset).ok()
+                .and_then(|offset| offset.checked_add(size))
+                .is_some_and(|end| end <= image_size)
+        };
+        assert!(
+            inside(offset.x, extent.width, config.extent.width) && inside(offset.y, extent.height, config.extent.height),
+            "Region {:?} {:?} exceeds the image extent of {}x{}", offset, extent, config.extent.width, config.extent.height
+        );
+        let size = upload_size(target, vk::Extent3D { width: extent.width, height: extent.height, depth: 1 }, 1);
+        assert_eq!(data.len(), size, "Upload of {} bytes to a region of {} bytes", data.len(), size);
+
+        let (staging, staging_offset) = self.uploader.stage(&self.gfx.device, &mut self.gfx.allocator, data);
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        self.command_buffer.copy_buffer_to_image(
+            &staging,
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::BufferImageCopy::default()
+                .buffer_offset(staging_offset)
+                .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(target.aspect_mask())
+                    .layer_count(1))
+                .image_offset(vk::Offset3D { x: offset.x, y: offset.y, z: 0 })
+                .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })]
+        );
+        self.command_buffer.image_barrier(
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        );
+    }
+
+    /// Upload data to the start of a buffer with `TRANSFER_DST` usage, e.g. one in `GpuOnly` memory.
+    /// The copy is recorded into the current command buffer and is visible to all commands recorded after it.
+    pub fn upload_buffer(&mut self, buffer: &Buffer, data: &[u8]) {
+        assert!(data.len() as vk::DeviceSize <= buffer.size(), "Upload of {} bytes exceeds the buffer size of {}", data.len(), buffer.size());
+        if data.is_empty() {
+            return;
+        }
+
+        let (staging, offset) = self.uploader.stage(&self.gfx.device, &mut self.gfx.allocator, data);
+        let size = data.len() as vk::DeviceSize;
+
+        // Earlier commands may still use the previous contents
+        self.command_buffer.buffer_barrier(
+            buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::TRANSFER_WRITE,
+            0,
+            size
+        );
+        self.command_buffer.copy_buffer(
+            &staging,
+            buffer,
+            &[vk::BufferCopy::default()
+                .src_offset(offset)
+                .size(size)]
+        );
+        self.command_buffer.buffer_barrier(
+            buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            0,
+            size
+        );
+    }
+
+    /// Read back a byte range of a buffer with `TRANSFER_SRC` usage, e.g. counters or picking results written by
+    /// a compute pass. The copy into host visible memory is recorded into the current command buffer after the
+    /// commands so far, and `callback` gets the bytes once it executed, see [`run_on_finish`](Self::run_on_finish).
+    /// Nothing waits on the GPU, so the results arrive a few frames later.
+    pub fn read_buffer(&mut self, buffer: &Buffer, range: Range<vk::DeviceSize>, callback: impl FnOnce(&[u8]) + 'static) {
+        assert!(range.start <= range.end && range.end <= buffer.size(), "Readback of {:?} exceeds the buffer size of {}", range, buffer.size());
+        assert!(buffer.usage().contains(vk::BufferUsageFlags::TRANSFER_SRC), "Readback needs a buffer with TRANSFER_SRC usage");
+        let size = range.end - range.start;
+        if size == 0 {
+            self.run_on_finish(move || callback(&[]));
+            return;
+        }
+
+        let readback = Buffer::new(&self.gfx.device, &mut self.gfx.allocator, MemoryLocation::GpuToCpu, size, vk::BufferUsageFlags::TRANSFER_DST);
+
+        self.command_buffer.buffer_barrier(
+            buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::MEMORY_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+            range.start,
+            size
+        );
+        self.command_buffer.copy_buffer(
+            buffer,
+            &readback,
+            &[vk::BufferCopy::default()
+                .src_offset(range.start)
+                .size(size)]
+        );
+        self.command_buffer.buffer_barrier(
+            &readback,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::HOST_READ,
+            0,
+            size
+        );
+
+        self.run_on_finish(move || {
+            let mapped = readback.mapped().expect("Failed to map the readback buffer");
+            callback(&mapped.as_slice()[..size as usize]);
+        });
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use ash::vk;
+use crate::app::engine::CenContext;
+use crate::app::{ImageFlags, ImageResource};
+use crate::vulkan::{compile_shader_code, glsl_image_format, ComputePipeline, DescriptorSetLayout, ImageConfig, ImageTrait, SamplerConfig, STORAGE_COLOR_FORMATS};
+
+const RAYMARCH_SHADER: &str = r#"
+#version 450
+
+layout ( local_size_x = 8, local_size_y = 8, local_size_z = 1 ) in;
+
+layout( binding = 0 ) uniform sampler3D volume;
+layout( binding = 1 ) uniform sampler2D transfer_function;
+layout( binding = 2, OUTPUT_FORMAT ) uniform writeonly image2D output_image;
+
+layout( push_constant ) uniform PushConstants {
+    mat4 inverse_view_projection;
+    float step_size;
+    float density_scale;
+    uint max_steps;
+} pc;
+
+vec3 unproject( vec2 ndc, float depth )
+{
+    vec4 position = pc.inverse_view_projection * vec4( ndc, depth, 1.0 );
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    ivec2 pixel = ivec2( gl_GlobalInvocationID.xy );
+    ivec2 size = imageSize( output_image );
+    if( pixel.x >= size.x || pixel.y >= size.y )
+    {
+        return;
+    }
+
+    vec2 ndc = ( vec2( pixel ) + 0.5 ) / vec2( size ) * 2.0 - 1.0;
+    vec3 origin = unproject( ndc, 0.0 );
+    vec3 direction = normalize( unproject( ndc, 1.0 ) - origin );
+
+    // Intersect the ray with the unit cube the volume occupies
+    vec3 t0 = -origin / direction;
+    vec3 t1 = ( 1.0 - origin ) / direction;
+    vec3 t_min = min( t0, t1 );
+    vec3 t_max = max( t0, t1 );
+    float t_enter = max( max( t_min.x, t_min.y ), max( t_min.z, 0.0 ) );
+    float t_exit = min( min( t_max.x, t_max.y ), t_max.z );
+
+    // Keep samples away from the borders, the samplers repeat
+    vec3 half_voxel = 0.5 / vec3( textureSize( volume, 0 ) );
+    float lut_size = float( textureSize( transfer_function, 0 ).x );
+
+    vec4 color = vec4( 0.0 );
+    float t = t_enter + 0.5 * pc.step_size;
+    for( uint i = 0u; i < pc.max_steps && t < t_exit && color.a < 0.99; i++ )
+    {
+        vec3 position = clamp( origin + t * direction, half_voxel, 1.0 - half_voxel );
+        float density = clamp( texture( volume, position ).r, 0.0, 1.0 );
+        vec4 sample_color = texture( transfer_function, vec2( ( density * ( lut_size - 1.0 ) + 0.5 ) / lut_size, 0.5 ) );
+
+        // The transfer function alpha is an extinction per unit length, so the result doesn't depend on the step size
+        float alpha = 1.0 - exp( -sample_color.a * pc.density_scale * pc.step_size );
+        color.rgb += ( 1.0 - color.a ) * alpha * sample_color.rgb;
+        color.a += ( 1.0 - color.a ) * alpha;
+        t += pc.step_size;
+    }
+
+    imageStore( output_image, pixel, color );
+}
+"#;
+
+/// Number of entries of the default transfer function.
+const TRANSFER_FUNCTION_SIZE: usize = 256;
+
+/// Raymarches a 3D texture into a 2D image, mapping densities to color and opacity with a transfer function.
+///
+/// The volume occupies the unit cube `[0, 1]^3`, its first channel is read as density in the range [0, 1].
+/// The output has premultiplied alpha, so it can be composited over other content. It is an `R8G8B8A8_UNORM` image,
+/// or a float RGBA one on devices without storage support for it.
+pub struct VolumeRenderer {
+    pipeline: ComputePipeline,
+    _layout: DescriptorSetLayout,
+    image: ImageResource,
+    transfer_function: ImageResource,
+    step_size: f32,
+    density_scale: f32,
+    max_steps: u32,
+}
+
+impl VolumeRenderer {
+
+    /// The target image is created with `extent` and `flags`, e.g. [`ImageFlags::MATCH_SWAPCHAIN_EXTENT`].
+    /// Starts out with a grayscale ramp as transfer function.
+    pub fn new(ctx: &mut CenContext, extent: vk::Extent2D, flags: ImageFlags) -> Self {
+        let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC;
+        let format = ctx.gfx.device.select_format(&STORAGE_COLOR_FORMATS, usage)
+            .expect("No RGBA storage image format is supported");
+        let image = ctx.create_image(
+            ImageConfig {
+                extent: vk::Extent3D { width: extent.width, height: extent.height, depth: 1 },
+                format,
+                image_usage_flags: usage,
+                ..Default::default()
+            },
+            flags
+        );
+
+        let layout_bindings = [
+            (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (2, vk::DescriptorType::STORAGE_IMAGE),
+        ].map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        });
+        let layout = DescriptorSetLayout::new_push_descriptor(&ctx.gfx.device, &layout_bindings);
+
+        let macros = HashMap::from([("OUTPUT_FORMAT".to_string(), glsl_image_format(format).unwrap().to_string())]);
+        let code = compile_shader_code(RAYMARCH_SHADER, "cen/volume_raymarch.comp", shaderc::ShaderKind::Compute, &macros)
+            .expect("Failed to compile volume raymarching shader");
+        let pipeline = ComputePipeline::from_spirv(
+            &ctx.gfx.device,
+            code,
+            &[layout.clone()],
+            &[vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .size(76)]
+        );
+
+        let ramp = (0..TRANSFER_FUNCTION_SIZE)
+            .map(|i| {
+                let v = (i * 255 / (TRANSFER_FUNCTION_SIZE - 1)) as u8;
+                [v, v, v, v]
+            })
+            .collect::<Vec<_>>();
+        let transfer_function = create_transfer_function(ctx, &ramp);
+
+        Self {
+            pipeline,
+            _layout: layout,
+            image,
+            transfer_function,
+            step_size: 1.0 / 256.0,
+            density_scale: 32.0,
+            max_steps: 1024,
+        }
+    }
+
+    /// Replace the transfer function, a table of RGBA colors indexed by density from 0 to 1.
+    /// Alpha is the opacity per unit length, scaled by the density scale.
+    pub fn set_transfer_function(&mut self, ctx: &mut CenContext, colors: &[[u8; 4]]) {
+        assert!(!colors.is_empty(), "A transfer function needs at least one entry");
+        self.transfer_function = create_transfer_function(ctx, colors);
+    }
+
+    /// Distance between samples along a ray, in volume units. Smaller steps are more accurate and slower.
+    pub fn set_step_size(&mut self, step_size: f32) {
+        assert!(step_size > 0.0, "The step size must be positive");
+        self.step_size = step_size;
+    }
+
+    pub fn step_size(&self) -> f32 {
+        self.step_size
+    }
+
+    /// Multiplier of the transfer function opacity.
+    pub fn set_density_scale(&mut self, density_scale: f32) {
+        self.density_scale = density_scale;
+    }
+
+    pub fn density_scale(&self) -> f32 {
+        self.density_scale
+    }
+
+    /// Upper bound on the samples per ray.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    /// The rendered image, left in `ImageLayout::GENERAL`.
+    pub fn image(&self) -> &ImageResource {
+        &self.image
+    }
+
+    /// Render a 3D image with `SAMPLED` usage that is in `SHADER_READ_ONLY_OPTIMAL`.
+    /// The column-major `inverse_view_projection` maps clip space to volume space, depth is expected in the range [0, 1].
+    pub fn render(&self, ctx: &mut CenContext, volume: &ImageResource, inverse_view_projection: [[f32; 4]; 4]) {
+        let volume = ctx.images.get(volume);
+        let transfer_function = ctx.images.get(&self.transfer_function);
+        let image = ctx.images.get(&self.image);
+        assert_eq!(volume.config().image_type, vk::ImageType::TYPE_3D, "Volume rendering requires a 3D image");
+
+        ctx.command_buffer.image_barrier(
+            image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::MEMORY_READ,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+
+        let mut push_constants = inverse_view_projection.iter().flatten().flat_map(|v| v.to_ne_bytes()).collect::<Vec<u8>>();
+        push_constants.extend_from_slice(&self.step_size.to_ne_bytes());
+        push_constants.extend_from_slice(&self.density_scale.to_ne_bytes());
+        push_constants.extend_from_slice(&self.max_steps.to_ne_bytes());
+
+        let volume_binding = [volume.binding(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let transfer_function_binding = [transfer_function.binding(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let image_binding = [image.binding(vk::ImageLayout::GENERAL)];
+
+        ctx.command_buffer.bind_pipeline(&self.pipeline);
+        ctx.command_buffer.push_descriptor_set(&self.pipeline, 0, &[
+            vk::WriteDescriptorSet::default()
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&volume_binding),
+            vk::WriteDescriptorSet::default()
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&transfer_function_binding),
+            vk::WriteDescriptorSet::default()
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&image_binding),
+        ]);
+        ctx.command_buffer.push_constants(&self.pipeline, vk::ShaderStageFlags::COMPUTE, 0, &push_constants);
+        ctx.command_buffer.dispatch(image.width().div_ceil(8), image.height().div_ceil(8), 1);
+
+        ctx.command_buffer.image_barrier(
+            image,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::GENERAL,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::MEMORY_READ,
+        );
+    }
+}
+
+fn create_transfer_function(ctx: &mut CenContext, colors: &[[u8; 4]]) -> ImageResource {
+    let image = ctx.create_image(
+        ImageConfig {
+            extent: vk::Extent3D { width: colors.len() as u32, height: 1, depth: 1 },
+            format: vk::Format::R8G8B8A8_UNORM,
+            image_usage_flags: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            sampler: SamplerConfig::default().filter(vk::Filter::LINEAR),
+            ..Default::default()
+        },
+        ImageFlags::empty()
+    );
+    ctx.upload_image(&image, colors.as_flattened());
+    image
+}
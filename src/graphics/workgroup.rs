@@ -0,0 +1,237 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::time::Duration;
+use ash::vk;
+use log::warn;
+use crate::app::engine::CenContext;
+use crate::vulkan::{CommandBuffer, ComputePipeline, ComputePipelineConfig, DevicePlugin, FeatureChain, PipelineErr, PipelineStatistic, QueryPool};
+
+/// Enables `VK_KHR_pipeline_executable_properties`, so compute pipelines report driver statistics like register
+/// and shared memory usage in [`WorkgroupReport`]s. Add it with [`AppConfig::device_plugin`](crate::app::app::AppConfig::device_plugin).
+pub struct PipelineStatisticsPlugin;
+
+impl DevicePlugin for PipelineStatisticsPlugin {
+    fn extensions(&self) -> Vec<&'static CStr> {
+        vec![ash::khr::pipeline_executable_properties::NAME]
+    }
+
+    fn features(&self, chain: &mut FeatureChain) {
+        chain.push(vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR::default()
+            .pipeline_executable_info(true));
+    }
+}
+
+/// Invocations per workgroup that candidates are generated for.
+const CANDIDATE_INVOCATIONS: [u32; 4] = [32, 64, 128, 256];
+
+/// Dispatches per candidate in [`CenContext::tune_workgroup_size`], averaged after a warm-up dispatch.
+const TUNING_DISPATCHES: u32 = 8;
+
+/// Workgroup sizes of 32 to 256 invocations in powers of two, spread over the dimensions of `extent` that are
+/// larger than one and limited to `max_invocations`.
+pub fn workgroup_candidates(extent: [u32; 3], max_invocations: u32) -> Vec<[u32; 3]> {
+    let mut candidates = Vec::new();
+    for invocations in CANDIDATE_INVOCATIONS.into_iter().filter(|invocations| *invocations <= max_invocations) {
+        for x in powers_of_two(invocations) {
+            for y in powers_of_two(invocations / x) {
+                let size = [x, y, invocations / x / y];
+                if (0..3).all(|i| size[i] == 1 || extent[i] > 1) {
+                    candidates.push(size);
+                }
+            }
+        }
+    }
+    candidates
+}
+
+fn powers_of_two(max: u32) -> impl Iterator<Item = u32> {
+    (0..32).map(|shift| 1u32 << shift).take_while(move |value| *value <= max)
+}
+
+/// Invocations of a dispatch over `extent` that fall outside of it.
+pub fn idle_invocations(extent: [u32; 3], workgroup_size: [u32; 3]) -> u64 {
+    let dispatched: u64 = (0..3).map(|i| (extent[i].div_ceil(workgroup_size[i]) * workgroup_size[i]) as u64).product();
+    let covered: u64 = extent.iter().map(|size| *size as u64).product();
+    dispatched - covered
+}
+
+/// Workgroup size for `extent` without measuring: a multiple of the subgroup size of at least 64 invocations,
+/// with the fewest idle invocations, preferring wide rows for coalesced memory access.
+pub fn suggest_workgroup_size(extent: [u32; 3], subgroup_size: u32, max_invocations: u32) -> [u32; 3] {
+    let invocations = subgroup_size.max(64).min(max_invocations);
+    workgroup_candidates(extent, max_invocations).into_iter()
+        .filter(|size| size.iter().product::<u32>() == invocations)
+        .min_by_key(|size| (idle_invocations(extent, *size), std::cmp::Reverse(size[0])))
+        .unwrap_or([1, 1, 1])
+}
+
+/// Occupancy-relevant data of a compute pipeline dispatched over an extent, see [`CenContext::workgroup_report`].
+#[derive(Clone, Debug)]
+pub struct WorkgroupReport {
+    pub extent: [u32; 3],
+    /// Local size the shader declares, `None` when it is set through specialization constants.
+    pub workgroup_size: Option<[u32; 3]>,
+    pub group_count: Option<[u32; 3]>,
+    /// Invocations of the dispatch that fall outside of the extent.
+    pub idle_invocations: Option<u64>,
+    pub subgroup_size: u32,
+    pub max_invocations: u32,
+    /// Shared memory available to a workgroup in bytes.
+    pub max_shared_memory: u32,
+    pub suggested_size: [u32; 3],
+    /// Driver statistics, empty without the [`PipelineStatisticsPlugin`].
+    pub statistics: Vec<PipelineStatistic>,
+}
+
+impl fmt::Display for WorkgroupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Extent: {:?}", self.extent)?;
+        match (self.workgroup_size, self.group_count, self.idle_invocations) {
+            (Some(size), Some(count), Some(idle)) => {
+                writeln!(f, "Workgroup size: {:?} ({} invocations)", size, size.iter().product::<u32>())?;
+                writeln!(f, "Group count: {:?}, {} idle invocations", count, idle)?;
+            }
+            _ => writeln!(f, "Workgroup size: unknown")?,
+        }
+        writeln!(f, "Subgroup size: {}, max invocations: {}, shared memory: {} bytes", self.subgroup_size, self.max_invocations, self.max_shared_memory)?;
+        write!(f, "Suggested size: {:?}", self.suggested_size)?;
+        for statistic in &self.statistics {
+            write!(f, "\n{} {}: {}", statistic.executable, statistic.name, statistic.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measured GPU time of a workgroup size, see [`CenContext::tune_workgroup_size`].
+#[derive(Clone, Debug)]
+pub struct WorkgroupTiming {
+    pub workgroup_size: [u32; 3],
+    /// Average time of a dispatch.
+    pub duration: Duration,
+}
+
+impl CenContext<'_> {
+
+    /// Describe how a pipeline occupies the GPU when dispatched over `extent` invocations.
+    pub fn workgroup_report(&self, pipeline: &ComputePipeline, extent: [u32; 3]) -> WorkgroupReport {
+        let device = &self.gfx.device;
+        let limits = device.limits();
+        let subgroup_size = device.subgroup_size();
+        let workgroup_size = pipeline.workgroup_size();
+
+        WorkgroupReport {
+            extent,
+            workgroup_size,
+            group_count: workgroup_size.map(|_| pipeline.group_count(extent)),
+            idle_invocations: workgroup_size.map(|size| idle_invocations(extent, size)),
+            subgroup_size,
+            max_invocations: limits.max_compute_work_group_invocations,
+            max_shared_memory: limits.max_compute_shared_memory_size,
+            suggested_size: suggest_workgroup_size(extent, subgroup_size, limits.max_compute_work_group_invocations),
+            statistics: pipeline.statistics(),
+        }
+    }
+
+    /// Time a compute shader with different workgroup sizes, fastest first.
+    ///
+    /// The shader is compiled once per candidate with the size in the `CEN_LOCAL_SIZE_X`, `CEN_LOCAL_SIZE_Y` and
+    /// `CEN_LOCAL_SIZE_Z` macros, so it has to declare its local size with them:
+    ///
+    /// ```glsl
+    /// layout(local_size_x = CEN_LOCAL_SIZE_X, local_size_y = CEN_LOCAL_SIZE_Y, local_size_z = CEN_LOCAL_SIZE_Z) in;
+    /// ```
+    ///
+    /// `bind` binds the descriptor sets and push constants of each candidate pipeline. The dispatches run on an
+    /// auxiliary command buffer that is waited on, see [`run_immediate`](Self::run_immediate). Without `candidates`,
+    /// [`workgroup_candidates`] are timed. Empty when the device doesn't support timestamps.
+    pub fn tune_workgroup_size(
+        &mut self,
+        config: &ComputePipelineConfig,
+        extent: [u32; 3],
+        candidates: &[[u32; 3]],
+        mut bind: impl FnMut(&mut CommandBuffer, &ComputePipeline)
+    ) -> Result<Vec<WorkgroupTiming>, PipelineErr> {
+        let limits = self.gfx.device.limits();
+        if limits.timestamp_compute_and_graphics != vk::TRUE {
+            warn!("Timestamp queries are not supported, workgroup sizes can't be tuned");
+            return Ok(Vec::new());
+        }
+
+        let candidates = if candidates.is_empty() {
+            workgroup_candidates(extent, limits.max_compute_work_group_invocations)
+        } else {
+            candidates.to_vec()
+        };
+
+        let mut timings = Vec::with_capacity(candidates.len());
+        for workgroup_size in candidates {
+            let mut config = config.clone();
+            for (axis, size) in ["X", "Y", "Z"].iter().zip(workgroup_size) {
+                config.macros.insert(format!("CEN_LOCAL_SIZE_{}", axis), size.to_string());
+            }
+            let pipeline = ComputePipeline::new(&self.gfx.device, config)?;
+            let query_pool = QueryPool::new_timestamp(&self.gfx.device, 2);
+
+            self.gfx.run_immediate(|command_buffer| {
+                command_buffer.reset_query_pool(&query_pool, 0, 2);
+                command_buffer.bind_pipeline(&pipeline);
+                bind(command_buffer, &pipeline);
+
+                // Warm up caches, then measure back to back dispatches
+                command_buffer.dispatch_extent(&pipeline, extent);
+                for dispatch in 0..=TUNING_DISPATCHES {
+                    command_buffer.memory_barrier(
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                    );
+                    if dispatch == 0 {
+                        command_buffer.write_timestamp(&query_pool, vk::PipelineStageFlags::TOP_OF_PIPE, 0);
+                    } else {
+                        command_buffer.dispatch_extent(&pipeline, extent);
+                    }
+                }
+                command_buffer.write_timestamp(&query_pool, vk::PipelineStageFlags::BOTTOM_OF_PIPE, 1);
+            });
+
+            let Some(timestamps) = query_pool.results(0, 2) else {
+                warn!("Timestamps of workgroup size {:?} are not available", workgroup_size);
+                continue;
+            };
+            let nanoseconds = timestamps[1].saturating_sub(timestamps[0]) as f64 * limits.timestamp_period as f64;
+            timings.push(WorkgroupTiming {
+                workgroup_size,
+                duration: Duration::from_nanos((nanoseconds / TUNING_DISPATCHES as f64) as u64),
+            });
+        }
+
+        timings.sort_by_key(|timing| timing.duration);
+        Ok(timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_follow_extent() {
+        let candidates = workgroup_candidates([1920, 1080, 1], 1024);
+        assert!(candidates.contains(&[8, 8, 1]));
+        assert!(candidates.contains(&[256, 1, 1]));
+        assert!(candidates.iter().all(|size| size[2] == 1));
+
+        let linear = workgroup_candidates([100000, 1, 1], 128);
+        assert_eq!(linear, vec![[32, 1, 1], [64, 1, 1], [128, 1, 1]]);
+    }
+
+    #[test]
+    fn suggest_sizes() {
+        assert_eq!(idle_invocations([10, 10, 1], [8, 8, 1]), 16 * 16 - 100);
+        assert_eq!(suggest_workgroup_size([1024, 1024, 1], 32, 1024), [64, 1, 1]);
+        // Rows of 64 would leave most of every group idle
+        assert_eq!(suggest_workgroup_size([8, 4096, 1], 32, 1024), [8, 8, 1]);
+        assert_eq!(suggest_workgroup_size([1000, 1, 1], 64, 1024), [64, 1, 1]);
+    }
+}
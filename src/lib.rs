@@ -1,11 +1,33 @@
 extern crate shaderc;
 
+// Enter a `tracing` span until the end of the scope, with the `tracing` feature.
+macro_rules! trace_scope {
+    ($($span:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($span)*).entered();
+    };
+}
+
 pub mod vulkan;
 pub mod app;
 pub mod graphics;
+pub mod bench;
+pub mod prelude;
+#[cfg(feature = "glam")]
+pub mod math;
 
+// Dependencies that are part of the public API of cen.
+// Their major versions only change together with a cen release:
+// ash 0.38, bytemuck 1, egui 0.33, egui_dock 0.18, gpu-allocator 0.28, log 0.4, winit 0.30, glam 0.30 (with the `glam` feature)
+pub use bytemuck;
 pub use egui;
 pub use egui_dock;
+#[cfg(feature = "glam")]
+pub use glam;
 pub use gpu_allocator;
+pub use log;
 pub use ash;
 pub use winit;
+
+/// Compile a GLSL shader to SPIR-V at build time, see [`cen_macros::include_shader`].
+pub use cen_macros::include_shader;
@@ -0,0 +1,173 @@
+//! [glam] types laid out for shaders, enabled with the `glam` feature.
+//!
+//! glam's `Mat4`, `Vec4`, `Vec2` and friends implement [`Pod`], so they can be written to buffers with
+//! [`Buffer::write_slice`](crate::vulkan::Buffer::write_slice) or passed as push constants with
+//! [`bytemuck::bytes_of`]. `Vec3` and `Mat3` need padding in uniform and storage buffers, see
+//! [`std140_vec3`] and [`std140_mat3`].
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat3, Mat4, Vec3, Vec4};
+
+/// A `vec3` padded to the 16 byte alignment it has in std140 and std430 buffers.
+pub fn std140_vec3(v: Vec3) -> Vec4 {
+    v.extend(0.0)
+}
+
+/// A `mat3` in its std140 and std430 layout, three columns padded to 16 bytes.
+pub fn std140_mat3(m: Mat3) -> [Vec4; 3] {
+    [m.x_axis.extend(0.0), m.y_axis.extend(0.0), m.z_axis.extend(0.0)]
+}
+
+/// Perspective camera in a right-handed, y up world, looking along -z when yaw and pitch are zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Rotation around the y axis in radians, positive turns left.
+    pub yaw: f32,
+    /// Rotation up or down in radians.
+    pub pitch: f32,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: 60f32.to_radians(),
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+/// Just below straight up or down, where the view direction and the up vector would coincide.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.001;
+
+impl Camera {
+
+    /// A camera at `eye` that looks at `target`.
+    pub fn look_at(eye: Vec3, target: Vec3) -> Self {
+        let mut camera = Self { position: eye, ..Default::default() };
+        camera.look_at_target(target);
+        camera
+    }
+
+    /// Turn the camera towards `target`.
+    pub fn look_at_target(&mut self, target: Vec3) {
+        let direction = (target - self.position).normalize_or(Vec3::NEG_Z);
+        self.yaw = (-direction.x).atan2(-direction.z);
+        self.pitch = direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Turn by the given angles in radians, e.g. from mouse movement.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
+        Vec3::new(-yaw_sin * pitch_cos, pitch_sin, -yaw_cos * pitch_cos)
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(Vec3::Y).normalize()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.right().cross(self.forward())
+    }
+
+    /// World to view space.
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
+    }
+
+    /// View to Vulkan clip space, with y pointing down and depth from 0 at `near` to 1 at `far`.
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        let mut projection = Mat4::perspective_rh(self.fov_y, aspect_ratio, self.near, self.far);
+        projection.y_axis.y = -projection.y_axis.y;
+        projection
+    }
+
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection(aspect_ratio) * self.view()
+    }
+
+    /// Matrices of the camera for a uniform buffer or push constants.
+    pub fn uniform(&self, aspect_ratio: f32) -> CameraUniform {
+        let view = self.view();
+        let projection = self.projection(aspect_ratio);
+        CameraUniform {
+            view,
+            projection,
+            view_projection: projection * view,
+            position: self.position.extend(1.0),
+        }
+    }
+}
+
+/// Camera matrices with the same layout in std140, std430 and push constants:
+///
+/// ```glsl
+/// struct Camera {
+///     mat4 view;
+///     mat4 projection;
+///     mat4 view_projection;
+///     vec4 position;
+/// };
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    /// World position of the camera, `w` is 1.
+    pub position: Vec4,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_projects_to_vulkan_clip_space() {
+        let camera = Camera::look_at(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO);
+        assert!(camera.forward().abs_diff_eq(Vec3::NEG_Z, 1e-6));
+
+        let view_projection = camera.view_projection(1.0);
+        let project = |point: Vec3| view_projection.project_point3(point);
+
+        // Up in the world is up on screen, which is -y in Vulkan
+        assert!(project(Vec3::new(0.0, 1.0, 0.0)).y < 0.0);
+        assert!(project(Vec3::new(1.0, 0.0, 0.0)).x > 0.0);
+
+        let near = project(Vec3::new(0.0, 0.0, 5.0 - camera.near));
+        let far = project(Vec3::new(0.0, 0.0, 5.0 - camera.far));
+        assert!((near.z - 0.0).abs() < 1e-4);
+        assert!((far.z - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn look_at_matches_forward() {
+        let target = Vec3::new(3.0, -2.0, 1.0);
+        let camera = Camera::look_at(Vec3::new(-1.0, 4.0, 2.0), target);
+        let direction = (target - camera.position).normalize();
+        assert!(camera.forward().abs_diff_eq(direction, 1e-5));
+        assert!(camera.right().dot(Vec3::Y).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shader_layouts() {
+        assert_eq!(size_of::<CameraUniform>(), 3 * 64 + 16);
+        assert_eq!(bytemuck::bytes_of(&std140_mat3(Mat3::IDENTITY)).len(), 48);
+        assert_eq!(std140_vec3(Vec3::ONE), Vec4::new(1.0, 1.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,40 @@
+//! Commonly used types of cen.
+//!
+//! ```ignore
+//! use cen::prelude::*;
+//! ```
+
+pub use crate::app::app::{AppComponent, AppConfig, Cen, RedrawMode, WindowComponent};
+pub use crate::app::engine::CenContext;
+pub use crate::app::update::UpdateComponent;
+pub use crate::app::input::{InputComponent, InputState};
+pub use crate::app::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+pub use crate::app::splash::{LoadingProgress, Splash};
+pub use crate::app::components::{ComponentRegistry, ComponentToggle};
+pub use crate::app::logging::LogSubsystem;
+pub use crate::app::power::{ActiveHours, PowerMode};
+pub use crate::app::gui::{GuiComponent, GuiContext, TextureKey};
+pub use crate::app::{ImageFlags, ImageResource};
+pub use crate::graphics::renderer::{FrameFlags, RenderComponent};
+pub use crate::graphics::pipeline_store::{PipelineEntry, PipelineKey, PipelineNamespace, ShaderPaths};
+pub use crate::graphics::assets::Asset;
+pub use crate::graphics::surface_size::{SurfaceSizePolicy, SurfaceSizes};
+pub use crate::graphics::texture::TextureOptions;
+pub use crate::graphics::render_graph::{BufferAccess, ImageAccess, RenderGraph};
+pub use crate::vulkan::{
+    BlendMode, Buffer, ColorAttachment, CommandBuffer, ComputePipelineConfig, DepthAttachment, DescriptorSetLayout, Filter, Format, GraphicsPipelineConfig, Image,
+    ImageConfig, ImageTrait, ImageUsage, Layout, Pipeline, PipelineErr, Sampler, SamplerConfig, ShaderSource, SpecializationConstant
+};
+
+pub use std::time::Duration;
+
+pub use ash::vk;
+pub use bytemuck::{Pod, Zeroable};
+#[cfg(feature = "glam")]
+pub use glam::{Mat4, Vec2, Vec3, Vec4};
+#[cfg(feature = "glam")]
+pub use crate::math::{Camera, CameraUniform};
+pub use gpu_allocator::MemoryLocation;
+pub use log::LevelFilter;
+pub use winit::event::{MouseButton, WindowEvent};
+pub use winit::keyboard::KeyCode;
@@ -1,22 +1,122 @@
+use std::fmt;
 use std::sync::{Arc, Mutex};
-use gpu_allocator::vulkan::AllocatorCreateDesc;
-use log::trace;
+use ash::vk;
+use gpu_allocator::AllocatorReport;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocatorCreateDesc};
+use log::{debug, trace};
 use crate::vulkan::device::DeviceInner;
-use crate::vulkan::{Device, LOG_TARGET};
+use crate::vulkan::{Device, FrameEvent, LOG_TARGET, OBJECTS_LOG_TARGET};
+
+/// Usage of a memory heap of the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeapReport {
+    pub size: vk::DeviceSize,
+    /// Whether the heap is VRAM.
+    pub device_local: bool,
+    /// Bytes of the allocations placed in the heap.
+    pub allocated_bytes: u64,
+    pub allocation_count: usize,
+}
+
+/// Memory usage of an [`Allocator`], see [`Allocator::memory_report`].
+#[derive(Clone)]
+pub struct MemoryReport {
+    /// One per memory heap of the device.
+    pub heaps: Vec<HeapReport>,
+    /// The memory blocks and the allocations placed in them.
+    pub allocator: AllocatorReport,
+}
+
+impl MemoryReport {
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocator.total_allocated_bytes
+    }
+
+    /// Bytes of the memory blocks, including the free space within them.
+    pub fn capacity_bytes(&self) -> u64 {
+        self.allocator.total_capacity_bytes
+    }
+
+    pub fn allocation_count(&self) -> usize {
+        self.allocator.allocations.len()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {} allocations, {} in {} blocks",
+            format_bytes(self.allocated_bytes()), self.allocation_count(),
+            format_bytes(self.capacity_bytes()), self.allocator.blocks.len())?;
+        for (index, heap) in self.heaps.iter().enumerate() {
+            write!(f, "\nHeap {}{}: {} in {} allocations of {}",
+                index, if heap.device_local { " (device local)" } else { "" },
+                format_bytes(heap.allocated_bytes), heap.allocation_count, format_bytes(heap.size))?;
+        }
+        Ok(())
+    }
+}
+
+/// Size in the largest binary unit that keeps it above one, e.g. `1.50 MiB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+/// Heap of the first memory type with the given properties, which is the one the allocator picks for them.
+fn heap_index(memory_types: &[vk::MemoryType], properties: vk::MemoryPropertyFlags) -> Option<usize> {
+    memory_types.iter()
+        .find(|memory_type| memory_type.property_flags == properties)
+        .map(|memory_type| memory_type.heap_index as usize)
+}
 
 pub struct AllocatorInner {
     // IMPORTANT: Ordering matters a lot here. We want to drop the allocator before the device
     pub allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
-    #[allow(dead_code)]
+    memory_types: Vec<vk::MemoryType>,
+    heaps: Vec<HeapReport>,
     pub device_dep: Arc<DeviceInner>,
 }
 
+impl AllocatorInner {
+    pub(crate) fn allocate(&mut self, desc: &AllocationCreateDesc) -> gpu_allocator::Result<Allocation> {
+        let allocation = self.allocator.lock().unwrap().allocate(desc)?;
+        if let Some(heap) = heap_index(&self.memory_types, allocation.memory_properties()) {
+            self.heaps[heap].allocated_bytes += allocation.size();
+            self.heaps[heap].allocation_count += 1;
+        }
+        Ok(allocation)
+    }
+
+    pub(crate) fn free(&mut self, allocation: Allocation) -> gpu_allocator::Result<()> {
+        if let Some(heap) = heap_index(&self.memory_types, allocation.memory_properties()) {
+            self.heaps[heap].allocated_bytes -= allocation.size();
+            self.heaps[heap].allocation_count -= 1;
+        }
+        self.allocator.lock().unwrap().free(allocation)
+    }
+}
+
 impl Drop for AllocatorInner {
     fn drop(&mut self) {
         let allocator = self.allocator.lock().unwrap();
         let report = allocator.generate_report();
-        println!("{:?}", report);
-        trace!(target: LOG_TARGET, "Destroyed allocator");
+        debug!(target: LOG_TARGET, "Allocator memory on destruction: {:?}", report);
+        if !report.allocations.is_empty() {
+            self.device_dep.event_log.record(FrameEvent::Warning {
+                message: format!("Allocator destroyed with {} allocations of {} alive", report.allocations.len(), format_bytes(report.total_allocated_bytes)),
+            });
+        }
+        trace!(target: OBJECTS_LOG_TARGET, "Destroyed allocator");
     }
 }
 
@@ -32,12 +132,23 @@ impl Clone for Allocator {
 
 impl Allocator {
     pub fn new(device: &Device, desc: &AllocatorCreateDesc) -> Self {
+        let properties = unsafe { desc.instance.get_physical_device_memory_properties(desc.physical_device) };
+        let heaps = properties.memory_heaps_as_slice().iter()
+            .map(|heap| HeapReport {
+                size: heap.size,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                ..Default::default()
+            })
+            .collect();
+
         let allocator = Arc::new( Mutex::new(AllocatorInner {
             device_dep: device.inner.clone(),
-            allocator: Arc::new(Mutex::new(gpu_allocator::vulkan::Allocator::new(desc).expect("Failed to create allocator")))
+            allocator: Arc::new(Mutex::new(gpu_allocator::vulkan::Allocator::new(desc).expect("Failed to create allocator"))),
+            memory_types: properties.memory_types_as_slice().to_vec(),
+            heaps,
         } ) );
 
-        trace!(target: LOG_TARGET, "Created allocator");
+        trace!(target: OBJECTS_LOG_TARGET, "Created allocator");
 
         Self {
             inner: allocator,
@@ -47,4 +158,36 @@ impl Allocator {
     pub fn handle(&self) -> Arc<Mutex<gpu_allocator::vulkan::Allocator>> {
         self.inner.lock().unwrap().allocator.clone()
     }
-}
\ No newline at end of file
+
+    /// Current memory usage per heap, along with the memory blocks of the allocator. Cheap enough to query every
+    /// frame, e.g. to track VRAM growth while the app runs. Only counts the buffers and images cen allocated.
+    pub fn memory_report(&self) -> MemoryReport {
+        let inner = self.inner.lock().unwrap();
+        let allocator = inner.allocator.lock().unwrap().generate_report();
+        MemoryReport {
+            heaps: inner.heaps.clone(),
+            allocator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heaps_of_memory_properties() {
+        let memory_types = [
+            vk::MemoryType { property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL, heap_index: 0 },
+            vk::MemoryType { property_flags: vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, heap_index: 1 },
+            vk::MemoryType { property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE, heap_index: 0 },
+        ];
+        assert_eq!(heap_index(&memory_types, vk::MemoryPropertyFlags::DEVICE_LOCAL), Some(0));
+        assert_eq!(heap_index(&memory_types, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT), Some(1));
+        assert_eq!(heap_index(&memory_types, vk::MemoryPropertyFlags::HOST_CACHED), None);
+
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.50 MiB");
+        assert_eq!(format_bytes(8 << 30), "8.00 GiB");
+    }
+}
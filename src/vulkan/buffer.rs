@@ -1,12 +1,17 @@
 use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex, MutexGuard};
 use ash::vk;
+use bytemuck::Pod;
 use gpu_allocator::MemoryLocation;
 use gpu_allocator::vulkan::{Allocation, AllocationScheme};
 use log::{trace};
-use crate::vulkan::{Allocator, Device, LOG_TARGET};
+use crate::vulkan::{Allocator, CommandBuffer, Device, FrameEvent, QueueFamilies, OBJECTS_LOG_TARGET};
 use crate::vulkan::allocator::AllocatorInner;
 use crate::vulkan::device::DeviceInner;
+use crate::vulkan::live_objects::object_key;
 use crate::vulkan::memory::GpuResource;
 
 pub struct BufferInner {
@@ -14,6 +19,10 @@ pub struct BufferInner {
     pub allocator_dep: Arc<Mutex<AllocatorInner>>,
     pub(crate) buffer: vk::Buffer,
     pub size: vk::DeviceSize,
+    pub usage: vk::BufferUsageFlags,
+    pub location: MemoryLocation,
+    pub(crate) name: String,
+    queue_families: QueueFamilies,
     pub allocation: Mutex<Option<Allocation>>,
 }
 
@@ -23,16 +32,38 @@ pub struct Buffer {
 }
 
 impl Drop for BufferInner {
+    fn drop(&mut self) {
+        // Frames in flight may still use the buffer without tracking it
+        let destruction = BufferDestruction {
+            device_dep: self.device_dep.clone(),
+            allocator_dep: self.allocator_dep.clone(),
+            buffer: self.buffer,
+            allocation: self.allocation.lock().unwrap().take(),
+        };
+        self.device_dep.deletion_queue.defer(Arc::new(destruction));
+    }
+}
+
+/// Handles of a dropped buffer, destroyed once the frames in flight executed.
+struct BufferDestruction {
+    device_dep: Arc<DeviceInner>,
+    allocator_dep: Arc<Mutex<AllocatorInner>>,
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+}
+
+impl Drop for BufferDestruction {
     fn drop(&mut self) {
         unsafe {
             let buffer_addr = format!("{:?}", self.buffer);
-            if let Some(allocation) = self.allocation.lock().unwrap().take() {
+            if let Some(allocation) = self.allocation.take() {
                 let memory_addr = format!("{:?}, {:?}", allocation.memory(), allocation.chunk_id());
-                self.allocator_dep.lock().unwrap().allocator.lock().unwrap().free(allocation).unwrap();
-                trace!(target: LOG_TARGET, "Destroyed buffer memory: [{}]", memory_addr)
+                self.allocator_dep.lock().unwrap().free(allocation).unwrap();
+                trace!(target: OBJECTS_LOG_TARGET, "Destroyed buffer memory: [{}]", memory_addr)
             }
             self.device_dep.device.destroy_buffer(self.buffer, None);
-            trace!(target: LOG_TARGET, "Destroyed buffer: [{}]", buffer_addr)
+            self.device_dep.live_objects.destroyed(self.buffer);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed buffer: [{}]", buffer_addr)
         }
     }
 }
@@ -46,11 +77,35 @@ impl GpuResource for Buffer {
 
 impl Buffer {
     pub fn new(device: &Device, allocator: &mut Allocator, location: MemoryLocation, size: vk::DeviceSize, buffer_usage_flags: vk::BufferUsageFlags) -> Buffer {
+        Self::new_named(device, allocator, location, size, buffer_usage_flags, "Buffer")
+    }
 
-        // Image
+    /// Create a buffer whose allocation is named `name` in the reports of the allocator, see
+    /// [`AppConfig::allocator_debug_settings`](crate::app::app::AppConfig::allocator_debug_settings).
+    /// Leak reports show it as well.
+    pub fn new_named(device: &Device, allocator: &mut Allocator, location: MemoryLocation, size: vk::DeviceSize, buffer_usage_flags: vk::BufferUsageFlags, name: &str) -> Buffer {
+        Self::create(device, allocator, location, size, buffer_usage_flags, QueueFamilies::default(), name)
+    }
+
+    /// Create a named buffer the queue families use without ownership transfers, e.g. with
+    /// `QueueFamilies::all(&device)`. Stays exclusive when only one family is given.
+    pub fn new_concurrent(device: &Device, allocator: &mut Allocator, location: MemoryLocation, size: vk::DeviceSize, buffer_usage_flags: vk::BufferUsageFlags, queue_families: QueueFamilies, name: &str) -> Buffer {
+        Self::create(device, allocator, location, size, buffer_usage_flags, queue_families, name)
+    }
+
+    fn create(
+        device: &Device,
+        allocator: &mut Allocator,
+        location: MemoryLocation,
+        size: vk::DeviceSize,
+        buffer_usage_flags: vk::BufferUsageFlags,
+        queue_families: QueueFamilies,
+        name: &str
+    ) -> Buffer {
         let create_info = vk::BufferCreateInfo::default()
             .usage(buffer_usage_flags)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .sharing_mode(queue_families.sharing_mode())
+            .queue_family_indices(queue_families.concurrent_indices())
             .size(size);
 
         let buffer = unsafe {
@@ -58,18 +113,27 @@ impl Buffer {
                 .expect("Failed to create buffer")
         };
 
-        trace!(target: LOG_TARGET, "Created buffer: [{:?}]", buffer);
+        trace!(target: OBJECTS_LOG_TARGET, "Created buffer: [{:?}]", buffer);
+        device.inner.live_objects.created(buffer);
+        device.inner.live_objects.named(object_key(buffer), name);
 
         // Allocate memory
         let requirements = unsafe { device.handle().get_buffer_memory_requirements(buffer) };
-        let allocation = allocator.handle().lock().unwrap()
+        let allocation = allocator.inner.lock().unwrap()
             .allocate(&gpu_allocator::vulkan::AllocationCreateDesc {
-                name: "Buffer",
+                name,
                 requirements,
                 location,
                 linear: true,
                 allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-            }).unwrap();
+            }).unwrap_or_else(|e| {
+                device.event_log().record(FrameEvent::AllocationFailed {
+                    name: name.to_string(),
+                    size: requirements.size,
+                    error: e.to_string(),
+                });
+                panic!("Failed to allocate buffer memory: {}", e)
+            });
 
         unsafe {
             device.handle().bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
@@ -80,6 +144,10 @@ impl Buffer {
             inner: Arc::new(BufferInner {
                 buffer,
                 size,
+                usage: buffer_usage_flags,
+                location,
+                name: name.to_string(),
+                queue_families,
                 allocation: Mutex::new(Some(allocation)),
                 device_dep: device.inner.clone(),
                 allocator_dep: allocator.inner.clone(),
@@ -87,6 +155,48 @@ impl Buffer {
         }
     }
 
+    /// Allocate a replacement of `new_size` bytes with the same usage, location, sharing and name, instead of
+    /// recreating the buffer by hand. With `preserve`, the start of the contents is copied over on the GPU by
+    /// `command_buffer`, after the commands recorded before it, which needs `TRANSFER_SRC` usage. The replacement gets
+    /// `TRANSFER_DST` for it.
+    ///
+    /// The old buffer is handed to [`Device::defer_drop`], so it stays alive until the frames in flight executed
+    /// while the caller drops its handle. Components resize with `Buffer::resized`, which records into the frame.
+    pub fn resized_with(&self, device: &Device, allocator: &mut Allocator, command_buffer: &mut CommandBuffer, new_size: vk::DeviceSize, preserve: bool) -> Buffer {
+        let mut usage = self.inner.usage;
+        if preserve {
+            assert!(usage.contains(vk::BufferUsageFlags::TRANSFER_SRC), "Preserving the contents of a buffer needs TRANSFER_SRC usage");
+            usage |= vk::BufferUsageFlags::TRANSFER_DST;
+        }
+        let buffer = Buffer::create(device, allocator, self.inner.location, new_size, usage, self.inner.queue_families, &self.inner.name);
+
+        let size = self.inner.size.min(new_size);
+        if preserve && size > 0 {
+            command_buffer.buffer_barrier(
+                self,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::MEMORY_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+                0,
+                size,
+            );
+            command_buffer.copy_buffer(self, &buffer, &[vk::BufferCopy { src_offset: 0, dst_offset: 0, size }]);
+            command_buffer.buffer_barrier(
+                &buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                0,
+                size,
+            );
+        }
+        device.defer_drop(self);
+
+        buffer
+    }
+
     pub fn mapped(&self) -> Result<MappedBufferGuard<'_>, BufferError> {
 
         let allocation_guard = self.inner.allocation.lock().unwrap();
@@ -104,6 +214,44 @@ impl Buffer {
         Err(BufferError::NotAllocated)
     }
 
+    /// Map the buffer as a slice of `T`. The buffer size must be a multiple of the size of `T`.
+    pub fn mapped_as<T: Pod>(&self) -> Result<TypedMappedBufferGuard<'_, T>, BufferError> {
+        let guard = self.mapped()?;
+        let size = self.inner.size as usize;
+        let bytes = &guard.as_slice()[..size];
+        check_element_size::<T>(size)?;
+        if bytes.as_ptr() as usize % std::mem::align_of::<T>() != 0 {
+            return Err(BufferError::Misaligned { alignment: std::mem::align_of::<T>() });
+        }
+
+        Ok(TypedMappedBufferGuard {
+            guard,
+            size,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Copy `data` into the mapped buffer, starting `offset` bytes in.
+    pub fn write_slice<T: Pod>(&self, offset: vk::DeviceSize, data: &[T]) -> Result<(), BufferError> {
+        let bytes = bytemuck::cast_slice::<T, u8>(data);
+        let end = offset.checked_add(bytes.len() as vk::DeviceSize).filter(|end| *end <= self.inner.size);
+        let Some(end) = end else {
+            return Err(BufferError::OutOfBounds { offset, len: bytes.len() as vk::DeviceSize, size: self.inner.size });
+        };
+
+        self.mapped()?.as_mut_slice()[offset as usize..end as usize].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Copy the contents of the mapped buffer into a vector of `T`.
+    /// The buffer size must be a multiple of the size of `T`.
+    pub fn read_to_vec<T: Pod>(&self) -> Result<Vec<T>, BufferError> {
+        let size = self.inner.size as usize;
+        check_element_size::<T>(size)?;
+        let guard = self.mapped()?;
+        Ok(bytemuck::pod_collect_to_vec(&guard.as_slice()[..size]))
+    }
+
     pub fn binding(&self) -> vk::DescriptorBufferInfo {
        vk::DescriptorBufferInfo::default()
            .buffer(self.inner.buffer)
@@ -118,6 +266,20 @@ impl Buffer {
     pub fn size(&self) -> vk::DeviceSize {
         self.inner.size
     }
+
+    pub fn usage(&self) -> vk::BufferUsageFlags {
+        self.inner.usage
+    }
+
+    /// Name the buffer in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.buffer, name);
+    }
+
+    /// Whether other handles to the buffer exist, e.g. held by command buffers that use it.
+    pub(crate) fn in_use(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
 }
 
 pub struct MappedBufferGuard<'a> {
@@ -134,8 +296,117 @@ impl<'a> MappedBufferGuard<'a> {
     }
 }
 
+/// Mapped memory of a buffer as a slice of `T`, see [`Buffer::mapped_as`].
+pub struct TypedMappedBufferGuard<'a, T: Pod> {
+    guard: MappedBufferGuard<'a>,
+    size: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> Deref for TypedMappedBufferGuard<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        bytemuck::cast_slice(&self.guard.as_slice()[..self.size])
+    }
+}
+
+impl<T: Pod> DerefMut for TypedMappedBufferGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        let size = self.size;
+        bytemuck::cast_slice_mut(&mut self.guard.as_mut_slice()[..size])
+    }
+}
+
+/// Check that an indirect command of type `T` can be read from a buffer with `usage` and `size` at `offset`.
+pub(crate) fn check_indirect_command<T>(usage: vk::BufferUsageFlags, size: vk::DeviceSize, offset: vk::DeviceSize) -> Result<(), BufferError> {
+    if !usage.contains(vk::BufferUsageFlags::INDIRECT_BUFFER) {
+        return Err(BufferError::MissingUsage(vk::BufferUsageFlags::INDIRECT_BUFFER));
+    }
+    if offset % 4 != 0 {
+        return Err(BufferError::MisalignedOffset { offset, alignment: 4 });
+    }
+    let len = std::mem::size_of::<T>() as vk::DeviceSize;
+    if offset.checked_add(len).is_none_or(|end| end > size) {
+        return Err(BufferError::OutOfBounds { offset, len, size });
+    }
+    Ok(())
+}
+
+fn check_element_size<T>(size: usize) -> Result<(), BufferError> {
+    let element_size = std::mem::size_of::<T>();
+    if element_size == 0 || size % element_size != 0 {
+        return Err(BufferError::SizeMismatch { size: size as vk::DeviceSize, element_size });
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum BufferError {
     NotMapped,
     NotAllocated,
+    /// The mapped memory isn't aligned for the element type.
+    Misaligned { alignment: usize },
+    /// The buffer size isn't a multiple of the element size.
+    SizeMismatch { size: vk::DeviceSize, element_size: usize },
+    /// A write of `len` bytes at `offset` doesn't fit in the buffer.
+    OutOfBounds { offset: vk::DeviceSize, len: vk::DeviceSize, size: vk::DeviceSize },
+    /// The buffer wasn't created with the usage a command requires.
+    MissingUsage(vk::BufferUsageFlags),
+    /// A command reads the buffer at an offset that isn't a multiple of `alignment`.
+    MisalignedOffset { offset: vk::DeviceSize, alignment: vk::DeviceSize },
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BufferError::NotMapped => write!(f, "Buffer memory is not host visible"),
+            BufferError::NotAllocated => write!(f, "Buffer memory is not allocated"),
+            BufferError::Misaligned { alignment } => write!(f, "Mapped buffer memory is not aligned to {} bytes", alignment),
+            BufferError::SizeMismatch { size, element_size } => {
+                write!(f, "Buffer size of {} bytes is not a multiple of the element size of {} bytes", size, element_size)
+            }
+            BufferError::OutOfBounds { offset, len, size } => {
+                write!(f, "Write of {} bytes at offset {} exceeds the buffer size of {} bytes", len, offset, size)
+            }
+            BufferError::MissingUsage(usage) => write!(f, "Buffer was not created with the {:?} usage", usage),
+            BufferError::MisalignedOffset { offset, alignment } => {
+                write!(f, "Buffer offset {} is not a multiple of {} bytes", offset, alignment)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_size_must_divide_buffer_size() {
+        assert!(check_element_size::<f32>(16).is_ok());
+        assert!(matches!(check_element_size::<[f32; 3]>(16), Err(BufferError::SizeMismatch { size: 16, element_size: 12 })));
+        assert!(check_element_size::<()>(16).is_err());
+    }
+
+    #[test]
+    fn indirect_commands_must_fit() {
+        let usage = vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER;
+        assert!(check_indirect_command::<vk::DispatchIndirectCommand>(usage, 24, 12).is_ok());
+        assert!(matches!(
+            check_indirect_command::<vk::DispatchIndirectCommand>(vk::BufferUsageFlags::STORAGE_BUFFER, 24, 0),
+            Err(BufferError::MissingUsage(vk::BufferUsageFlags::INDIRECT_BUFFER))
+        ));
+        assert!(matches!(
+            check_indirect_command::<vk::DispatchIndirectCommand>(usage, 24, 2),
+            Err(BufferError::MisalignedOffset { offset: 2, alignment: 4 })
+        ));
+        assert!(matches!(
+            check_indirect_command::<vk::DispatchIndirectCommand>(usage, 24, 16),
+            Err(BufferError::OutOfBounds { offset: 16, len: 12, size: 24 })
+        ));
+        assert!(matches!(
+            check_indirect_command::<vk::DispatchIndirectCommand>(usage, 24, u64::MAX - 3),
+            Err(BufferError::OutOfBounds { .. })
+        ));
+    }
 }
@@ -1,8 +1,11 @@
 use std::any::Any;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use ash::vk;
-use ash::vk::{BufferImageCopy, DeviceSize, FenceCreateFlags, ImageAspectFlags, ImageCopy, ImageLayout, ImageMemoryBarrier, WriteDescriptorSet};
-use crate::vulkan::{Buffer, CommandPool, Device, Framebuffer, ImageTrait, Pipeline, RenderPass};
+use ash::vk::{BufferImageCopy, DeviceSize, FenceCreateFlags, ImageCopy, ImageLayout, ImageMemoryBarrier, WriteDescriptorSet};
+use crate::vulkan::{Buffer, ColorAttachment, CommandPool, ComputePipeline, DepthAttachment, DescriptorSet, DescriptorWriter, Device, Framebuffer, ImageTrait, Pipeline, QueryPool, RenderPass, Workaround};
+use crate::vulkan::buffer::check_indirect_command;
 use crate::vulkan::device::DeviceInner;
 use crate::vulkan::memory::GpuResource;
 
@@ -21,9 +24,11 @@ fn layout_stage_access(layout: vk::ImageLayout) -> (vk::PipelineStageFlags, vk::
             (S::ALL_COMMANDS,   A::SHADER_READ),
         vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL =>
             (S::COLOR_ATTACHMENT_OUTPUT, A::COLOR_ATTACHMENT_READ | A::COLOR_ATTACHMENT_WRITE),
-        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL =>
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL =>
             (S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS,
              A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL =>
+            (S::ALL_COMMANDS,   A::DEPTH_STENCIL_ATTACHMENT_READ | A::SHADER_READ),
         vk::ImageLayout::PRESENT_SRC_KHR =>
             (S::BOTTOM_OF_PIPE, A::empty()),
         _ =>
@@ -36,6 +41,10 @@ pub struct CommandBufferInner {
     command_buffer: vk::CommandBuffer,
     in_flight_fence: vk::Fence,
     resource_handles: Mutex<Vec<Arc<dyn Any>>>,
+    // Swapchain epoch of the frame being recorded, 0 when not recording a frame
+    swapchain_epoch: AtomicU64,
+    // Whether beginning rendering sets the viewport and scissor to the render area
+    default_viewport: AtomicBool,
 }
 
 pub struct CommandBuffer {
@@ -47,6 +56,8 @@ impl Drop for CommandBufferInner {
         unsafe {
             self.device_dep.device.destroy_fence(self.in_flight_fence, None);
         }
+        self.device_dep.counters.fence_completed(self.in_flight_fence);
+        self.device_dep.counters.fences_alive.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -76,12 +87,17 @@ impl CommandBuffer {
                 .expect("Failed to create fence")
         };
 
+        device.inner.counters.command_buffers_created.fetch_add(1, Ordering::Relaxed);
+        device.inner.counters.fences_alive.fetch_add(1, Ordering::Relaxed);
+
         CommandBuffer {
             inner: Arc::new(CommandBufferInner {
                 device_dep: device.inner.clone(),
                 command_buffer,
                 in_flight_fence: fence,
                 resource_handles: Mutex::new(Vec::new()),
+                swapchain_epoch: AtomicU64::new(0),
+                default_viewport: AtomicBool::new(true),
             }),
         }
     }
@@ -91,6 +107,60 @@ impl CommandBuffer {
         lock.push(resource.reference());
     }
 
+    /// Track an image, checking that it isn't left over from an earlier swapchain.
+    fn track_image(&mut self, image: &dyn ImageTrait) {
+        debug_assert!(
+            !self.is_stale(image),
+            "Recorded an image of swapchain epoch {:?} into a frame of epoch {}. Images sized to the swapchain are recreated on resize, don't keep them across frames.",
+            image.swapchain_epoch(),
+            self.inner.swapchain_epoch.load(Ordering::Relaxed)
+        );
+        self.track(image);
+    }
+
+    /// Set the epoch of the swapchain the recorded frame renders to, see [`is_stale`](Self::is_stale).
+    pub(crate) fn set_swapchain_epoch(&self, epoch: u64) {
+        self.inner.swapchain_epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    /// Whether beginning rendering or a render pass sets the viewport and scissor to cover the render area, so
+    /// graphics pipelines draw without setting them. Enabled by default, disable it when the viewport is set before
+    /// beginning rendering. Setting them after beginning overrides the defaults either way.
+    pub fn set_default_viewport(&self, enabled: bool) {
+        self.inner.default_viewport.store(enabled, Ordering::Relaxed);
+    }
+
+    fn set_default_viewport_to(&self, render_area: vk::Rect2D) {
+        if !self.inner.default_viewport.load(Ordering::Relaxed) {
+            return;
+        }
+        self.set_viewport(vk::Viewport::default()
+            .x(render_area.offset.x as f32)
+            .y(render_area.offset.y as f32)
+            .width(render_area.extent.width as f32)
+            .height(render_area.extent.height as f32)
+            .max_depth(1.0));
+        self.set_scissor(render_area);
+    }
+
+    /// Whether the image belongs to an earlier swapchain than the frame being recorded, e.g. a swapchain image or
+    /// an image matching the swapchain extent that was held on to across a resize. Recording it is a bug, caught
+    /// by a debug assertion, so components that cache images can use this to skip stale ones.
+    pub fn is_stale(&self, image: &dyn ImageTrait) -> bool {
+        let epoch = self.inner.swapchain_epoch.load(Ordering::Relaxed);
+        epoch != 0 && image.swapchain_epoch().is_some_and(|image_epoch| image_epoch != epoch)
+    }
+
+    /// Drop the references to the tracked resources, once the command buffer completed.
+    pub(crate) fn release_resources(&mut self) {
+        self.inner.resource_handles.lock().expect("Failed to lock mutex").clear();
+    }
+
+    /// Whether no other handle to the command buffer exists.
+    pub(crate) fn is_unique(&self) -> bool {
+        Arc::strong_count(&self.inner) == 1
+    }
+
     pub fn begin(&mut self) {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::default();
         unsafe {
@@ -130,15 +200,61 @@ impl CommandBuffer {
             self.inner.device_dep.device
                 .cmd_begin_render_pass(self.inner.command_buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
         }
+        self.set_default_viewport_to(render_pass_begin_info.render_area);
     }
     
+    /// Begin dynamic rendering, setting the viewport and scissor to the render area unless disabled with
+    /// [`set_default_viewport`](Self::set_default_viewport).
     pub fn begin_rendering(&self, rendering_info: &vk::RenderingInfoKHR<'_>) {
         unsafe {
             self.inner.device_dep.dynamic_rendering_loader
                 .cmd_begin_rendering(self.inner.command_buffer, rendering_info);
         }
+        self.set_default_viewport_to(rendering_info.render_area);
     }
     
+    /// Begin dynamic rendering to the color attachments over their full extent, which they have to share.
+    /// Multisampled attachments can be resolved into single-sampled images, see [`ColorAttachment::resolve`].
+    pub fn begin_rendering_to(&mut self, color_attachments: &[ColorAttachment]) {
+        self.begin_rendering_attachments(color_attachments, None);
+    }
+
+    /// Begin dynamic rendering to the color attachments and a depth attachment of the same extent, e.g. the depth
+    /// buffer of [`AppConfig::depth_buffer`](crate::app::app::AppConfig::depth_buffer). Depth formats with a
+    /// stencil aspect are bound as the stencil attachment as well.
+    pub fn begin_rendering_with_depth(&mut self, color_attachments: &[ColorAttachment], depth_attachment: DepthAttachment) {
+        self.begin_rendering_attachments(color_attachments, Some(depth_attachment));
+    }
+
+    fn begin_rendering_attachments(&mut self, color_attachments: &[ColorAttachment], depth_attachment: Option<DepthAttachment>) {
+        for attachment in color_attachments {
+            self.track_image(attachment.image);
+            if let Some(target) = attachment.resolve {
+                self.track_image(target);
+            }
+        }
+        if let Some(attachment) = &depth_attachment {
+            self.track_image(attachment.image);
+        }
+
+        let extent = color_attachments.first().map(|attachment| attachment.image.extent())
+            .or(depth_attachment.map(|attachment| attachment.image.extent()))
+            .unwrap_or_default();
+        let attachment_infos: Vec<_> = color_attachments.iter().map(ColorAttachment::info).collect();
+        let depth_info = depth_attachment.as_ref().map(DepthAttachment::info);
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D::default().extent(extent))
+            .layer_count(1)
+            .color_attachments(&attachment_infos);
+        if let (Some(attachment), Some(info)) = (&depth_attachment, &depth_info) {
+            rendering_info = rendering_info.depth_attachment(info);
+            if attachment.image.aspect_mask().contains(vk::ImageAspectFlags::STENCIL) {
+                rendering_info = rendering_info.stencil_attachment(info);
+            }
+        }
+        self.begin_rendering(&rendering_info);
+    }
+
     pub fn end_rendering(&self) {
         unsafe {
             self.inner.device_dep.dynamic_rendering_loader
@@ -157,7 +273,7 @@ impl CommandBuffer {
         dst_access_flags: vk::AccessFlags,
     )
     {
-        images.iter().for_each(|image| self.track(*image));
+        images.iter().for_each(|image| self.track_image(*image));
 
         let image_memory_barriers = images.iter().map(|i| {
 
@@ -169,13 +285,7 @@ impl CommandBuffer {
                 .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                 .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                 .image(i.handle())
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })
+                .subresource_range(i.subresource_range())
         }).collect::<Vec<ImageMemoryBarrier>>();
         unsafe {
             self.inner.device_dep.device.cmd_pipeline_barrier(
@@ -190,7 +300,8 @@ impl CommandBuffer {
         }
     }
 
-    pub fn transition(&mut self, image: &impl ImageTrait, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) {
+    pub fn transition(&mut self, image: &impl ImageTrait, old_layout: impl Into<vk::ImageLayout>, new_layout: impl Into<vk::ImageLayout>) {
+        let (old_layout, new_layout) = (old_layout.into(), new_layout.into());
         let (src_stage, src_access) = layout_stage_access(old_layout);
         let (dst_stage, dst_access) = layout_stage_access(new_layout);
         self.image_barrier(image, old_layout, new_layout, src_stage, dst_stage, src_access, dst_access);
@@ -207,7 +318,23 @@ impl CommandBuffer {
         dst_access_flags: vk::AccessFlags,
     )
     {
-        self.track(image);
+        self.image_subresource_barrier(image, image.subresource_range(), old_layout, new_layout, src_stage_mask, dst_stage_mask, src_access_flags, dst_access_flags);
+    }
+
+    /// Barrier of some mip levels or layers of an image, e.g. while generating mips.
+    pub fn image_subresource_barrier(
+        &mut self,
+        image: &impl ImageTrait,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        src_access_flags: vk::AccessFlags,
+        dst_access_flags: vk::AccessFlags,
+    )
+    {
+        self.track_image(image);
 
         let image_memory_barrier = vk::ImageMemoryBarrier::default()
             .old_layout(old_layout)
@@ -217,13 +344,82 @@ impl CommandBuffer {
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(image.handle())
-            .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: ImageAspectFlags::COLOR,
-                base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1,
-            });
+            .subresource_range(subresource_range);
+        unsafe {
+            self.inner.device_dep.device.cmd_pipeline_barrier(
+                self.inner.command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_memory_barrier]
+            )
+        }
+    }
+
+    /// Release an exclusive image to another queue family, keeping it in `layout`. The other family has to acquire
+    /// it with [`acquire_image`](Self::acquire_image) after a semaphore wait on this submission. Images created
+    /// [`concurrent`](crate::vulkan::ImageConfig::concurrent) need no transfers.
+    ///
+    /// ```ignore
+    /// // On the graphics queue, after writing the image
+    /// graphics.release_image(&image, vk::ImageLayout::GENERAL, graphics_family, compute_family,
+    ///     vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_WRITE);
+    /// // On the compute queue, waiting on a semaphore the graphics submission signals
+    /// compute.acquire_image(&image, vk::ImageLayout::GENERAL, graphics_family, compute_family,
+    ///     vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_READ);
+    /// ```
+    pub fn release_image(
+        &mut self,
+        image: &impl ImageTrait,
+        layout: vk::ImageLayout,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        src_stage_mask: vk::PipelineStageFlags,
+        src_access_flags: vk::AccessFlags,
+    )
+    {
+        self.queue_family_transfer(image, layout, src_queue_family_index, dst_queue_family_index,
+            (src_stage_mask, src_access_flags), (vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::empty()));
+    }
+
+    /// Acquire an exclusive image that another queue family released with [`release_image`](Self::release_image).
+    pub fn acquire_image(
+        &mut self,
+        image: &impl ImageTrait,
+        layout: vk::ImageLayout,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        dst_stage_mask: vk::PipelineStageFlags,
+        dst_access_flags: vk::AccessFlags,
+    )
+    {
+        self.queue_family_transfer(image, layout, src_queue_family_index, dst_queue_family_index,
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty()), (dst_stage_mask, dst_access_flags));
+    }
+
+    fn queue_family_transfer(
+        &mut self,
+        image: &impl ImageTrait,
+        layout: vk::ImageLayout,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        (src_stage_mask, src_access_flags): (vk::PipelineStageFlags, vk::AccessFlags),
+        (dst_stage_mask, dst_access_flags): (vk::PipelineStageFlags, vk::AccessFlags),
+    )
+    {
+        self.track_image(image);
+
+        let image_memory_barrier = vk::ImageMemoryBarrier::default()
+            .old_layout(layout)
+            .new_layout(layout)
+            .src_access_mask(src_access_flags)
+            .dst_access_mask(dst_access_flags)
+            .src_queue_family_index(src_queue_family_index)
+            .dst_queue_family_index(dst_queue_family_index)
+            .image(image.handle())
+            .subresource_range(image.subresource_range());
         unsafe {
             self.inner.device_dep.device.cmd_pipeline_barrier(
                 self.inner.command_buffer,
@@ -237,6 +433,66 @@ impl CommandBuffer {
         }
     }
 
+    /// Release an exclusive buffer to another queue family, see [`release_image`](Self::release_image).
+    pub fn release_buffer(
+        &mut self,
+        buffer: &Buffer,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        src_stage_mask: vk::PipelineStageFlags,
+        src_access_flags: vk::AccessFlags,
+    )
+    {
+        self.buffer_queue_family_transfer(buffer, src_queue_family_index, dst_queue_family_index,
+            (src_stage_mask, src_access_flags), (vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::empty()));
+    }
+
+    /// Acquire an exclusive buffer that another queue family released with [`release_buffer`](Self::release_buffer).
+    pub fn acquire_buffer(
+        &mut self,
+        buffer: &Buffer,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        dst_stage_mask: vk::PipelineStageFlags,
+        dst_access_flags: vk::AccessFlags,
+    )
+    {
+        self.buffer_queue_family_transfer(buffer, src_queue_family_index, dst_queue_family_index,
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty()), (dst_stage_mask, dst_access_flags));
+    }
+
+    fn buffer_queue_family_transfer(
+        &mut self,
+        buffer: &Buffer,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+        (src_stage_mask, src_access_flags): (vk::PipelineStageFlags, vk::AccessFlags),
+        (dst_stage_mask, dst_access_flags): (vk::PipelineStageFlags, vk::AccessFlags),
+    )
+    {
+        self.track(buffer);
+
+        let buffer_memory_barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(src_access_flags)
+            .dst_access_mask(dst_access_flags)
+            .src_queue_family_index(src_queue_family_index)
+            .dst_queue_family_index(dst_queue_family_index)
+            .buffer(*buffer.handle())
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        unsafe {
+            self.inner.device_dep.device.cmd_pipeline_barrier(
+                self.inner.command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_memory_barrier],
+                &[]
+            )
+        }
+    }
+
     pub fn push_descriptor_set(&mut self, pipeline: &dyn Pipeline, set: u32, write_descriptor_sets: &[WriteDescriptorSet]) {
         self.track(pipeline.resource());
 
@@ -253,7 +509,7 @@ impl CommandBuffer {
 
     pub fn bind_push_descriptor_images(&mut self, pipeline: &dyn Pipeline, images: &[&dyn ImageTrait]) {
         self.track(pipeline.resource());
-        images.iter().for_each(|image| self.track(*image));
+        images.iter().for_each(|image| self.track_image(*image));
 
         let bindings = images.iter().map(|image| {
             vk::DescriptorImageInfo::default()
@@ -280,7 +536,7 @@ impl CommandBuffer {
     }
 
     pub fn bind_push_descriptor_image(&mut self, pipeline: &dyn Pipeline, set: u32, image: &impl ImageTrait) {
-        self.track(image);
+        self.track_image(image);
         self.track(pipeline.resource());
 
         // TODO: Set bindings dynamically
@@ -320,6 +576,39 @@ impl CommandBuffer {
         }
     }
 
+    /// Push the descriptors of a writer to a set of the pipeline, tracking their images and buffers.
+    pub fn push_descriptors(&mut self, pipeline: &dyn Pipeline, set: u32, writer: &DescriptorWriter) {
+        self.track_descriptors(writer);
+        self.push_descriptor_set(pipeline, set, &writer.writes());
+    }
+
+    /// Keep the images, buffers and samplers of a writer alive until the command buffer executed, for descriptors
+    /// written to sets that don't reference their resources, e.g. transient ones.
+    pub fn track_descriptors(&mut self, writer: &DescriptorWriter) {
+        for image in &writer.images {
+            self.track_image(*image);
+        }
+        for buffer in &writer.buffers {
+            self.track(*buffer);
+        }
+        for sampler in &writer.samplers {
+            self.track(*sampler);
+        }
+    }
+
+    pub fn push_storage_buffer(&mut self, pipeline: &dyn Pipeline, set: u32, binding: u32, buffer: &Buffer) {
+        self.push_descriptors(pipeline, set, &DescriptorWriter::new().storage_buffer(binding, buffer));
+    }
+
+    pub fn push_uniform_buffer(&mut self, pipeline: &dyn Pipeline, set: u32, binding: u32, buffer: &Buffer) {
+        self.push_descriptors(pipeline, set, &DescriptorWriter::new().uniform_buffer(binding, buffer));
+    }
+
+    /// Push an image with its sampler as a combined image sampler.
+    pub fn push_sampled_image(&mut self, pipeline: &dyn Pipeline, set: u32, binding: u32, image: &impl ImageTrait, layout: ImageLayout) {
+        self.push_descriptors(pipeline, set, &DescriptorWriter::new().sampled_image(binding, image, layout));
+    }
+
     pub fn end_render_pass(&self) {
         unsafe {
             self.inner.device_dep.device
@@ -346,11 +635,39 @@ impl CommandBuffer {
         instance_count: u32,
         first_index: u32,
         vertex_offset: i32,
-        _first_instance: u32,
+        first_instance: u32,
     ) {
         unsafe {
             self.inner.device_dep.device
-                .cmd_draw_indexed(self.inner.command_buffer, index_count, instance_count, first_index, vertex_offset, first_index);
+                .cmd_draw_indexed(self.inner.command_buffer, index_count, instance_count, first_index, vertex_offset, first_instance);
+        }
+    }
+
+    /// Bind buffers with their offsets to consecutive vertex input bindings, starting at `first_binding`.
+    /// See [`GraphicsPipelineConfig::vertex_bindings`](crate::vulkan::GraphicsPipelineConfig::vertex_bindings).
+    pub fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[(&Buffer, DeviceSize)]) {
+        for (buffer, _) in buffers {
+            self.track(*buffer);
+        }
+        let handles: Vec<vk::Buffer> = buffers.iter().map(|(buffer, _)| *buffer.handle()).collect();
+        let offsets: Vec<DeviceSize> = buffers.iter().map(|(_, offset)| *offset).collect();
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_bind_vertex_buffers(self.inner.command_buffer, first_binding, &handles, &offsets);
+        }
+    }
+
+    pub fn bind_vertex_buffer(&mut self, binding: u32, buffer: &Buffer) {
+        self.bind_vertex_buffers(binding, &[(buffer, 0)]);
+    }
+
+    pub fn bind_index_buffer(&mut self, buffer: &Buffer, offset: DeviceSize, index_type: vk::IndexType) {
+        self.track(buffer);
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_bind_index_buffer(self.inner.command_buffer, *buffer.handle(), offset, index_type);
         }
     }
 
@@ -377,19 +694,15 @@ impl CommandBuffer {
         }
     }
 
-    pub fn clear_color_image_u32<'a>(&mut self, image: &impl ImageTrait, layout: ImageLayout, color: [u32; 4])
+    pub fn clear_color_image_u32<'a>(&mut self, image: &impl ImageTrait, layout: impl Into<ImageLayout>, color: [u32; 4])
     {
-        self.track(image);
+        let layout = layout.into();
+        self.track_image(image);
 
         unsafe {
             let mut clear_color_value = vk::ClearColorValue::default();
             clear_color_value.uint32 = color;
-            let sub_resource_ranges = [ vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_array_layer(0)
-                .base_mip_level(0)
-                .layer_count(1)
-                .level_count(1) ];
+            let sub_resource_ranges = [ image.subresource_range() ];
             self.inner.device_dep.device
                 .cmd_clear_color_image(
                     self.inner.command_buffer,
@@ -401,19 +714,15 @@ impl CommandBuffer {
         }
     }
 
-    pub fn clear_color_image<'a>(&mut self, image: &impl ImageTrait, layout: ImageLayout, color: [f32; 4])
+    pub fn clear_color_image<'a>(&mut self, image: &impl ImageTrait, layout: impl Into<ImageLayout>, color: [f32; 4])
     {
-        self.track(image);
+        let layout = layout.into();
+        self.track_image(image);
 
         unsafe {
             let mut clear_color_value = vk::ClearColorValue::default();
             clear_color_value.float32 = color;
-            let sub_resource_ranges = [ vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_array_layer(0)
-                .base_mip_level(0)
-                .layer_count(1)
-                .level_count(1) ];
+            let sub_resource_ranges = [ image.subresource_range() ];
             self.inner.device_dep.device
                 .cmd_clear_color_image(
                     self.inner.command_buffer,
@@ -425,10 +734,31 @@ impl CommandBuffer {
         }
     }
 
-    pub fn blit_image<'a>(&mut self, src_image: &impl ImageTrait, src_layout: ImageLayout, dst_image: &impl ImageTrait, dst_layout: ImageLayout, regions: &[vk::ImageBlit], filter: vk::Filter)
+    /// Clear the depth, and the stencil to 0 for formats that have one.
+    pub fn clear_depth_image(&mut self, image: &impl ImageTrait, layout: impl Into<ImageLayout>, depth: f32)
+    {
+        let layout = layout.into();
+        self.track_image(image);
+
+        unsafe {
+            let clear_depth_value = vk::ClearDepthStencilValue { depth, stencil: 0 };
+            let sub_resource_ranges = [ image.subresource_range() ];
+            self.inner.device_dep.device
+                .cmd_clear_depth_stencil_image(
+                    self.inner.command_buffer,
+                    image.handle(),
+                    layout,
+                    &clear_depth_value,
+                    &sub_resource_ranges
+                )
+        }
+    }
+
+    pub fn blit_image<'a>(&mut self, src_image: &impl ImageTrait, src_layout: impl Into<ImageLayout>, dst_image: &impl ImageTrait, dst_layout: impl Into<ImageLayout>, regions: &[vk::ImageBlit], filter: impl Into<vk::Filter>)
     {
-        self.track(src_image);
-        self.track(dst_image);
+        let (src_layout, dst_layout, filter) = (src_layout.into(), dst_layout.into(), filter.into());
+        self.track_image(src_image);
+        self.track_image(dst_image);
 
         unsafe {
             self.inner.device_dep.device.cmd_blit_image(
@@ -443,6 +773,79 @@ impl CommandBuffer {
         }
     }
 
+    /// Fill the mip levels of all layers of an image, each from the previous one with a linear blit. All levels
+    /// have to be in `TRANSFER_DST_OPTIMAL` with the first one written, e.g. after an upload, and end up in `layout`.
+    /// The image needs `TRANSFER_SRC` usage and a format with linear blit support.
+    pub fn generate_mips(&mut self, image: &impl ImageTrait, layout: vk::ImageLayout) {
+        let range = image.subresource_range();
+        let level_range = |level: u32, count: u32| vk::ImageSubresourceRange {
+            base_mip_level: level,
+            level_count: count,
+            ..range
+        };
+        let layers = |level: u32| vk::ImageSubresourceLayers {
+            aspect_mask: range.aspect_mask,
+            mip_level: level,
+            base_array_layer: 0,
+            layer_count: range.layer_count,
+        };
+        let corner = |level: u32| vk::Offset3D {
+            x: (image.width() >> level).max(1) as i32,
+            y: (image.height() >> level).max(1) as i32,
+            z: (image.depth() >> level).max(1) as i32,
+        };
+
+        for level in 1..range.level_count {
+            self.image_subresource_barrier(
+                image,
+                level_range(level - 1, 1),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            );
+            self.blit_image(
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageBlit::default()
+                    .src_subresource(layers(level - 1))
+                    .src_offsets([vk::Offset3D::default(), corner(level - 1)])
+                    .dst_subresource(layers(level))
+                    .dst_offsets([vk::Offset3D::default(), corner(level)])],
+                vk::Filter::LINEAR,
+            );
+        }
+
+        // All levels but the last were read from
+        let last = range.level_count - 1;
+        if last > 0 {
+            self.image_subresource_barrier(
+                image,
+                level_range(0, last),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                layout,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::MEMORY_READ,
+            );
+        }
+        self.image_subresource_barrier(
+            image,
+            level_range(last, 1),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            layout,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::MEMORY_READ,
+        );
+    }
+
     pub fn bind_pipeline(&mut self, pipeline: &dyn Pipeline) {
         self.track(pipeline.resource());
 
@@ -458,7 +861,61 @@ impl CommandBuffer {
                 .cmd_dispatch(self.inner.command_buffer, x, y, z);
         }
     }
+
+    /// Dispatch enough workgroups of a bound compute pipeline to cover `extent` invocations,
+    /// see [`ComputePipeline::group_count`].
+    pub fn dispatch_extent(&self, pipeline: &ComputePipeline, extent: [u32; 3]) {
+        let [x, y, z] = pipeline.group_count(extent);
+        self.dispatch(x, y, z);
+    }
     
+    /// Dispatch a bound compute pipeline with the workgroup counts a `vk::DispatchIndirectCommand` in `buffer` at
+    /// `offset` holds when the dispatch executes, e.g. counts an earlier dispatch wrote, see
+    /// [`indirect_barrier`](Self::indirect_barrier). The buffer needs the `INDIRECT_BUFFER` usage.
+    pub fn dispatch_indirect(&mut self, buffer: &Buffer, offset: DeviceSize) {
+        check_indirect_command::<vk::DispatchIndirectCommand>(buffer.usage(), buffer.size(), offset)
+            .unwrap_or_else(|err| panic!("Invalid indirect dispatch: {}", err));
+        self.track(buffer);
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_dispatch_indirect(self.inner.command_buffer, *buffer.handle(), offset);
+        }
+    }
+
+    /// Make the compute shader writes to `buffer` visible to the indirect commands that read it.
+    pub fn indirect_barrier(&mut self, buffer: &Buffer) {
+        self.buffer_barrier(
+            buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+            0,
+            vk::WHOLE_SIZE
+        );
+    }
+
+    /// Reset queries before they are written again. Must be recorded outside of rendering.
+    pub fn reset_query_pool(&mut self, query_pool: &QueryPool, first: u32, count: u32) {
+        self.track(query_pool);
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_reset_query_pool(self.inner.command_buffer, query_pool.handle(), first, count);
+        }
+    }
+
+    /// Write the time at which all previous commands have completed `stage` into a query.
+    pub fn write_timestamp(&mut self, query_pool: &QueryPool, stage: vk::PipelineStageFlags, query: u32) {
+        self.track(query_pool);
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_write_timestamp(self.inner.command_buffer, stage, query_pool.handle(), query);
+        }
+    }
+
     pub fn fill_buffer(&mut self, buffer: &Buffer, offset: DeviceSize, size: DeviceSize, data: u32) {
         self.track(buffer);
 
@@ -474,10 +931,26 @@ impl CommandBuffer {
         }
     }
 
-    pub fn copy_buffer_to_image(&mut self, buffer: &Buffer, image: &impl ImageTrait, layout: ImageLayout, regions: &[BufferImageCopy])
+    pub fn copy_buffer(&mut self, src: &Buffer, dst: &Buffer, regions: &[vk::BufferCopy]) {
+        self.track(src);
+        self.track(dst);
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_copy_buffer(
+                    self.inner.command_buffer,
+                    *src.handle(),
+                    *dst.handle(),
+                    regions
+                );
+        }
+    }
+
+    pub fn copy_buffer_to_image(&mut self, buffer: &Buffer, image: &impl ImageTrait, layout: impl Into<ImageLayout>, regions: &[BufferImageCopy])
     {
+        let layout = layout.into();
         self.track(buffer);
-        self.track(image);
+        self.track_image(image);
 
         unsafe {
             self.inner.device_dep.device
@@ -491,8 +964,9 @@ impl CommandBuffer {
         }
     }
 
-    pub fn copy_image_to_buffer(&mut self, image: &impl ImageTrait, layout: ImageLayout, buffer: &Buffer, regions: &[BufferImageCopy]) {
-        self.track(image);
+    pub fn copy_image_to_buffer(&mut self, image: &impl ImageTrait, layout: impl Into<ImageLayout>, buffer: &Buffer, regions: &[BufferImageCopy]) {
+        let layout = layout.into();
+        self.track_image(image);
         self.track(buffer);
 
         unsafe {
@@ -507,9 +981,29 @@ impl CommandBuffer {
         }
     }
     
-    pub fn copy_image(&mut self, from: &impl ImageTrait, from_layout: ImageLayout, to: &impl ImageTrait, to_layout: ImageLayout, regions: &[ImageCopy]) {
-        self.track(from);
-        self.track(to);
+    /// Copy between images. Copies to the swapchain are recorded as a blit of the same regions on drivers with the
+    /// [`BlitToSwapchain`](Workaround::BlitToSwapchain) workaround.
+    pub fn copy_image(&mut self, from: &impl ImageTrait, from_layout: impl Into<ImageLayout>, to: &impl ImageTrait, to_layout: impl Into<ImageLayout>, regions: &[ImageCopy]) {
+        let (from_layout, to_layout) = (from_layout.into(), to_layout.into());
+        if to.is_swapchain_image() && self.inner.device_dep.workarounds.contains(&Workaround::BlitToSwapchain) {
+            let blits = regions.iter().map(|region| {
+                let end = |offset: vk::Offset3D| vk::Offset3D {
+                    x: offset.x + region.extent.width as i32,
+                    y: offset.y + region.extent.height as i32,
+                    z: offset.z + region.extent.depth as i32,
+                };
+                vk::ImageBlit::default()
+                    .src_subresource(region.src_subresource)
+                    .src_offsets([region.src_offset, end(region.src_offset)])
+                    .dst_subresource(region.dst_subresource)
+                    .dst_offsets([region.dst_offset, end(region.dst_offset)])
+            }).collect::<Vec<_>>();
+            self.blit_image(from, from_layout, to, to_layout, &blits, vk::Filter::NEAREST);
+            return;
+        }
+
+        self.track_image(from);
+        self.track_image(to);
 
         unsafe {
             self.inner.device_dep.device
@@ -524,16 +1018,17 @@ impl CommandBuffer {
         }
     }
     
+    /// Make writes to a range of a buffer available to later accesses, e.g. between compute passes
+    /// writing and reading a storage buffer. Use `vk::WHOLE_SIZE` to cover the rest of the buffer from `offset`.
     pub fn buffer_barrier(
         &mut self,
+        buffer: &Buffer,
         src_stage_mask: vk::PipelineStageFlags,
         dst_stage_mask: vk::PipelineStageFlags,
         src_access_mask: vk::AccessFlags,
         dst_access_mask: vk::AccessFlags,
-        dependency_flags: vk::DependencyFlags,
-        size: vk::DeviceSize,
         offset: vk::DeviceSize,
-        buffer: &Buffer
+        size: vk::DeviceSize,
     ) {
         self.track(buffer);
 
@@ -543,15 +1038,15 @@ impl CommandBuffer {
                     self.inner.command_buffer,
                     src_stage_mask,
                     dst_stage_mask,
-                    dependency_flags,
+                    vk::DependencyFlags::empty(),
                     &[],
                     &[vk::BufferMemoryBarrier::default()
                         .src_access_mask(src_access_mask)
                         .dst_access_mask(dst_access_mask)
-                        .size(size)
                         .offset(offset)
-                        .src_queue_family_index(0)
-                        .dst_queue_family_index(0)
+                        .size(size)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                         .buffer(*buffer.handle())
                     ],
                     &[]
@@ -559,6 +1054,71 @@ impl CommandBuffer {
         }
     }
 
+    /// Barriers of several images and buffers in one command, e.g. everything a pass reads and writes. The handles,
+    /// queue family indices and ranges of the barriers are filled in, covering the full image or buffer.
+    pub fn pipeline_barrier(
+        &mut self,
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        images: &[(&dyn ImageTrait, vk::ImageMemoryBarrier)],
+        buffers: &[(&Buffer, vk::BufferMemoryBarrier)],
+    ) {
+        images.iter().for_each(|(image, _)| self.track_image(*image));
+        buffers.iter().for_each(|(buffer, _)| self.track(*buffer));
+
+        let image_memory_barriers = images.iter().map(|(image, barrier)| {
+            barrier
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image.handle())
+                .subresource_range(image.subresource_range())
+        }).collect::<Vec<ImageMemoryBarrier>>();
+        let buffer_memory_barriers = buffers.iter().map(|(buffer, barrier)| {
+            barrier
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(*buffer.handle())
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+        }).collect::<Vec<vk::BufferMemoryBarrier>>();
+        unsafe {
+            self.inner.device_dep.device.cmd_pipeline_barrier(
+                self.inner.command_buffer,
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_memory_barriers,
+                &image_memory_barriers
+            )
+        }
+    }
+
+    /// Global memory barrier that covers all buffers and images, e.g. after a pass that wrote several storage buffers.
+    pub fn memory_barrier(
+        &mut self,
+        src_stage_mask: vk::PipelineStageFlags,
+        dst_stage_mask: vk::PipelineStageFlags,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+    ) {
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_pipeline_barrier(
+                    self.inner.command_buffer,
+                    src_stage_mask,
+                    dst_stage_mask,
+                    vk::DependencyFlags::empty(),
+                    &[vk::MemoryBarrier::default()
+                        .src_access_mask(src_access_mask)
+                        .dst_access_mask(dst_access_mask)
+                    ],
+                    &[],
+                    &[]
+                );
+        }
+    }
+
     pub fn bind_descriptor_sets(&mut self, pipeline: &dyn Pipeline, descriptor_sets: &[vk::DescriptorSet]) {
         self.track(pipeline.resource());
 
@@ -575,10 +1135,58 @@ impl CommandBuffer {
         }
     }
 
+    pub fn bind_descriptor_set(&mut self, pipeline: &dyn Pipeline, set: u32, descriptor_set: &DescriptorSet) {
+        self.track(descriptor_set);
+        self.bind_descriptor_set_handle(pipeline, set, descriptor_set.handle());
+    }
+
+    /// Bind a set that is owned elsewhere, e.g. by a [`TransientDescriptorAllocator`](crate::vulkan::TransientDescriptorAllocator).
+    /// The set has to stay valid until the command buffer executed.
+    pub fn bind_descriptor_set_handle(&mut self, pipeline: &dyn Pipeline, set: u32, descriptor_set: vk::DescriptorSet) {
+        self.track(pipeline.resource());
+
+        unsafe {
+            self.inner.device_dep.device
+                .cmd_bind_descriptor_sets(
+                    self.inner.command_buffer,
+                    pipeline.bind_point(),
+                    pipeline.layout(),
+                    set,
+                    &[descriptor_set],
+                    &[]
+                );
+        }
+    }
+
     pub fn handle(&self) -> vk::CommandBuffer {
         self.inner.command_buffer
     }
 
+    /// Name the command buffer in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.command_buffer, name);
+    }
+
+    /// Open a labeled region of commands, shown in graphics debuggers like RenderDoc. Regions can be nested
+    /// and have to be closed with [`end_label`](Self::end_label) in the same command buffer.
+    pub fn begin_label(&mut self, name: &str, color: [f32; 4]) {
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(&name)
+            .color(color);
+        unsafe {
+            self.inner.device_dep.debug_utils
+                .cmd_begin_debug_utils_label(self.inner.command_buffer, &label);
+        }
+    }
+
+    pub fn end_label(&mut self) {
+        unsafe {
+            self.inner.device_dep.debug_utils
+                .cmd_end_debug_utils_label(self.inner.command_buffer);
+        }
+    }
+
     pub fn fence(&self) -> vk::Fence {
         self.inner.in_flight_fence
     }
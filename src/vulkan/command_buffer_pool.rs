@@ -0,0 +1,42 @@
+use crate::vulkan::{CommandBuffer, CommandPool, Device};
+
+/// Recycles single-time command buffers together with their fences, instead of creating new ones for every submission.
+#[derive(Default)]
+pub struct CommandBufferPool {
+    free: Vec<CommandBuffer>,
+    recycled: usize,
+}
+
+impl CommandBufferPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A command buffer with an unsignaled fence, ready to be begun.
+    pub fn acquire(&mut self, device: &Device, command_pool: &CommandPool) -> CommandBuffer {
+        self.free.pop()
+            .unwrap_or_else(|| CommandBuffer::new(device, command_pool, false))
+    }
+
+    /// Return a command buffer that was acquired from this pool and that completed execution.
+    /// Its fence is reset and the resources it tracks are released.
+    pub fn recycle(&mut self, device: &Device, mut command_buffer: CommandBuffer) {
+        assert!(command_buffer.is_unique(), "Recycled command buffers may not be referenced elsewhere");
+        assert!(device.get_fence_status(command_buffer.fence()), "Recycled command buffers must have completed");
+
+        device.reset_fence(command_buffer.fence());
+        command_buffer.release_resources();
+        self.free.push(command_buffer);
+        self.recycled += 1;
+    }
+
+    /// Command buffers that are ready to be acquired again.
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Number of times a command buffer was returned to the pool.
+    pub fn recycled_count(&self) -> usize {
+        self.recycled
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use ash::vk;
 use log::trace;
-use crate::vulkan::{Device, LOG_TARGET};
+use crate::vulkan::{Device, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 
 pub struct CommandPool {
@@ -23,7 +23,7 @@ impl CommandPool {
                 .expect("Failed to create command pool")
         };
 
-        trace!(target: LOG_TARGET, "Created command pool: {:?}", command_pool);
+        trace!(target: OBJECTS_LOG_TARGET, "Created command pool: {:?}", command_pool);
 
         Self {
             device_dep: device.inner.clone(),
@@ -42,7 +42,7 @@ impl Drop for CommandPool {
         unsafe {
             let command_pool_addr = format!("{:?}", self.command_pool);
             self.device_dep.device.destroy_command_pool(self.command_pool, None);
-            trace!(target: LOG_TARGET, "Destroyed command pool: [{}]", command_pool_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed command pool: [{}]", command_pool_addr);
         }
     }
 }
\ No newline at end of file
@@ -5,17 +5,20 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use ash::vk;
 use log::{trace};
-use crate::vulkan::{DescriptorSetLayout, Device, GpuHandle, Pipeline, LOG_TARGET};
+use crate::vulkan::{DescriptorSetLayout, Device, GpuHandle, Pipeline, PipelineLayout, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 use crate::vulkan::memory::GpuResource;
-use crate::vulkan::pipeline::{create_shader_module, load_shader_code, load_slang_shader_code, PipelineErr, SlangModule};
+use crate::vulkan::pipeline::{create_shader_module, PipelineErr, ShaderSource, SlangModule, SpecializationConstant, SpecializationData};
 
 #[derive(Clone)]
 pub struct ComputePipelineConfig {
-    pub shader_source: PathBuf,
+    pub shader_source: ShaderSource,
     pub descriptor_set_layouts: Vec<DescriptorSetLayout>,
     pub push_constant_ranges: Vec<vk::PushConstantRange>,
     pub macros: HashMap<String, String>,
+    /// Values of specialization constants by `constant_id`, e.g. the workgroup size, without compiling the shader
+    /// per value like `macros` do.
+    pub specialization_constants: HashMap<u32, SpecializationConstant>,
     pub slang_modules: Vec<SlangModule>,
 }
 
@@ -26,15 +29,28 @@ impl Default for ComputePipelineConfig {
             descriptor_set_layouts: vec![],
             push_constant_ranges: vec![],
             macros: HashMap::new(),
+            specialization_constants: HashMap::new(),
             slang_modules: vec![],
         }
     }
 }
 
 pub struct ComputePipelineInner {
-    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline_layout: PipelineLayout,
     pub compute_pipeline: vk::Pipeline,
     pub device_dep: Arc<DeviceInner>,
+    workgroup_size: Option<[u32; 3]>,
+    includes: Vec<PathBuf>,
+}
+
+/// A statistic the driver reports about a compiled pipeline, e.g. its register or shared memory usage.
+#[derive(Clone, Debug)]
+pub struct PipelineStatistic {
+    /// Name of the shader executable the statistic belongs to.
+    pub executable: String,
+    pub name: String,
+    pub description: String,
+    pub value: String,
 }
 
 impl Drop for ComputePipelineInner {
@@ -42,8 +58,8 @@ impl Drop for ComputePipelineInner {
         unsafe {
             let compute_pipeline_addr = format!("{:?}", self.compute_pipeline);
             self.device_dep.device.destroy_pipeline(self.compute_pipeline, None);
-            self.device_dep.device.destroy_pipeline_layout(self.pipeline_layout, None);
-            trace!(target: LOG_TARGET, "Destroyed compute pipeline: [{}]", compute_pipeline_addr);
+            self.device_dep.live_objects.destroyed(self.compute_pipeline);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed compute pipeline: [{}]", compute_pipeline_addr);
         }
     }
 }
@@ -65,7 +81,7 @@ impl Pipeline for ComputePipeline {
     }
 
     fn layout(&self) -> vk::PipelineLayout {
-        self.inner.pipeline_layout
+        self.inner.pipeline_layout.handle()
     }
 
     fn resource(&self) -> &dyn GpuResource {
@@ -85,57 +101,225 @@ pub fn new(
     device: &Device,
     config: ComputePipelineConfig
 ) -> Result<Self, PipelineErr> {
+        trace_scope!("compile_pipeline", shader = ?config.shader_source.path());
 
-        let shader_code = if config.shader_source.extension().map_or(false, |e| e == "slang") {
-            load_slang_shader_code(config.shader_source, &config.slang_modules)?
-        } else {
-            load_shader_code(config.shader_source, &config.macros)?
-        };
-        let shader_module = create_shader_module(device.handle(), shader_code.to_vec());
+        let (shader_code, includes) = config.shader_source.load_with_includes(&config.macros, &config.slang_modules)?;
+
+        Ok(Self::create(device, shader_code, &config.descriptor_set_layouts, &config.push_constant_ranges, &config.specialization_constants, includes))
+    }
+
+    /// Create a compute pipeline from already compiled SPIR-V code.
+    pub fn from_spirv(
+        device: &Device,
+        shader_code: Vec<u32>,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange]
+    ) -> Self {
+        Self::create(device, shader_code, descriptor_set_layouts, push_constant_ranges, &HashMap::new(), Vec::new())
+    }
+
+    fn create(
+        device: &Device,
+        shader_code: Vec<u32>,
+        descriptor_set_layouts: &[DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+        specialization_constants: &HashMap<u32, SpecializationConstant>,
+        includes: Vec<PathBuf>
+    ) -> Self {
+        let workgroup_size = spirv_local_size(&shader_code, specialization_constants);
+        let shader_module = create_shader_module(device.handle(), shader_code);
 
         let binding = CString::new("main").unwrap();
-        let shader_stages = [
-            vk::PipelineShaderStageCreateInfo::default()
-                .stage(vk::ShaderStageFlags::COMPUTE)
-                .module(shader_module)
-                .name(binding.as_c_str()),
-        ];
-
-        // Layout
-        let desc_layouts = config.descriptor_set_layouts
-            .iter().map(|layout| layout.handle()).collect::<Vec<_>>();
-        let create_info = vk::PipelineLayoutCreateInfo::default()
-            .set_layouts(&desc_layouts)
-            .push_constant_ranges(&config.push_constant_ranges);
-        let pipeline_layout = unsafe {
-            device.handle()
-                .create_pipeline_layout(&create_info, None)
-                .expect("Failed to create pipeline layout")
-        };
+        let specialization = SpecializationData::new(specialization_constants);
+        let specialization_info = specialization.info();
+        let mut stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader_module)
+            .name(binding.as_c_str());
+        if let Some(info) = &specialization_info {
+            stage = stage.specialization_info(info);
+        }
+        let shader_stages = [stage];
+
+        // Layout, shared with other pipelines that use the same descriptor set layouts and push constants
+        let pipeline_layout = PipelineLayout::new(device, descriptor_set_layouts, push_constant_ranges);
 
         // pipeline
+        // Driver statistics are only kept when requested at creation
+        let flags = if device.is_extension_enabled(ash::khr::pipeline_executable_properties::NAME) {
+            vk::PipelineCreateFlags::CAPTURE_STATISTICS_KHR
+        } else {
+            vk::PipelineCreateFlags::empty()
+        };
         let compute_pipeline_create_info = vk::ComputePipelineCreateInfo::default()
+            .flags(flags)
             .stage(shader_stages[0])
-            .layout(pipeline_layout);
+            .layout(pipeline_layout.handle());
 
         let compute_pipeline = unsafe {
             device.handle()
-                .create_compute_pipelines(vk::PipelineCache::null(), &[compute_pipeline_create_info], None)
+                .create_compute_pipelines(device.inner.pipeline_cache, &[compute_pipeline_create_info], None)
                 .expect("Failed to create graphics pipeline")[0]
         };
 
-        trace!(target: LOG_TARGET, "Created compute pipeline: [{:?}]", compute_pipeline);
+        trace!(target: OBJECTS_LOG_TARGET, "Created compute pipeline: [{:?}]", compute_pipeline);
+        device.inner.live_objects.created(compute_pipeline);
 
         unsafe { device.handle().destroy_shader_module(shader_module, None); }
 
         let pipeline_inner = ComputePipelineInner {
             pipeline_layout,
             compute_pipeline,
-            device_dep: device.inner.clone()
+            device_dep: device.inner.clone(),
+            workgroup_size,
+            includes,
         };
 
-        Ok(Self {
+        Self {
             inner: Arc::new(pipeline_inner)
-        })
+        }
+    }
+    /// Name the pipeline in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.compute_pipeline, name);
+    }
+
+    /// Files the shader included when it was compiled, which the pipeline store watches for changes.
+    pub fn includes(&self) -> &[PathBuf] {
+        &self.inner.includes
+    }
+
+    /// Local size the shader declares, with the specialization constants of the config applied.
+    pub fn workgroup_size(&self) -> Option<[u32; 3]> {
+        self.inner.workgroup_size
+    }
+
+    /// Number of workgroups that cover `extent` invocations.
+    pub fn group_count(&self, extent: [u32; 3]) -> [u32; 3] {
+        let size = self.workgroup_size().expect("The workgroup size of the pipeline is unknown");
+        [0, 1, 2].map(|i| extent[i].div_ceil(size[i]))
+    }
+
+    /// Statistics of the compiled pipeline, e.g. register and shared memory usage.
+    /// Empty unless `VK_KHR_pipeline_executable_properties` is enabled, see
+    /// [`PipelineStatisticsPlugin`](crate::graphics::PipelineStatisticsPlugin).
+    pub fn statistics(&self) -> Vec<PipelineStatistic> {
+        let device = &self.inner.device_dep;
+        let extension = ash::khr::pipeline_executable_properties::NAME;
+        if !device.enabled_extensions.iter().any(|enabled| enabled.as_c_str() == extension) {
+            return Vec::new();
+        }
+
+        let loader = ash::khr::pipeline_executable_properties::Device::new(&device.instance_dep.instance, &device.device);
+        let pipeline_info = vk::PipelineInfoKHR::default().pipeline(self.inner.compute_pipeline);
+        let executables = unsafe { loader.get_pipeline_executable_properties(&pipeline_info) }.unwrap_or_default();
+
+        let mut statistics = Vec::new();
+        for (index, executable) in executables.iter().enumerate() {
+            let executable_name = to_string(executable.name_as_c_str());
+            let executable_info = vk::PipelineExecutableInfoKHR::default()
+                .pipeline(self.inner.compute_pipeline)
+                .executable_index(index as u32);
+            let executable_statistics = unsafe { loader.get_pipeline_executable_statistics(&executable_info) }.unwrap_or_default();
+            statistics.extend(executable_statistics.iter().map(|statistic| PipelineStatistic {
+                executable: executable_name.clone(),
+                name: to_string(statistic.name_as_c_str()),
+                description: to_string(statistic.description_as_c_str()),
+                value: unsafe {
+                    match statistic.format {
+                        vk::PipelineExecutableStatisticFormatKHR::BOOL32 => (statistic.value.b32 == vk::TRUE).to_string(),
+                        vk::PipelineExecutableStatisticFormatKHR::INT64 => statistic.value.i64.to_string(),
+                        vk::PipelineExecutableStatisticFormatKHR::UINT64 => statistic.value.u64.to_string(),
+                        vk::PipelineExecutableStatisticFormatKHR::FLOAT64 => statistic.value.f64.to_string(),
+                        _ => String::new(),
+                    }
+                },
+            }));
+        }
+        statistics
+    }
+}
+
+fn to_string(name: Result<&std::ffi::CStr, std::ffi::FromBytesUntilNulError>) -> String {
+    name.map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// The local size of a SPIR-V module. A `WorkgroupSize` built-in overrides the `LocalSize` execution mode, with
+/// its components taken from the specialization constants that set them.
+fn spirv_local_size(code: &[u32], specialization_constants: &HashMap<u32, SpecializationConstant>) -> Option<[u32; 3]> {
+    const OP_EXECUTION_MODE: u32 = 16;
+    const OP_CONSTANT: u32 = 43;
+    const OP_CONSTANT_COMPOSITE: u32 = 44;
+    const OP_SPEC_CONSTANT: u32 = 50;
+    const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    const OP_DECORATE: u32 = 71;
+    const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+    const DECORATION_SPEC_ID: u32 = 1;
+    const DECORATION_BUILT_IN: u32 = 11;
+    const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+
+    let mut local_size = None;
+    let mut workgroup_size_id = None;
+    let mut spec_ids = HashMap::new();
+    let mut scalars = HashMap::new();
+    let mut composites = HashMap::new();
+
+    // Instructions start after the 5 word header, with their word count in the high half of the first word
+    let mut offset = 5;
+    while offset < code.len() {
+        let word_count = (code[offset] >> 16) as usize;
+        let opcode = code[offset] & 0xffff;
+        if word_count == 0 || offset + word_count > code.len() {
+            return None;
+        }
+        let operands = &code[offset + 1..offset + word_count];
+        match (opcode, operands) {
+            (OP_EXECUTION_MODE, [_, EXECUTION_MODE_LOCAL_SIZE, x, y, z]) => local_size = Some([*x, *y, *z]),
+            (OP_DECORATE, [target, DECORATION_BUILT_IN, BUILT_IN_WORKGROUP_SIZE]) => workgroup_size_id = Some(*target),
+            (OP_DECORATE, [target, DECORATION_SPEC_ID, spec_id]) => { spec_ids.insert(*target, *spec_id); }
+            (OP_CONSTANT | OP_SPEC_CONSTANT, [_, id, value]) => { scalars.insert(*id, *value); }
+            (OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE, [_, id, x, y, z]) => { composites.insert(*id, [*x, *y, *z]); }
+            _ => {}
+        }
+        offset += word_count;
+    }
+
+    let Some(components) = workgroup_size_id.and_then(|id| composites.get(&id)) else {
+        return local_size;
+    };
+    let mut size = [0; 3];
+    for (size, id) in size.iter_mut().zip(components) {
+        let specialized = spec_ids.get(id).and_then(|spec_id| specialization_constants.get(spec_id));
+        *size = match specialized {
+            Some(constant) => constant.to_bits(),
+            None => *scalars.get(id)?,
+        };
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vulkan::compile_shader_code;
+
+    #[test]
+    fn read_local_size() {
+        let source = "#version 450\nlayout(local_size_x = 16, local_size_y = 4) in;\nvoid main() {}\n";
+        let code = compile_shader_code(source, "local_size.comp", shaderc::ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile shader");
+        assert_eq!(spirv_local_size(&code, &HashMap::new()), Some([16, 4, 1]));
+        assert_eq!(spirv_local_size(&code[..5], &HashMap::new()), None);
+    }
+
+    #[test]
+    fn specialized_local_size() {
+        let source = "#version 450\nlayout(local_size_x_id = 0, local_size_y = 4) in;\nvoid main() { uint size = gl_WorkGroupSize.x; }\n";
+        let code = compile_shader_code(source, "local_size.comp", shaderc::ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile shader");
+        assert_eq!(spirv_local_size(&code, &HashMap::new()), Some([1, 4, 1]));
+
+        let constants = HashMap::from([(0, SpecializationConstant::from(64u32))]);
+        assert_eq!(spirv_local_size(&code, &constants), Some([64, 4, 1]));
     }
 }
@@ -0,0 +1,130 @@
+use std::any::Any;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Keeps GPU resources that were dropped while frames were in flight alive until those frames executed.
+///
+/// Images and buffers queue their handles with the frame being recorded once their last reference is dropped, also
+/// when that was the one of a command buffer tracking them. Deferring only starts with the first frame, resources
+/// are destroyed right away before that.
+///
+/// Renderers sharing the device complete their frames independently, resources are only released once all frames
+/// up to theirs executed.
+///
+/// Queued resources hold on to the device, so the queue has to be [flushed](Self::flush) once the device is idle.
+#[derive(Default)]
+pub(crate) struct DeletionQueue {
+    state: Mutex<DeletionState>,
+}
+
+#[derive(Default)]
+struct DeletionState {
+    /// Frame resources are queued with, 0 until the first frame.
+    frame: u64,
+    /// Frames that began and didn't complete yet.
+    in_flight: BTreeSet<u64>,
+    pending: VecDeque<(u64, Arc<dyn Any>)>,
+}
+
+impl DeletionQueue {
+
+    /// Keep the resource alive until the current frame executed.
+    pub(crate) fn defer(&self, resource: Arc<dyn Any>) {
+        let mut state = self.state.lock().unwrap();
+        if state.frame == 0 {
+            // Released after the lock
+            drop(state);
+            return;
+        }
+        let frame = state.frame;
+        state.pending.push_back((frame, resource));
+    }
+
+    /// Start queueing resources with a new frame, returns its number.
+    pub(crate) fn begin_frame(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.frame += 1;
+        let frame = state.frame;
+        state.in_flight.insert(frame);
+        frame
+    }
+
+    /// Release the resources of the frame and all earlier ones that completed, after waiting for the frame to execute.
+    pub(crate) fn frame_completed(&self, frame: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight.remove(&frame);
+        let oldest_in_flight = state.in_flight.first().copied().unwrap_or(u64::MAX);
+        let count = state.pending.iter().take_while(|(queued, _)| *queued < oldest_in_flight).count();
+        let released: Vec<_> = state.pending.drain(..count).collect();
+
+        // Destroying resources may drop other resources, which queue themselves
+        drop(state);
+        drop(released);
+    }
+
+    /// Release all resources and stop deferring, once the device is idle.
+    pub(crate) fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.frame = 0;
+        state.in_flight.clear();
+        let released = std::mem::take(&mut state.pending);
+        drop(state);
+        drop(released);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_after_frame() {
+        let queue = DeletionQueue::default();
+        let resource: Arc<dyn Any> = Arc::new(1u32);
+
+        // Not deferred before the first frame
+        queue.defer(resource.clone());
+        assert_eq!(queue.len(), 0);
+
+        let first = queue.begin_frame();
+        queue.defer(resource.clone());
+        let second = queue.begin_frame();
+        queue.defer(resource.clone());
+        assert_eq!(Arc::strong_count(&resource), 3);
+
+        queue.frame_completed(first);
+        assert_eq!(queue.len(), 1);
+        queue.frame_completed(second);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(Arc::strong_count(&resource), 1);
+
+        queue.begin_frame();
+        queue.defer(resource.clone());
+        queue.flush();
+        assert_eq!(Arc::strong_count(&resource), 1);
+        queue.defer(resource.clone());
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn wait_for_earlier_frames() {
+        let queue = DeletionQueue::default();
+        let resource: Arc<dyn Any> = Arc::new(1u32);
+
+        // Frames of two renderers on the same device
+        let first = queue.begin_frame();
+        queue.defer(resource.clone());
+        let second = queue.begin_frame();
+        queue.defer(resource.clone());
+
+        // The first frame of the other renderer may still execute
+        queue.frame_completed(second);
+        assert_eq!(queue.len(), 2);
+        queue.frame_completed(first);
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,348 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use ash::vk;
+use log::trace;
+use crate::vulkan::{DescriptorSetLayout, DescriptorWriter, Device, OBJECTS_LOG_TARGET};
+use crate::vulkan::device::DeviceInner;
+use crate::vulkan::memory::GpuResource;
+
+const INITIAL_SETS_PER_POOL: u32 = 64;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Descriptors reserved per set for each descriptor type, used to size new pools.
+pub const DEFAULT_POOL_RATIOS: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::SAMPLER, 0.5),
+];
+
+struct DescriptorAllocatorState {
+    pools: Vec<vk::DescriptorPool>,
+    sets_per_pool: u32,
+    // Sets that were dropped, ready to be handed out again for the same layout
+    free_sets: HashMap<vk::DescriptorSetLayout, Vec<vk::DescriptorSet>>,
+}
+
+pub struct DescriptorAllocatorInner {
+    device_dep: Arc<DeviceInner>,
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    flags: vk::DescriptorPoolCreateFlags,
+    state: Mutex<DescriptorAllocatorState>,
+}
+
+/// Pool sizes for `sets` sets with descriptors of each type reserved according to `ratios`, and at least the
+/// `required` descriptors, e.g. of a set that is larger than the ratios.
+fn pool_sizes(ratios: &[(vk::DescriptorType, f32)], sets: u32, required: &[(vk::DescriptorType, u32)]) -> Vec<vk::DescriptorPoolSize> {
+    let mut sizes = ratios.iter()
+        .map(|(ty, ratio)| vk::DescriptorPoolSize {
+            ty: *ty,
+            descriptor_count: ((sets as f32 * ratio).ceil() as u32).max(1),
+        })
+        .collect::<Vec<_>>();
+    for (ty, count) in required {
+        match sizes.iter_mut().find(|size| size.ty == *ty) {
+            Some(size) => size.descriptor_count = size.descriptor_count.max(*count),
+            None => sizes.push(vk::DescriptorPoolSize { ty: *ty, descriptor_count: *count }),
+        }
+    }
+    sizes
+}
+
+/// A pool for `sets` sets, see [`pool_sizes`].
+fn create_pool(device: &ash::Device, ratios: &[(vk::DescriptorType, f32)], flags: vk::DescriptorPoolCreateFlags, sets: u32, required: &[(vk::DescriptorType, u32)]) -> vk::DescriptorPool {
+    let sizes = pool_sizes(ratios, sets, required);
+
+    let create_info = vk::DescriptorPoolCreateInfo::default()
+        .pool_sizes(&sizes)
+        .max_sets(sets)
+        .flags(flags);
+
+    let pool = unsafe {
+        device.create_descriptor_pool(&create_info, None)
+            .expect("Failed to create descriptor pool")
+    };
+
+    trace!(target: OBJECTS_LOG_TARGET, "Created descriptor pool: {:?} ({} sets)", pool, sets);
+
+    pool
+}
+
+impl DescriptorAllocatorInner {
+    fn create_pool(&self, sets: u32, required: &[(vk::DescriptorType, u32)]) -> vk::DescriptorPool {
+        create_pool(&self.device_dep.device, &self.ratios, self.flags, sets, required)
+    }
+
+    fn recycle(&self, layout: vk::DescriptorSetLayout, set: vk::DescriptorSet) {
+        self.state.lock().unwrap().free_sets.entry(layout).or_default().push(set);
+    }
+}
+
+impl Drop for DescriptorAllocatorInner {
+    fn drop(&mut self) {
+        let state = self.state.lock().unwrap();
+        for pool in &state.pools {
+            unsafe {
+                self.device_dep.device.destroy_descriptor_pool(*pool, None);
+            }
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed descriptor pool: [{:?}]", pool);
+        }
+    }
+}
+
+/// Growable chain of descriptor pools.
+/// A new, larger pool is created whenever the current one runs out of space, with room for at least the set that
+/// didn't fit, also when it needs more descriptors than the ratios reserve.
+/// Dropped [`DescriptorSet`]s are recycled for later allocations with the same layout.
+#[derive(Clone)]
+pub struct DescriptorAllocator {
+    inner: Arc<DescriptorAllocatorInner>,
+}
+
+impl DescriptorAllocator {
+
+    /// Create an allocator. `ratios` lists the number of descriptors reserved per set for each type.
+    pub fn new(device: &Device, ratios: &[(vk::DescriptorType, f32)]) -> DescriptorAllocator {
+        Self::with_flags(device, ratios, vk::DescriptorPoolCreateFlags::empty())
+    }
+
+    pub fn with_flags(device: &Device, ratios: &[(vk::DescriptorType, f32)], flags: vk::DescriptorPoolCreateFlags) -> DescriptorAllocator {
+        let inner = DescriptorAllocatorInner {
+            device_dep: device.inner.clone(),
+            ratios: ratios.to_vec(),
+            flags,
+            state: Mutex::new(DescriptorAllocatorState {
+                pools: vec![],
+                sets_per_pool: INITIAL_SETS_PER_POOL,
+                free_sets: HashMap::new(),
+            }),
+        };
+
+        let pool = inner.create_pool(INITIAL_SETS_PER_POOL, &[]);
+        inner.state.lock().unwrap().pools.push(pool);
+
+        DescriptorAllocator {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Allocate a descriptor set. The set keeps the layout alive.
+    pub fn allocate(&self, layout: &DescriptorSetLayout) -> DescriptorSet {
+        let mut set = self.allocate_raw(layout.handle(), layout.descriptor_counts());
+        Arc::get_mut(&mut set.inner).unwrap()._layout_dep = Some(layout.clone());
+        set
+    }
+
+    /// Allocate a descriptor set for a layout that is owned elsewhere, with the descriptors of each type it needs.
+    /// The layout must outlive the allocator.
+    pub(crate) fn allocate_raw(&self, layout: vk::DescriptorSetLayout, descriptor_counts: &[(vk::DescriptorType, u32)]) -> DescriptorSet {
+        let mut state = self.inner.state.lock().unwrap();
+
+        let recycled = state.free_sets.get_mut(&layout).and_then(|sets| sets.pop());
+        let set = match recycled {
+            Some(set) => set,
+            None => {
+                let layouts = [layout];
+                let mut grown = false;
+                loop {
+                    let pool = *state.pools.last().unwrap();
+                    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(pool)
+                        .set_layouts(&layouts);
+
+                    match unsafe { self.inner.device_dep.device.allocate_descriptor_sets(&allocate_info) } {
+                        Ok(sets) => break sets[0],
+                        Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) if !grown => {
+                            // Grow the chain with a larger pool, that fits the set
+                            state.sets_per_pool = (state.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+                            let pool = self.inner.create_pool(state.sets_per_pool, descriptor_counts);
+                            state.pools.push(pool);
+                            grown = true;
+                        }
+                        Err(e) => panic!("Failed to allocate descriptor set: {:?}", e),
+                    }
+                }
+            }
+        };
+
+        DescriptorSet {
+            inner: Arc::new(DescriptorSetInner {
+                allocator_dep: self.inner.clone(),
+                set,
+                layout,
+                _layout_dep: None,
+            })
+        }
+    }
+
+    /// Number of pools currently in the chain.
+    pub fn pool_count(&self) -> usize {
+        self.inner.state.lock().unwrap().pools.len()
+    }
+}
+
+pub struct DescriptorSetInner {
+    allocator_dep: Arc<DescriptorAllocatorInner>,
+    set: vk::DescriptorSet,
+    layout: vk::DescriptorSetLayout,
+    _layout_dep: Option<DescriptorSetLayout>,
+}
+
+impl Drop for DescriptorSetInner {
+    fn drop(&mut self) {
+        self.allocator_dep.recycle(self.layout, self.set);
+    }
+}
+
+/// A persistent descriptor set, returned to its allocator once the last reference is dropped.
+/// Command buffers keep the set alive while they are in flight.
+#[derive(Clone)]
+pub struct DescriptorSet {
+    inner: Arc<DescriptorSetInner>,
+}
+
+impl GpuResource for DescriptorSet {
+    fn reference(&self) -> Arc<dyn Any> {
+        self.inner.clone()
+    }
+}
+
+impl DescriptorSet {
+    pub fn handle(&self) -> vk::DescriptorSet {
+        self.inner.set
+    }
+
+    /// Update the descriptors of the set. The destination set of the writes is filled in automatically.
+    pub fn write(&self, writes: &[vk::WriteDescriptorSet]) {
+        let writes = writes.iter()
+            .map(|write| write.dst_set(self.inner.set))
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.inner.allocator_dep.device_dep.device.update_descriptor_sets(&writes, &[]);
+        }
+    }
+}
+
+/// Descriptor pools whose sets are all freed at once by [`reset`](Self::reset), for throwaway sets that are only used
+/// by the commands of one frame, e.g. materials with a varying number of textures. Every frame in flight has one,
+/// reset once the frame executed, see [`CenContext::bind_transient_descriptors`](crate::app::engine::CenContext::bind_transient_descriptors).
+///
+/// Grows like a [`DescriptorAllocator`], keeping its pools across resets. Sets larger than the ratios reserve get a
+/// new pool that fits them.
+pub struct TransientDescriptorAllocator {
+    device_dep: Arc<DeviceInner>,
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    pools: Vec<vk::DescriptorPool>,
+    // Pool sets are allocated from, the ones before it ran out of space
+    current: usize,
+    sets_per_pool: u32,
+    allocated: usize,
+}
+
+impl Drop for TransientDescriptorAllocator {
+    fn drop(&mut self) {
+        for pool in &self.pools {
+            unsafe {
+                self.device_dep.device.destroy_descriptor_pool(*pool, None);
+            }
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed descriptor pool: [{:?}]", pool);
+        }
+    }
+}
+
+impl TransientDescriptorAllocator {
+    pub fn new(device: &Device, ratios: &[(vk::DescriptorType, f32)]) -> TransientDescriptorAllocator {
+        let pool = create_pool(device.handle(), ratios, vk::DescriptorPoolCreateFlags::empty(), INITIAL_SETS_PER_POOL, &[]);
+        TransientDescriptorAllocator {
+            device_dep: device.inner.clone(),
+            ratios: ratios.to_vec(),
+            pools: vec![pool],
+            current: 0,
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+            allocated: 0,
+        }
+    }
+
+    /// Allocate a set and write the descriptors of `writer` to it. The set is valid until the next reset, and
+    /// doesn't keep the resources of the writer alive.
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout, writer: &DescriptorWriter) -> vk::DescriptorSet {
+        let layouts = [layout.handle()];
+        let mut grown = false;
+        let set = loop {
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(self.pools[self.current])
+                .set_layouts(&layouts);
+
+            match unsafe { self.device_dep.device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(sets) => break sets[0],
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) if self.current + 1 < self.pools.len() => {
+                    // Continue in the next pool that was created before the last reset
+                    self.current += 1;
+                }
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) if !grown => {
+                    // Grow the chain with a larger pool, that fits the set
+                    self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+                    let pool = create_pool(&self.device_dep.device, &self.ratios, vk::DescriptorPoolCreateFlags::empty(), self.sets_per_pool, layout.descriptor_counts());
+                    self.pools.push(pool);
+                    self.current = self.pools.len() - 1;
+                    grown = true;
+                }
+                Err(e) => panic!("Failed to allocate descriptor set: {:?}", e),
+            }
+        };
+        self.allocated += 1;
+
+        let writes = writer.writes().into_iter()
+            .map(|write| write.dst_set(set))
+            .collect::<Vec<_>>();
+        unsafe {
+            self.device_dep.device.update_descriptor_sets(&writes, &[]);
+        }
+        set
+    }
+
+    /// Free all sets. None of them may be in use by commands that haven't executed yet.
+    pub fn reset(&mut self) {
+        for pool in &self.pools {
+            unsafe {
+                self.device_dep.device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())
+                    .expect("Failed to reset descriptor pool");
+            }
+        }
+        self.current = 0;
+        self.allocated = 0;
+    }
+
+    /// Number of sets allocated since the last reset.
+    pub fn allocated_count(&self) -> usize {
+        self.allocated
+    }
+
+    /// Number of pools currently in the chain.
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_pools_for_large_sets() {
+        let ratios = [(vk::DescriptorType::STORAGE_IMAGE, 2.0), (vk::DescriptorType::SAMPLER, 0.5)];
+        let sizes = pool_sizes(&ratios, 4, &[]);
+        assert_eq!(sizes.iter().map(|size| size.descriptor_count).collect::<Vec<_>>(), vec![8, 2]);
+
+        let required = [(vk::DescriptorType::STORAGE_IMAGE, 32), (vk::DescriptorType::UNIFORM_BUFFER, 3)];
+        let sizes = pool_sizes(&ratios, 4, &required);
+        let count = |ty| sizes.iter().find(|size| size.ty == ty).map(|size| size.descriptor_count);
+        assert_eq!(count(vk::DescriptorType::STORAGE_IMAGE), Some(32));
+        assert_eq!(count(vk::DescriptorType::SAMPLER), Some(2));
+        assert_eq!(count(vk::DescriptorType::UNIFORM_BUFFER), Some(3));
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use ash::vk;
 use log::trace;
-use crate::vulkan::{Device, LOG_TARGET};
+use crate::vulkan::{Device, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 
 pub struct DescriptorPool {
@@ -23,7 +23,7 @@ impl DescriptorPool {
             .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET);
         let descriptor_pool = unsafe { device.handle().create_descriptor_pool(&create_info, None).unwrap() };
 
-        trace!(target: LOG_TARGET, "Created descriptor pool: {:?}", descriptor_pool);
+        trace!(target: OBJECTS_LOG_TARGET, "Created descriptor pool: {:?}", descriptor_pool);
 
         Self {
             device_dep: device.inner.clone(),
@@ -42,7 +42,7 @@ impl Drop for DescriptorPool {
         unsafe {
             let command_pool_addr = format!("{:?}", self.descriptor_pool);
             self.device_dep.device.destroy_descriptor_pool(self.descriptor_pool, None);
-            trace!(target: LOG_TARGET, "Destroyed command pool: [{}]", command_pool_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed command pool: [{}]", command_pool_addr);
         }
     }
 }
\ No newline at end of file
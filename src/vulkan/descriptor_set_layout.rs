@@ -2,12 +2,13 @@ use std::sync::Arc;
 use ash::vk;
 use ash::vk::DescriptorSetLayoutBinding;
 use log::trace;
-use crate::vulkan::{Device, LOG_TARGET};
+use crate::vulkan::{Device, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 
 struct DescriptorSetLayoutInner {
     device_dep: Arc<DeviceInner>,
     layout: vk::DescriptorSetLayout,
+    descriptor_counts: Vec<(vk::DescriptorType, u32)>,
 }
 
 #[derive(Clone)]
@@ -20,18 +21,23 @@ impl Drop for DescriptorSetLayoutInner {
         unsafe {
             let layout_addr = format!("{:?}", self.layout);
             self.device_dep.device.destroy_descriptor_set_layout(self.layout, None);
-            trace!(target: LOG_TARGET, "Destroyed descriptor set layout: [{}]", layout_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed descriptor set layout: [{}]", layout_addr);
         }
     }
 }
 
 impl DescriptorSetLayout {
 
-    fn create(device: &Device, flags: vk::DescriptorSetLayoutCreateFlags, layout_bindings: &[DescriptorSetLayoutBinding]) -> DescriptorSetLayout {
+    fn create(device: &Device, flags: vk::DescriptorSetLayoutCreateFlags, layout_bindings: &[DescriptorSetLayoutBinding], binding_flags: &[vk::DescriptorBindingFlags]) -> DescriptorSetLayout {
 
-        let layout_create_info = vk::DescriptorSetLayoutCreateInfo::default()
+        let mut binding_flags_create_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+            .binding_flags(binding_flags);
+        let mut layout_create_info = vk::DescriptorSetLayoutCreateInfo::default()
             .flags(flags)
             .bindings(layout_bindings);
+        if !binding_flags.is_empty() {
+            layout_create_info = layout_create_info.push_next(&mut binding_flags_create_info);
+        }
 
         let layout = unsafe {
             device.handle()
@@ -39,22 +45,30 @@ impl DescriptorSetLayout {
                 .expect("Failed to create descriptor set layout")
         };
 
-        trace!(target: LOG_TARGET, "Created descriptor set layout: {:?}", layout);
+        trace!(target: OBJECTS_LOG_TARGET, "Created descriptor set layout: {:?}", layout);
 
         DescriptorSetLayout {
             inner: Arc::new(DescriptorSetLayoutInner {
                 device_dep: device.inner.clone(),
                 layout,
+                descriptor_counts: descriptor_counts(layout_bindings),
             }),
         }
     }
 
     pub fn new(device: &Device, layout_bindings: &[vk::DescriptorSetLayoutBinding]) -> DescriptorSetLayout {
-        DescriptorSetLayout::create(device, vk::DescriptorSetLayoutCreateFlags::empty(), layout_bindings)
+        DescriptorSetLayout::create(device, vk::DescriptorSetLayoutCreateFlags::empty(), layout_bindings, &[])
     }
 
     pub fn new_push_descriptor(device: &Device, layout_bindings: &[DescriptorSetLayoutBinding]) -> DescriptorSetLayout {
-        DescriptorSetLayout::create(device, vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR, layout_bindings)
+        DescriptorSetLayout::create(device, vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR, layout_bindings, &[])
+    }
+
+    /// Create a layout with flags per binding, e.g. `PARTIALLY_BOUND` for descriptor indexing.
+    /// `binding_flags` has one entry per binding.
+    pub fn with_binding_flags(device: &Device, flags: vk::DescriptorSetLayoutCreateFlags, layout_bindings: &[DescriptorSetLayoutBinding], binding_flags: &[vk::DescriptorBindingFlags]) -> DescriptorSetLayout {
+        assert_eq!(layout_bindings.len(), binding_flags.len(), "Expected flags for every binding");
+        DescriptorSetLayout::create(device, flags, layout_bindings, binding_flags)
     }
 
     pub fn clone(&self) -> DescriptorSetLayout {
@@ -66,4 +80,26 @@ impl DescriptorSetLayout {
     pub(crate) fn handle(&self) -> vk::DescriptorSetLayout {
         self.inner.layout
     }
-}
\ No newline at end of file
+
+    /// Descriptors of each type a set of the layout needs.
+    pub(crate) fn descriptor_counts(&self) -> &[(vk::DescriptorType, u32)] {
+        &self.inner.descriptor_counts
+    }
+
+    /// Name the layout in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.layout, name);
+    }
+}
+
+/// Total descriptors of each type over the bindings.
+fn descriptor_counts(layout_bindings: &[DescriptorSetLayoutBinding]) -> Vec<(vk::DescriptorType, u32)> {
+    let mut counts: Vec<(vk::DescriptorType, u32)> = Vec::new();
+    for binding in layout_bindings.iter().filter(|binding| binding.descriptor_count > 0) {
+        match counts.iter_mut().find(|(ty, _)| *ty == binding.descriptor_type) {
+            Some((_, count)) => *count += binding.descriptor_count,
+            None => counts.push((binding.descriptor_type, binding.descriptor_count)),
+        }
+    }
+    counts
+}
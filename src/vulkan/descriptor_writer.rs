@@ -0,0 +1,104 @@
+use ash::vk;
+use crate::vulkan::{Buffer, DescriptorSet, ImageTrait, Sampler};
+
+enum DescriptorInfo {
+    Image(vk::DescriptorImageInfo),
+    Buffer(vk::DescriptorBufferInfo),
+}
+
+/// Descriptors of a set, by binding, without building `WriteDescriptorSet`s by hand.
+///
+/// Pushed with [`CommandBuffer::push_descriptors`](crate::vulkan::CommandBuffer::push_descriptors), which also tracks
+/// the resources, or written to an allocated set with [`update`](Self::update).
+///
+/// ```ignore
+/// let writer = DescriptorWriter::new()
+///     .storage_image(0, &output)
+///     .sampled_image(1, &input, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+///     .uniform_buffer(2, &globals);
+/// command_buffer.push_descriptors(&pipeline, 0, &writer);
+/// ```
+#[derive(Default)]
+pub struct DescriptorWriter<'a> {
+    writes: Vec<(u32, vk::DescriptorType, DescriptorInfo)>,
+    pub(crate) images: Vec<&'a dyn ImageTrait>,
+    pub(crate) buffers: Vec<&'a Buffer>,
+    pub(crate) samplers: Vec<&'a Sampler>,
+}
+
+impl<'a> DescriptorWriter<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An image of the given descriptor type, e.g. an `INPUT_ATTACHMENT`.
+    pub fn image(mut self, binding: u32, descriptor_type: vk::DescriptorType, image: &'a impl ImageTrait, layout: impl Into<vk::ImageLayout>) -> Self {
+        self.writes.push((binding, descriptor_type, DescriptorInfo::Image(image.binding(layout.into()))));
+        self.images.push(image);
+        self
+    }
+
+    /// A storage image in the `GENERAL` layout.
+    pub fn storage_image(self, binding: u32, image: &'a impl ImageTrait) -> Self {
+        self.image(binding, vk::DescriptorType::STORAGE_IMAGE, image, vk::ImageLayout::GENERAL)
+    }
+
+    /// An image with its sampler, as a combined image sampler.
+    pub fn sampled_image(self, binding: u32, image: &'a impl ImageTrait, layout: impl Into<vk::ImageLayout>) -> Self {
+        self.image(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, image, layout)
+    }
+
+    /// An image combined with a standalone sampler instead of its own, as a combined image sampler.
+    pub fn sampled_image_with(mut self, binding: u32, image: &'a impl ImageTrait, sampler: &'a Sampler, layout: impl Into<vk::ImageLayout>) -> Self {
+        let info = image.binding(layout.into()).sampler(sampler.handle());
+        self.writes.push((binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, DescriptorInfo::Image(info)));
+        self.images.push(image);
+        self.samplers.push(sampler);
+        self
+    }
+
+    /// A standalone sampler, for shaders that combine it with separately bound images.
+    pub fn sampler(mut self, binding: u32, sampler: &'a Sampler) -> Self {
+        let info = vk::DescriptorImageInfo::default().sampler(sampler.handle());
+        self.writes.push((binding, vk::DescriptorType::SAMPLER, DescriptorInfo::Image(info)));
+        self.samplers.push(sampler);
+        self
+    }
+
+    /// A range of a buffer of the given descriptor type.
+    pub fn buffer_range(mut self, binding: u32, descriptor_type: vk::DescriptorType, buffer: &'a Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Self {
+        let info = vk::DescriptorBufferInfo::default()
+            .buffer(*buffer.handle())
+            .offset(offset)
+            .range(range);
+        self.writes.push((binding, descriptor_type, DescriptorInfo::Buffer(info)));
+        self.buffers.push(buffer);
+        self
+    }
+
+    pub fn storage_buffer(self, binding: u32, buffer: &'a Buffer) -> Self {
+        self.buffer_range(binding, vk::DescriptorType::STORAGE_BUFFER, buffer, 0, vk::WHOLE_SIZE)
+    }
+
+    pub fn uniform_buffer(self, binding: u32, buffer: &'a Buffer) -> Self {
+        self.buffer_range(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer, 0, vk::WHOLE_SIZE)
+    }
+
+    /// The writes, referencing the infos of the writer. The destination set is left empty.
+    pub(crate) fn writes(&self) -> Vec<vk::WriteDescriptorSet<'_>> {
+        self.writes.iter().map(|(binding, descriptor_type, info)| {
+            let write = vk::WriteDescriptorSet::default()
+                .dst_binding(*binding)
+                .descriptor_type(*descriptor_type);
+            match info {
+                DescriptorInfo::Image(info) => write.image_info(std::slice::from_ref(info)),
+                DescriptorInfo::Buffer(info) => write.buffer_info(std::slice::from_ref(info)),
+            }
+        }).collect()
+    }
+
+    /// Write the descriptors to an allocated set. The set doesn't keep the resources alive.
+    pub fn update(&self, set: &DescriptorSet) {
+        set.write(&self.writes());
+    }
+}
@@ -1,18 +1,85 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use ash::khr::swapchain;
 use ash::{vk};
 use ash::vk::{PipelineStageFlags, Queue};
-use log::trace;
-use crate::vulkan::{CommandBuffer, Instance, LOG_TARGET};
+use log::{info, trace, warn};
+use crate::vulkan::{CommandBuffer, DeviceConfig, DeviceError, DevicePlugin, DriverInfo, EventLog, FeatureChain, Instance, LeakReport, QueueFamilies, Workaround, LOG_TARGET, OBJECTS_LOG_TARGET};
+use crate::vulkan::deletion_queue::DeletionQueue;
+use crate::vulkan::device_config::merge_features;
+use crate::vulkan::live_objects::ObjectRegistry;
+use crate::vulkan::driver_info::workarounds_for;
+use crate::vulkan::image::first_supported_format;
+use crate::vulkan::memory::GpuResource;
 use crate::vulkan::instance::InstanceInner;
+use crate::vulkan::pipeline_layout::PipelineLayoutCache;
 
 /// A connection to a physical GPU.
 pub struct DeviceInner {
     pub instance_dep: Arc<InstanceInner>,
     pub device: ash::Device,
+    pub physical_device: vk::PhysicalDevice,
     pub device_push_descriptor: ash::khr::push_descriptor::Device,
     pub queue_family_index: u32,
-    pub dynamic_rendering_loader: ash::khr::dynamic_rendering::Device
+    /// Queue family dedicated to compute, without graphics support.
+    pub compute_queue_family_index: Option<u32>,
+    /// Queue family dedicated to transfers, without graphics or compute support.
+    pub transfer_queue_family_index: Option<u32>,
+    /// Queue family presenting to the surface when the main family can't, see [`Device::with_present_queue`].
+    pub present_queue_family_index: Option<u32>,
+    pub dynamic_rendering_loader: ash::khr::dynamic_rendering::Device,
+    pub debug_utils: ash::ext::debug_utils::Device,
+    pub pipeline_cache: vk::PipelineCache,
+    pub(crate) pipeline_layouts: PipelineLayoutCache,
+    pub(crate) event_log: EventLog,
+    pub(crate) counters: ObjectCounters,
+    pub(crate) deletion_queue: DeletionQueue,
+    /// Shared, so leaks can be reported after the last handle of the device was expected to be dropped.
+    pub(crate) live_objects: Arc<ObjectRegistry>,
+    pub enabled_extensions: Vec<CString>,
+    /// Whether `bufferDeviceAddress` was enabled through the feature chain.
+    pub buffer_device_address: bool,
+    /// Whether `samplerAnisotropy` was enabled, see [`SamplerConfig::max_anisotropy`](crate::vulkan::SamplerConfig::max_anisotropy).
+    pub sampler_anisotropy: bool,
+    pub driver_info: DriverInfo,
+    /// Platform quirks of the driver the engine works around.
+    pub workarounds: Vec<Workaround>,
+}
+
+/// Live counts of synchronization objects and command buffers, see [`Device::object_stats`].
+#[derive(Default)]
+pub(crate) struct ObjectCounters {
+    pub(crate) command_buffers_created: AtomicUsize,
+    pub(crate) fences_alive: AtomicUsize,
+    semaphores_alive: AtomicUsize,
+    fences_in_flight: Mutex<HashSet<vk::Fence>>,
+}
+
+impl ObjectCounters {
+    pub(crate) fn fence_submitted(&self, fence: vk::Fence) {
+        self.fences_in_flight.lock().unwrap().insert(fence);
+    }
+
+    pub(crate) fn fence_completed(&self, fence: vk::Fence) {
+        self.fences_in_flight.lock().unwrap().remove(&fence);
+    }
+}
+
+/// Snapshot of the object counters of a device.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ObjectStats {
+    /// Command buffers created since the device was created.
+    pub command_buffers_created: usize,
+    /// Fences of command buffers that are alive.
+    pub fences_alive: usize,
+    /// Fences of submissions that were not waited on yet.
+    pub fences_in_flight: usize,
+    /// Semaphores created with [`Device::create_semaphore`] that are alive.
+    pub semaphores_alive: usize,
+    /// Dropped resources waiting for the frames in flight to execute, see [`Device::defer_drop`].
+    pub pending_deletions: usize,
 }
 
 impl Drop for DeviceInner {
@@ -20,8 +87,26 @@ impl Drop for DeviceInner {
         unsafe {
             let device_addr = format!("{:?}", self.device.handle());
             self.device.device_wait_idle().unwrap();
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
             self.device.destroy_device(None);
-            trace!(target: LOG_TARGET, "Destroyed device: [{}]", device_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed device: [{}]", device_addr);
+        }
+    }
+}
+
+impl DeviceInner {
+    /// Name a Vulkan object, shown in validation messages and graphics debuggers.
+    pub(crate) fn set_debug_name(&self, handle: impl vk::Handle, name: &str) {
+        let Ok(name) = CString::new(name) else {
+            warn!(target: LOG_TARGET, "Debug name {:?} contains a nul byte", name);
+            return;
+        };
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        self.live_objects.named((name_info.object_type, name_info.object_handle), &name.to_string_lossy());
+        if let Err(e) = unsafe { self.debug_utils.set_debug_utils_object_name(&name_info) } {
+            warn!(target: LOG_TARGET, "Failed to set debug name {:?}: {}", name, e);
         }
     }
 }
@@ -38,69 +123,399 @@ impl Clone for Device {
 
 impl Device {
     pub fn new(instance: &Instance, physical_device: vk::PhysicalDevice, queue_family_index: u32) -> Device {
-        let priorities = [1.0];
+        Self::with_plugins(instance, physical_device, queue_family_index, &[])
+    }
+
+    /// Create a device with additional extensions and features requested by plugins.
+    pub fn with_plugins(instance: &Instance, physical_device: vk::PhysicalDevice, queue_family_index: u32, plugins: &[Box<dyn DevicePlugin>]) -> Device {
+        Self::with_config(instance, physical_device, queue_family_index, &DeviceConfig::default(), plugins)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Create a device with the extensions and features of the config and the plugins.
+    /// Fails when the device doesn't support the required ones.
+    pub fn with_config(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        config: &DeviceConfig,
+        plugins: &[Box<dyn DevicePlugin>]
+    ) -> Result<Device, DeviceError> {
+        Self::create(instance, physical_device, queue_family_index, None, config, plugins)
+    }
+
+    /// Create a device that presents from a queue of another family than `queue_family_index`, for devices whose
+    /// graphics families can't present to the surface. No extra queue is created when both families are the same.
+    pub fn with_present_queue(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        present_queue_family_index: u32,
+        config: &DeviceConfig,
+        plugins: &[Box<dyn DevicePlugin>]
+    ) -> Result<Device, DeviceError> {
+        let present_queue_family_index = Some(present_queue_family_index).filter(|index| *index != queue_family_index);
+        Self::create(instance, physical_device, queue_family_index, present_queue_family_index, config, plugins)
+    }
+
+    fn create(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        present_queue_family_index: Option<u32>,
+        config: &DeviceConfig,
+        plugins: &[Box<dyn DevicePlugin>]
+    ) -> Result<Device, DeviceError> {
+        config.check(instance, physical_device)?;
 
-        let queue_info = vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(queue_family_index)
-            .queue_priorities(&priorities);
+        let priorities = [1.0];
 
-        let device_extension_names_raw = [
-            swapchain::NAME.as_ptr(),
+        let queue_families = unsafe {
+            instance.handle().get_physical_device_queue_family_properties(physical_device)
+        };
+        let compute_queue_family_index = find_dedicated_queue_family(
+            &queue_families,
+            vk::QueueFlags::COMPUTE,
+            vk::QueueFlags::GRAPHICS
+        );
+        let transfer_queue_family_index = find_dedicated_queue_family(
+            &queue_families,
+            vk::QueueFlags::TRANSFER,
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE
+        );
+
+        // One queue for each distinct family
+        let mut queue_family_indices = vec![queue_family_index];
+        queue_family_indices.extend(compute_queue_family_index);
+        queue_family_indices.extend(transfer_queue_family_index);
+        queue_family_indices.extend(present_queue_family_index);
+        queue_family_indices.sort();
+        queue_family_indices.dedup();
+        let queue_infos = queue_family_indices.iter()
+            .map(|index| {
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(*index)
+                    .queue_priorities(&priorities)
+            })
+            .collect::<Vec<_>>();
+
+        let mut enabled_extensions: Vec<&CStr> = vec![
+            swapchain::NAME,
             // Push descriptors
-            ash::khr::push_descriptor::NAME.as_ptr(),
+            ash::khr::push_descriptor::NAME,
             // Dynamic rendering
-            ash::khr::dynamic_rendering::NAME.as_ptr(),
+            ash::khr::dynamic_rendering::NAME,
             // MoltenVK
             #[cfg(target_os = "macos")]
-                ash::khr::portability_subset::NAME.as_ptr(),
+                ash::khr::portability_subset::NAME,
         ];
 
-        let features = vk::PhysicalDeviceFeatures {
+        // Required extensions, the config checked that the device supports them
+        for extension in &config.required_extensions {
+            if !enabled_extensions.contains(&extension.as_c_str()) {
+                enabled_extensions.push(extension.as_c_str());
+            }
+        }
+
+        // Optional and plugin extensions, skipping the ones the device doesn't support
+        let supported_extensions = unsafe {
+            instance.handle()
+                .enumerate_device_extension_properties(physical_device)
+                .expect("Failed to enumerate device extensions")
+        };
+        let is_supported = |extension: &CStr| supported_extensions.iter()
+            .any(|properties| properties.extension_name_as_c_str().is_ok_and(|name| name == extension));
+
+        // The features of a plugin need its extensions, so plugins are skipped as a whole
+        let plugins = plugins.iter()
+            .filter(|plugin| {
+                let missing = plugin.extensions().into_iter()
+                    .filter(|extension| !is_supported(extension))
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    warn!(target: LOG_TARGET, "Skipping device plugin, unsupported extensions: {:?}", missing);
+                }
+                missing.is_empty()
+            })
+            .collect::<Vec<_>>();
+
+        let optional_extensions = config.optional_extensions.iter()
+            .map(|extension| extension.as_c_str())
+            .chain(plugins.iter().flat_map(|plugin| plugin.extensions()));
+        for extension in optional_extensions {
+            if enabled_extensions.contains(&extension) {
+                continue;
+            }
+            if is_supported(extension) {
+                enabled_extensions.push(extension);
+            } else {
+                warn!(target: LOG_TARGET, "Skipping unsupported device extension: {:?}", extension);
+            }
+        }
+
+        let device_extension_names_raw = enabled_extensions.iter()
+            .map(|extension| extension.as_ptr())
+            .collect::<Vec<_>>();
+
+        let features = merge_features(&vk::PhysicalDeviceFeatures {
             shader_clip_distance: 1,
             ..Default::default()
-        };
+        }, &config.features);
 
         let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default()
             .dynamic_rendering(true);
 
-        let device_create_info = vk::DeviceCreateInfo::default()
-            .queue_create_infos(std::slice::from_ref(&queue_info))
+        let mut device_create_info = vk::DeviceCreateInfo::default()
+            .queue_create_infos(&queue_infos)
             .enabled_extension_names(&device_extension_names_raw)
             .enabled_features(&features)
             .push_next(&mut dynamic_rendering_features);
 
+        // Config and plugin features are prepended to the chain
+        let mut feature_chain = FeatureChain::default();
+        config.push_features(&mut feature_chain);
+        for plugin in plugins {
+            plugin.features(&mut feature_chain);
+        }
+        device_create_info.p_next = unsafe { feature_chain.link(device_create_info.p_next) };
+
         let device = unsafe {
             instance.handle()
                 .create_device(physical_device, &device_create_info, None)
-        }.unwrap();
+        }.map_err(DeviceError::Creation)?;
 
-        trace!(target: LOG_TARGET, "Created device: {:?}", device.handle());
+        let buffer_device_address = feature_chain.get::<vk::PhysicalDeviceVulkan12Features<'static>>()
+            .is_some_and(|features| features.buffer_device_address == vk::TRUE)
+            || feature_chain.get::<vk::PhysicalDeviceBufferDeviceAddressFeatures<'static>>()
+            .is_some_and(|features| features.buffer_device_address == vk::TRUE);
+
+        trace!(target: OBJECTS_LOG_TARGET, "Created device: {:?}", device.handle());
 
         let device_push_descriptor = ash::khr::push_descriptor::Device::new(instance.handle(), &device);
         
         let dynamic_rendering_loader = ash::khr::dynamic_rendering::Device::new(instance.handle(), &device);
 
+        let debug_utils = ash::ext::debug_utils::Device::new(instance.handle(), &device);
+
+        // Shared by all pipelines created on this device
+        let pipeline_cache = unsafe {
+            device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+                .expect("Failed to create pipeline cache")
+        };
+
+        let driver_info = DriverInfo::query(instance, physical_device);
+        info!(target: LOG_TARGET, "GPU: {}", driver_info);
+        let workarounds = workarounds_for(&driver_info).into_iter()
+            .map(|rule| {
+                info!(target: LOG_TARGET, "Enabled workaround {:?}: {}", rule.workaround, rule.reason);
+                rule.workaround
+            })
+            .collect();
+
         let device_inner = DeviceInner {
             instance_dep: instance.inner.clone(),
             device,
+            physical_device,
             device_push_descriptor,
             queue_family_index,
+            compute_queue_family_index,
+            transfer_queue_family_index,
+            present_queue_family_index,
             dynamic_rendering_loader,
+            debug_utils,
+            pipeline_cache,
+            pipeline_layouts: PipelineLayoutCache::default(),
+            event_log: instance.inner.event_log.clone(),
+            counters: ObjectCounters::default(),
+            deletion_queue: DeletionQueue::default(),
+            live_objects: Arc::new(ObjectRegistry::default()),
+            enabled_extensions: enabled_extensions.iter().map(|extension| CString::from(*extension)).collect(),
+            buffer_device_address,
+            sampler_anisotropy: features.sampler_anisotropy == vk::TRUE,
+            driver_info,
+            workarounds,
         };
 
-        Self {
+        Ok(Self {
             inner: Arc::new(device_inner),
-        }
+        })
     }
 
     pub fn handle(&self) -> &ash::Device {
         &self.inner.device
     }
 
+    pub fn physical_device(&self) -> vk::PhysicalDevice {
+        self.inner.physical_device
+    }
+
+    pub fn limits(&self) -> vk::PhysicalDeviceLimits {
+        unsafe {
+            self.inner.instance_dep.instance.get_physical_device_properties(self.inner.physical_device).limits
+        }
+    }
+
+    /// Features of the format for linear and optimal tiling, and for buffers.
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        unsafe {
+            self.inner.instance_dep.instance.get_physical_device_format_properties(self.inner.physical_device, format)
+        }
+    }
+
+    /// The first of the formats, in order of preference, that optimally tiled images of the usage support on this
+    /// device, e.g. to fall back from `R8G8B8A8` to `B8G8R8A8` variants. `None` if none of them does.
+    pub fn select_format(&self, formats: &[vk::Format], usage: impl Into<vk::ImageUsageFlags>) -> Option<vk::Format> {
+        first_supported_format(formats, usage.into(), |format| self.format_properties(format))
+    }
+
+    /// Number of invocations in a subgroup, the unit that shader cores execute in lockstep.
+    pub fn subgroup_size(&self) -> u32 {
+        let mut subgroup_properties = vk::PhysicalDeviceSubgroupProperties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default()
+            .push_next(&mut subgroup_properties);
+        unsafe {
+            self.inner.instance_dep.instance.get_physical_device_properties2(self.inner.physical_device, &mut properties2);
+        }
+        subgroup_properties.subgroup_size
+    }
+
+    /// Device extensions that were enabled at creation, including the ones requested by plugins.
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.inner.enabled_extensions
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.inner.enabled_extensions.iter().any(|extension| extension.as_c_str() == name)
+    }
+
+    /// Vendor, device and driver version of the GPU this device was created on.
+    pub fn driver_info(&self) -> &DriverInfo {
+        &self.inner.driver_info
+    }
+
+    /// Workarounds from the [`WORKAROUNDS`](crate::vulkan::WORKAROUNDS) registry that apply to this device's driver.
+    pub fn workarounds(&self) -> &[Workaround] {
+        &self.inner.workarounds
+    }
+
+    pub fn has_workaround(&self, workaround: Workaround) -> bool {
+        self.inner.workarounds.contains(&workaround)
+    }
+
+    /// Name any Vulkan object of this device with `VK_EXT_debug_utils`, shown in validation messages and
+    /// graphics debuggers like RenderDoc. The wrappers have their own `set_debug_name`.
+    pub fn set_debug_name(&self, handle: impl vk::Handle, name: &str) {
+        self.inner.set_debug_name(handle, name);
+    }
+
+    /// Structured log of notable per-frame events of this device, shared with its instance.
+    pub fn event_log(&self) -> &EventLog {
+        &self.inner.event_log
+    }
+
+    /// Number of distinct pipeline layouts that are currently shared between pipelines.
+    pub fn pipeline_layout_count(&self) -> usize {
+        self.inner.pipeline_layouts.len()
+    }
+
+    pub fn object_stats(&self) -> ObjectStats {
+        let counters = &self.inner.counters;
+        ObjectStats {
+            command_buffers_created: counters.command_buffers_created.load(Ordering::Relaxed),
+            fences_alive: counters.fences_alive.load(Ordering::Relaxed),
+            fences_in_flight: counters.fences_in_flight.lock().unwrap().len(),
+            semaphores_alive: counters.semaphores_alive.load(Ordering::Relaxed),
+            pending_deletions: self.inner.deletion_queue.len(),
+        }
+    }
+
+    /// Highest sample count up to `requested` that color attachments support, e.g. to fall back from 8x MSAA.
+    pub fn color_sample_count(&self, requested: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        let supported = self.limits().framebuffer_color_sample_counts;
+        let mut count = requested;
+        while count.as_raw() > 1 && !supported.contains(count) {
+            count = vk::SampleCountFlags::from_raw(count.as_raw() >> 1);
+        }
+        count
+    }
+
+    /// Images, buffers, samplers and pipelines of this device that are alive, with their debug names.
+    /// The engine reports the ones still alive at shutdown as leaks.
+    pub fn live_objects(&self) -> LeakReport {
+        self.inner.live_objects.report()
+    }
+
+    /// Keep a resource alive until the frames that are currently in flight or being recorded executed, so its
+    /// handle can be dropped right away. Images and buffers do this by themselves when their last handle is
+    /// dropped, this is for other resources, e.g. pipelines bound without being tracked.
+    /// Resources are released right away while no frames are rendered.
+    pub fn defer_drop(&self, resource: &dyn GpuResource) {
+        self.inner.deletion_queue.defer(resource.reference());
+    }
+
+    pub fn create_semaphore(&self) -> vk::Semaphore {
+        let semaphore = unsafe {
+            self.handle().create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                .expect("Failed to create semaphore")
+        };
+        self.inner.counters.semaphores_alive.fetch_add(1, Ordering::Relaxed);
+        semaphore
+    }
+
+    /// Destroy a semaphore created with [`create_semaphore`](Self::create_semaphore).
+    pub fn destroy_semaphore(&self, semaphore: vk::Semaphore) {
+        unsafe {
+            self.handle().destroy_semaphore(semaphore, None);
+        }
+        self.inner.counters.semaphores_alive.fetch_sub(1, Ordering::Relaxed);
+    }
+
     pub fn get_queue(&self, queue_index: u32) -> Queue {
         unsafe { self.handle().get_device_queue(self.inner.queue_family_index, queue_index) }
     }
 
+    /// Queue of the dedicated compute family, if the device has one.
+    /// Work submitted here can run concurrently with the graphics queue.
+    pub fn get_compute_queue(&self) -> Option<Queue> {
+        self.inner.compute_queue_family_index
+            .map(|index| unsafe { self.handle().get_device_queue(index, 0) })
+    }
+
+    /// Queue of the dedicated transfer family, if the device has one.
+    pub fn get_transfer_queue(&self) -> Option<Queue> {
+        self.inner.transfer_queue_family_index
+            .map(|index| unsafe { self.handle().get_device_queue(index, 0) })
+    }
+
+    /// Queue presenting to the surface, if the device was created with a separate present family.
+    pub fn get_present_queue(&self) -> Option<Queue> {
+        self.inner.present_queue_family_index
+            .map(|index| unsafe { self.handle().get_device_queue(index, 0) })
+    }
+
+    pub fn compute_queue_family_index(&self) -> Option<u32> {
+        self.inner.compute_queue_family_index
+    }
+
+    pub fn transfer_queue_family_index(&self) -> Option<u32> {
+        self.inner.transfer_queue_family_index
+    }
+
+    pub fn present_queue_family_index(&self) -> Option<u32> {
+        self.inner.present_queue_family_index
+    }
+
+    /// The graphics family and the compute, transfer and present families the device created queues on.
+    pub fn queue_families(&self) -> QueueFamilies {
+        let indices = [
+            Some(self.inner.queue_family_index),
+            self.inner.compute_queue_family_index,
+            self.inner.transfer_queue_family_index,
+            self.inner.present_queue_family_index,
+        ];
+        QueueFamilies::new(&indices.into_iter().flatten().collect::<Vec<_>>())
+    }
+
     pub fn wait_idle(&self) {
         unsafe {
             self.handle().device_wait_idle().unwrap();
@@ -114,14 +529,19 @@ impl Device {
                 .wait_for_fences(&fences, true, u64::MAX)
                 .expect("Failed to destroy fence");
         }
+        self.inner.counters.fence_completed(fence);
     }
 
     pub fn get_fence_status(&self, fence: vk::Fence) -> bool {
-        unsafe {
+        let signaled = unsafe {
             self.handle()
                 .get_fence_status(fence)
                 .expect("Failed to destroy fence")
+        };
+        if signaled {
+            self.inner.counters.fence_completed(fence);
         }
+        signaled
     }
 
     pub fn reset_fence(&self, fence: vk::Fence) {
@@ -131,6 +551,7 @@ impl Device {
                 .reset_fences(&fences)
                 .unwrap()
         }
+        self.inner.counters.fence_completed(fence);
     }
 
     pub fn submit_single_time_command(
@@ -146,6 +567,7 @@ impl Device {
             let submits = [submit_info];
             self.handle().queue_submit(queue, &submits, command_buffer.fence()).unwrap();
         }
+        self.inner.counters.fence_submitted(command_buffer.fence());
     }
 
     /// Submit a command buffer for execution
@@ -176,6 +598,33 @@ impl Device {
         let submits = [submit_info];
         let fence = command_buffer.fence();
         unsafe { self.handle().queue_submit(*queue, &submits, fence).unwrap(); }
+        self.inner.counters.fence_submitted(fence);
+    }
+
+    /// Submit a command buffer that waits on and signals any number of semaphores.
+    ///
+    /// - `wait_semaphores` - Semaphores to wait on, with the stages that wait on them.
+    /// - `signal_semaphores` - Semaphores to signal after execution.
+    pub fn submit_command_buffer_with(
+        &self,
+        queue: &Queue,
+        wait_semaphores: &[(vk::Semaphore, PipelineStageFlags)],
+        signal_semaphores: &[vk::Semaphore],
+        command_buffer: &CommandBuffer
+    ) {
+        let command_buffers = [command_buffer.handle()];
+        let (wait_semaphores, wait_dst_stage_masks): (Vec<_>, Vec<_>) = wait_semaphores.iter().copied().unzip();
+
+        let submit_info = vk::SubmitInfo::default()
+            .command_buffers(&command_buffers)
+            .wait_semaphores(&wait_semaphores)
+            .signal_semaphores(signal_semaphores)
+            .wait_dst_stage_mask(&wait_dst_stage_masks);
+
+        let submits = [submit_info];
+        let fence = command_buffer.fence();
+        unsafe { self.handle().queue_submit(*queue, &submits, fence).unwrap(); }
+        self.inner.counters.fence_submitted(fence);
     }
 
     pub fn clone(&self) -> Device {
@@ -185,10 +634,21 @@ impl Device {
     }
 }
 
+/// Find a queue family that supports `required` but none of `excluded`.
+fn find_dedicated_queue_family(families: &[vk::QueueFamilyProperties], required: vk::QueueFlags, excluded: vk::QueueFlags) -> Option<u32> {
+    families.iter()
+        .position(|family| {
+            family.queue_count > 0
+                && family.queue_flags.contains(required)
+                && !family.queue_flags.intersects(excluded)
+        })
+        .map(|index| index as u32)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ash::Entry;
-    use crate::vulkan::{CommandBuffer, CommandPool};
+    use crate::vulkan::{CommandBuffer, CommandBufferPool, CommandPool, DescriptorSetLayout, PipelineLayout};
     use super::*;
 
     #[test]
@@ -199,6 +659,27 @@ mod tests {
         let _device = Device::new(&instance, physical_device, queue_family_index);
     }
 
+    #[test]
+    fn query_driver_info() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+        assert!(!device.driver_info().device_name.is_empty());
+    }
+
+    #[test]
+    fn fall_back_to_supported_sample_count() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+
+        let supported = device.limits().framebuffer_color_sample_counts;
+        assert!(supported.contains(device.color_sample_count(vk::SampleCountFlags::TYPE_64)));
+        assert_eq!(device.color_sample_count(vk::SampleCountFlags::TYPE_1), vk::SampleCountFlags::TYPE_1);
+    }
+
     #[test]
     fn submit_command_buffer() {
         let entry = Entry::linked();
@@ -217,4 +698,152 @@ mod tests {
         device.wait_for_fence(cmd.fence());
     }
 
+    #[test]
+    fn count_fences_in_flight() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+
+        let pool = CommandPool::new(&device, queue_family_index);
+        let mut cmd = CommandBuffer::new(&device, &pool, false);
+        cmd.begin();
+        cmd.end();
+
+        device.submit_single_time_command(device.get_queue(0), &cmd);
+        assert_eq!(device.object_stats().fences_in_flight, 1);
+        device.wait_for_fence(cmd.fence());
+        assert_eq!(device.object_stats().fences_in_flight, 0);
+    }
+
+    #[test]
+    fn debug_names_and_labels() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+
+        let pool = CommandPool::new(&device, queue_family_index);
+        let mut cmd = CommandBuffer::new(&device, &pool, false);
+        cmd.set_debug_name("labeled commands");
+        device.set_debug_name(pool.handle(), "labeled pool");
+
+        cmd.begin();
+        cmd.begin_label("outer", [1.0, 0.0, 0.0, 1.0]);
+        cmd.begin_label("inner", [0.0; 4]);
+        cmd.end_label();
+        cmd.end_label();
+        cmd.end();
+
+        device.submit_single_time_command(device.get_queue(0), &cmd);
+        device.wait_for_fence(cmd.fence());
+    }
+
+    #[test]
+    fn recycle_command_buffers() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+        let pool = CommandPool::new(&device, queue_family_index);
+        let queue = device.get_queue(0);
+
+        let mut command_buffers = CommandBufferPool::new();
+        for _ in 0..3 {
+            let mut cmd = command_buffers.acquire(&device, &pool);
+            cmd.begin();
+            cmd.end();
+            device.submit_single_time_command(queue, &cmd);
+            device.wait_for_fence(cmd.fence());
+            command_buffers.recycle(&device, cmd);
+        }
+
+        assert_eq!(device.object_stats().command_buffers_created, 1);
+        assert_eq!(command_buffers.free_count(), 1);
+    }
+
+    struct TimelinePlugin;
+
+    impl DevicePlugin for TimelinePlugin {
+        fn extensions(&self) -> Vec<&'static CStr> {
+            vec![ash::khr::timeline_semaphore::NAME, c"VK_CEN_unsupported_extension"]
+        }
+
+        fn features(&self, chain: &mut FeatureChain) {
+            chain.push(vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true));
+        }
+    }
+
+    #[test]
+    fn create_device_with_plugins() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let plugins: Vec<Box<dyn DevicePlugin>> = vec![Box::new(TimelinePlugin)];
+        let device = Device::with_plugins(&instance, physical_device, queue_family_index, &plugins);
+
+        assert!(device.is_extension_enabled(ash::khr::timeline_semaphore::NAME));
+        assert!(device.is_extension_enabled(ash::khr::swapchain::NAME));
+        assert!(!device.is_extension_enabled(c"VK_CEN_unsupported_extension"));
+    }
+
+    #[test]
+    fn dedicated_queue_families() {
+        let families = [
+            vk::QueueFamilyProperties { queue_flags: vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, queue_count: 1, ..Default::default() },
+            vk::QueueFamilyProperties { queue_flags: vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, queue_count: 2, ..Default::default() },
+            vk::QueueFamilyProperties { queue_flags: vk::QueueFlags::TRANSFER, queue_count: 1, ..Default::default() },
+        ];
+        assert_eq!(find_dedicated_queue_family(&families, vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS), Some(1));
+        assert_eq!(find_dedicated_queue_family(&families, vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE), Some(2));
+        assert_eq!(find_dedicated_queue_family(&families[..1], vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS), None);
+    }
+
+    #[test]
+    fn get_dedicated_queues() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+
+        assert_eq!(device.get_compute_queue().is_some(), device.compute_queue_family_index().is_some());
+        assert_eq!(device.get_transfer_queue().is_some(), device.transfer_queue_family_index().is_some());
+        assert_ne!(device.compute_queue_family_index(), Some(queue_family_index));
+    }
+
+    #[test]
+    fn share_pipeline_layouts() {
+        let entry = Entry::linked();
+        let instance = Instance::new(&entry, None);
+        let (physical_device, queue_family_index) = instance.create_physical_device_headless();
+        let device = Device::new(&instance, physical_device, queue_family_index);
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        ];
+        let set_layout = DescriptorSetLayout::new(&device, &bindings);
+        let push_constants = [
+            vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .size(16)
+        ];
+
+        let a = PipelineLayout::new(&device, &[set_layout.clone()], &push_constants);
+        let b = PipelineLayout::new(&device, &[set_layout.clone()], &push_constants);
+        let c = PipelineLayout::new(&device, &[set_layout.clone()], &[]);
+
+        assert_eq!(a.handle(), b.handle());
+        assert_ne!(a.handle(), c.handle());
+        assert_eq!(device.pipeline_layout_count(), 2);
+
+        drop(a);
+        drop(b);
+        drop(c);
+        assert_eq!(device.pipeline_layout_count(), 0);
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,270 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::mem::size_of;
+use ash::vk;
+use crate::vulkan::{FeatureChain, Instance};
+
+/// Extensions and features an application needs from the device, see [`Device::with_config`](crate::vulkan::Device::with_config).
+///
+/// ```ignore
+/// let config = DeviceConfig::default()
+///     .require_extension(ash::khr::ray_query::NAME)
+///     .core_features(vk::PhysicalDeviceFeatures { shader_int64: vk::TRUE, ..Default::default() })
+///     .feature(vk::PhysicalDeviceVulkan12Features::default().buffer_device_address(true));
+/// ```
+#[derive(Default)]
+pub struct DeviceConfig {
+    /// Device creation fails when the device doesn't support one of these.
+    pub required_extensions: Vec<CString>,
+    /// Skipped when not supported, check [`Device::is_extension_enabled`](crate::vulkan::Device::is_extension_enabled).
+    pub optional_extensions: Vec<CString>,
+    /// Core features enabled in addition to the ones cen needs. Device creation fails when one is not supported.
+    pub features: vk::PhysicalDeviceFeatures,
+    chain: Vec<Box<dyn Fn(&mut FeatureChain)>>,
+}
+
+/// Extensions and features for common workloads, see [`DeviceConfig::profile`].
+///
+/// The profiles push their feature structs onto the chain, so don't push the same structs again, e.g. with the
+/// [`DescriptorIndexingPlugin`](crate::graphics::materials::DescriptorIndexingPlugin), which `Compute` covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeatureProfile {
+    /// Only what cen needs itself.
+    #[default]
+    Minimal,
+    /// Bindless descriptor indexing, buffer device addresses, timeline semaphores and synchronization2.
+    Compute,
+    /// `Compute` with acceleration structures, ray queries and ray tracing pipelines.
+    RaytracingPreview,
+}
+
+impl DeviceConfig {
+
+    /// A config requiring the extensions and features of a profile, which can be extended like any other.
+    ///
+    /// ```ignore
+    /// let config = DeviceConfig::profile(FeatureProfile::Compute)
+    ///     .optional_extension(ash::ext::memory_budget::NAME);
+    /// ```
+    pub fn profile(profile: FeatureProfile) -> Self {
+        let config = DeviceConfig::default();
+        if profile == FeatureProfile::Minimal {
+            return config;
+        }
+
+        let config = config
+            .require_extension(ash::khr::synchronization2::NAME)
+            .feature(vk::PhysicalDeviceDescriptorIndexingFeatures::default()
+                .shader_sampled_image_array_non_uniform_indexing(true)
+                .shader_storage_buffer_array_non_uniform_indexing(true)
+                .shader_storage_image_array_non_uniform_indexing(true)
+                .descriptor_binding_sampled_image_update_after_bind(true)
+                .descriptor_binding_storage_image_update_after_bind(true)
+                .descriptor_binding_storage_buffer_update_after_bind(true)
+                .descriptor_binding_update_unused_while_pending(true)
+                .descriptor_binding_partially_bound(true)
+                .runtime_descriptor_array(true))
+            .feature(vk::PhysicalDeviceBufferDeviceAddressFeatures::default().buffer_device_address(true))
+            .feature(vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true))
+            .feature(vk::PhysicalDeviceSynchronization2Features::default().synchronization2(true));
+        if profile == FeatureProfile::Compute {
+            return config;
+        }
+
+        config
+            .require_extension(ash::khr::deferred_host_operations::NAME)
+            .require_extension(ash::khr::acceleration_structure::NAME)
+            .require_extension(ash::khr::ray_query::NAME)
+            .require_extension(ash::khr::ray_tracing_pipeline::NAME)
+            .feature(vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default().acceleration_structure(true))
+            .feature(vk::PhysicalDeviceRayQueryFeaturesKHR::default().ray_query(true))
+            .feature(vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default().ray_tracing_pipeline(true))
+    }
+
+    pub fn require_extension(mut self, name: &CStr) -> Self {
+        self.required_extensions.push(name.to_owned());
+        self
+    }
+
+    pub fn optional_extension(mut self, name: &CStr) -> Self {
+        self.optional_extensions.push(name.to_owned());
+        self
+    }
+
+    pub fn core_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Push a feature struct onto the pNext chain of the device create info, see [`FeatureChain`].
+    /// Device creation fails with `ERROR_FEATURE_NOT_PRESENT` when one of its features is not supported.
+    pub fn feature<T: vk::ExtendsDeviceCreateInfo + Clone + 'static>(mut self, feature: T) -> Self {
+        self.chain.push(Box::new(move |chain| chain.push(feature.clone())));
+        self
+    }
+
+    pub(crate) fn push_features(&self, chain: &mut FeatureChain) {
+        for push in &self.chain {
+            push(chain);
+        }
+    }
+
+    /// Check that the device supports the required extensions and core features.
+    pub fn check(&self, instance: &Instance, physical_device: vk::PhysicalDevice) -> Result<(), DeviceError> {
+        let (supported_extensions, supported_features, properties) = unsafe {
+            (
+                instance.handle()
+                    .enumerate_device_extension_properties(physical_device)
+                    .expect("Failed to enumerate device extensions"),
+                instance.handle().get_physical_device_features(physical_device),
+                instance.handle().get_physical_device_properties(physical_device),
+            )
+        };
+
+        let extensions: Vec<CString> = self.required_extensions.iter()
+            .filter(|extension| {
+                !supported_extensions.iter()
+                    .any(|properties| properties.extension_name_as_c_str().is_ok_and(|name| name == extension.as_c_str()))
+            })
+            .cloned()
+            .collect();
+        let features = missing_features(&self.features, &supported_features);
+
+        if extensions.is_empty() && features.is_empty() {
+            return Ok(());
+        }
+        Err(DeviceError::Unsupported {
+            device: properties.device_name_as_c_str().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            extensions,
+            features,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceError {
+    /// The device doesn't support required extensions or core features.
+    Unsupported { device: String, extensions: Vec<CString>, features: Vec<&'static str> },
+    /// Device creation failed, e.g. with `ERROR_FEATURE_NOT_PRESENT` for an unsupported feature of the chain.
+    Creation(vk::Result),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::Unsupported { device, extensions, features } => {
+                write!(f, "{} doesn't support", device)?;
+                if !extensions.is_empty() {
+                    let names: Vec<_> = extensions.iter().map(|name| name.to_string_lossy()).collect();
+                    write!(f, " the required extensions {}", names.join(", "))?;
+                }
+                if !features.is_empty() {
+                    if !extensions.is_empty() {
+                        write!(f, " and")?;
+                    }
+                    write!(f, " the required features {}", features.join(", "))?;
+                }
+                Ok(())
+            }
+            DeviceError::Creation(result) => write!(f, "Failed to create device: {}", result),
+        }
+    }
+}
+
+/// Members of `vk::PhysicalDeviceFeatures`, in declaration order.
+const CORE_FEATURE_NAMES: [&str; 55] = [
+    "robustBufferAccess", "fullDrawIndexUint32", "imageCubeArray", "independentBlend", "geometryShader",
+    "tessellationShader", "sampleRateShading", "dualSrcBlend", "logicOp", "multiDrawIndirect",
+    "drawIndirectFirstInstance", "depthClamp", "depthBiasClamp", "fillModeNonSolid", "depthBounds", "wideLines",
+    "largePoints", "alphaToOne", "multiViewport", "samplerAnisotropy", "textureCompressionETC2",
+    "textureCompressionASTC_LDR", "textureCompressionBC", "occlusionQueryPrecise", "pipelineStatisticsQuery",
+    "vertexPipelineStoresAndAtomics", "fragmentStoresAndAtomics", "shaderTessellationAndGeometryPointSize",
+    "shaderImageGatherExtended", "shaderStorageImageExtendedFormats", "shaderStorageImageMultisample",
+    "shaderStorageImageReadWithoutFormat", "shaderStorageImageWriteWithoutFormat",
+    "shaderUniformBufferArrayDynamicIndexing", "shaderSampledImageArrayDynamicIndexing",
+    "shaderStorageBufferArrayDynamicIndexing", "shaderStorageImageArrayDynamicIndexing", "shaderClipDistance",
+    "shaderCullDistance", "shaderFloat64", "shaderInt64", "shaderInt16", "shaderResourceResidency",
+    "shaderResourceMinLod", "sparseBinding", "sparseResidencyBuffer", "sparseResidencyImage2D",
+    "sparseResidencyImage3D", "sparseResidency2Samples", "sparseResidency4Samples", "sparseResidency8Samples",
+    "sparseResidency16Samples", "sparseResidencyAliased", "variableMultisampleRate", "inheritedQueries",
+];
+
+// The features are read as an array of flags
+const _: () = assert!(size_of::<vk::PhysicalDeviceFeatures>() == CORE_FEATURE_NAMES.len() * size_of::<vk::Bool32>());
+
+fn feature_flags(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32; CORE_FEATURE_NAMES.len()] {
+    unsafe { &*(features as *const vk::PhysicalDeviceFeatures as *const [vk::Bool32; CORE_FEATURE_NAMES.len()]) }
+}
+
+/// Features enabled in either set.
+pub(crate) fn merge_features(a: &vk::PhysicalDeviceFeatures, b: &vk::PhysicalDeviceFeatures) -> vk::PhysicalDeviceFeatures {
+    let mut merged = *a;
+    let flags = unsafe { &mut *(&mut merged as *mut vk::PhysicalDeviceFeatures as *mut [vk::Bool32; CORE_FEATURE_NAMES.len()]) };
+    for (flag, other) in flags.iter_mut().zip(feature_flags(b)) {
+        *flag |= *other;
+    }
+    merged
+}
+
+/// Names of the requested features that are not supported.
+pub(crate) fn missing_features(requested: &vk::PhysicalDeviceFeatures, supported: &vk::PhysicalDeviceFeatures) -> Vec<&'static str> {
+    feature_flags(requested).iter()
+        .zip(feature_flags(supported))
+        .zip(CORE_FEATURE_NAMES)
+        .filter(|((requested, supported), _)| **requested != vk::FALSE && **supported == vk::FALSE)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_missing_features() {
+        let requested = vk::PhysicalDeviceFeatures {
+            shader_int64: vk::TRUE,
+            sampler_anisotropy: vk::TRUE,
+            inherited_queries: vk::TRUE,
+            ..Default::default()
+        };
+        let supported = vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: vk::TRUE,
+            ..Default::default()
+        };
+        assert_eq!(missing_features(&requested, &supported), vec!["shaderInt64", "inheritedQueries"]);
+
+        let merged = merge_features(&supported, &vk::PhysicalDeviceFeatures { shader_clip_distance: vk::TRUE, ..Default::default() });
+        assert_eq!(merged.sampler_anisotropy, vk::TRUE);
+        assert_eq!(merged.shader_clip_distance, vk::TRUE);
+        assert!(missing_features(&supported, &merged).is_empty());
+
+        let error = DeviceError::Unsupported {
+            device: "llvmpipe".to_string(),
+            extensions: vec![ash::khr::ray_query::NAME.to_owned()],
+            features: missing_features(&requested, &supported),
+        };
+        assert_eq!(error.to_string(), "llvmpipe doesn't support the required extensions VK_KHR_ray_query and the required features shaderInt64, inheritedQueries");
+    }
+
+    #[test]
+    fn feature_profiles() {
+        let chain = |config: &DeviceConfig| {
+            let mut chain = FeatureChain::default();
+            config.push_features(&mut chain);
+            chain
+        };
+
+        let minimal = DeviceConfig::profile(FeatureProfile::Minimal);
+        assert!(minimal.required_extensions.is_empty());
+        assert!(chain(&minimal).is_empty());
+
+        let compute = chain(&DeviceConfig::profile(FeatureProfile::Compute));
+        assert!(compute.get::<vk::PhysicalDeviceBufferDeviceAddressFeatures<'static>>().is_some_and(|features| features.buffer_device_address == vk::TRUE));
+        assert!(compute.get::<vk::PhysicalDeviceRayQueryFeaturesKHR<'static>>().is_none());
+
+        let raytracing = DeviceConfig::profile(FeatureProfile::RaytracingPreview);
+        assert!(raytracing.required_extensions.iter().any(|name| name.as_c_str() == ash::khr::acceleration_structure::NAME));
+        assert_eq!(chain(&raytracing).len(), compute.len() + 3);
+    }
+}
@@ -0,0 +1,60 @@
+use std::any::Any;
+use std::ffi::{c_void, CStr};
+use ash::vk;
+
+/// Extends device creation with extensions and features that cen doesn't enable by itself.
+pub trait DevicePlugin {
+    /// Device extensions to enable. When the device doesn't support one of them the whole plugin is skipped,
+    /// including its [features](Self::features), check
+    /// [`Device::is_extension_enabled`](crate::vulkan::Device::is_extension_enabled) afterwards.
+    fn extensions(&self) -> Vec<&'static CStr> {
+        vec![]
+    }
+
+    /// Push feature structs onto the pNext chain of the device create info.
+    fn features(&self, _chain: &mut FeatureChain) {}
+}
+
+/// Owned pNext chain of structs extending `vk::DeviceCreateInfo`.
+/// Each struct type may only be pushed once, including the ones cen enables itself:
+/// `PhysicalDeviceDynamicRenderingFeatures`.
+#[derive(Default)]
+pub struct FeatureChain {
+    // Boxed so the addresses stay stable while the chain is linked
+    structs: Vec<(Box<dyn Any>, *mut vk::BaseOutStructure<'static>)>,
+}
+
+impl FeatureChain {
+
+    pub fn push<T: vk::ExtendsDeviceCreateInfo + 'static>(&mut self, feature: T) {
+        let mut feature = Box::new(feature);
+        let ptr = feature.as_mut() as *mut T as *mut vk::BaseOutStructure<'static>;
+        self.structs.push((feature, ptr));
+    }
+
+    /// Get a struct that was pushed onto the chain.
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.structs.iter().find_map(|(feature, _)| feature.downcast_ref::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.structs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.structs.is_empty()
+    }
+
+    /// Link all structs and append `next` at the end. Returns the head of the chain.
+    ///
+    /// # Safety
+    /// The returned pointer is only valid as long as the chain is alive and not modified.
+    pub(crate) unsafe fn link(&mut self, next: *const c_void) -> *const c_void {
+        let mut p_next = next as *mut vk::BaseOutStructure<'static>;
+        for (_, ptr) in self.structs.iter().rev() {
+            (**ptr).p_next = p_next;
+            p_next = *ptr;
+        }
+        p_next as *const c_void
+    }
+}
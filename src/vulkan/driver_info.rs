@@ -0,0 +1,224 @@
+use std::fmt;
+use std::ops::Range;
+use ash::vk;
+use crate::vulkan::Instance;
+
+/// Vendor of a GPU, from the PCI vendor id of its properties.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Vendor {
+    Amd,
+    Apple,
+    Arm,
+    ImgTec,
+    Intel,
+    Mesa,
+    Nvidia,
+    Qualcomm,
+    Other(u32),
+}
+
+impl Vendor {
+    pub fn from_id(vendor_id: u32) -> Self {
+        match vendor_id {
+            0x1002 => Vendor::Amd,
+            0x106B => Vendor::Apple,
+            0x13B5 => Vendor::Arm,
+            0x1010 => Vendor::ImgTec,
+            0x8086 => Vendor::Intel,
+            0x10005 => Vendor::Mesa,
+            0x10DE => Vendor::Nvidia,
+            0x5143 => Vendor::Qualcomm,
+            id => Vendor::Other(id),
+        }
+    }
+}
+
+/// Driver version, decoded from the vendor specific packing of `driverVersion`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl DriverVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Lowest and highest possible versions, to build open ranges.
+    pub const MIN: Self = Self::new(0, 0, 0);
+    pub const MAX: Self = Self::new(u32::MAX, u32::MAX, u32::MAX);
+
+    pub fn decode(vendor: Vendor, driver_id: vk::DriverId, version: u32) -> Self {
+        match (vendor, driver_id) {
+            (_, vk::DriverId::MOLTENVK) => Self::new(version / 10000, version / 100 % 100, version % 100),
+            (Vendor::Nvidia, vk::DriverId::NVIDIA_PROPRIETARY) => Self::new(version >> 22, (version >> 14) & 0xff, (version >> 6) & 0xff),
+            (Vendor::Intel, vk::DriverId::INTEL_PROPRIETARY_WINDOWS) => Self::new(version >> 14, version & 0x3fff, 0),
+            _ => Self::new(
+                vk::api_version_major(version),
+                vk::api_version_minor(version),
+                vk::api_version_patch(version)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Identification of the GPU and driver a device was created on, see [`Device::driver_info`](crate::vulkan::Device::driver_info).
+#[derive(Clone, Debug)]
+pub struct DriverInfo {
+    pub vendor: Vendor,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub device_name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub driver_id: vk::DriverId,
+    pub driver_name: String,
+    /// Free form driver description, e.g. the build of Mesa.
+    pub driver_info: String,
+    pub driver_version: DriverVersion,
+    pub api_version: u32,
+}
+
+impl DriverInfo {
+    pub fn query(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut driver_properties = vk::PhysicalDeviceDriverProperties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default()
+            .push_next(&mut driver_properties);
+        unsafe {
+            instance.handle().get_physical_device_properties2(physical_device, &mut properties2);
+        }
+        let properties = properties2.properties;
+
+        let vendor = Vendor::from_id(properties.vendor_id);
+        let to_string = |name: Result<&std::ffi::CStr, _>| name
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Self {
+            vendor,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            device_name: to_string(properties.device_name_as_c_str()),
+            device_type: properties.device_type,
+            driver_id: driver_properties.driver_id,
+            driver_name: to_string(driver_properties.driver_name_as_c_str()),
+            driver_info: to_string(driver_properties.driver_info_as_c_str()),
+            driver_version: DriverVersion::decode(vendor, driver_properties.driver_id, properties.driver_version),
+            api_version: properties.api_version,
+        }
+    }
+}
+
+impl fmt::Display for DriverInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?}), {} {}", self.device_name, self.vendor, self.driver_name, self.driver_version)?;
+        if !self.driver_info.is_empty() {
+            write!(f, " ({})", self.driver_info)?;
+        }
+        Ok(())
+    }
+}
+
+/// A platform quirk the engine works around, enabled per device from [`WORKAROUNDS`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Workaround {
+    /// Copies to swapchain images don't synchronize properly with presentation,
+    /// [`CommandBuffer::copy_image`](crate::vulkan::CommandBuffer::copy_image) records a blit instead.
+    BlitToSwapchain,
+}
+
+/// Matches drivers that need a [`Workaround`], every field that is set has to match.
+pub struct WorkaroundRule {
+    pub workaround: Workaround,
+    pub vendor: Option<Vendor>,
+    pub driver_id: Option<vk::DriverId>,
+    /// Affected driver versions, the end is exclusive.
+    pub versions: Range<DriverVersion>,
+    pub reason: &'static str,
+}
+
+impl WorkaroundRule {
+    pub fn matches(&self, info: &DriverInfo) -> bool {
+        self.vendor.is_none_or(|vendor| vendor == info.vendor)
+            && self.driver_id.is_none_or(|driver_id| driver_id == info.driver_id)
+            && self.versions.contains(&info.driver_version)
+    }
+}
+
+/// Known platform quirks and the drivers they apply to.
+pub const WORKAROUNDS: &[WorkaroundRule] = &[
+    WorkaroundRule {
+        workaround: Workaround::BlitToSwapchain,
+        vendor: None,
+        driver_id: Some(vk::DriverId::MOLTENVK),
+        versions: DriverVersion::MIN..DriverVersion::MAX,
+        reason: "image copies to the swapchain are not synchronized with presentation on MoltenVK",
+    },
+];
+
+/// The workarounds from [`WORKAROUNDS`] that apply to a driver, without duplicates.
+pub fn workarounds_for(info: &DriverInfo) -> Vec<&'static WorkaroundRule> {
+    let mut rules: Vec<&'static WorkaroundRule> = Vec::new();
+    for rule in WORKAROUNDS.iter().filter(|rule| rule.matches(info)) {
+        if !rules.iter().any(|enabled| enabled.workaround == rule.workaround) {
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driver(vendor: Vendor, driver_id: vk::DriverId, driver_version: DriverVersion) -> DriverInfo {
+        DriverInfo {
+            vendor,
+            vendor_id: 0,
+            device_id: 0,
+            device_name: String::new(),
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            driver_id,
+            driver_name: String::new(),
+            driver_info: String::new(),
+            driver_version,
+            api_version: 0,
+        }
+    }
+
+    #[test]
+    fn decode_driver_versions() {
+        assert_eq!(DriverVersion::decode(Vendor::Nvidia, vk::DriverId::NVIDIA_PROPRIETARY, (535 << 22) | (104 << 14) | (5 << 6)), DriverVersion::new(535, 104, 5));
+        assert_eq!(DriverVersion::decode(Vendor::Apple, vk::DriverId::MOLTENVK, 10211), DriverVersion::new(1, 2, 11));
+        assert_eq!(DriverVersion::decode(Vendor::Intel, vk::DriverId::INTEL_PROPRIETARY_WINDOWS, (101 << 14) | 5122), DriverVersion::new(101, 5122, 0));
+        assert_eq!(DriverVersion::decode(Vendor::Amd, vk::DriverId::MESA_RADV, vk::make_api_version(0, 24, 1, 3)), DriverVersion::new(24, 1, 3));
+    }
+
+    #[test]
+    fn match_workarounds() {
+        let molten_vk = driver(Vendor::Apple, vk::DriverId::MOLTENVK, DriverVersion::new(1, 2, 11));
+        let workarounds = workarounds_for(&molten_vk);
+        assert!(workarounds.iter().any(|rule| rule.workaround == Workaround::BlitToSwapchain));
+
+        let radv = driver(Vendor::Amd, vk::DriverId::MESA_RADV, DriverVersion::new(24, 1, 3));
+        assert!(workarounds_for(&radv).is_empty());
+
+        let rule = WorkaroundRule {
+            workaround: Workaround::BlitToSwapchain,
+            vendor: Some(Vendor::Amd),
+            driver_id: None,
+            versions: DriverVersion::new(24, 0, 0)..DriverVersion::new(24, 1, 0),
+            reason: "",
+        };
+        assert!(!rule.matches(&radv));
+        assert!(rule.matches(&driver(Vendor::Amd, vk::DriverId::MESA_RADV, DriverVersion::new(24, 0, 9))));
+    }
+}
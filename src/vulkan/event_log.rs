@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(feature = "event-log")]
+use std::time::Instant;
+#[cfg(feature = "event-log")]
+use std::fs::File;
+#[cfg(feature = "event-log")]
+use std::io::{BufWriter, Write};
+use log::warn;
+#[cfg(feature = "event-log")]
+use log::error;
+use crate::vulkan::LOG_TARGET;
+
+/// Notable events that happen while rendering a frame.
+#[derive(Clone, Debug)]
+pub enum FrameEvent {
+    SwapchainRecreated { width: u32, height: u32, image_count: u32 },
+    PipelineReloaded { path: PathBuf, error: Option<String> },
+    AllocationFailed { name: String, size: u64, error: String },
+    AcquireStall { timeout: Duration },
+    /// Warnings of the validation layer, and allocations still alive when the allocator is destroyed. Recording
+    /// one logs it as well.
+    Warning { message: String },
+}
+
+impl FrameEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FrameEvent::SwapchainRecreated { .. } => "swapchain_recreated",
+            FrameEvent::PipelineReloaded { .. } => "pipeline_reloaded",
+            FrameEvent::AllocationFailed { .. } => "allocation_failed",
+            FrameEvent::AcquireStall { .. } => "acquire_stall",
+            FrameEvent::Warning { .. } => "warning",
+        }
+    }
+
+    /// Serialize the event as a single line of JSON.
+    pub fn to_json(&self, frame: u64, time: f64) -> String {
+        let fields = match self {
+            FrameEvent::SwapchainRecreated { width, height, image_count } => {
+                format!(r#""width":{},"height":{},"image_count":{}"#, width, height, image_count)
+            }
+            FrameEvent::PipelineReloaded { path, error } => {
+                let error = error.as_ref().map_or("null".to_string(), |e| json_string(e));
+                format!(r#""path":{},"success":{},"error":{}"#, json_string(&path.to_string_lossy()), error == "null", error)
+            }
+            FrameEvent::AllocationFailed { name, size, error } => {
+                format!(r#""name":{},"size":{},"error":{}"#, json_string(name), size, json_string(error))
+            }
+            FrameEvent::AcquireStall { timeout } => {
+                format!(r#""timeout_ms":{:.3}"#, timeout.as_secs_f64() * 1000.0)
+            }
+            FrameEvent::Warning { message } => {
+                format!(r#""message":{}"#, json_string(message))
+            }
+        };
+        format!(r#"{{"frame":{},"time":{:.6},"event":"{}",{}}}"#, frame, time, self.kind(), fields)
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+struct EventLogState {
+    frame: u64,
+    #[cfg(feature = "event-log")]
+    start_time: Instant,
+    #[cfg(feature = "event-log")]
+    writer: Option<BufWriter<File>>,
+}
+
+/// Structured log of per-frame events, written as JSON lines for post-mortem analysis.
+/// Requires the `event-log` feature; without it, recording is a no-op.
+#[derive(Clone)]
+pub struct EventLog {
+    inner: Arc<Mutex<EventLogState>>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(EventLogState {
+                frame: 0,
+                #[cfg(feature = "event-log")]
+                start_time: Instant::now(),
+                #[cfg(feature = "event-log")]
+                writer: None,
+            }))
+        }
+    }
+}
+
+impl EventLog {
+
+    /// Start writing events to the given file. The file is truncated.
+    pub fn open(&self, path: &Path) {
+        #[cfg(feature = "event-log")]
+        {
+            match File::create(path) {
+                Ok(file) => self.inner.lock().unwrap().writer = Some(BufWriter::new(file)),
+                Err(e) => error!(target: LOG_TARGET, "Failed to create event log {:?}: {}", path, e),
+            }
+        }
+        #[cfg(not(feature = "event-log"))]
+        warn!(target: LOG_TARGET, "Event log {:?} requested, but cen was built without the `event-log` feature", path);
+    }
+
+    #[cfg(feature = "event-log")]
+    pub fn is_enabled(&self) -> bool {
+        self.inner.lock().unwrap().writer.is_some()
+    }
+
+    #[cfg(not(feature = "event-log"))]
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    /// Advance to the next frame. Returns the new frame id.
+    pub fn begin_frame(&self) -> u64 {
+        let mut state = self.inner.lock().unwrap();
+        state.frame += 1;
+        state.frame
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.inner.lock().unwrap().frame
+    }
+
+    pub fn record(&self, event: FrameEvent) {
+        if let FrameEvent::Warning { message } = &event {
+            warn!(target: LOG_TARGET, "{}", message);
+        }
+
+        #[cfg(feature = "event-log")]
+        {
+            let mut state = self.inner.lock().unwrap();
+            let line = event.to_json(state.frame, state.start_time.elapsed().as_secs_f64());
+            if let Some(writer) = state.writer.as_mut() {
+                // Flush every event, the log should survive a crash
+                if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+                    error!(target: LOG_TARGET, "Failed to write event log: {}", e);
+                    state.writer = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_events() {
+        let event = FrameEvent::PipelineReloaded {
+            path: "shaders/a.comp".into(),
+            error: Some("line 1: \"x\" undeclared".into()),
+        };
+        assert_eq!(
+            event.to_json(3, 0.5),
+            r#"{"frame":3,"time":0.500000,"event":"pipeline_reloaded","path":"shaders/a.comp","success":false,"error":"line 1: \"x\" undeclared"}"#
+        );
+
+        let event = FrameEvent::SwapchainRecreated { width: 800, height: 600, image_count: 3 };
+        assert_eq!(
+            event.to_json(1, 0.0),
+            r#"{"frame":1,"time":0.000000,"event":"swapchain_recreated","width":800,"height":600,"image_count":3}"#
+        );
+    }
+}
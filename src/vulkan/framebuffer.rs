@@ -2,7 +2,7 @@ use std::sync::Arc;
 use ash::vk;
 use ash::vk::Extent2D;
 use log::trace;
-use crate::vulkan::{Device, RenderPass, LOG_TARGET};
+use crate::vulkan::{Device, RenderPass, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 
 pub struct FramebufferInner {
@@ -16,7 +16,7 @@ impl Drop for FramebufferInner {
         unsafe {
             let framebuffer_addr = format!("{:?}", self.framebuffer);
             self.device_dep.device.destroy_framebuffer(self.framebuffer, None);
-            trace!(target: LOG_TARGET, "Destroyed framebuffer: [{}]", framebuffer_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed framebuffer: [{}]", framebuffer_addr);
         }
     }
 }
@@ -41,7 +41,7 @@ impl Framebuffer {
                 .expect("Failed to create framebuffer")
         };
 
-        trace!(target: LOG_TARGET, "Created framebuffer: {:?}", framebuffer);
+        trace!(target: OBJECTS_LOG_TARGET, "Created framebuffer: {:?}", framebuffer);
 
         let framebuffer_inner = FramebufferInner {
             device_dep: device.inner.clone(),
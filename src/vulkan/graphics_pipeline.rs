@@ -6,27 +6,110 @@ use std::sync::Arc;
 use ash::vk;
 use ash::vk::{PushConstantRange, SampleCountFlags};
 use log::trace;
-use crate::vulkan::{DescriptorSetLayout, Device, GpuHandle, Pipeline, LOG_TARGET};
+use crate::vulkan::{DescriptorSetLayout, Device, GpuHandle, Pipeline, PipelineLayout, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
+use crate::vulkan::image::format_aspect;
 use crate::vulkan::memory::GpuResource;
-use crate::vulkan::pipeline::{create_shader_module, load_shader_code, PipelineErr};
+use crate::vulkan::pipeline::{create_shader_module, PipelineErr, ShaderSource, SpecializationConstant, SpecializationData};
+
+/// Color blending of all color attachments.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BlendMode {
+    /// Overwrite the attachment.
+    Opaque,
+    /// Blend with straight alpha.
+    #[default]
+    Alpha,
+    /// Blend with premultiplied alpha.
+    Premultiplied,
+    /// Add to the attachment, weighted by alpha.
+    Additive,
+}
+
+impl BlendMode {
+    fn attachment_state(&self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .alpha_blend_op(vk::BlendOp::ADD);
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => return state.blend_enable(false),
+            BlendMode::Alpha => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+            BlendMode::Premultiplied => (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE, vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+        };
+        state
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+    }
+}
 
 #[derive(Clone)]
 pub struct GraphicsPipelineConfig {
     pub color_formats: Vec<vk::Format>,
     pub depth_format: Option<vk::Format>,
+    /// Samples of the attachments, multisampled passes resolve with [`ColorAttachment::resolve`](crate::vulkan::ColorAttachment::resolve).
     pub sample_count: SampleCountFlags,
-    pub vertex_shader_source: PathBuf,
-    pub fragment_shader_source: PathBuf,
+    pub vertex_shader_source: ShaderSource,
+    pub fragment_shader_source: ShaderSource,
     pub descriptor_set_layouts: Vec<DescriptorSetLayout>,
     pub push_constant_ranges: Vec<PushConstantRange>,
-    pub macros: HashMap<String, String>
+    pub macros: HashMap<String, String>,
+    /// Values of specialization constants by `constant_id`, for both stages.
+    pub specialization_constants: HashMap<u32, SpecializationConstant>,
+    /// Vertex buffers the pipeline reads, bound with [`CommandBuffer::bind_vertex_buffers`](crate::vulkan::CommandBuffer::bind_vertex_buffers).
+    /// Empty for pipelines that generate their vertices in the vertex shader.
+    pub vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    /// Vertex shader inputs, each read from one of the `vertex_bindings`.
+    pub vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    pub topology: vk::PrimitiveTopology,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub blend: BlendMode,
+    /// Test fragments against the depth attachment with `depth_compare_op`.
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    /// Constant and slope-scaled bias added to the depth of fragments, e.g. against acne in shadow maps.
+    pub depth_bias: Option<(f32, f32)>,
+}
+
+impl Default for GraphicsPipelineConfig {
+    fn default() -> Self {
+        Self {
+            color_formats: vec![],
+            depth_format: None,
+            sample_count: SampleCountFlags::TYPE_1,
+            vertex_shader_source: "".into(),
+            fragment_shader_source: "".into(),
+            descriptor_set_layouts: vec![],
+            push_constant_ranges: vec![],
+            macros: HashMap::new(),
+            specialization_constants: HashMap::new(),
+            vertex_bindings: vec![],
+            vertex_attributes: vec![],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            blend: BlendMode::Alpha,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::LESS,
+            depth_bias: None,
+        }
+    }
 }
 
 pub struct GraphicsPipelineInner {
-    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline_layout: PipelineLayout,
     pub graphics_pipeline: vk::Pipeline,
     pub device_dep: Arc<DeviceInner>,
+    includes: Vec<PathBuf>,
 }
 
 impl Drop for GraphicsPipelineInner {
@@ -34,8 +117,8 @@ impl Drop for GraphicsPipelineInner {
         unsafe {
             let graphics_pipeline_addr = format!("{:?}", self.graphics_pipeline);
             self.device_dep.device.destroy_pipeline(self.graphics_pipeline, None);
-            self.device_dep.device.destroy_pipeline_layout(self.pipeline_layout, None);
-            trace!(target: LOG_TARGET, "Destroyed graphics pipeline: [{}]", graphics_pipeline_addr);
+            self.device_dep.live_objects.destroyed(self.graphics_pipeline);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed graphics pipeline: [{}]", graphics_pipeline_addr);
         }
     }
 }
@@ -57,7 +140,7 @@ impl Pipeline for GraphicsPipeline {
     }
 
     fn layout(&self) -> vk::PipelineLayout {
-        self.inner.pipeline_layout
+        self.inner.pipeline_layout.handle()
     }
 
     fn resource(&self) -> &dyn GpuResource {
@@ -77,21 +160,36 @@ impl GraphicsPipeline {
         device: &Device,
         config: GraphicsPipelineConfig
     ) -> Result<Self, PipelineErr> {
-
-        // Dynamic rendering
+        trace_scope!(
+            "compile_pipeline",
+            vertex_shader = ?config.vertex_shader_source.path(),
+            fragment_shader = ?config.fragment_shader_source.path()
+        );
+
+        // Dynamic rendering, formats with stencil are bound as the stencil attachment as well
+        let depth_format = config.depth_format.unwrap_or(vk::Format::UNDEFINED);
+        let stencil_format = if format_aspect(depth_format).contains(vk::ImageAspectFlags::STENCIL) { depth_format } else { vk::Format::UNDEFINED };
         let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::default()
             .color_attachment_formats(&config.color_formats)
-            .depth_attachment_format(config.depth_format.unwrap_or(vk::Format::UNDEFINED));
+            .depth_attachment_format(depth_format)
+            .stencil_attachment_format(stencil_format);
 
         // Shaders
-        let vertex_shader_code = load_shader_code(config.vertex_shader_source, &config.macros)?;
-        let fragment_shader_code = load_shader_code(config.fragment_shader_source, &config.macros)?;
+        let (vertex_shader_code, mut includes) = config.vertex_shader_source.load_with_includes(&config.macros, &[])?;
+        let (fragment_shader_code, fragment_includes) = config.fragment_shader_source.load_with_includes(&config.macros, &[])?;
+        for include in fragment_includes {
+            if !includes.contains(&include) {
+                includes.push(include);
+            }
+        }
 
         let vertex_shader_module = create_shader_module(device.handle(), vertex_shader_code.to_vec());
         let fragment_shader_module = create_shader_module(device.handle(), fragment_shader_code.to_vec());
 
         let binding = CString::new("main").unwrap();
-        let shader_stages = [
+        let specialization = SpecializationData::new(&config.specialization_constants);
+        let specialization_info = specialization.info();
+        let mut shader_stages = [
             // Vertex shader
             vk::PipelineShaderStageCreateInfo::default()
                 .stage(vk::ShaderStageFlags::VERTEX)
@@ -103,6 +201,9 @@ impl GraphicsPipeline {
                 .module(fragment_shader_module)
                 .name(binding.as_c_str())
         ];
+        if let Some(info) = &specialization_info {
+            shader_stages = shader_stages.map(|stage| stage.specialization_info(info));
+        }
 
         // Multisample
         let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::default()
@@ -126,31 +227,28 @@ impl GraphicsPipeline {
             .scissors(&scissors);
 
         // Vertex input
-        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::default();
+        let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&config.vertex_bindings)
+            .vertex_attribute_descriptions(&config.vertex_attributes);
 
         // Input assembly
         let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::default()
             .primitive_restart_enable(false)
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+            .topology(config.topology);
 
         // Rasterization
         let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-            .polygon_mode(vk::PolygonMode::FILL)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE)
+            .polygon_mode(config.polygon_mode)
+            .cull_mode(config.cull_mode)
+            .front_face(config.front_face)
+            .depth_bias_enable(config.depth_bias.is_some())
+            .depth_bias_constant_factor(config.depth_bias.map_or(0.0, |(constant, _)| constant))
+            .depth_bias_slope_factor(config.depth_bias.map_or(0.0, |(_, slope)| slope))
             .line_width(1.0);
 
         // Color blending
-        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
-            .blend_enable(true)
-            .color_write_mask(vk::ColorComponentFlags::RGBA)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-            .alpha_blend_op(vk::BlendOp::ADD);
-        let color_blend_attachment_states = [color_blend_attachment_state];
+        // One state per color attachment
+        let color_blend_attachment_states = vec![config.blend.attachment_state(); config.color_formats.len()];
 
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
             .logic_op_enable(false)
@@ -160,26 +258,17 @@ impl GraphicsPipeline {
 
         // Depth stencil
         let depth_stencil_state_create_info = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(false)
-            .depth_write_enable(false)
-            .depth_compare_op(vk::CompareOp::ALWAYS)
+            .depth_test_enable(config.depth_test)
+            .depth_write_enable(config.depth_write)
+            .depth_compare_op(config.depth_compare_op)
             .depth_bounds_test_enable(false)
             .stencil_test_enable(false);
 
         let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::default()
             .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
 
-        // Layout
-        let desc_layouts = config.descriptor_set_layouts
-            .iter().map(|layout| layout.handle()).collect::<Vec<_>>();
-        let create_info = vk::PipelineLayoutCreateInfo::default()
-            .push_constant_ranges(&config.push_constant_ranges)
-            .set_layouts(&desc_layouts);
-        let pipeline_layout = unsafe {
-            device.handle()
-                .create_pipeline_layout(&create_info, None)
-                .expect("Failed to create pipeline layout")
-        };
+        // Layout, shared with other pipelines that use the same descriptor set layouts and push constants
+        let pipeline_layout = PipelineLayout::new(device, &config.descriptor_set_layouts, &config.push_constant_ranges);
 
         // pipeline
         let graphics_pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
@@ -193,15 +282,16 @@ impl GraphicsPipeline {
             .rasterization_state(&rasterization_state)
             .depth_stencil_state(&depth_stencil_state_create_info)
             .dynamic_state(&dynamic_state_create_info)
-            .layout(pipeline_layout);
+            .layout(pipeline_layout.handle());
 
         let graphics_pipeline = unsafe {
             device.handle()
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[graphics_pipeline_create_info], None)
+                .create_graphics_pipelines(device.inner.pipeline_cache, &[graphics_pipeline_create_info], None)
                 .expect("Failed to create graphics pipeline")[0]
         };
 
-        trace!(target: LOG_TARGET, "Created graphics pipeline: [{:?}]", graphics_pipeline);
+        trace!(target: OBJECTS_LOG_TARGET, "Created graphics pipeline: [{:?}]", graphics_pipeline);
+        device.inner.live_objects.created(graphics_pipeline);
 
         unsafe { device.handle().destroy_shader_module(fragment_shader_module, None); }
         unsafe { device.handle().destroy_shader_module(vertex_shader_module, None); }
@@ -209,11 +299,21 @@ impl GraphicsPipeline {
         let pipeline_inner = GraphicsPipelineInner {
             pipeline_layout,
             graphics_pipeline,
-            device_dep: device.inner.clone()
+            device_dep: device.inner.clone(),
+            includes,
         };
 
         Ok(Self {
             inner: Arc::new(pipeline_inner)
         })
     }
+    /// Name the pipeline in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.graphics_pipeline, name);
+    }
+
+    /// Files the shaders included when they were compiled, which the pipeline store watches for changes.
+    pub fn includes(&self) -> &[PathBuf] {
+        &self.inner.includes
+    }
 }
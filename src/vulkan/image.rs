@@ -1,14 +1,20 @@
 use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use ash::vk;
 use ash::vk::{ComponentMapping, DescriptorImageInfo, Extent2D, ImageAspectFlags, ImageLayout, ImageView, Sampler};
 use gpu_allocator::MemoryLocation;
 use gpu_allocator::vulkan::{Allocation, AllocationScheme};
 use log::{trace};
-use crate::vulkan::{Allocator, Device, LOG_TARGET};
+use crate::vulkan::{Allocator, Device, FrameEvent, QueueFamilies, SamplerConfig, OBJECTS_LOG_TARGET};
 use crate::vulkan::allocator::AllocatorInner;
 use crate::vulkan::device::DeviceInner;
+use crate::vulkan::live_objects::object_key;
 use crate::vulkan::memory::GpuResource;
+use crate::vulkan::sampler::create_sampler;
+use crate::vulkan::types::FORMATS;
 
 #[derive(Copy, Clone)]
 pub struct ImageConfig {
@@ -17,17 +23,105 @@ pub struct ImageConfig {
     pub image_usage_flags: vk::ImageUsageFlags,
     pub image_create_flags: vk::ImageCreateFlags,
     pub image_view_create_flags: vk::ImageViewCreateFlags,
+    /// `CONCURRENT` shares the image between all queue families of the device while
+    /// [`queue_families`](Self::queue_families) is left at its default, which decides the sharing mode otherwise.
+    #[deprecated(note = "the sharing mode follows `queue_families`, see `ImageConfig::concurrent`")]
     pub sharing_mode: vk::SharingMode,
+    /// Families sharing the image, which is `CONCURRENT` with two or more of them, see [`concurrent`](Self::concurrent).
+    pub queue_families: QueueFamilies,
     pub initial_layout: vk::ImageLayout,
     pub array_layers: u32,
     pub mip_levels: u32,
     pub image_type: vk::ImageType,
     pub format: vk::Format,
     pub view_format: Option<vk::Format>,
-    pub filter: vk::Filter
+    pub tiling: vk::ImageTiling,
+    /// The sampler created along with the image, see [`ImageTrait::sampler`].
+    pub sampler: SamplerConfig,
+    /// Filter of the sampler, replaces the filter of [`sampler`](Self::sampler) unless it's `NEAREST`.
+    #[deprecated(note = "use `sampler`, e.g. `SamplerConfig::default().filter(filter)`")]
+    pub filter: vk::Filter,
+}
+
+impl ImageConfig {
+    /// A 2D image, e.g. `ImageConfig::new(width, height, Format::Rgba8Unorm, ImageUsage::STORAGE)`.
+    pub fn new(width: u32, height: u32, format: impl Into<vk::Format>, usage: impl Into<vk::ImageUsageFlags>) -> Self {
+        Self {
+            extent: vk::Extent3D { width, height, depth: 1 },
+            format: format.into(),
+            image_usage_flags: usage.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A depth attachment, e.g. `ImageConfig::depth(width, height, Format::D32Float)`. Add `SAMPLED` to the usage
+    /// to read the depth in later passes.
+    pub fn depth(width: u32, height: u32, format: impl Into<vk::Format>) -> Self {
+        Self::new(width, height, format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+    }
+
+    /// A 3D image, e.g. a volume.
+    pub fn new_3d(width: u32, height: u32, depth: u32, format: impl Into<vk::Format>, usage: impl Into<vk::ImageUsageFlags>) -> Self {
+        Self {
+            extent: vk::Extent3D { width, height, depth },
+            image_type: vk::ImageType::TYPE_3D,
+            ..Self::new(width, height, format, usage)
+        }
+    }
+
+    /// A 2D array image with `layers` layers.
+    pub fn array(width: u32, height: u32, layers: u32, format: impl Into<vk::Format>, usage: impl Into<vk::ImageUsageFlags>) -> Self {
+        Self {
+            array_layers: layers,
+            ..Self::new(width, height, format, usage)
+        }
+    }
+
+    /// A cubemap, with the faces as its six layers in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn cube(size: u32, format: impl Into<vk::Format>, usage: impl Into<vk::ImageUsageFlags>) -> Self {
+        Self {
+            array_layers: 6,
+            image_create_flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            ..Self::new(size, size, format, usage)
+        }
+    }
+
+    pub fn filter(mut self, filter: impl Into<vk::Filter>) -> Self {
+        self.sampler = self.sampler.filter(filter);
+        self
+    }
+
+    pub fn sampler(mut self, sampler: SamplerConfig) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// The sampler of the image, with the deprecated `filter` applied.
+    #[allow(deprecated)]
+    pub(crate) fn sampler_config(&self) -> SamplerConfig {
+        with_filter(self.sampler, self.filter)
+    }
+
+    /// Share the image between queue families without ownership transfers, e.g.
+    /// `config.concurrent(QueueFamilies::all(&device))`. Stays exclusive when only one family is given.
+    pub fn concurrent(mut self, queue_families: QueueFamilies) -> Self {
+        self.queue_families = queue_families;
+        self
+    }
+
+    /// Families sharing the image, `device_families` when only the deprecated `sharing_mode` asks for `CONCURRENT`.
+    #[allow(deprecated)]
+    fn sharing_families(&self, device_families: QueueFamilies) -> QueueFamilies {
+        if self.sharing_mode == vk::SharingMode::CONCURRENT && self.queue_families == QueueFamilies::default() {
+            device_families
+        } else {
+            self.queue_families
+        }
+    }
 }
 
 impl Default for ImageConfig {
+    #[allow(deprecated)]
     fn default() -> Self {
         ImageConfig {
             extent: vk::Extent3D { width: 0, height: 0, depth: 1 },
@@ -36,13 +130,179 @@ impl Default for ImageConfig {
             image_view_create_flags: vk::ImageViewCreateFlags::empty(),
             samples: vk::SampleCountFlags::TYPE_1,
             sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_families: QueueFamilies::default(),
             initial_layout: vk::ImageLayout::UNDEFINED,
             array_layers: 1,
             mip_levels: 1,
             image_type: vk::ImageType::TYPE_2D,
             format: vk::Format::R8G8B8A8_UNORM,
             view_format: None,
-            filter: vk::Filter::NEAREST
+            tiling: vk::ImageTiling::OPTIMAL,
+            sampler: SamplerConfig::default(),
+            filter: vk::Filter::NEAREST,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    /// The device can't use the format with the usage and tiling of the image. `alternatives` are common formats of
+    /// the same aspect that support them.
+    UnsupportedFormat {
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        tiling: vk::ImageTiling,
+        missing: vk::FormatFeatureFlags,
+        alternatives: Vec<vk::Format>,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::UnsupportedFormat { format, usage, tiling, missing, alternatives } => {
+                write!(f, "Format {:?} with {:?} tiling does not support the {:?} usage, the device lacks {:?}",
+                    format, tiling, usage, missing)?;
+                if alternatives.is_empty() {
+                    write!(f, ", no common format supports it either")
+                } else {
+                    let alternatives: Vec<_> = alternatives.iter().map(|format| format!("{:?}", format)).collect();
+                    write!(f, ", supported alternatives: {}", alternatives.join(", "))
+                }
+            }
+        }
+    }
+}
+
+/// Color formats of storage images, in order of preference. Each has a GLSL image format qualifier, see
+/// [`glsl_image_format`].
+pub(crate) const STORAGE_COLOR_FORMATS: [vk::Format; 3] = [
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+];
+
+/// Image format qualifier of the format in GLSL, e.g. `rgba8`.
+pub(crate) fn glsl_image_format(format: vk::Format) -> Option<&'static str> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM => Some("rgba8"),
+        vk::Format::R16G16B16A16_SFLOAT => Some("rgba16f"),
+        vk::Format::R32G32B32A32_SFLOAT => Some("rgba32f"),
+        _ => None,
+    }
+}
+
+/// Bytes per texel of uncompressed formats with a single aspect, `None` for block compressed and combined
+/// depth/stencil formats, or formats that are not listed.
+pub fn texel_size(format: vk::Format) -> Option<vk::DeviceSize> {
+    use vk::Format as F;
+    let size = match format {
+        F::R8_UNORM | F::R8_SNORM | F::R8_UINT | F::R8_SINT | F::R8_SRGB | F::S8_UINT => 1,
+        F::R8G8_UNORM | F::R8G8_SNORM | F::R8G8_UINT | F::R8G8_SINT | F::R8G8_SRGB
+        | F::R16_UNORM | F::R16_SNORM | F::R16_UINT | F::R16_SINT | F::R16_SFLOAT | F::D16_UNORM => 2,
+        F::R8G8B8_UNORM | F::R8G8B8_SRGB | F::B8G8R8_UNORM | F::B8G8R8_SRGB => 3,
+        F::R8G8B8A8_UNORM | F::R8G8B8A8_SNORM | F::R8G8B8A8_UINT | F::R8G8B8A8_SINT | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM | F::B8G8R8A8_SRGB | F::A2B10G10R10_UNORM_PACK32 | F::A2R10G10B10_UNORM_PACK32
+        | F::B10G11R11_UFLOAT_PACK32 | F::E5B9G9R9_UFLOAT_PACK32
+        | F::R16G16_UNORM | F::R16G16_SNORM | F::R16G16_UINT | F::R16G16_SINT | F::R16G16_SFLOAT
+        | F::R32_UINT | F::R32_SINT | F::R32_SFLOAT | F::D32_SFLOAT | F::X8_D24_UNORM_PACK32 => 4,
+        F::R16G16B16_UNORM | F::R16G16B16_SFLOAT => 6,
+        F::R16G16B16A16_UNORM | F::R16G16B16A16_SNORM | F::R16G16B16A16_UINT | F::R16G16B16A16_SINT
+        | F::R16G16B16A16_SFLOAT | F::R32G32_UINT | F::R32G32_SINT | F::R32G32_SFLOAT => 8,
+        F::R32G32B32_UINT | F::R32G32B32_SINT | F::R32G32B32_SFLOAT => 12,
+        F::R32G32B32A32_UINT | F::R32G32B32A32_SINT | F::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    };
+    Some(size)
+}
+
+/// First of the formats whose optimal tiling supports the usage, with `properties` of the device.
+pub(crate) fn first_supported_format(formats: &[vk::Format], usage: vk::ImageUsageFlags, properties: impl Fn(vk::Format) -> vk::FormatProperties) -> Option<vk::Format> {
+    let required = usage_features(usage);
+    formats.iter().copied().find(|format| properties(*format).optimal_tiling_features.contains(required))
+}
+
+/// Format features an image of the usage needs.
+fn usage_features(usage: vk::ImageUsageFlags) -> vk::FormatFeatureFlags {
+    const FEATURES: [(vk::ImageUsageFlags, vk::FormatFeatureFlags); 6] = [
+        (vk::ImageUsageFlags::TRANSFER_SRC, vk::FormatFeatureFlags::TRANSFER_SRC),
+        (vk::ImageUsageFlags::TRANSFER_DST, vk::FormatFeatureFlags::TRANSFER_DST),
+        (vk::ImageUsageFlags::SAMPLED, vk::FormatFeatureFlags::SAMPLED_IMAGE),
+        (vk::ImageUsageFlags::STORAGE, vk::FormatFeatureFlags::STORAGE_IMAGE),
+        (vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::FormatFeatureFlags::COLOR_ATTACHMENT),
+        (vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT),
+    ];
+    FEATURES.iter()
+        .filter(|(flag, _)| usage.contains(*flag))
+        .fold(vk::FormatFeatureFlags::empty(), |features, (_, feature)| features | *feature)
+}
+
+/// Check the format supports the usage and tiling of the image, with `properties` of the device.
+fn check_format(config: &ImageConfig, properties: impl Fn(vk::Format) -> vk::FormatProperties) -> Result<(), ImageError> {
+    let required = usage_features(config.image_usage_flags);
+    let supported = |format: vk::Format| {
+        let properties = properties(format);
+        match config.tiling {
+            vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+            _ => properties.optimal_tiling_features,
+        }
+    };
+
+    let missing = required & !supported(config.format);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let alternatives = FORMATS.iter()
+        .map(|(_, format)| *format)
+        .filter(|format| format_aspect(*format) == format_aspect(config.format) && supported(*format).contains(required))
+        .collect();
+    Err(ImageError::UnsupportedFormat {
+        format: config.format,
+        usage: config.image_usage_flags,
+        tiling: config.tiling,
+        missing,
+        alternatives,
+    })
+}
+
+/// Description of an image that was created outside of cen, e.g. by a video decoder or through interop.
+#[derive(Copy, Clone)]
+pub struct ExternalImageConfig {
+    pub extent: vk::Extent3D,
+    pub format: vk::Format,
+    pub image_type: vk::ImageType,
+    pub image_usage_flags: vk::ImageUsageFlags,
+    /// The layout the image is in at the moment of import.
+    pub layout: vk::ImageLayout,
+    pub aspect_mask: ImageAspectFlags,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub sampler: SamplerConfig,
+    /// Filter of the sampler, replaces the filter of [`sampler`](Self::sampler) unless it's `NEAREST`.
+    #[deprecated(note = "use `sampler`, e.g. `SamplerConfig::default().filter(filter)`")]
+    pub filter: vk::Filter,
+    /// Take ownership of the image, destroying it once the last reference is dropped.
+    pub adopt: bool,
+    /// Memory backing the image. Freed alongside the image when it is adopted.
+    pub memory: Option<vk::DeviceMemory>,
+}
+
+impl Default for ExternalImageConfig {
+    #[allow(deprecated)]
+    fn default() -> Self {
+        ExternalImageConfig {
+            extent: vk::Extent3D { width: 0, height: 0, depth: 1 },
+            format: vk::Format::R8G8B8A8_UNORM,
+            image_type: vk::ImageType::TYPE_2D,
+            image_usage_flags: vk::ImageUsageFlags::empty(),
+            layout: vk::ImageLayout::UNDEFINED,
+            aspect_mask: ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+            sampler: SamplerConfig::default(),
+            filter: vk::Filter::NEAREST,
+            adopt: false,
+            memory: None,
         }
     }
 }
@@ -53,10 +313,103 @@ pub trait ImageTrait: GpuResource {
     fn sampler(&self) -> vk::Sampler;
     fn width(&self) -> u32;
     fn height(&self) -> u32;
+    fn depth(&self) -> u32 {
+        1
+    }
     fn extent(&self) -> Extent2D {
         Extent2D { width: self.width(), height: self.height() }
     }
     fn binding(&self, layout: vk::ImageLayout) -> vk::DescriptorImageInfo;
+    fn aspect_mask(&self) -> ImageAspectFlags {
+        ImageAspectFlags::COLOR
+    }
+    fn mip_levels(&self) -> u32 {
+        1
+    }
+    fn array_layers(&self) -> u32 {
+        1
+    }
+    fn samples(&self) -> vk::SampleCountFlags {
+        vk::SampleCountFlags::TYPE_1
+    }
+    /// Epoch of the swapchain the image belongs to or is sized for, `None` if it is independent of the swapchain.
+    /// See [`CommandBuffer::is_stale`](crate::vulkan::CommandBuffer::is_stale).
+    fn swapchain_epoch(&self) -> Option<u64> {
+        None
+    }
+    /// Whether the image is owned by a swapchain and presented.
+    fn is_swapchain_image(&self) -> bool {
+        false
+    }
+    /// The full subresource range of the image, used for barriers and clears.
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: self.aspect_mask(),
+            base_mip_level: 0,
+            level_count: self.mip_levels(),
+            base_array_layer: 0,
+            layer_count: self.array_layers(),
+        }
+    }
+}
+
+/// The sampler with the filter of the deprecated `filter` fields, which only replaces the filter when changed.
+fn with_filter(sampler: SamplerConfig, filter: vk::Filter) -> SamplerConfig {
+    if filter == vk::Filter::NEAREST { sampler } else { sampler.filter(filter) }
+}
+
+/// Aspects of the format, `COLOR` for anything that isn't a depth or stencil format.
+pub(crate) fn format_aspect(format: vk::Format) -> ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT =>
+            ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL,
+        vk::Format::S8_UINT => ImageAspectFlags::STENCIL,
+        _ => ImageAspectFlags::COLOR,
+    }
+}
+
+/// Aspect of the views of an image. Views used in descriptors can only cover one of depth and stencil, so they
+/// see the depth.
+fn view_aspect(aspect_mask: ImageAspectFlags) -> ImageAspectFlags {
+    if aspect_mask.contains(ImageAspectFlags::DEPTH) { ImageAspectFlags::DEPTH } else { aspect_mask }
+}
+
+/// Whether shaders sample the format as floats, i.e. a color format that isn't an integer format.
+pub(crate) fn is_float_sampleable(format: vk::Format) -> bool {
+    use vk::Format as F;
+    let integer = matches!(format,
+        F::R8_UINT | F::R8_SINT | F::R8G8_UINT | F::R8G8_SINT | F::R8G8B8_UINT | F::R8G8B8_SINT
+        | F::R8G8B8A8_UINT | F::R8G8B8A8_SINT | F::B8G8R8A8_UINT | F::B8G8R8A8_SINT
+        | F::A8B8G8R8_UINT_PACK32 | F::A8B8G8R8_SINT_PACK32 | F::A2B10G10R10_UINT_PACK32 | F::A2R10G10B10_UINT_PACK32
+        | F::R16_UINT | F::R16_SINT | F::R16G16_UINT | F::R16G16_SINT | F::R16G16B16_UINT | F::R16G16B16_SINT
+        | F::R16G16B16A16_UINT | F::R16G16B16A16_SINT
+        | F::R32_UINT | F::R32_SINT | F::R32G32_UINT | F::R32G32_SINT | F::R32G32B32_UINT | F::R32G32B32_SINT
+        | F::R32G32B32A32_UINT | F::R32G32B32A32_SINT
+        | F::R64_UINT | F::R64_SINT | F::R64G64_UINT | F::R64G64_SINT | F::R64G64B64_UINT | F::R64G64B64_SINT
+        | F::R64G64B64A64_UINT | F::R64G64B64A64_SINT);
+    !integer && format_aspect(format) == ImageAspectFlags::COLOR
+}
+
+/// View type covering all layers of an image.
+fn view_type(image_type: vk::ImageType, array_layers: u32, flags: vk::ImageCreateFlags) -> vk::ImageViewType {
+    if flags.contains(vk::ImageCreateFlags::CUBE_COMPATIBLE) && array_layers % 6 == 0 {
+        return if array_layers == 6 { vk::ImageViewType::CUBE } else { vk::ImageViewType::CUBE_ARRAY };
+    }
+    match (image_type, array_layers > 1) {
+        (vk::ImageType::TYPE_1D, false) => vk::ImageViewType::TYPE_1D,
+        (vk::ImageType::TYPE_1D, true) => vk::ImageViewType::TYPE_1D_ARRAY,
+        (vk::ImageType::TYPE_3D, _) => vk::ImageViewType::TYPE_3D,
+        (_, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+        _ => vk::ImageViewType::TYPE_2D,
+    }
+}
+
+impl ImageConfig {
+    /// Type of the view the image is created with.
+    pub(crate) fn view_type(&self) -> vk::ImageViewType {
+        view_type(self.image_type, self.array_layers, self.image_create_flags)
+    }
 }
 
 struct ImageInner {
@@ -67,6 +420,14 @@ struct ImageInner {
     pub(crate) sampler: vk::Sampler,
     pub allocation: Mutex<Option<Allocation>>,
     pub config: ImageConfig,
+    aspect_mask: ImageAspectFlags,
+    // Whether cen is responsible for destroying the image handle
+    owned: bool,
+    external_memory: Option<vk::DeviceMemory>,
+    // Swapchain epoch of images sized to match the swapchain, 0 for other images
+    swapchain_epoch: AtomicU64,
+    // Views of single layers, created on first use
+    layer_views: Mutex<HashMap<u32, vk::ImageView>>,
 }
 
 struct SwapchainImageInner {
@@ -75,6 +436,7 @@ struct SwapchainImageInner {
     image_view: vk::ImageView,
     sampler: vk::Sampler,
     extent: vk::Extent2D,
+    swapchain_epoch: AtomicU64,
 }
 
 pub struct Image {
@@ -90,26 +452,69 @@ impl Drop for SwapchainImageInner {
         unsafe {
             self.device_dep.device.destroy_sampler(self.sampler, None);
             self.device_dep.device.destroy_image_view(self.image_view, None);
-            trace!(target: LOG_TARGET, "Destroyed external image data: [{:?}]", self.image);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed external image data: [{:?}]", self.image);
         }
     }
 }
 
 impl Drop for ImageInner {
+    fn drop(&mut self) {
+        // Frames in flight may still use the image without tracking it
+        let destruction = ImageDestruction {
+            device_dep: self.device_dep.clone(),
+            allocator_dep: self.allocator_dep.clone(),
+            image: self.image,
+            image_view: self.image_view,
+            sampler: self.sampler,
+            layer_views: std::mem::take(&mut *self.layer_views.lock().unwrap()),
+            allocation: self.allocation.lock().unwrap().take(),
+            owned: self.owned,
+            external_memory: self.external_memory,
+        };
+        self.device_dep.deletion_queue.defer(Arc::new(destruction));
+    }
+}
+
+/// Handles of a dropped image, destroyed once the frames in flight executed.
+struct ImageDestruction {
+    device_dep: Arc<DeviceInner>,
+    allocator_dep: Option<Arc<Mutex<AllocatorInner>>>,
+    image: vk::Image,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+    layer_views: HashMap<u32, vk::ImageView>,
+    allocation: Option<Allocation>,
+    owned: bool,
+    external_memory: Option<vk::DeviceMemory>,
+}
+
+impl Drop for ImageDestruction {
     fn drop(&mut self) {
         unsafe {
             let image_addr = format!("{:?}", self.image);
             self.device_dep.device.destroy_sampler(self.sampler, None);
             self.device_dep.device.destroy_image_view(self.image_view, None);
+            for view in self.layer_views.values() {
+                self.device_dep.device.destroy_image_view(*view, None);
+            }
+            self.device_dep.live_objects.destroyed(self.image);
 
-            if let Some(allocation) = self.allocation.lock().unwrap().take() {
+            if let Some(allocation) = self.allocation.take() {
                 let memory_addr = format!("{:?}, {:?}", allocation.memory(), allocation.chunk_id());
-                self.allocator_dep.as_ref().expect("").lock().unwrap().allocator.lock().unwrap().free(allocation).unwrap();
-                trace!(target: LOG_TARGET, "Destroyed image memory: [{}]", memory_addr);
+                self.allocator_dep.as_ref().expect("").lock().unwrap().free(allocation).unwrap();
+                trace!(target: OBJECTS_LOG_TARGET, "Destroyed image memory: [{}]", memory_addr);
+            }
+
+            if !self.owned {
+                trace!(target: OBJECTS_LOG_TARGET, "Released external image: [{}]", image_addr);
+                return;
             }
 
             self.device_dep.device.destroy_image(self.image, None);
-            trace!(target: LOG_TARGET, "Destroyed image: [{}]", image_addr);
+            if let Some(memory) = self.external_memory {
+                self.device_dep.device.free_memory(memory, None);
+            }
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed image: [{}]", image_addr);
         }
     }
 }
@@ -129,7 +534,8 @@ impl GpuResource for SwapchainImage {
 impl SwapchainImage {
 
     /**
-     * Wrap an existing Vulkan image
+     * Wrap an existing Vulkan image.
+     * Only the view and sampler are owned, the image itself stays owned by the swapchain.
      */
     pub fn from_raw(device: &Device, image: vk::Image, format: vk::Format, extent: Extent2D) -> SwapchainImage {
         // Image view
@@ -168,14 +574,39 @@ impl SwapchainImage {
                 sampler,
                 device_dep: device.inner.clone(),
                 extent,
+                swapchain_epoch: AtomicU64::new(0),
             })
         }
     }
+
+    pub(crate) fn set_swapchain_epoch(&self, epoch: u64) {
+        self.inner.swapchain_epoch.store(epoch, Ordering::Relaxed);
+    }
 }
 
 impl Image {
 
     pub fn new(device: &Device, allocator: &mut Allocator, config: ImageConfig) -> Self {
+        Self::new_named(device, allocator, config, "Image")
+    }
+
+    /// Create an image whose allocation is named `name` in the reports of the allocator, see
+    /// [`AppConfig::allocator_debug_settings`](crate::app::app::AppConfig::allocator_debug_settings).
+    /// Leak reports show it as well.
+    pub fn new_named(device: &Device, allocator: &mut Allocator, config: ImageConfig, name: &str) -> Self {
+        Self::try_new_named(device, allocator, config, name)
+            .unwrap_or_else(|e| panic!("Failed to create image: {}", e))
+    }
+
+    /// Create an image, or list the formats that would work when the device can't use the format with the usage
+    /// and tiling of the image. Storage support in particular differs between platforms, e.g. on MoltenVK.
+    pub fn try_new(device: &Device, allocator: &mut Allocator, config: ImageConfig) -> Result<Self, ImageError> {
+        Self::try_new_named(device, allocator, config, "Image")
+    }
+
+    pub fn try_new_named(device: &Device, allocator: &mut Allocator, mut config: ImageConfig, name: &str) -> Result<Self, ImageError> {
+        check_format(&config, |format| device.format_properties(format))?;
+        config.queue_families = config.sharing_families(device.queue_families());
 
         // Image
         let image_create_info = vk::ImageCreateInfo::default()
@@ -183,11 +614,13 @@ impl Image {
             .extent(config.extent)
             .samples(config.samples)
             .usage(config.image_usage_flags)
-            .sharing_mode(config.sharing_mode)
+            .sharing_mode(config.queue_families.sharing_mode())
+            .queue_family_indices(config.queue_families.concurrent_indices())
             .initial_layout(config.initial_layout)
             .array_layers(config.array_layers)
             .mip_levels(config.mip_levels)
             .image_type(config.image_type)
+            .tiling(config.tiling)
             .format(config.format);
         let image = unsafe {
             device.handle().create_image(&image_create_info, None)
@@ -196,25 +629,33 @@ impl Image {
 
         // Allocate memory
         let requirements = unsafe { device.handle().get_image_memory_requirements(image) };
-        let allocation = allocator.handle().lock().unwrap()
+        let allocation = allocator.inner.lock().unwrap()
             .allocate(&gpu_allocator::vulkan::AllocationCreateDesc {
-                name: "Image",
+                name,
                 requirements,
                 location: MemoryLocation::GpuOnly,
                 linear: true,
                 allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-            }).unwrap();
+            }).unwrap_or_else(|e| {
+                device.event_log().record(FrameEvent::AllocationFailed {
+                    name: name.to_string(),
+                    size: requirements.size,
+                    error: e.to_string(),
+                });
+                panic!("Failed to allocate image memory: {}", e)
+            });
 
         unsafe {
             device.handle().bind_image_memory(image, allocation.memory(), allocation.offset())
                 .expect("Failed to bind image memory")
         }
 
-        // Image view
+        // Image view, barriers cover all aspects of the format
+        let aspect_mask = format_aspect(config.format);
         let image_view_create_info = vk::ImageViewCreateInfo::default()
             .flags(config.image_view_create_flags)
             .format(config.view_format.unwrap_or(config.format))
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type(config.image_type, config.array_layers, config.image_create_flags))
             .image(image)
             .components(ComponentMapping {
                 r: vk::ComponentSwizzle::R,
@@ -223,29 +664,24 @@ impl Image {
                 a: vk::ComponentSwizzle::A,
             })
             .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: ImageAspectFlags::COLOR,
+                aspect_mask: view_aspect(aspect_mask),
                 base_mip_level: 0,
-                level_count: 1,
+                level_count: config.mip_levels,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: config.array_layers,
             });
         let image_view = unsafe {
             device.handle().create_image_view(&image_view_create_info, None)
                 .expect("Failed to create image view")
         };
 
-        // Sampler
-        let sampler_create_info = vk::SamplerCreateInfo::default()
-            .mag_filter(config.filter)
-            .min_filter(config.filter);
-        let sampler = unsafe {
-            device.handle().create_sampler(&sampler_create_info, None)
-                .expect("Failed to create sampler")
-        };
+        let sampler = create_sampler(device, &config.sampler_config());
 
-        trace!(target: LOG_TARGET, "Created image: [{:?}]", image);
+        trace!(target: OBJECTS_LOG_TARGET, "Created image: [{:?}]", image);
+        device.inner.live_objects.created(image);
+        device.inner.live_objects.named(object_key(image), name);
 
-        Self {
+        Ok(Self {
             inner: Arc::new(ImageInner {
                 image,
                 image_view,
@@ -253,7 +689,78 @@ impl Image {
                 allocation: Mutex::new(Some(allocation)),
                 device_dep: device.inner.clone(),
                 allocator_dep: Some(allocator.inner.clone()),
-                config
+                config,
+                aspect_mask,
+                owned: true,
+                external_memory: None,
+                swapchain_epoch: AtomicU64::new(0),
+                layer_views: Mutex::new(HashMap::new()),
+            })
+        })
+    }
+
+    /**
+     * Import an image created outside of cen.
+     * The image can be used with all barrier and binding helpers. Unless `adopt` is set,
+     * the caller stays responsible for destroying it after the last reference is dropped.
+     */
+    pub fn from_raw(device: &Device, image: vk::Image, external: ExternalImageConfig) -> Self {
+
+        let image_view_create_info = vk::ImageViewCreateInfo::default()
+            .format(external.format)
+            .view_type(view_type(external.image_type, external.array_layers, vk::ImageCreateFlags::empty()))
+            .image(image)
+            .components(ComponentMapping {
+                r: vk::ComponentSwizzle::R,
+                g: vk::ComponentSwizzle::G,
+                b: vk::ComponentSwizzle::B,
+                a: vk::ComponentSwizzle::A,
+            })
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: view_aspect(external.aspect_mask),
+                base_mip_level: 0,
+                level_count: external.mip_levels,
+                base_array_layer: 0,
+                layer_count: external.array_layers,
+            });
+        let image_view = unsafe {
+            device.handle().create_image_view(&image_view_create_info, None)
+                .expect("Failed to create image view")
+        };
+
+        #[allow(deprecated)]
+        let sampler_config = with_filter(external.sampler, external.filter);
+        let sampler = create_sampler(device, &sampler_config);
+
+        let config = ImageConfig {
+            extent: external.extent,
+            image_usage_flags: external.image_usage_flags,
+            initial_layout: external.layout,
+            array_layers: external.array_layers,
+            mip_levels: external.mip_levels,
+            image_type: external.image_type,
+            format: external.format,
+            sampler: sampler_config,
+            ..Default::default()
+        };
+
+        trace!(target: OBJECTS_LOG_TARGET, "Imported external image: [{:?}]", image);
+        device.inner.live_objects.created(image);
+
+        Self {
+            inner: Arc::new(ImageInner {
+                image,
+                image_view,
+                sampler,
+                allocation: Mutex::new(None),
+                device_dep: device.inner.clone(),
+                allocator_dep: None,
+                config,
+                aspect_mask: external.aspect_mask,
+                owned: external.adopt,
+                external_memory: external.memory.filter(|_| external.adopt),
+                swapchain_epoch: AtomicU64::new(0),
+                layer_views: Mutex::new(HashMap::new()),
             })
         }
     }
@@ -261,6 +768,43 @@ impl Image {
     pub fn config(&self) -> ImageConfig {
         self.inner.config
     }
+
+    /// 2D view of the first mip level of a single layer, e.g. to render to a face of a cubemap or to a slice of an
+    /// array. Created on first use and destroyed along with the image.
+    pub fn layer_view(&self, layer: u32) -> ImageView {
+        let config = &self.inner.config;
+        assert!(config.image_type != vk::ImageType::TYPE_3D, "3D images have no layers");
+        assert!(layer < config.array_layers, "Layer {} out of range, the image has {} layers", layer, config.array_layers);
+
+        *self.inner.layer_views.lock().unwrap().entry(layer).or_insert_with(|| {
+            let image_view_create_info = vk::ImageViewCreateInfo::default()
+                .format(config.view_format.unwrap_or(config.format))
+                .view_type(view_type(config.image_type, 1, vk::ImageCreateFlags::empty()))
+                .image(self.inner.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: view_aspect(self.inner.aspect_mask),
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: layer,
+                    layer_count: 1,
+                });
+            unsafe {
+                self.inner.device_dep.device.create_image_view(&image_view_create_info, None)
+                    .expect("Failed to create image view")
+            }
+        })
+    }
+
+    /// Tie the image to a swapchain epoch, for images that are recreated along with the swapchain.
+    pub(crate) fn set_swapchain_epoch(&self, epoch: u64) {
+        self.inner.swapchain_epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    /// Name the image and its view in validation messages and graphics debuggers.
+    pub fn set_debug_name(&self, name: &str) {
+        self.inner.device_dep.set_debug_name(self.inner.image, name);
+        self.inner.device_dep.set_debug_name(self.inner.image_view, &format!("{} view", name));
+    }
 }
 
 impl ImageTrait for Image {
@@ -284,12 +828,36 @@ impl ImageTrait for Image {
         self.inner.config.extent.height
     }
 
+    fn depth(&self) -> u32 {
+        self.inner.config.extent.depth
+    }
+
     fn binding(&self, layout: vk::ImageLayout) -> vk::DescriptorImageInfo {
         vk::DescriptorImageInfo::default()
             .image_layout(layout)
             .image_view(self.inner.image_view)
             .sampler(self.inner.sampler)
     }
+
+    fn aspect_mask(&self) -> ImageAspectFlags {
+        self.inner.aspect_mask
+    }
+
+    fn mip_levels(&self) -> u32 {
+        self.inner.config.mip_levels
+    }
+
+    fn array_layers(&self) -> u32 {
+        self.inner.config.array_layers
+    }
+
+    fn samples(&self) -> vk::SampleCountFlags {
+        self.inner.config.samples
+    }
+
+    fn swapchain_epoch(&self) -> Option<u64> {
+        Some(self.inner.swapchain_epoch.load(Ordering::Relaxed)).filter(|epoch| *epoch != 0)
+    }
 }
 
 impl ImageTrait for SwapchainImage {
@@ -319,4 +887,99 @@ impl ImageTrait for SwapchainImage {
             .image_view(self.inner.image_view)
             .sampler(self.inner.sampler)
     }
+
+    fn swapchain_epoch(&self) -> Option<u64> {
+        Some(self.inner.swapchain_epoch.load(Ordering::Relaxed))
+    }
+
+    fn is_swapchain_image(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_types() {
+        let empty = vk::ImageCreateFlags::empty();
+        assert_eq!(view_type(vk::ImageType::TYPE_2D, 1, empty), vk::ImageViewType::TYPE_2D);
+        assert_eq!(view_type(vk::ImageType::TYPE_2D, 6, empty), vk::ImageViewType::TYPE_2D_ARRAY);
+        assert_eq!(view_type(vk::ImageType::TYPE_3D, 1, empty), vk::ImageViewType::TYPE_3D);
+        assert_eq!(view_type(vk::ImageType::TYPE_1D, 4, empty), vk::ImageViewType::TYPE_1D_ARRAY);
+
+        let cube = vk::ImageCreateFlags::CUBE_COMPATIBLE;
+        assert_eq!(view_type(vk::ImageType::TYPE_2D, 6, cube), vk::ImageViewType::CUBE);
+        assert_eq!(view_type(vk::ImageType::TYPE_2D, 12, cube), vk::ImageViewType::CUBE_ARRAY);
+        assert_eq!(view_type(vk::ImageType::TYPE_2D, 4, cube), vk::ImageViewType::TYPE_2D_ARRAY);
+
+        let config = ImageConfig::cube(64, vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::SAMPLED);
+        assert_eq!(view_type(config.image_type, config.array_layers, config.image_create_flags), vk::ImageViewType::CUBE);
+        let config = ImageConfig::new_3d(8, 8, 8, vk::Format::R8_UNORM, vk::ImageUsageFlags::STORAGE);
+        assert_eq!(config.extent.depth, 8);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_concurrent_sharing() {
+        let device_families = QueueFamilies::new(&[0, 1]);
+        let config = ImageConfig { sharing_mode: vk::SharingMode::CONCURRENT, ..Default::default() };
+        assert_eq!(config.sharing_families(device_families), device_families);
+
+        // The queue families take precedence once set
+        let config = config.concurrent(QueueFamilies::new(&[0, 2]));
+        assert_eq!(config.sharing_families(device_families), QueueFamilies::new(&[0, 2]));
+        assert_eq!(ImageConfig::default().sharing_families(device_families), QueueFamilies::default());
+    }
+
+    #[test]
+    fn depth_aspects() {
+        assert_eq!(format_aspect(vk::Format::R8G8B8A8_UNORM), ImageAspectFlags::COLOR);
+        assert_eq!(format_aspect(vk::Format::D32_SFLOAT), ImageAspectFlags::DEPTH);
+        assert_eq!(format_aspect(vk::Format::D24_UNORM_S8_UINT), ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL);
+        assert_eq!(view_aspect(ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL), ImageAspectFlags::DEPTH);
+        assert_eq!(view_aspect(ImageAspectFlags::STENCIL), ImageAspectFlags::STENCIL);
+    }
+
+    #[test]
+    fn float_sampleable_formats() {
+        assert!(is_float_sampleable(vk::Format::R8G8B8A8_UNORM));
+        assert!(is_float_sampleable(vk::Format::R32_SFLOAT));
+        assert!(!is_float_sampleable(vk::Format::R32_UINT));
+        assert!(!is_float_sampleable(vk::Format::R8G8B8A8_SINT));
+        assert!(!is_float_sampleable(vk::Format::D32_SFLOAT));
+    }
+
+    #[test]
+    fn unsupported_formats_list_alternatives() {
+        // A device without storage support for 8-bit sRGB and any depth format, as on some MoltenVK setups
+        let properties = |format: vk::Format| {
+            let mut features = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST;
+            if format != vk::Format::R8G8B8A8_SRGB && format_aspect(format) == ImageAspectFlags::COLOR {
+                features |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+            }
+            vk::FormatProperties { optimal_tiling_features: features, ..Default::default() }
+        };
+
+        let sampled = ImageConfig::new(4, 4, vk::Format::R8G8B8A8_SRGB, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST);
+        assert!(check_format(&sampled, properties).is_ok());
+
+        let storage = ImageConfig::new(4, 4, vk::Format::R8G8B8A8_SRGB, vk::ImageUsageFlags::STORAGE);
+        let Err(ImageError::UnsupportedFormat { missing, alternatives, .. }) = check_format(&storage, properties) else {
+            panic!("Storage images of the format are unsupported");
+        };
+        assert_eq!(missing, vk::FormatFeatureFlags::STORAGE_IMAGE);
+        assert!(alternatives.contains(&vk::Format::R8G8B8A8_UNORM));
+        assert!(!alternatives.contains(&vk::Format::R8G8B8A8_SRGB) && !alternatives.contains(&vk::Format::D32_SFLOAT));
+
+        // Linear tiling supports nothing
+        let linear = ImageConfig { tiling: vk::ImageTiling::LINEAR, ..sampled };
+        assert!(check_format(&linear, properties).is_err());
+
+        let formats = [vk::Format::R8G8B8A8_SRGB, vk::Format::B8G8R8A8_SRGB, vk::Format::D32_SFLOAT];
+        assert_eq!(first_supported_format(&formats, vk::ImageUsageFlags::STORAGE, properties), Some(vk::Format::B8G8R8A8_SRGB));
+        assert_eq!(first_supported_format(&formats, vk::ImageUsageFlags::SAMPLED, properties), Some(vk::Format::R8G8B8A8_SRGB));
+        assert_eq!(first_supported_format(&formats, vk::ImageUsageFlags::COLOR_ATTACHMENT, properties), None);
+    }
 }
@@ -5,22 +5,78 @@ use std::ffi::{c_char, CStr, CString};
 use std::os::raw::c_void;
 use std::{ptr, vec};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use ash::khr::surface;
 use log::{debug, error, info, warn};
 use crate::vulkan::window_state::WindowState;
-use crate::vulkan::LOG_TARGET;
+use crate::vulkan::{EventLog, FrameEvent, LOG_TARGET};
 use crate::vulkan::surface::Surface;
 
-struct ValidationInfo {
-    required_validation_layers: Vec<CString>,
+/// Additional instance extensions and layers.
+/// Entries that are not available on the system are skipped with a warning.
+#[derive(Clone)]
+pub struct InstanceConfig {
+    pub extensions: Vec<CString>,
+    pub layers: Vec<CString>,
+    /// Enable `VK_LAYER_KHRONOS_validation` when available.
+    pub validation: bool,
+    /// Have the validation layer also report synchronization hazards, through `VK_EXT_validation_features`. Slows
+    /// down submissions considerably, only applies with [`validation`](Self::validation).
+    pub synchronization_validation: bool,
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        Self {
+            extensions: vec![],
+            layers: vec![],
+            validation: cfg!(debug_assertions),
+            synchronization_validation: false,
+        }
+    }
+}
+
+/// Messages of the validation layer since the instance was created, e.g. to fail tests on them, see
+/// [`AppConfig::soak_test`](crate::app::app::AppConfig::soak_test).
+#[derive(Debug, Default)]
+pub struct ValidationCounts {
+    errors: AtomicU64,
+    warnings: AtomicU64,
+}
+
+impl ValidationCounts {
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn warnings(&self) -> u64 {
+        self.warnings.load(Ordering::Relaxed)
+    }
+}
+
+/// State of the debug messenger callback, owned by the instance so it outlives the messenger.
+struct MessengerState {
+    validation_counts: Arc<ValidationCounts>,
+    event_log: EventLog,
 }
 
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
+    // The state of the instance outlives its messenger
+    let state = (p_user_data as *const MessengerState).as_ref();
+    if let Some(state) = state.filter(|_| message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION)) {
+        let counts = &state.validation_counts;
+        if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+            counts.errors.fetch_add(1, Ordering::Relaxed);
+        } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+            counts.warnings.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     let types = match message_type {
         vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "",
         vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "",
@@ -30,7 +86,11 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     let message = CStr::from_ptr((*p_callback_data).p_message).to_str().unwrap();
     match message_severity {
         vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => debug!(target: LOG_TARGET, "{}{}", types, message),
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => warn!(target: LOG_TARGET, "{}{}", types, message),
+        // Recording the warning logs it as well
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => match state {
+            Some(state) => state.event_log.record(FrameEvent::Warning { message: format!("{}{}", types, message) }),
+            None => warn!(target: LOG_TARGET, "{}{}", types, message),
+        },
         vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => error!(target: LOG_TARGET, "{}{}", types, message),
         vk::DebugUtilsMessageSeverityFlagsEXT::INFO => info!(target: LOG_TARGET, "{}{}", types, message),
         _ => warn!("{} {}", types, message),
@@ -41,9 +101,17 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
 
 /// Vulkan instance. The root interface between the application and the graphics driver.
 pub struct InstanceInner {
-    instance: ash::Instance,
+    pub(crate) instance: ash::Instance,
     pub debug_utils: ash::ext::debug_utils::Instance,
     pub debug_utils_messenger: DebugUtilsMessengerEXT,
+    pub enabled_extensions: Vec<CString>,
+    pub enabled_layers: Vec<CString>,
+    pub validation_counts: Arc<ValidationCounts>,
+    /// Shared with the devices of the instance, which record their events in it.
+    pub(crate) event_log: EventLog,
+    // Read by the debug messenger through its user data
+    #[allow(dead_code)]
+    messenger_state: Box<MessengerState>,
 }
 
 impl Drop for InstanceInner {
@@ -56,6 +124,7 @@ impl Drop for InstanceInner {
     }
 }
 
+#[derive(Clone)]
 pub struct Instance {
     pub inner: Arc<InstanceInner>,
 }
@@ -63,6 +132,10 @@ pub struct Instance {
 impl Instance {
 
     pub fn new(entry: &Entry, window: Option<&WindowState>) -> Self {
+        Self::with_config(entry, window, &InstanceConfig::default())
+    }
+
+    pub fn with_config(entry: &Entry, window: Option<&WindowState>, config: &InstanceConfig) -> Self {
         let app_name = CString::new("cen").unwrap();
         let engine_name = CString::new("Cen").unwrap();
         let app_info = vk::ApplicationInfo::default()
@@ -87,14 +160,67 @@ impl Instance {
         }
 
 
-        let validation: ValidationInfo = ValidationInfo {
-            required_validation_layers: vec![
-                CString::new("VK_LAYER_KHRONOS_validation").unwrap()
-            ],
+        // Layers
+        let available_layers = unsafe {
+            entry.enumerate_instance_layer_properties()
+                .expect("Failed to enumerate instance layers")
+        };
+        let mut requested_layers = vec![];
+        if config.validation {
+            requested_layers.push(CString::new("VK_LAYER_KHRONOS_validation").unwrap());
+        }
+        requested_layers.extend(config.layers.iter().cloned());
+
+        let mut enabled_layers: Vec<CString> = vec![];
+        for layer in requested_layers {
+            if enabled_layers.contains(&layer) {
+                continue;
+            }
+            let available = available_layers.iter()
+                .any(|properties| properties.layer_name_as_c_str().is_ok_and(|name| name == layer.as_c_str()));
+            if available {
+                enabled_layers.push(layer);
+            } else {
+                warn!(target: LOG_TARGET, "Skipping unavailable instance layer: {:?}", layer);
+            }
+        }
+
+        // User extensions, either provided by the implementation or by one of the enabled layers
+        let mut available_extensions = unsafe {
+            entry.enumerate_instance_extension_properties(None)
+                .expect("Failed to enumerate instance extensions")
         };
+        for layer in &enabled_layers {
+            if let Ok(properties) = unsafe { entry.enumerate_instance_extension_properties(Some(layer.as_c_str())) } {
+                available_extensions.extend(properties);
+            }
+        }
+        for extension in &config.extensions {
+            if extension_names.iter().any(|name| unsafe { CStr::from_ptr(*name) } == extension.as_c_str()) {
+                continue;
+            }
+            let available = available_extensions.iter()
+                .any(|properties| properties.extension_name_as_c_str().is_ok_and(|name| name == extension.as_c_str()));
+            if available {
+                extension_names.push(extension.as_ptr());
+            } else {
+                warn!(target: LOG_TARGET, "Skipping unavailable instance extension: {:?}", extension);
+            }
+        }
+
+        // Provided by the validation layer
+        let validation_layer = enabled_layers.iter().any(|layer| layer.as_bytes() == b"VK_LAYER_KHRONOS_validation");
+        let synchronization_validation = config.synchronization_validation && validation_layer && available_extensions.iter()
+            .any(|properties| properties.extension_name_as_c_str().is_ok_and(|name| name == ash::ext::validation_features::NAME));
+        if synchronization_validation {
+            if !extension_names.iter().any(|name| unsafe { CStr::from_ptr(*name) } == ash::ext::validation_features::NAME) {
+                extension_names.push(ash::ext::validation_features::NAME.as_ptr());
+            }
+        } else if config.synchronization_validation && validation_layer {
+            warn!(target: LOG_TARGET, "Skipping synchronization validation, {:?} is not available", ash::ext::validation_features::NAME);
+        }
 
-        let c_ptr_validation_layers = validation
-            .required_validation_layers
+        let c_ptr_layers = enabled_layers
             .iter()
             .map(|layer_name| layer_name.as_ptr())
             .collect::<Vec<_>>();
@@ -105,14 +231,16 @@ impl Instance {
             vk::InstanceCreateFlags::default()
         };
 
+        let enabled_validation_features = [vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION];
+        let mut validation_features = vk::ValidationFeaturesEXT::default()
+            .enabled_validation_features(&enabled_validation_features);
         let mut create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
             .enabled_extension_names(&extension_names)
+            .enabled_layer_names(&c_ptr_layers)
             .flags(create_flags);
-
-        #[cfg(debug_assertions)]
-        {
-            create_info = create_info.enabled_layer_names(&c_ptr_validation_layers);
+        if synchronization_validation {
+            create_info = create_info.push_next(&mut validation_features);
         }
 
         let instance: ash::Instance = unsafe {
@@ -121,6 +249,12 @@ impl Instance {
                 .expect("Instance creation error")
         };
 
+        let validation_counts = Arc::new(ValidationCounts::default());
+        let event_log = EventLog::default();
+        let messenger_state = Box::new(MessengerState {
+            validation_counts: validation_counts.clone(),
+            event_log: event_log.clone(),
+        });
         let debug_utils_create_info = vk::DebugUtilsMessengerCreateInfoEXT {
             s_type: vk::StructureType::DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
             p_next: ptr::null(),
@@ -133,7 +267,7 @@ impl Instance {
                 | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                 | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
             pfn_user_callback: Some(vulkan_debug_utils_callback),
-            p_user_data: ptr::null_mut(),
+            p_user_data: &*messenger_state as *const MessengerState as *mut c_void,
             _marker: Default::default(),
         };
 
@@ -142,10 +276,19 @@ impl Instance {
             unsafe { debug_utils.create_debug_utils_messenger(&debug_utils_create_info, None) }
                 .expect("Failed to create debug utils messenger");
 
+        let enabled_extensions = extension_names.iter()
+            .map(|name| unsafe { CStr::from_ptr(*name) }.to_owned())
+            .collect();
+
         let instance_inner = InstanceInner {
             instance,
             debug_utils,
-            debug_utils_messenger
+            debug_utils_messenger,
+            enabled_extensions,
+            enabled_layers,
+            validation_counts,
+            event_log,
+            messenger_state,
         };
 
         Self {
@@ -182,6 +325,8 @@ impl Instance {
         (physical_device, queue_family_index as u32)
     }
 
+    /// Device that can present to the surface, and its graphics queue family. The graphics family presents as well
+    /// when the device has one that does both, see [`find_present_queue_family`](Self::find_present_queue_family).
     pub fn create_physical_device(&self, entry: &Entry, surface: &Surface) -> (PhysicalDevice, u32) {
         let physical_devices = unsafe {
             self.handle()
@@ -189,36 +334,121 @@ impl Instance {
                 .expect("Failed to enumerate physical devices.")
         };
         let surface_loader = surface::Instance::new(entry, self.handle());
-        let (physical_device, queue_family_index) = physical_devices
+        let (physical_device, (queue_family_index, _)) = physical_devices
             .iter()
             .find_map(|physical_device| {
+                let families = unsafe { self.handle().get_physical_device_queue_family_properties(*physical_device) };
+                let presents = |index: u32| unsafe {
+                    surface_loader.get_physical_device_surface_support(*physical_device, index, *surface.handle())
+                        .expect("error")
+                };
+                select_queue_families(&families, presents).map(|families| (*physical_device, families))
+            })
+            .expect("Couldn't find a suitable device.");
+        (physical_device, queue_family_index)
+    }
+
+    /// Queue family presenting to the surface, `queue_family_index` itself when it can.
+    pub fn find_present_queue_family(&self, physical_device: PhysicalDevice, surface: &Surface, queue_family_index: u32) -> Option<u32> {
+        let families = unsafe {
+            self.handle().get_physical_device_queue_family_properties(physical_device)
+        };
+        if surface.supports_present(&physical_device, queue_family_index) {
+            return Some(queue_family_index);
+        }
+        (0..families.len() as u32).find(|index| surface.supports_present(&physical_device, *index))
+    }
+
+    /// Device and graphics queue family for offscreen work, preferring discrete over integrated GPUs, and those
+    /// over virtual and cpu devices.
+    pub fn create_physical_device_offscreen(&self) -> (PhysicalDevice, u32) {
+        let physical_devices = unsafe {
+            self.handle()
+                .enumerate_physical_devices()
+                .expect("Failed to enumerate physical devices.")
+        };
+        let rank = |device_type: vk::PhysicalDeviceType| match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 3,
+            _ => 4,
+        };
+        physical_devices
+            .iter()
+            .filter_map(|physical_device| {
                 unsafe {
+                    let device_type = self.handle().get_physical_device_properties(*physical_device).device_type;
                     self.handle().get_physical_device_queue_family_properties(*physical_device)
                         .iter()
-                        .enumerate()
-                        .find_map(|(index, _info)| {
-                            let supports_graphics_and_surface =
-                                surface_loader.get_physical_device_surface_support(
-                                    *physical_device,
-                                    index as u32,
-                                    *surface.handle()
-                                ).expect("error");
-                            if supports_graphics_and_surface {
-                                Some((*physical_device, index))
-                            } else {
-                                None
-                            }
-                        })
+                        .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE))
+                        .map(|index| (*physical_device, index as u32, rank(device_type)))
                 }
             })
-            .expect("Couldn't find a suitable device.");
-        (physical_device, queue_family_index as u32)
+            .min_by_key(|(_, _, rank)| *rank)
+            .map(|(physical_device, index, _)| (physical_device, index))
+            .expect("Couldn't find a suitable device.")
+    }
+
+    /// Queue family dedicated to compute, without graphics support, that can present to the surface.
+    pub fn find_compute_present_queue_family(&self, physical_device: PhysicalDevice, surface: &Surface) -> Option<u32> {
+        let families = unsafe {
+            self.handle().get_physical_device_queue_family_properties(physical_device)
+        };
+        families.iter()
+            .enumerate()
+            .position(|(index, family)| {
+                family.queue_count > 0
+                    && family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                    && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                    && surface.supports_present(&physical_device, index as u32)
+            })
+            .map(|index| index as u32)
     }
 
     pub fn handle(&self) -> &ash::Instance {
         &self.inner.instance
     }
 
+    /// Instance extensions that were enabled at creation.
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.inner.enabled_extensions
+    }
+
+    /// Instance layers that were enabled at creation.
+    pub fn enabled_layers(&self) -> &[CString] {
+        &self.inner.enabled_layers
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.inner.enabled_extensions.iter().any(|extension| extension.as_c_str() == name)
+    }
+
+    /// Whether `VK_LAYER_KHRONOS_validation` was enabled, see [`InstanceConfig::validation`].
+    pub fn is_validation_enabled(&self) -> bool {
+        self.inner.enabled_layers.iter().any(|layer| layer.as_bytes() == b"VK_LAYER_KHRONOS_validation")
+    }
+
+    /// Validation messages reported so far, counted whether or not they're logged.
+    pub fn validation_counts(&self) -> Arc<ValidationCounts> {
+        self.inner.validation_counts.clone()
+    }
+
+}
+
+/// Graphics queue family and the family presenting to the surface, preferring a single family that does both.
+fn select_queue_families(families: &[vk::QueueFamilyProperties], presents: impl Fn(u32) -> bool) -> Option<(u32, u32)> {
+    let graphics = |index: &u32| {
+        let family = &families[*index as usize];
+        family.queue_count > 0 && family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+    };
+    let indices = 0..families.len() as u32;
+    if let Some(index) = indices.clone().filter(graphics).find(|index| presents(*index)) {
+        return Some((index, index));
+    }
+    let graphics_index = indices.clone().find(graphics)?;
+    let present_index = indices.clone().find(|index| presents(*index))?;
+    Some((graphics_index, present_index))
 }
 
 #[cfg(test)]
@@ -231,6 +461,35 @@ mod tests {
         let _instance = Instance::new(&entry, None);
     }
 
+    #[test]
+    fn skip_unavailable_extensions_and_layers() {
+        let entry = Entry::linked();
+        let config = InstanceConfig {
+            extensions: vec![CString::new("VK_CEN_unavailable_extension").unwrap()],
+            layers: vec![CString::new("VK_LAYER_CEN_unavailable").unwrap()],
+            ..Default::default()
+        };
+        let instance = Instance::with_config(&entry, None, &config);
+
+        assert!(instance.is_extension_enabled(debug_utils::NAME));
+        assert!(!instance.is_extension_enabled(c"VK_CEN_unavailable_extension"));
+        assert!(!instance.enabled_layers().iter().any(|layer| layer.as_c_str() == c"VK_LAYER_CEN_unavailable"));
+    }
+
+    #[test]
+    fn prefer_graphics_family_that_presents() {
+        let family = |queue_flags| vk::QueueFamilyProperties { queue_flags, queue_count: 1, ..Default::default() };
+        let families = [
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE),
+            family(vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::GRAPHICS),
+        ];
+        assert_eq!(select_queue_families(&families, |index| index != 0), Some((2, 2)));
+        assert_eq!(select_queue_families(&families, |index| index == 1), Some((0, 1)));
+        assert_eq!(select_queue_families(&families, |_| false), None);
+        assert_eq!(select_queue_families(&families[1..2], |_| true), None);
+    }
+
     #[test]
     fn create_physical_device() {
         let entry = Entry::linked();
@@ -0,0 +1,149 @@
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use ash::vk;
+
+/// An image, buffer, sampler or pipeline that is alive, see [`Device::live_objects`](crate::vulkan::Device::live_objects).
+#[derive(Clone, Debug)]
+pub struct LiveObject {
+    pub object_type: vk::ObjectType,
+    pub handle: u64,
+    /// Name given with `set_debug_name`, e.g. [`Image::set_debug_name`](crate::vulkan::Image::set_debug_name).
+    pub name: Option<String>,
+    /// Where the object was created, captured in debug builds when `RUST_BACKTRACE` is set.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl fmt::Display for LiveObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {:#x}", self.object_type, self.handle)?;
+        if let Some(name) = &self.name {
+            write!(f, " {:?}", name)?;
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, ", created at:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// GPU objects of a device that are alive, in creation order.
+///
+/// At shutdown, after the engine released its own resources, any object left was leaked, e.g. by a component kept
+/// alive elsewhere. It keeps the device alive, which causes validation errors once the instance is destroyed.
+#[derive(Clone, Debug, Default)]
+pub struct LeakReport {
+    pub objects: Vec<LiveObject>,
+}
+
+impl LeakReport {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} GPU objects alive", self.objects.len())?;
+        for object in &self.objects {
+            write!(f, "\n  {}", object)?;
+        }
+        Ok(())
+    }
+}
+
+type ObjectKey = (vk::ObjectType, u64);
+
+/// Images, buffers, samplers and pipelines of a device that were created and not destroyed yet.
+#[derive(Default)]
+pub(crate) struct ObjectRegistry {
+    state: Mutex<RegistryState>,
+}
+
+#[derive(Default)]
+struct RegistryState {
+    created: u64,
+    objects: HashMap<ObjectKey, (u64, LiveObject)>,
+}
+
+pub(crate) fn object_key<H: vk::Handle>(handle: H) -> ObjectKey {
+    (H::TYPE, handle.as_raw())
+}
+
+impl ObjectRegistry {
+
+    pub(crate) fn created(&self, handle: impl vk::Handle) {
+        let key = object_key(handle);
+
+        #[cfg(debug_assertions)]
+        let backtrace = Some(Backtrace::capture())
+            .filter(|backtrace| backtrace.status() == std::backtrace::BacktraceStatus::Captured)
+            .map(Arc::new);
+        #[cfg(not(debug_assertions))]
+        let backtrace = None;
+
+        let object = LiveObject {
+            object_type: key.0,
+            handle: key.1,
+            name: None,
+            backtrace,
+        };
+        let mut state = self.state.lock().unwrap();
+        state.created += 1;
+        let order = state.created;
+        state.objects.insert(key, (order, object));
+    }
+
+    pub(crate) fn destroyed(&self, handle: impl vk::Handle) {
+        self.state.lock().unwrap().objects.remove(&object_key(handle));
+    }
+
+    /// Record the debug name of an object, other objects than images, buffers, samplers and pipelines are ignored.
+    pub(crate) fn named(&self, key: ObjectKey, name: &str) {
+        if let Some((_, object)) = self.state.lock().unwrap().objects.get_mut(&key) {
+            object.name = Some(name.to_string());
+        }
+    }
+
+    pub(crate) fn report(&self) -> LeakReport {
+        let state = self.state.lock().unwrap();
+        let mut objects: Vec<_> = state.objects.values().collect();
+        objects.sort_by_key(|(order, _)| *order);
+        LeakReport {
+            objects: objects.into_iter().map(|(_, object)| object.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn report_live_objects() {
+        let registry = ObjectRegistry::default();
+        let image = vk::Image::from_raw(0x10);
+        let buffer = vk::Buffer::from_raw(0x10);
+        registry.created(image);
+        registry.created(buffer);
+        registry.named(object_key(buffer), "vertices");
+        registry.named(object_key(vk::Sampler::from_raw(0x10)), "ignored");
+
+        let report = registry.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report.objects[0].object_type, vk::ObjectType::IMAGE);
+        assert_eq!(report.objects[1].name.as_deref(), Some("vertices"));
+        assert!(report.to_string().starts_with("2 GPU objects alive\n  IMAGE 0x10"));
+        assert!(report.objects[1].to_string().starts_with("BUFFER 0x10 \"vertices\""));
+
+        registry.destroyed(image);
+        registry.destroyed(buffer);
+        assert!(registry.report().is_empty());
+    }
+}
@@ -1,46 +1,92 @@
 pub(crate) mod device;
+mod device_plugin;
+mod device_config;
+mod driver_info;
 mod window_state;
 mod instance;
 mod surface;
 mod swapchain;
 mod graphics_pipeline;
 mod renderpass;
+mod rendering;
+mod types;
 mod framebuffer;
 mod command_pool;
 mod command_buffer;
+mod command_buffer_pool;
 mod compute_pipeline;
 mod pipeline;
+mod pipeline_layout;
 mod image;
 mod descriptor_set_layout;
 mod allocator;
 mod buffer;
 pub(crate) mod memory;
 mod descriptor_pool;
+mod descriptor_allocator;
+mod descriptor_writer;
+mod query_pool;
+mod sampler;
+mod deletion_queue;
+mod live_objects;
+mod queue_families;
+pub(crate) mod event_log;
 
 pub(crate) const LOG_TARGET: &str = "cen::vulkan";
+/// Creation and destruction of Vulkan objects, see [`LogSubsystem::Objects`](crate::app::LogSubsystem::Objects).
+pub(crate) const OBJECTS_LOG_TARGET: &str = "cen::vulkan::objects";
+pub(crate) const SWAPCHAIN_LOG_TARGET: &str = "cen::vulkan::swapchain";
 
-pub use self::allocator::Allocator;
-pub use self::buffer::Buffer;
+pub use self::allocator::{Allocator, HeapReport, MemoryReport};
+pub use self::buffer::{Buffer, BufferError, MappedBufferGuard, TypedMappedBufferGuard};
 pub use self::command_buffer::CommandBuffer;
+pub use self::command_buffer_pool::CommandBufferPool;
 pub use self::command_pool::CommandPool;
 pub use self::compute_pipeline::ComputePipeline;
 pub use self::compute_pipeline::ComputePipelineConfig;
-pub use self::device::Device;
+pub use self::compute_pipeline::PipelineStatistic;
+pub use self::device::{Device, ObjectStats};
+pub use self::live_objects::{LeakReport, LiveObject};
+pub use self::device_plugin::{DevicePlugin, FeatureChain};
+pub use self::queue_families::QueueFamilies;
+pub use self::device_config::{DeviceConfig, DeviceError, FeatureProfile};
+pub use self::driver_info::{DriverInfo, DriverVersion, Vendor, Workaround, WorkaroundRule, WORKAROUNDS};
 pub use self::descriptor_set_layout::DescriptorSetLayout;
 pub use self::descriptor_pool::DescriptorPool;
+pub use self::descriptor_allocator::{DescriptorAllocator, DescriptorSet, TransientDescriptorAllocator, DEFAULT_POOL_RATIOS};
+pub use self::descriptor_writer::DescriptorWriter;
+pub use self::types::{Filter, Format, ImageUsage, Layout};
+pub use self::query_pool::QueryPool;
+pub use self::sampler::{Sampler, SamplerConfig};
+pub use self::event_log::{EventLog, FrameEvent};
 pub use self::framebuffer::Framebuffer;
 pub use self::graphics_pipeline::GraphicsPipeline;
 pub use self::graphics_pipeline::GraphicsPipelineConfig;
+pub use self::graphics_pipeline::BlendMode;
 pub use self::image::ImageTrait;
 pub use self::image::SwapchainImage;
 pub use self::image::Image;
 pub use self::image::ImageConfig;
+pub use self::image::texel_size;
+pub(crate) use self::image::is_float_sampleable;
+pub use self::image::ImageError;
+pub use self::image::ExternalImageConfig;
 pub use self::instance::Instance;
+pub use self::instance::InstanceConfig;
+pub use self::instance::ValidationCounts;
 pub use self::window_state::WindowState;
 pub use self::surface::Surface;
 pub use self::swapchain::Swapchain;
 pub use self::pipeline::Pipeline;
 pub use self::pipeline::PipelineErr;
+pub use self::pipeline::compile_shader_code;
+pub use self::pipeline::{load_spirv_file, ShaderSource, SpecializationConstant};
+pub use self::pipeline_layout::PipelineLayout;
 pub use self::pipeline::SlangModule;
+pub use cen_shaders::FULLSCREEN_VERTEX_SHADER;
+pub(crate) use cen_shaders::is_builtin_shader;
+pub(crate) use self::allocator::{format_bytes, AllocatorInner};
+pub(crate) use self::image::{glsl_image_format, STORAGE_COLOR_FORMATS};
 pub use self::renderpass::RenderPass;
+pub use self::rendering::{ColorAttachment, DepthAttachment};
 pub use self::memory::GpuHandle;
@@ -1,12 +1,12 @@
 use std::collections::HashMap;
 use std::{fmt, fs};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use ash::vk;
 use ash::vk::ShaderModule;
 use log::{info, trace};
-use shaderc::{IncludeType, ResolvedInclude};
 use crate::vulkan::{LOG_TARGET};
 use crate::vulkan::memory::GpuResource;
+use cen_shaders::builtin_shader;
 
 pub trait Pipeline {
     fn handle(&self) -> vk::Pipeline;
@@ -27,8 +27,12 @@ pub fn create_shader_module(device: &ash::Device, code: Vec<u32>) -> ShaderModul
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum PipelineErr {
-    ShaderCompilation(String)
+    ShaderCompilation(String),
+    /// A pipeline was already registered under the name, see
+    /// [`PipelineStore::insert_named`](crate::graphics::pipeline_store::PipelineStore::insert_named).
+    DuplicateName(String),
 }
 
 impl fmt::Display for PipelineErr {
@@ -37,10 +41,194 @@ impl fmt::Display for PipelineErr {
             PipelineErr::ShaderCompilation(ref err) => {
                 write!(f, "{}", err)
             },
+            PipelineErr::DuplicateName(ref name) => {
+                write!(f, "A pipeline named {:?} already exists", name)
+            },
         }
     }
 }
 
+/// First word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x07230203;
+
+/// Code of a shader stage.
+///
+/// Converts from paths, so configs can keep using `"shader.comp".into()`, and from SPIR-V words.
+#[derive(Clone, Debug)]
+pub enum ShaderSource {
+    /// A file, chosen by its extension: precompiled `.spv` SPIR-V, `.slang` source, or GLSL source otherwise.
+    /// Files are watched and hot reloaded by the pipeline store.
+    File(PathBuf),
+    /// Precompiled SPIR-V, e.g. embedded into the binary.
+    SpirV(Vec<u32>),
+}
+
+impl ShaderSource {
+    /// SPIR-V from raw bytes, e.g. `include_bytes!("shader.spv")`.
+    pub fn from_spirv_bytes(bytes: &[u8]) -> Result<Self, PipelineErr> {
+        let code = ash::util::read_spv(&mut std::io::Cursor::new(bytes))
+            .map_err(|e| PipelineErr::ShaderCompilation(format!("Invalid SPIR-V: {}", e)))?;
+        Ok(ShaderSource::SpirV(code))
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            ShaderSource::File(path) => Some(path),
+            ShaderSource::SpirV(_) => None,
+        }
+    }
+
+    /// Whether the source is loaded from `path`, which may be the absolute path of a changed file.
+    pub(crate) fn is_loaded_from(&self, path: &Path) -> bool {
+        self.path().is_some_and(|source| path.ends_with(source))
+    }
+
+    /// Load the SPIR-V code, compiling source files.
+    pub(crate) fn load(&self, macros: &HashMap<String, String>, slang_modules: &[SlangModule]) -> Result<Vec<u32>, PipelineErr> {
+        self.load_with_includes(macros, slang_modules).map(|(code, _)| code)
+    }
+
+    /// Load the SPIR-V code together with the files the source included, see [`load_shader_code_with_includes`].
+    pub(crate) fn load_with_includes(&self, macros: &HashMap<String, String>, slang_modules: &[SlangModule]) -> Result<(Vec<u32>, Vec<PathBuf>), PipelineErr> {
+        match self {
+            ShaderSource::SpirV(code) => {
+                if code.first() != Some(&SPIRV_MAGIC) {
+                    return Err(PipelineErr::ShaderCompilation("Invalid SPIR-V: missing magic number".into()));
+                }
+                Ok((code.clone(), Vec::new()))
+            }
+            ShaderSource::File(path) if path.extension().is_some_and(|e| e == "slang") => {
+                load_slang_shader_code(path.clone(), slang_modules).map(|code| (code, Vec::new()))
+            }
+            ShaderSource::File(path) => load_shader_code_with_includes(path.clone(), macros),
+        }
+    }
+}
+
+impl From<PathBuf> for ShaderSource {
+    fn from(path: PathBuf) -> Self {
+        ShaderSource::File(path)
+    }
+}
+
+impl From<&Path> for ShaderSource {
+    fn from(path: &Path) -> Self {
+        ShaderSource::File(path.to_path_buf())
+    }
+}
+
+impl From<&str> for ShaderSource {
+    fn from(path: &str) -> Self {
+        ShaderSource::File(path.into())
+    }
+}
+
+impl From<String> for ShaderSource {
+    fn from(path: String) -> Self {
+        ShaderSource::File(path.into())
+    }
+}
+
+impl From<Vec<u32>> for ShaderSource {
+    fn from(code: Vec<u32>) -> Self {
+        ShaderSource::SpirV(code)
+    }
+}
+
+impl Default for ShaderSource {
+    fn default() -> Self {
+        ShaderSource::File(PathBuf::new())
+    }
+}
+
+/// Value of a specialization constant, set per pipeline without compiling the shader again. Declared in GLSL with
+/// e.g. `layout(constant_id = 0) const uint SAMPLES = 4;`, or `layout(local_size_x_id = 0) in;` for workgroup sizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecializationConstant {
+    Bool(bool),
+    Int(i32),
+    Uint(u32),
+    Float(f32),
+}
+
+impl SpecializationConstant {
+    /// The 32 bits of the value, as the shader reads it.
+    pub fn to_bits(self) -> u32 {
+        match self {
+            SpecializationConstant::Bool(value) => value as u32,
+            SpecializationConstant::Int(value) => value as u32,
+            SpecializationConstant::Uint(value) => value,
+            SpecializationConstant::Float(value) => value.to_bits(),
+        }
+    }
+}
+
+impl From<bool> for SpecializationConstant {
+    fn from(value: bool) -> Self {
+        SpecializationConstant::Bool(value)
+    }
+}
+
+impl From<i32> for SpecializationConstant {
+    fn from(value: i32) -> Self {
+        SpecializationConstant::Int(value)
+    }
+}
+
+impl From<u32> for SpecializationConstant {
+    fn from(value: u32) -> Self {
+        SpecializationConstant::Uint(value)
+    }
+}
+
+impl From<f32> for SpecializationConstant {
+    fn from(value: f32) -> Self {
+        SpecializationConstant::Float(value)
+    }
+}
+
+/// Map entries and data of specialization constants, referenced by the `vk::SpecializationInfo` of a stage.
+pub(crate) struct SpecializationData {
+    entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializationData {
+    pub(crate) fn new(constants: &HashMap<u32, SpecializationConstant>) -> Self {
+        let mut ids = constants.keys().copied().collect::<Vec<u32>>();
+        ids.sort_unstable();
+
+        let entries = ids.iter().enumerate()
+            .map(|(index, id)| vk::SpecializationMapEntry {
+                constant_id: *id,
+                offset: (index * std::mem::size_of::<u32>()) as u32,
+                size: std::mem::size_of::<u32>(),
+            })
+            .collect();
+        let data = ids.iter()
+            .flat_map(|id| constants[id].to_bits().to_ne_bytes())
+            .collect();
+        Self { entries, data }
+    }
+
+    /// The info of a stage, `None` without constants.
+    pub(crate) fn info(&self) -> Option<vk::SpecializationInfo<'_>> {
+        (!self.entries.is_empty()).then(|| vk::SpecializationInfo::default()
+            .map_entries(&self.entries)
+            .data(&self.data))
+    }
+}
+
+/// Read a precompiled SPIR-V file.
+pub fn load_spirv_file(path: &Path) -> Result<Vec<u32>, PipelineErr> {
+    let mut file = fs::File::open(path)
+        .map_err(|e| PipelineErr::ShaderCompilation(format!("{:?}: {}", path, e)))?;
+    let code = ash::util::read_spv(&mut file)
+        .map_err(|e| PipelineErr::ShaderCompilation(format!("{:?}: invalid SPIR-V: {}", path, e)))?;
+    trace!(target: LOG_TARGET, "Loaded SPIR-V: {:?}", path);
+    Ok(code)
+}
+
 #[derive(Clone)]
 pub struct SlangModule {
     pub name: String,
@@ -119,11 +307,25 @@ pub fn load_slang_shader_code(source_file: PathBuf, modules: &[SlangModule]) ->
 
 /**
  * Load a shader from a file and compile it into SPIR-V.
+ * `.spv` files are precompiled and loaded as is, without the macros.
  */
 pub fn load_shader_code(source_file: PathBuf, macros: &HashMap<String, String>) -> Result<Vec<u32>, PipelineErr>
+{
+    load_shader_code_with_includes(source_file, macros).map(|(code, _)| code)
+}
+
+/**
+ * Load a shader like [`load_shader_code`], also returning the canonical paths of the files it included,
+ * directly or through other includes.
+ */
+pub fn load_shader_code_with_includes(source_file: PathBuf, macros: &HashMap<String, String>) -> Result<(Vec<u32>, Vec<PathBuf>), PipelineErr>
 {
     use shaderc;
 
+    if source_file.extension().is_some_and(|e| e == "spv") {
+        return load_spirv_file(&source_file).map(|code| (code, Vec::new()));
+    }
+
     let shader_kind = match source_file.to_str().unwrap().split(".").last() {
         Some("vert") => shaderc::ShaderKind::Vertex,
         Some("frag") => shaderc::ShaderKind::Fragment,
@@ -131,49 +333,36 @@ pub fn load_shader_code(source_file: PathBuf, macros: &HashMap<String, String>)
         _ => panic!("Unknown shader type")
     };
 
-    let source = fs::read_to_string(source_file.clone()).unwrap_or_else(|_| panic!("Failed to read file: {:?}", source_file));
-
-    let compiler = shaderc::Compiler::new().unwrap();
-    let mut options = shaderc::CompileOptions::new().unwrap();
-    options.set_include_callback(|include_name, include_type, original_source, _| {
-        let original_path = PathBuf::from(original_source);
-
-        match include_type {
-            IncludeType::Relative => {
-                let path = original_path.parent().unwrap().join(PathBuf::from(include_name));
-                let source = fs::read_to_string(path.clone()).unwrap_or_else(|_| panic!("Failed to read file: {:?}", path));
-                info!("Loaded shader include: {}", path.to_str().unwrap());
-                Ok(ResolvedInclude {
-                    resolved_name: path.to_str().unwrap().to_string(),
-                    content: source,
-                })
-            }
-            IncludeType::Standard => {
-                Err(format!("Only relative includes are supported. Can't include {}", include_name))
-            }
-        }
+    let source = match builtin_shader(&source_file) {
+        Some(source) => source.to_string(),
+        None => fs::read_to_string(source_file.clone()).unwrap_or_else(|_| panic!("Failed to read file: {:?}", source_file)),
+    };
 
-    });
-    options.add_macro_definition("EP", Some("main"));
-    for ( k, v ) in macros {
-        options.add_macro_definition(k, Some(v.to_string().as_str()));
-    }
+    compile_shader_code_with_includes(&source, source_file.to_str().unwrap(), shader_kind, macros)
+}
 
-    let binary_result = compiler.compile_into_spirv(
-        source.as_str(),
-        shader_kind,
-        source_file.to_str().unwrap(),
-        "main",
-        Some(&options)
-    );
+/**
+ * Compile GLSL source code into SPIR-V.
+ * `file_name` is used for error messages and as the base path of relative includes.
+ */
+pub fn compile_shader_code(source: &str, file_name: &str, shader_kind: shaderc::ShaderKind, macros: &HashMap<String, String>) -> Result<Vec<u32>, PipelineErr>
+{
+    compile_shader_code_with_includes(source, file_name, shader_kind, macros).map(|(code, _)| code)
+}
 
-    match binary_result {
-        Ok(result) => {
-            trace!(target: LOG_TARGET, "Compiled shader code: {:?}", source_file);
-            Ok(result.as_binary().to_vec())
+fn compile_shader_code_with_includes(source: &str, file_name: &str, shader_kind: shaderc::ShaderKind, macros: &HashMap<String, String>) -> Result<(Vec<u32>, Vec<PathBuf>), PipelineErr>
+{
+    // Shared with `include_shader!`, so shaders compile the same at build time
+    match cen_shaders::compile_glsl(source, file_name, shader_kind, macros) {
+        Ok((code, includes)) => {
+            for include in &includes {
+                info!("Loaded shader include: {}", include.display());
+            }
+            trace!(target: LOG_TARGET, "Compiled shader code: {:?}", file_name);
+            Ok((code, includes))
         },
         Err(error) => {
-            Err(PipelineErr::ShaderCompilation(error.to_string()))
+            Err(PipelineErr::ShaderCompilation(error))
         }
     }
 }
@@ -184,7 +373,48 @@ mod tests {
     use super::*;
     use crate::vulkan::Instance;
 
-    const SPIRV_MAGIC: u32 = 0x07230203;
+    #[test]
+    fn spirv_sources() {
+        let words = [SPIRV_MAGIC, 0x00010000, 0, 1, 0];
+        let bytes = words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+
+        let source = ShaderSource::from_spirv_bytes(&bytes).expect("Failed to read SPIR-V");
+        assert_eq!(source.load(&HashMap::new(), &[]).unwrap(), words);
+        assert!(source.path().is_none());
+
+        assert!(ShaderSource::from_spirv_bytes(&bytes[..6]).is_err());
+        assert!(ShaderSource::SpirV(vec![0, 1, 2]).load(&HashMap::new(), &[]).is_err());
+
+        let file = ShaderSource::from("shaders/shader.comp");
+        assert!(file.is_loaded_from(Path::new("/project/shaders/shader.comp")));
+        assert!(!file.is_loaded_from(Path::new("/project/shaders/other.comp")));
+    }
+
+    #[test]
+    fn track_nested_includes() {
+        let dir = std::env::temp_dir().join(format!("cen_includes_{}", std::process::id()));
+        fs::create_dir_all(dir.join("common")).unwrap();
+        fs::write(dir.join("common/constants.glsl"), "#ifndef CONSTANTS_GLSL\n#define CONSTANTS_GLSL\nconst uint SIZE = 4;\n#endif\n").unwrap();
+        fs::write(dir.join("common/math.glsl"), "#include \"constants.glsl\"\nuint twice(uint x) { return x * 2; }\n").unwrap();
+        fs::write(dir.join("shader.comp"), concat!(
+            "#version 450\n",
+            "#include \"common/math.glsl\"\n",
+            "#include \"common/constants.glsl\"\n",
+            "layout(local_size_x = SIZE) in;\n",
+            "void main() { twice(gl_LocalInvocationID.x); }\n",
+        )).unwrap();
+
+        let (code, includes) = load_shader_code_with_includes(dir.join("shader.comp"), &HashMap::new())
+            .expect("Failed to compile shader with includes");
+        assert_eq!(code[0], SPIRV_MAGIC);
+        let canonical = |path: &str| fs::canonicalize(dir.join(path)).unwrap();
+        assert_eq!(includes, vec![canonical("common/math.glsl"), canonical("common/constants.glsl")]);
+
+        fs::write(dir.join("broken.comp"), "#version 450\n#include \"missing.glsl\"\nvoid main() {}\n").unwrap();
+        assert!(load_shader_code_with_includes(dir.join("broken.comp"), &HashMap::new()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
     #[test]
     fn slang_compiles_to_valid_spirv() {
@@ -222,4 +452,43 @@ mod tests {
         assert!(!spirv.is_empty());
         assert_eq!(spirv[0], SPIRV_MAGIC);
     }
+
+    #[test]
+    fn specialization_data() {
+        let data = SpecializationData::new(&HashMap::from([(3, 1.5f32.into()), (1, true.into())]));
+        let info = data.info().unwrap();
+        assert_eq!(info.map_entry_count, 2);
+        assert_eq!(data.entries[0].constant_id, 1);
+        assert_eq!(data.entries[1].offset, 4);
+        assert_eq!(data.data[4..], 1.5f32.to_bits().to_ne_bytes());
+        assert!(SpecializationData::new(&HashMap::new()).info().is_none());
+    }
+
+    #[test]
+    fn builtin_headers_compile() {
+        let source = r#"
+#version 450
+#include <cen/globals.glsl>
+#include <cen/random.glsl>
+#include <cen/noise.glsl>
+#include <cen/color.glsl>
+#include <cen/tonemap.glsl>
+
+layout( local_size_x = 1 ) in;
+layout( std430, set = 1, binding = 0 ) writeonly buffer Output {
+    vec4 values[];
+};
+
+void main()
+{
+    vec2 p = vec2( cen_globals.time, float( cen_globals.frame ) );
+    float noise = value_noise( p ) + value_noise( vec3( p, 1.0 ) ) + fbm( p, 4 );
+    vec3 color = hsv_to_rgb( rgb_to_hsv( srgb_to_linear( vec3( noise ) ) ) );
+    color = tonemap_aces( color ) + tonemap_reinhard( color ) + tonemap_reinhard_extended( color, 4.0 ) + tonemap_uncharted2( color );
+    values[ gl_GlobalInvocationID.x ] = vec4( linear_to_srgb( color ), luminance( color ) );
+}
+"#;
+        compile_shader_code(source, "cen/headers.comp", shaderc::ShaderKind::Compute, &HashMap::new())
+            .expect("Failed to compile the built-in headers");
+    }
 }
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use ash::vk;
+use log::trace;
+use crate::vulkan::{DescriptorSetLayout, Device, OBJECTS_LOG_TARGET};
+use crate::vulkan::device::DeviceInner;
+
+/// Contents of a pipeline layout, used to look up identical layouts.
+#[derive(Clone, Eq, Hash, PartialEq)]
+pub(crate) struct PipelineLayoutKey {
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<(vk::ShaderStageFlags, u32, u32)>,
+}
+
+/// Pipeline layouts that are currently alive, shared by all pipelines of a device.
+#[derive(Default)]
+pub(crate) struct PipelineLayoutCache {
+    layouts: Mutex<HashMap<PipelineLayoutKey, Weak<PipelineLayoutInner>>>,
+}
+
+impl PipelineLayoutCache {
+    pub(crate) fn len(&self) -> usize {
+        let mut layouts = self.layouts.lock().unwrap();
+        layouts.retain(|_, layout| layout.strong_count() > 0);
+        layouts.len()
+    }
+}
+
+pub struct PipelineLayoutInner {
+    device_dep: Arc<DeviceInner>,
+    layout: vk::PipelineLayout,
+    // The descriptor set layouts must outlive the pipeline layout, as their handles are part of the cache key
+    _set_layout_deps: Vec<DescriptorSetLayout>,
+}
+
+impl Drop for PipelineLayoutInner {
+    fn drop(&mut self) {
+        unsafe {
+            let layout_addr = format!("{:?}", self.layout);
+            self.device_dep.device.destroy_pipeline_layout(self.layout, None);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed pipeline layout: [{}]", layout_addr);
+        }
+    }
+}
+
+/// A pipeline layout shared between all pipelines with identical descriptor set layouts and push constant ranges.
+#[derive(Clone)]
+pub struct PipelineLayout {
+    inner: Arc<PipelineLayoutInner>,
+}
+
+impl PipelineLayout {
+
+    /// Get a pipeline layout from the device cache, or create it when no identical layout is alive.
+    pub fn new(device: &Device, descriptor_set_layouts: &[DescriptorSetLayout], push_constant_ranges: &[vk::PushConstantRange]) -> PipelineLayout {
+        let set_layouts = descriptor_set_layouts.iter()
+            .map(|layout| layout.handle())
+            .collect::<Vec<_>>();
+
+        let key = PipelineLayoutKey {
+            set_layouts: set_layouts.clone(),
+            push_constant_ranges: push_constant_ranges.iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect(),
+        };
+
+        let mut layouts = device.inner.pipeline_layouts.layouts.lock().unwrap();
+        if let Some(inner) = layouts.get(&key).and_then(|layout| layout.upgrade()) {
+            trace!(target: OBJECTS_LOG_TARGET, "Reused pipeline layout: {:?}", inner.layout);
+            return PipelineLayout { inner };
+        }
+
+        let create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe {
+            device.handle()
+                .create_pipeline_layout(&create_info, None)
+                .expect("Failed to create pipeline layout")
+        };
+
+        trace!(target: OBJECTS_LOG_TARGET, "Created pipeline layout: {:?}", layout);
+
+        let inner = Arc::new(PipelineLayoutInner {
+            device_dep: device.inner.clone(),
+            layout,
+            _set_layout_deps: descriptor_set_layouts.iter().map(|layout| layout.clone()).collect(),
+        });
+
+        // Drop expired entries while we hold the lock
+        layouts.retain(|_, layout| layout.strong_count() > 0);
+        layouts.insert(key, Arc::downgrade(&inner));
+
+        PipelineLayout { inner }
+    }
+
+    pub fn handle(&self) -> vk::PipelineLayout {
+        self.inner.layout
+    }
+}
@@ -0,0 +1,80 @@
+use std::any::Any;
+use std::sync::Arc;
+use ash::vk;
+use log::trace;
+use crate::vulkan::{Device, OBJECTS_LOG_TARGET};
+use crate::vulkan::device::DeviceInner;
+use crate::vulkan::memory::GpuResource;
+
+pub struct QueryPoolInner {
+    device_dep: Arc<DeviceInner>,
+    query_pool: vk::QueryPool,
+    query_count: u32,
+}
+
+impl Drop for QueryPoolInner {
+    fn drop(&mut self) {
+        unsafe {
+            let query_pool_addr = format!("{:?}", self.query_pool);
+            self.device_dep.device.destroy_query_pool(self.query_pool, None);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed query pool: [{}]", query_pool_addr);
+        }
+    }
+}
+
+/// Pool of timestamp queries, written with [`CommandBuffer::write_timestamp`](crate::vulkan::CommandBuffer::write_timestamp).
+#[derive(Clone)]
+pub struct QueryPool {
+    inner: Arc<QueryPoolInner>,
+}
+
+impl GpuResource for QueryPool {
+    fn reference(&self) -> Arc<dyn Any> {
+        self.inner.clone()
+    }
+}
+
+impl QueryPool {
+    pub fn new_timestamp(device: &Device, query_count: u32) -> QueryPool {
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(query_count);
+        let query_pool = unsafe {
+            device.handle().create_query_pool(&create_info, None)
+                .expect("Failed to create query pool")
+        };
+
+        trace!(target: OBJECTS_LOG_TARGET, "Created query pool: {:?}", query_pool);
+
+        QueryPool {
+            inner: Arc::new(QueryPoolInner {
+                device_dep: device.inner.clone(),
+                query_pool,
+                query_count,
+            })
+        }
+    }
+
+    pub fn handle(&self) -> vk::QueryPool {
+        self.inner.query_pool
+    }
+
+    pub fn query_count(&self) -> u32 {
+        self.inner.query_count
+    }
+
+    /// Read the 64 bit results of `count` queries starting at `first`.
+    /// Returns `None` when not all of them are available yet.
+    pub fn results(&self, first: u32, count: u32) -> Option<Vec<u64>> {
+        let mut results = vec![0u64; count as usize];
+        unsafe {
+            self.inner.device_dep.device.get_query_pool_results(
+                self.inner.query_pool,
+                first,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64
+            )
+        }.ok()?;
+        Some(results)
+    }
+}
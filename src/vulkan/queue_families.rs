@@ -0,0 +1,77 @@
+use ash::vk;
+use crate::vulkan::Device;
+
+/// Distinct queue families a device creates queues on at most: graphics, compute, transfer and present.
+const MAX_QUEUE_FAMILIES: usize = 4;
+
+/// Queue families that use a resource concurrently, see [`ImageConfig::concurrent`](crate::vulkan::ImageConfig::concurrent)
+/// and [`Buffer::new_concurrent`](crate::vulkan::Buffer::new_concurrent).
+///
+/// Concurrent resources need no ownership transfers between the families, at the cost of possibly slower access on
+/// some devices. Exclusive resources are transferred with [`CommandBuffer::release_image`](crate::vulkan::CommandBuffer::release_image)
+/// and [`CommandBuffer::acquire_image`](crate::vulkan::CommandBuffer::acquire_image) instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueFamilies {
+    indices: [u32; MAX_QUEUE_FAMILIES],
+    len: usize,
+}
+
+impl QueueFamilies {
+    /// Duplicate indices are skipped. Panics with more than four distinct families.
+    pub fn new(indices: &[u32]) -> Self {
+        let mut families = Self::default();
+        for index in indices {
+            if families.as_slice().contains(index) {
+                continue;
+            }
+            assert!(families.len < MAX_QUEUE_FAMILIES, "A resource can be shared by at most {} queue families", MAX_QUEUE_FAMILIES);
+            families.indices[families.len] = *index;
+            families.len += 1;
+        }
+        families
+    }
+
+    /// All queue families the device created queues on, see [`Device::queue_families`].
+    pub fn all(device: &Device) -> Self {
+        device.queue_families()
+    }
+
+    pub fn as_slice(&self) -> &[u32] {
+        &self.indices[..self.len]
+    }
+
+    /// `CONCURRENT` for two or more families, `EXCLUSIVE` otherwise as Vulkan requires for a single family.
+    pub fn sharing_mode(&self) -> vk::SharingMode {
+        if self.len > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        }
+    }
+
+    /// The indices for the create info, empty unless the sharing is concurrent.
+    pub(crate) fn concurrent_indices(&self) -> &[u32] {
+        match self.sharing_mode() {
+            vk::SharingMode::CONCURRENT => self.as_slice(),
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_families() {
+        let single = QueueFamilies::new(&[0, 0]);
+        assert_eq!(single.as_slice(), &[0]);
+        assert_eq!(single.sharing_mode(), vk::SharingMode::EXCLUSIVE);
+        assert!(single.concurrent_indices().is_empty());
+
+        let shared = QueueFamilies::new(&[0, 2, 0, 1]);
+        assert_eq!(shared.as_slice(), &[0, 2, 1]);
+        assert_eq!(shared.sharing_mode(), vk::SharingMode::CONCURRENT);
+        assert_eq!(shared.concurrent_indices(), &[0, 2, 1]);
+    }
+}
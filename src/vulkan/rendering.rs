@@ -0,0 +1,112 @@
+use ash::vk;
+use crate::vulkan::ImageTrait;
+
+/// A color attachment of a dynamic rendering pass, see [`CommandBuffer::begin_rendering_to`](crate::vulkan::CommandBuffer::begin_rendering_to).
+#[derive(Clone, Copy)]
+pub struct ColorAttachment<'a> {
+    pub image: &'a dyn ImageTrait,
+    /// Layout of the image, and of the resolve target, during the pass.
+    pub layout: vk::ImageLayout,
+    /// Clear the attachment to this color, its contents are loaded otherwise.
+    pub clear: Option<[f32; 4]>,
+    /// Single-sampled image a multisampled attachment is resolved into at the end of the pass, e.g. the swapchain
+    /// image. The multisampled contents are discarded after the resolve.
+    pub resolve: Option<&'a dyn ImageTrait>,
+    /// How samples are combined, averaged by default. Integer formats only support `SAMPLE_ZERO`.
+    pub resolve_mode: vk::ResolveModeFlags,
+}
+
+impl<'a> ColorAttachment<'a> {
+    pub fn new(image: &'a impl ImageTrait) -> Self {
+        Self {
+            image,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            clear: None,
+            resolve: None,
+            resolve_mode: vk::ResolveModeFlags::AVERAGE,
+        }
+    }
+
+    pub fn clear(mut self, color: [f32; 4]) -> Self {
+        self.clear = Some(color);
+        self
+    }
+
+    /// Resolve the multisampled attachment into `target`.
+    pub fn resolve(mut self, target: &'a impl ImageTrait) -> Self {
+        self.resolve = Some(target);
+        self
+    }
+
+    pub(crate) fn info(&self) -> vk::RenderingAttachmentInfo<'static> {
+        let mut info = vk::RenderingAttachmentInfo::default()
+            .image_view(self.image.image_view())
+            .image_layout(self.layout)
+            .store_op(vk::AttachmentStoreOp::STORE);
+        info = match self.clear {
+            Some(color) => info
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .clear_value(vk::ClearValue { color: vk::ClearColorValue { float32: color } }),
+            None => info.load_op(vk::AttachmentLoadOp::LOAD),
+        };
+        if let Some(target) = self.resolve {
+            debug_assert!(
+                self.image.samples() != vk::SampleCountFlags::TYPE_1 && target.samples() == vk::SampleCountFlags::TYPE_1,
+                "Resolving requires a multisampled attachment and a single-sampled target"
+            );
+            info = info
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .resolve_mode(self.resolve_mode)
+                .resolve_image_view(target.image_view())
+                .resolve_image_layout(self.layout);
+        }
+        info
+    }
+}
+
+/// The depth attachment of a dynamic rendering pass, see
+/// [`CommandBuffer::begin_rendering_with_depth`](crate::vulkan::CommandBuffer::begin_rendering_with_depth).
+#[derive(Clone, Copy)]
+pub struct DepthAttachment<'a> {
+    pub image: &'a dyn ImageTrait,
+    /// Layout of the image during the pass, read-only layouts test without writing.
+    pub layout: vk::ImageLayout,
+    /// Clear the attachment to this depth, its contents are loaded otherwise. The stencil is cleared to 0.
+    pub clear: Option<f32>,
+    /// Keep the depth after the pass, e.g. to sample it later. It is discarded otherwise.
+    pub store: bool,
+}
+
+impl<'a> DepthAttachment<'a> {
+    pub fn new(image: &'a impl ImageTrait) -> Self {
+        Self {
+            image,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            clear: None,
+            store: false,
+        }
+    }
+
+    pub fn clear(mut self, depth: f32) -> Self {
+        self.clear = Some(depth);
+        self
+    }
+
+    pub fn store(mut self) -> Self {
+        self.store = true;
+        self
+    }
+
+    pub(crate) fn info(&self) -> vk::RenderingAttachmentInfo<'static> {
+        let info = vk::RenderingAttachmentInfo::default()
+            .image_view(self.image.image_view())
+            .image_layout(self.layout)
+            .store_op(if self.store { vk::AttachmentStoreOp::STORE } else { vk::AttachmentStoreOp::DONT_CARE });
+        match self.clear {
+            Some(depth) => info
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth, stencil: 0 } }),
+            None => info.load_op(vk::AttachmentLoadOp::LOAD),
+        }
+    }
+}
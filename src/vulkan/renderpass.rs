@@ -2,7 +2,7 @@ use std::any::Any;
 use std::sync::Arc;
 use ash::{vk};
 use log::trace;
-use crate::vulkan::{Device, LOG_TARGET};
+use crate::vulkan::{Device, OBJECTS_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 use crate::vulkan::memory::GpuResource;
 
@@ -16,7 +16,7 @@ impl Drop for RenderPassInner {
         unsafe {
             let renderpass_addr = format!("{:?}", self.renderpass);
             self.device_dep.device.destroy_render_pass(self.renderpass, None);
-            log::trace!(target: LOG_TARGET, "Destroyed render pass: [{}]", renderpass_addr);
+            log::trace!(target: OBJECTS_LOG_TARGET, "Destroyed render pass: [{}]", renderpass_addr);
         }
     }
 }
@@ -65,7 +65,7 @@ impl RenderPass {
                 .expect("Failed to create render pass")
         };
 
-        trace!(target: LOG_TARGET, "Created render pass: {:?}", renderpass);
+        trace!(target: OBJECTS_LOG_TARGET, "Created render pass: {:?}", renderpass);
 
         let renderpass_inner = RenderPassInner {
             renderpass,
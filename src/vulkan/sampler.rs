@@ -0,0 +1,194 @@
+use std::any::Any;
+use std::sync::Arc;
+use ash::vk;
+use log::{trace, warn};
+use crate::vulkan::{Device, LOG_TARGET, OBJECTS_LOG_TARGET};
+use crate::vulkan::device::DeviceInner;
+use crate::vulkan::memory::GpuResource;
+
+/// Filtering and addressing of a sampler, for the sampler of an [`ImageConfig`](crate::vulkan::ImageConfig) or a
+/// standalone [`Sampler`].
+///
+/// ```ignore
+/// let config = SamplerConfig::default()
+///     .filter(Filter::Linear)
+///     .address_mode(vk::SamplerAddressMode::REPEAT)
+///     .anisotropy(16.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerConfig {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    /// Addressing of all coordinates.
+    pub address_mode: vk::SamplerAddressMode,
+    /// Used with the `CLAMP_TO_BORDER` address mode.
+    pub border_color: vk::BorderColor,
+    /// Maximum anisotropy, clamped to the device limit. Needs the `samplerAnisotropy` feature, see
+    /// [`DeviceConfig::core_features`](crate::vulkan::DeviceConfig::core_features), and is ignored without it.
+    pub max_anisotropy: Option<f32>,
+    pub mip_lod_bias: f32,
+    pub min_lod: f32,
+    /// Only the base level is sampled by default, `vk::LOD_CLAMP_NONE` samples all mip levels.
+    pub max_lod: f32,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            max_anisotropy: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: 0.0,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Minification and magnification filter.
+    pub fn filter(mut self, filter: impl Into<vk::Filter>) -> Self {
+        self.mag_filter = filter.into();
+        self.min_filter = self.mag_filter;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    pub fn address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn border_color(mut self, border_color: vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    pub fn anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    pub fn lod_bias(mut self, mip_lod_bias: f32) -> Self {
+        self.mip_lod_bias = mip_lod_bias;
+        self
+    }
+
+    /// Range of mip levels to sample, e.g. `lod(0.0, vk::LOD_CLAMP_NONE)` for all of them.
+    pub fn lod(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+
+    /// Create info of the sampler on the device, leaving anisotropy out when the device can't filter with it.
+    fn create_info(&self, sampler_anisotropy: bool, max_sampler_anisotropy: f32) -> vk::SamplerCreateInfo<'static> {
+        let max_anisotropy = self.max_anisotropy.filter(|_| sampler_anisotropy);
+        vk::SamplerCreateInfo::default()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode)
+            .address_mode_v(self.address_mode)
+            .address_mode_w(self.address_mode)
+            .border_color(self.border_color)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.map_or(1.0, |anisotropy| anisotropy.clamp(1.0, max_sampler_anisotropy)))
+            .mip_lod_bias(self.mip_lod_bias)
+            .min_lod(self.min_lod)
+            .max_lod(self.max_lod)
+    }
+}
+
+/// Create a sampler handle, owned by the caller.
+pub(crate) fn create_sampler(device: &Device, config: &SamplerConfig) -> vk::Sampler {
+    let sampler_anisotropy = device.inner.sampler_anisotropy;
+    if config.max_anisotropy.is_some() && !sampler_anisotropy {
+        warn!(target: LOG_TARGET, "Ignoring sampler anisotropy, the samplerAnisotropy feature is not enabled");
+    }
+    let create_info = config.create_info(sampler_anisotropy, device.limits().max_sampler_anisotropy);
+    unsafe {
+        device.handle().create_sampler(&create_info, None)
+            .expect("Failed to create sampler")
+    }
+}
+
+pub struct SamplerInner {
+    device_dep: Arc<DeviceInner>,
+    sampler: vk::Sampler,
+    config: SamplerConfig,
+}
+
+impl Drop for SamplerInner {
+    fn drop(&mut self) {
+        unsafe {
+            let sampler_addr = format!("{:?}", self.sampler);
+            self.device_dep.live_objects.destroyed(self.sampler);
+            self.device_dep.device.destroy_sampler(self.sampler, None);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed sampler: [{}]", sampler_addr);
+        }
+    }
+}
+
+/// A sampler independent of any image, combined with images in descriptor writes, see
+/// [`DescriptorWriter::sampled_image_with`](crate::vulkan::DescriptorWriter::sampled_image_with).
+#[derive(Clone)]
+pub struct Sampler {
+    inner: Arc<SamplerInner>,
+}
+
+impl GpuResource for Sampler {
+    fn reference(&self) -> Arc<dyn Any> {
+        self.inner.clone()
+    }
+}
+
+impl Sampler {
+    pub fn new(device: &Device, config: SamplerConfig) -> Sampler {
+        let sampler = create_sampler(device, &config);
+        trace!(target: OBJECTS_LOG_TARGET, "Created sampler: {:?}", sampler);
+        device.inner.live_objects.created(sampler);
+
+        Sampler {
+            inner: Arc::new(SamplerInner {
+                device_dep: device.inner.clone(),
+                sampler,
+                config,
+            })
+        }
+    }
+
+    pub fn handle(&self) -> vk::Sampler {
+        self.inner.sampler
+    }
+
+    pub fn config(&self) -> SamplerConfig {
+        self.inner.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_needs_feature() {
+        let config = SamplerConfig::default().filter(vk::Filter::LINEAR).anisotropy(32.0);
+        let create_info = config.create_info(true, 16.0);
+        assert_eq!(create_info.anisotropy_enable, vk::TRUE);
+        assert_eq!(create_info.max_anisotropy, 16.0);
+        assert_eq!(create_info.min_filter, vk::Filter::LINEAR);
+
+        let create_info = config.create_info(false, 16.0);
+        assert_eq!(create_info.anisotropy_enable, vk::FALSE);
+        assert_eq!(create_info.max_anisotropy, 1.0);
+    }
+}
@@ -3,7 +3,7 @@ use ash::vk;
 use ash::vk::{PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceKHR};
 use log::trace;
 use crate::vulkan::window_state::WindowState;
-use crate::vulkan::{Instance, LOG_TARGET};
+use crate::vulkan::{Instance, OBJECTS_LOG_TARGET};
 
 /// A presentation surface for rendering graphics to a window.
 pub struct Surface {
@@ -25,7 +25,7 @@ impl Surface {
             ).expect("Failed to get surface.")
         };
 
-        trace!(target: LOG_TARGET, "Created surface: {:?}", surface);
+        trace!(target: OBJECTS_LOG_TARGET, "Created surface: {:?}", surface);
 
         Surface {
             surface,
@@ -45,6 +45,14 @@ impl Surface {
         unsafe { self.surface_loader.get_physical_device_surface_present_modes(*physical_device, self.surface).unwrap() }
     }
 
+    /// Whether queues of the family can present to this surface.
+    pub fn supports_present(&self, physical_device: &vk::PhysicalDevice, queue_family_index: u32) -> bool {
+        unsafe {
+            self.surface_loader.get_physical_device_surface_support(*physical_device, queue_family_index, self.surface)
+                .unwrap_or(false)
+        }
+    }
+
     pub fn get_surface_capabilities(&self, physical_device: &vk::PhysicalDevice) -> SurfaceCapabilitiesKHR {
         unsafe { self.surface_loader.get_physical_device_surface_capabilities(*physical_device, self.surface).unwrap() }
     }
@@ -56,7 +64,7 @@ impl Drop for Surface {
         unsafe {
             let surface_addr = format!("{:?}", self.surface);
             self.surface_loader.destroy_surface(self.surface, None);
-            trace!(target: LOG_TARGET, "Destroyed surface: [{}]", surface_addr);
+            trace!(target: OBJECTS_LOG_TARGET, "Destroyed surface: [{}]", surface_addr);
         }
     }
 }
\ No newline at end of file
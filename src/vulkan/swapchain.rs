@@ -1,14 +1,19 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use ash::khr::swapchain;
 use ash::vk;
 use ash::vk::{CompositeAlphaFlagsKHR, ImageUsageFlags, PresentModeKHR, SharingMode, SurfaceFormatKHR, SwapchainKHR};
 use log::{debug, info};
 use crate::vulkan::window_state::WindowState;
 use crate::vulkan;
-use crate::vulkan::{Device, ImageTrait, Instance, Surface, LOG_TARGET};
+use crate::vulkan::{Device, ImageTrait, Instance, Surface, SWAPCHAIN_LOG_TARGET};
 use crate::vulkan::device::DeviceInner;
 use crate::vulkan::image::SwapchainImage;
 
+/// Source of swapchain epochs, unique for every swapchain that is created.
+static NEXT_EPOCH: AtomicU64 = AtomicU64::new(1);
+
 /// Vulkan does not have a concept of a "default framebuffer". Instead, we need a framework that "owns" the images that will eventually be presented to the screen.
 /// The general purpose of the swapchain is to synchronize the presentation of images with the refresh rate of the screen.
 pub struct SwapchainInner {
@@ -18,7 +23,9 @@ pub struct SwapchainInner {
     swapchain: vk::SwapchainKHR,
     images: Vec<SwapchainImage>,
     extent: vk::Extent2D,
-    format: SurfaceFormatKHR
+    format: SurfaceFormatKHR,
+    present_mode: PresentModeKHR,
+    epoch: u64,
 }
 
 impl Drop for SwapchainInner {
@@ -45,26 +52,9 @@ impl Swapchain {
     ) -> Swapchain {
         let swapchain_loader = swapchain::Device::new(instance.handle(), device.handle());
 
-        let available_formats = surface.get_formats(physical_device);
-        let surface_format = available_formats.iter()
-            .find(|f| {
-                #[cfg(any(target_os = "linux", target_os = "windows"))]
-                let preferred_format = &&vk::SurfaceFormatKHR {
-                    format: vk::Format::R8G8B8A8_SRGB,
-                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-                };
-                
-                #[cfg(target_os = "macos")]
-                let preferred_format = &&vk::SurfaceFormatKHR {
-                    format: vk::Format::B8G8R8A8_SRGB,
-                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-                };
-                
-                f == preferred_format
-            })
-            .unwrap_or(available_formats.first().expect("No surface format found"));
-
-        info!(target: LOG_TARGET, "Using swapchain surface format: {:?}", surface_format);
+        let surface_format = choose_surface_format(&surface.get_formats(physical_device));
+
+        info!(target: SWAPCHAIN_LOG_TARGET, "Using swapchain surface format: {:?}", surface_format);
 
         let surface_capabilities = surface.get_surface_capabilities(physical_device);
 
@@ -80,6 +70,16 @@ impl Swapchain {
             surface_capabilities.current_transform
         };
 
+        // Transparent windows need the surface to blend with the alpha of the swapchain
+        let composite_alpha = [CompositeAlphaFlagsKHR::PRE_MULTIPLIED, CompositeAlphaFlagsKHR::POST_MULTIPLIED, CompositeAlphaFlagsKHR::INHERIT]
+            .into_iter()
+            .filter(|_| window.transparent)
+            .find(|mode| surface_capabilities.supported_composite_alpha.contains(*mode))
+            .unwrap_or(CompositeAlphaFlagsKHR::OPAQUE);
+        if window.transparent && composite_alpha == CompositeAlphaFlagsKHR::OPAQUE {
+            info!(target: SWAPCHAIN_LOG_TARGET, "The surface doesn't support alpha compositing, the window stays opaque");
+        }
+
         let present_modes = surface.get_present_modes(physical_device);
         let present_mode = present_modes
             .iter()
@@ -87,15 +87,15 @@ impl Swapchain {
             .find(|&mode| mode == preferred_present_mode)
             .unwrap_or(vk::PresentModeKHR::FIFO);
         
-        debug!(target: LOG_TARGET, "Present mode: {:?}", present_mode);
+        debug!(target: SWAPCHAIN_LOG_TARGET, "Present mode: {:?}", present_mode);
 
         let extent = match surface_capabilities.current_extent.width {
             u32::MAX => window.extent2d,
             _ => surface_capabilities.current_extent
         };
-        info!(target: LOG_TARGET, "Using swapchain extent: {:?}", extent);
-        info!(target: LOG_TARGET, "Using scale factor: {:?}", window.scale_factor);
-        info!(target: LOG_TARGET, "Using image count: {:?}", desired_image_count);
+        info!(target: SWAPCHAIN_LOG_TARGET, "Using swapchain extent: {:?}", extent);
+        info!(target: SWAPCHAIN_LOG_TARGET, "Using scale factor: {:?}", window.scale_factor);
+        info!(target: SWAPCHAIN_LOG_TARGET, "Using image count: {:?}", desired_image_count);
 
         let mut create_info = vk::SwapchainCreateInfoKHR::default()
             .image_usage(ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST)
@@ -103,7 +103,7 @@ impl Swapchain {
             .image_sharing_mode(SharingMode::EXCLUSIVE)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
-            .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
+            .composite_alpha(composite_alpha)
             .pre_transform(pre_transform)
             .present_mode(present_mode)
             .min_image_count(desired_image_count)
@@ -121,13 +121,18 @@ impl Swapchain {
             .map(|&image| vulkan::SwapchainImage::from_raw(device, image, surface_format.format, extent))
             .collect::<Vec<vulkan::SwapchainImage>>();
 
+        let epoch = NEXT_EPOCH.fetch_add(1, Ordering::Relaxed);
+        images.iter().for_each(|image| image.set_swapchain_epoch(epoch));
+
         let swapchain_inner = SwapchainInner {
             device_dep: device.inner.clone(),
             swapchain_loader,
             swapchain,
             images,
             extent,
-            format: *surface_format,
+            format: surface_format,
+            present_mode,
+            epoch,
         };
 
         Self {
@@ -135,6 +140,12 @@ impl Swapchain {
         }
     }
 
+    /// Whether the surface now prefers another format than the one of the swapchain, e.g. after the window moved to
+    /// a monitor with another color space. The swapchain has to be recreated to follow it.
+    pub fn is_format_outdated(&self, physical_device: &vk::PhysicalDevice, surface: &Surface) -> bool {
+        choose_surface_format(&surface.get_formats(physical_device)) != self.inner.format
+    }
+
     pub fn get_images(&self) -> &Vec<SwapchainImage> {
         &self.inner.images
     }
@@ -147,6 +158,11 @@ impl Swapchain {
         self.inner.images.len() as u32
     }
 
+    /// Generation of the swapchain, every recreation gets a new epoch.
+    pub fn epoch(&self) -> u64 {
+        self.inner.epoch
+    }
+
     pub fn get_extent(&self) -> vk::Extent2D {
         self.inner.extent
     }
@@ -155,6 +171,11 @@ impl Swapchain {
         self.inner.format
     }
 
+    /// The present mode in use, FIFO when the preferred one isn't supported.
+    pub fn get_present_mode(&self) -> PresentModeKHR {
+        self.inner.present_mode
+    }
+
     pub fn handle(&self) -> SwapchainKHR {
         self.inner.swapchain
     }
@@ -179,21 +200,44 @@ impl Swapchain {
         }
     }
 
-    /// Acquire the next image in the swapchain.
-    /// * `semaphore` - A semaphore to signal when the image is available.
-    ///
-    /// https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkAcquireNextImageKHR.html
-    pub fn acquire_next_image(&self, semaphore: vk::Semaphore) -> u32 {
-        unsafe {
-            let (image_index, _) = self.inner.swapchain_loader
+    /// Acquire the next image to render to, signaling `semaphore` once it's available.
+    /// Returns `None` when no image became available within `timeout`, the semaphore is not signaled in that case.
+    pub fn acquire_next_image(&self, semaphore: vk::Semaphore, timeout: Duration) -> Option<u32> {
+        let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        let result = unsafe {
+            self.inner.swapchain_loader
                 .acquire_next_image(
                     self.handle(),
-                    u64::MAX,
+                    timeout,
                     semaphore,
                     vk::Fence::null()
                 )
-                .expect("Failed to acquire next image");
-            image_index
+        };
+
+        match result {
+            Ok((image_index, _)) => Some(image_index),
+            Err(vk::Result::TIMEOUT) | Err(vk::Result::NOT_READY) => None,
+            Err(e) => panic!("Failed to acquire next image: {:?}", e),
         }
     }
 }
+
+/// The preferred sRGB format when the surface supports it, otherwise the first one it reports.
+fn choose_surface_format(available_formats: &[SurfaceFormatKHR]) -> SurfaceFormatKHR {
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    let preferred_format = vk::SurfaceFormatKHR {
+        format: vk::Format::R8G8B8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    };
+
+    #[cfg(target_os = "macos")]
+    let preferred_format = vk::SurfaceFormatKHR {
+        format: vk::Format::B8G8R8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    };
+
+    available_formats.iter()
+        .copied()
+        .find(|format| *format == preferred_format)
+        .unwrap_or_else(|| *available_formats.first().expect("No surface format found"))
+}
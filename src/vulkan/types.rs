@@ -0,0 +1,163 @@
+//! Cen-owned types for the parameters components pass most often.
+//!
+//! Helpers accepting them take `impl Into<vk::...>`, so the raw `vk` values keep working as well. Unlike the `vk`
+//! types, these stay the same when cen moves to a new ash version.
+
+use ash::vk;
+use bitflags::bitflags;
+
+/// Common image formats.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Format {
+    R8Unorm,
+    Rg8Unorm,
+    Rgba8Unorm,
+    Rgba8Srgb,
+    Bgra8Unorm,
+    Bgra8Srgb,
+    R16Float,
+    Rg16Float,
+    Rgba16Float,
+    R32Float,
+    Rg32Float,
+    Rgba32Float,
+    R32Uint,
+    Rgba32Uint,
+    R32Sint,
+    D16Unorm,
+    D32Float,
+    D24UnormS8Uint,
+}
+
+pub(crate) const FORMATS: [(Format, vk::Format); 18] = [
+    (Format::R8Unorm, vk::Format::R8_UNORM),
+    (Format::Rg8Unorm, vk::Format::R8G8_UNORM),
+    (Format::Rgba8Unorm, vk::Format::R8G8B8A8_UNORM),
+    (Format::Rgba8Srgb, vk::Format::R8G8B8A8_SRGB),
+    (Format::Bgra8Unorm, vk::Format::B8G8R8A8_UNORM),
+    (Format::Bgra8Srgb, vk::Format::B8G8R8A8_SRGB),
+    (Format::R16Float, vk::Format::R16_SFLOAT),
+    (Format::Rg16Float, vk::Format::R16G16_SFLOAT),
+    (Format::Rgba16Float, vk::Format::R16G16B16A16_SFLOAT),
+    (Format::R32Float, vk::Format::R32_SFLOAT),
+    (Format::Rg32Float, vk::Format::R32G32_SFLOAT),
+    (Format::Rgba32Float, vk::Format::R32G32B32A32_SFLOAT),
+    (Format::R32Uint, vk::Format::R32_UINT),
+    (Format::Rgba32Uint, vk::Format::R32G32B32A32_UINT),
+    (Format::R32Sint, vk::Format::R32_SINT),
+    (Format::D16Unorm, vk::Format::D16_UNORM),
+    (Format::D32Float, vk::Format::D32_SFLOAT),
+    (Format::D24UnormS8Uint, vk::Format::D24_UNORM_S8_UINT),
+];
+
+impl From<Format> for vk::Format {
+    fn from(format: Format) -> Self {
+        FORMATS.iter().find(|(f, _)| *f == format).map(|(_, vk_format)| *vk_format).unwrap()
+    }
+}
+
+impl TryFrom<vk::Format> for Format {
+    /// The format has no cen counterpart.
+    type Error = vk::Format;
+
+    fn try_from(vk_format: vk::Format) -> Result<Self, Self::Error> {
+        FORMATS.iter().find(|(_, f)| *f == vk_format).map(|(format, _)| *format).ok_or(vk_format)
+    }
+}
+
+bitflags! {
+    /// How an image is used, see `vk::ImageUsageFlags`.
+    #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+    pub struct ImageUsage: u32 {
+        const TRANSFER_SRC = 1 << 0;
+        const TRANSFER_DST = 1 << 1;
+        const SAMPLED = 1 << 2;
+        const STORAGE = 1 << 3;
+        const COLOR_ATTACHMENT = 1 << 4;
+        const DEPTH_STENCIL_ATTACHMENT = 1 << 5;
+        const INPUT_ATTACHMENT = 1 << 7;
+    }
+}
+
+impl From<ImageUsage> for vk::ImageUsageFlags {
+    fn from(usage: ImageUsage) -> Self {
+        // The bits match the Vulkan ones
+        vk::ImageUsageFlags::from_raw(usage.bits())
+    }
+}
+
+/// Common image layouts.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Layout {
+    /// Contents are discarded when transitioning away from it.
+    Undefined,
+    /// Any access, e.g. storage images.
+    General,
+    ColorAttachment,
+    /// Depth, and stencil, attachment of a pass.
+    DepthAttachment,
+    /// Depth attachment that is tested against without writing, or sampled.
+    DepthReadOnly,
+    ShaderReadOnly,
+    TransferSrc,
+    TransferDst,
+    Present,
+}
+
+impl From<Layout> for vk::ImageLayout {
+    fn from(layout: Layout) -> Self {
+        match layout {
+            Layout::Undefined => vk::ImageLayout::UNDEFINED,
+            Layout::General => vk::ImageLayout::GENERAL,
+            Layout::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Layout::DepthAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Layout::DepthReadOnly => vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            Layout::ShaderReadOnly => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Layout::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            Layout::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            Layout::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+        }
+    }
+}
+
+/// Texel filtering of samplers and blits.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Linear,
+}
+
+impl From<Filter> for vk::Filter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => vk::Filter::NEAREST,
+            Filter::Linear => vk::Filter::LINEAR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_to_vk() {
+        for (format, vk_format) in FORMATS {
+            assert_eq!(vk::Format::from(format), vk_format);
+            assert_eq!(Format::try_from(vk_format), Ok(format));
+        }
+        assert_eq!(Format::try_from(vk::Format::BC7_SRGB_BLOCK), Err(vk::Format::BC7_SRGB_BLOCK));
+
+        assert_eq!(
+            vk::ImageUsageFlags::from(ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::INPUT_ATTACHMENT),
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::INPUT_ATTACHMENT
+        );
+        assert_eq!(
+            vk::ImageUsageFlags::from(ImageUsage::all()),
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT
+        );
+    }
+}
@@ -6,4 +6,6 @@ pub struct WindowState<'a> {
     pub display_handle: DisplayHandle<'a>,
     pub extent2d: Extent2D,
     pub scale_factor: f64,
+    /// Composite the swapchain with the desktop using its alpha, when the surface supports it.
+    pub transparent: bool,
 }